@@ -1,22 +1,33 @@
+#[cfg(target_os = "macos")]
 extern crate cocoa;
 extern crate image_capture_core;
 extern crate libc;
+#[cfg(target_os = "macos")]
 #[macro_use]
 extern crate objc;
 
+#[cfg(target_os = "macos")]
 use cocoa::appkit::{
     NSApp, NSApplication, NSApplicationActivateIgnoringOtherApps,
     NSApplicationActivationPolicyRegular, NSRunningApplication,
 };
+#[cfg(target_os = "macos")]
 use cocoa::base::{id, nil, BOOL};
+#[cfg(target_os = "macos")]
 use cocoa::foundation::NSAutoreleasePool;
+#[cfg(target_os = "macos")]
 use image_capture_core::device::{ICDevice, ICDeviceLocationTypeMask, ICDeviceTypeMask};
+#[cfg(target_os = "macos")]
 use image_capture_core::device_browser::ICDeviceBrowser;
+#[cfg(target_os = "macos")]
 use objc::declare::ClassDecl;
+#[cfg(target_os = "macos")]
 use objc::runtime::{Object, Sel};
+#[cfg(target_os = "macos")]
 use std::ffi::CStr;
 
 /// Convert an NSString object into a Rust String
+#[cfg(target_os = "macos")]
 pub fn nsstring_decode(str: id) -> String {
     unsafe {
         let cstr: *const libc::c_char = msg_send![str, UTF8String];
@@ -25,6 +36,7 @@ pub fn nsstring_decode(str: id) -> String {
     }
 }
 
+#[cfg(target_os = "macos")]
 fn main() {
     unsafe {
         let _pool = NSAutoreleasePool::new(nil);
@@ -85,3 +97,8 @@ fn main() {
         app.run();
     }
 }
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("ImageCaptureCore is only available on macOS");
+}
@@ -0,0 +1,1165 @@
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerFunctionalUnitType::ICScannerFunctionalUnitTypeDocumentFeeder;
+use crate::constants::{
+    ICEXIFOrientationType, ICScannerBitDepth, ICScannerDocumentType, ICScannerFunctionalUnitType,
+    ICScannerMeasurementUnit, ICScannerPixelDataType, ICScannerTransferMode,
+};
+use crate::error::{Error, Result};
+use crate::image::Image;
+use crate::measurement::ScanRect;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Identifies a device of a backend for as long as the backend runs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceId(pub String);
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Identifies a file or folder on a camera.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemId(pub String);
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Kind of an imaging device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    /// A camera, or another device with files to download.
+    Camera,
+    /// A scanner.
+    Scanner,
+}
+
+/// A device found by a backend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    /// Identifier of the device.
+    pub id: DeviceId,
+    /// Kind of the device.
+    pub kind: DeviceKind,
+    /// Name of the device.
+    pub name: String,
+    /// Transport the device is connected through, such as `"ICTransportTypeUSB"`.
+    pub transport: Option<String>,
+    /// Serial number of the device, if it reports one.
+    pub serial_number: Option<String>,
+    /// Indicates whether the device is shared by another host or found on the network.
+    pub is_remote: bool,
+    /// Capabilities of the device, such as `"ICCameraDeviceCanDeleteOneFile"`.
+    pub capabilities: Vec<String>,
+}
+
+/// A file or folder on a camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraItem {
+    /// Identifier of the item.
+    pub id: ItemId,
+    /// Name of the item.
+    pub name: String,
+    /// The folder holding the item, or `None` for items at the top of a storage.
+    pub parent: Option<ItemId>,
+    /// Indicates whether the item is a folder.
+    pub is_folder: bool,
+    /// Uniform Type Identifier of a file, such as `"public.jpeg"`.
+    pub uti: Option<String>,
+    /// Size of a file in bytes, 0 for folders.
+    pub size: u64,
+    /// Orientation a file should be downloaded with.
+    pub orientation: ICEXIFOrientationType,
+    /// Indicates whether the file is a raw image.
+    pub is_raw: bool,
+    /// Indicates whether the item is protected from deletion.
+    pub is_locked: bool,
+    /// Creation date of the item.
+    pub creation_date: Option<SystemTime>,
+    /// Modification date of the item.
+    pub modification_date: Option<SystemTime>,
+    /// PTP object handle of the item, or 0 for cameras that do not use PTP.
+    pub ptp_object_handle: u32,
+    /// Names of the sidecar files of a file, such as XMP or THM files.
+    pub sidecar_files: Vec<String>,
+    /// Indicates whether the item was added after the catalog was complete, such as a picture
+    /// taken in tethered capture.
+    pub added_after_catalog: bool,
+}
+
+/// The state of a camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CameraStatus {
+    /// Charge of the battery in percent, if the camera reports it.
+    pub battery_level: Option<u8>,
+    /// How much of the catalog has been received, in percent.
+    pub catalog_percent_completed: u8,
+    /// Indicates whether tethered capture is enabled.
+    pub tethered_capture_enabled: bool,
+    /// Indicates whether the camera is an Apple device that restricts access to its contents,
+    /// for example because it is locked with a passcode.
+    pub access_restricted: bool,
+}
+
+/// How a file is downloaded from a camera.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DownloadOptions {
+    /// Directory the file is saved in.
+    pub directory: PathBuf,
+    /// Name to save the file as, instead of its name on the camera.
+    pub file_name: Option<String>,
+    /// Indicates whether an existing file with the same name is replaced.
+    pub overwrite: bool,
+    /// Indicates whether the file is deleted from the camera after it is downloaded. A file that
+    /// cannot be deleted is reported with `Event::DeviceError`.
+    pub delete_after_download: bool,
+    /// Indicates whether sidecar files, such as XMP files, are downloaded with the file.
+    pub sidecar_files: bool,
+}
+
+impl DownloadOptions {
+    /// Create options that save files in `directory` under their own names, without replacing
+    /// existing files.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> DownloadOptions {
+        DownloadOptions {
+            directory: directory.into(),
+            file_name: None,
+            overwrite: false,
+            delete_after_download: false,
+            sidecar_files: false,
+        }
+    }
+
+    /// Set the name to save the file as.
+    pub fn with_file_name(mut self, file_name: &str) -> DownloadOptions {
+        self.file_name = Some(file_name.to_owned());
+        self
+    }
+
+    /// Set whether an existing file with the same name is replaced.
+    pub fn with_overwrite(mut self, overwrite: bool) -> DownloadOptions {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Set whether the file is deleted from the camera after it is downloaded.
+    pub fn with_delete_after_download(mut self, delete: bool) -> DownloadOptions {
+        self.delete_after_download = delete;
+        self
+    }
+
+    /// Set whether sidecar files are downloaded with the file.
+    pub fn with_sidecar_files(mut self, sidecar_files: bool) -> DownloadOptions {
+        self.sidecar_files = sidecar_files;
+        self
+    }
+}
+
+/// A vendor specific feature of a functional unit, mirroring `ICScannerFeature`. Template
+/// features are not represented.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScannerFeature {
+    /// Internal name of the feature, which settings refer to it by.
+    pub name: String,
+    /// Human readable name of the feature.
+    pub label: Option<String>,
+    /// The values the feature accepts and its current value.
+    pub kind: FeatureKind,
+}
+
+/// The values a scanner feature accepts and its current value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeatureKind {
+    /// One of several discrete values, mirroring `ICScannerFeatureEnumeration`. Numeric values
+    /// are given as their decimal representation.
+    Enumeration {
+        /// The possible values.
+        values: Vec<String>,
+        /// The current value.
+        current: String,
+    },
+    /// A value within a range, mirroring `ICScannerFeatureRange`.
+    Range {
+        /// The minimum value.
+        min: f64,
+        /// The maximum value.
+        max: f64,
+        /// The step size, or 0 for a continuous range.
+        step: f64,
+        /// The current value.
+        current: f64,
+    },
+    /// A value that is on or off, mirroring `ICScannerFeatureBoolean`.
+    Boolean(bool),
+}
+
+/// A value for a scanner feature.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeatureValue {
+    /// A value of an enumeration feature.
+    Enumeration(String),
+    /// A value of a range feature.
+    Range(f64),
+    /// A value of a boolean feature.
+    Boolean(bool),
+}
+
+impl ScannerFeature {
+    /// Create an enumeration feature.
+    pub fn enumeration(name: &str, values: &[&str], current: &str) -> ScannerFeature {
+        ScannerFeature {
+            name: name.to_owned(),
+            label: None,
+            kind: FeatureKind::Enumeration {
+                values: values.iter().map(|&value| value.to_owned()).collect(),
+                current: current.to_owned(),
+            },
+        }
+    }
+
+    /// Create a range feature.
+    pub fn range(name: &str, min: f64, max: f64, step: f64, current: f64) -> ScannerFeature {
+        ScannerFeature {
+            name: name.to_owned(),
+            label: None,
+            kind: FeatureKind::Range {
+                min,
+                max,
+                step,
+                current,
+            },
+        }
+    }
+
+    /// Create a boolean feature.
+    pub fn boolean(name: &str, value: bool) -> ScannerFeature {
+        ScannerFeature {
+            name: name.to_owned(),
+            label: None,
+            kind: FeatureKind::Boolean(value),
+        }
+    }
+
+    /// Set the human readable name.
+    pub fn with_label(mut self, label: &str) -> ScannerFeature {
+        self.label = Some(label.to_owned());
+        self
+    }
+
+    /// The current value.
+    pub fn value(&self) -> FeatureValue {
+        match self.kind {
+            FeatureKind::Enumeration { ref current, .. } => {
+                FeatureValue::Enumeration(current.clone())
+            }
+            FeatureKind::Range { current, .. } => FeatureValue::Range(current),
+            FeatureKind::Boolean(value) => FeatureValue::Boolean(value),
+        }
+    }
+
+    /// Indicates whether the feature accepts a value: it must be of the kind of the feature and
+    /// one of its values or within its range.
+    pub fn accepts(&self, value: &FeatureValue) -> bool {
+        match (&self.kind, value) {
+            (FeatureKind::Enumeration { values, .. }, FeatureValue::Enumeration(value)) => {
+                values.contains(value)
+            }
+            (FeatureKind::Range { min, max, .. }, FeatureValue::Range(value)) => {
+                value >= min && value <= max
+            }
+            (FeatureKind::Boolean(_), FeatureValue::Boolean(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Change the current value. Range values are moved to the nearest step, as scanners do.
+    /// Fails if the feature does not accept the value.
+    pub fn set_value(&mut self, value: &FeatureValue) -> Result<()> {
+        if !self.accepts(value) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        match (&mut self.kind, value) {
+            (FeatureKind::Enumeration { current, .. }, FeatureValue::Enumeration(value)) => {
+                *current = value.clone();
+            }
+            (
+                FeatureKind::Range {
+                    min,
+                    max,
+                    step,
+                    current,
+                },
+                &FeatureValue::Range(value),
+            ) => {
+                *current = if *step > 0.0 {
+                    (*min + ((value - *min) / *step).round() * *step).min(*max)
+                } else {
+                    value
+                };
+            }
+            (FeatureKind::Boolean(current), &FeatureValue::Boolean(value)) => *current = value,
+            _ => unreachable!("the kinds are checked above"),
+        }
+        Ok(())
+    }
+}
+
+/// The configuration of the selected functional unit of a scanner and of the scans it makes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionalUnit {
+    /// Type of the functional unit.
+    pub unit_type: ICScannerFunctionalUnitType,
+    /// Unit of the physical size and the scan area.
+    pub measurement_unit: ICScannerMeasurementUnit,
+    /// Width and height of the area the unit can scan.
+    pub physical_size: (f64, f64),
+    /// Resolutions the unit can scan at, in pixels per inch.
+    pub supported_resolutions: Vec<u64>,
+    /// Resolution of scans, in pixels per inch.
+    pub resolution: u64,
+    /// Bit depths the unit can scan with.
+    pub supported_bit_depths: Vec<ICScannerBitDepth>,
+    /// Bit depth of scans.
+    pub bit_depth: ICScannerBitDepth,
+    /// Pixel data type of scans.
+    pub pixel_data_type: ICScannerPixelDataType,
+    /// Area to scan, in `measurement_unit`.
+    pub scan_area: ScanRect,
+    /// Orientation of the scan area.
+    pub scan_area_orientation: ICEXIFOrientationType,
+    /// Document types the unit supports.
+    pub supported_document_types: Vec<ICScannerDocumentType>,
+    /// The current document type.
+    pub document_type: ICScannerDocumentType,
+    /// Indicates whether the unit can make overview scans.
+    pub can_perform_overview_scan: bool,
+    /// Resolution of overview scans, in pixels per inch.
+    pub overview_resolution: u64,
+    /// Indicates whether the unit accepts a threshold for black and white scanning.
+    pub accepts_threshold: bool,
+    /// Threshold for black and white scanning, from 0 to 255, if the unit uses one.
+    pub threshold: Option<u8>,
+    /// Indicates whether duplex scanning is enabled, for document feeders that support it.
+    pub duplex: Option<bool>,
+    /// How scans are transferred.
+    pub transfer_mode: ICScannerTransferMode,
+    /// Directory file based scans are saved in.
+    pub downloads_directory: Option<PathBuf>,
+    /// Name of file based scans.
+    pub document_name: Option<String>,
+    /// Uniform Type Identifier of file based scans, such as `"public.tiff"`.
+    pub document_uti: Option<String>,
+    /// Largest size in bytes of the bands of memory based scans.
+    pub max_memory_band_size: u32,
+    /// Vendor specific features of the unit.
+    pub vendor_features: Vec<ScannerFeature>,
+}
+
+impl FunctionalUnit {
+    /// Check that settings can be applied to the functional unit: resolutions, bit depths and
+    /// document types must be supported, the scan area must lie on the unit, duplex
+    /// scanning needs a document feeder that supports it and features must accept their values.
+    pub fn validate(&self, settings: &ScanSettings) -> Result<()> {
+        let invalid = Err(Error::ReturnCode(ICReturnInvalidParam));
+        let resolution = settings.resolution.unwrap_or(self.resolution);
+        if let Some(resolution) = settings.resolution {
+            if !self.supported_resolutions.contains(&resolution) {
+                return invalid;
+            }
+        }
+        if let Some(bit_depth) = settings.bit_depth {
+            if !self.supported_bit_depths.contains(&bit_depth) {
+                return invalid;
+            }
+        }
+        if let Some(document_type) = settings.document_type {
+            if !self.supported_document_types.contains(&document_type) {
+                return invalid;
+            }
+        }
+        if let Some(area) = settings.scan_area {
+            let area = area.to(self.measurement_unit, resolution)?;
+            // Allow for rounding in the unit conversion.
+            let tolerance = 1e-6 * (self.physical_size.0 + self.physical_size.1);
+            if area.x < -tolerance
+                || area.y < -tolerance
+                || !(area.width > 0.0 && area.height > 0.0)
+                || area.x + area.width > self.physical_size.0 + tolerance
+                || area.y + area.height > self.physical_size.1 + tolerance
+            {
+                return invalid;
+            }
+        }
+        if settings.duplex == Some(true)
+            && (self.unit_type != ICScannerFunctionalUnitTypeDocumentFeeder
+                || self.duplex.is_none())
+        {
+            return invalid;
+        }
+        if settings.threshold.is_some() && !self.accepts_threshold {
+            return invalid;
+        }
+        if settings.max_memory_band_size == Some(0) {
+            return invalid;
+        }
+        for (name, value) in &settings.features {
+            if !self
+                .vendor_features
+                .iter()
+                .any(|feature| feature.name == *name && feature.accepts(value))
+            {
+                return invalid;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Changes to the configuration of a scanner. Settings that are `None` are left alone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanSettings {
+    /// Resolution in pixels per inch.
+    pub resolution: Option<u64>,
+    /// Bit depth.
+    pub bit_depth: Option<ICScannerBitDepth>,
+    /// Pixel data type.
+    pub pixel_data_type: Option<ICScannerPixelDataType>,
+    /// Measurement unit of the functional unit.
+    pub measurement_unit: Option<ICScannerMeasurementUnit>,
+    /// Area to scan, in any unit.
+    pub scan_area: Option<ScanRect>,
+    /// Orientation of the scan area.
+    pub scan_area_orientation: Option<ICEXIFOrientationType>,
+    /// Document type.
+    pub document_type: Option<ICScannerDocumentType>,
+    /// Duplex scanning on a document feeder.
+    pub duplex: Option<bool>,
+    /// Threshold for black and white scanning, from 0 to 255. Setting it also makes the unit use
+    /// it.
+    pub threshold: Option<u8>,
+    /// Resolution of overview scans in pixels per inch.
+    pub overview_resolution: Option<u64>,
+    /// How scans are transferred.
+    pub transfer_mode: Option<ICScannerTransferMode>,
+    /// Directory file based scans are saved in.
+    pub downloads_directory: Option<PathBuf>,
+    /// Name of file based scans.
+    pub document_name: Option<String>,
+    /// Uniform Type Identifier of file based scans.
+    pub document_uti: Option<String>,
+    /// Largest size in bytes of the bands of memory based scans.
+    pub max_memory_band_size: Option<u32>,
+    /// Values of vendor specific features, by internal name.
+    pub features: Vec<(String, FeatureValue)>,
+}
+
+impl ScanSettings {
+    /// Create settings that change nothing.
+    pub fn new() -> ScanSettings {
+        ScanSettings::default()
+    }
+
+    /// Set the resolution.
+    pub fn with_resolution(mut self, resolution: u64) -> ScanSettings {
+        self.resolution = Some(resolution);
+        self
+    }
+
+    /// Set the bit depth.
+    pub fn with_bit_depth(mut self, bit_depth: ICScannerBitDepth) -> ScanSettings {
+        self.bit_depth = Some(bit_depth);
+        self
+    }
+
+    /// Set the pixel data type.
+    pub fn with_pixel_data_type(mut self, pixel_data_type: ICScannerPixelDataType) -> ScanSettings {
+        self.pixel_data_type = Some(pixel_data_type);
+        self
+    }
+
+    /// Set the measurement unit.
+    pub fn with_measurement_unit(mut self, unit: ICScannerMeasurementUnit) -> ScanSettings {
+        self.measurement_unit = Some(unit);
+        self
+    }
+
+    /// Set the scan area.
+    pub fn with_scan_area(mut self, scan_area: ScanRect) -> ScanSettings {
+        self.scan_area = Some(scan_area);
+        self
+    }
+
+    /// Set the orientation of the scan area.
+    pub fn with_scan_area_orientation(
+        mut self,
+        orientation: ICEXIFOrientationType,
+    ) -> ScanSettings {
+        self.scan_area_orientation = Some(orientation);
+        self
+    }
+
+    /// Set the document type.
+    pub fn with_document_type(mut self, document_type: ICScannerDocumentType) -> ScanSettings {
+        self.document_type = Some(document_type);
+        self
+    }
+
+    /// Enable or disable duplex scanning.
+    pub fn with_duplex(mut self, duplex: bool) -> ScanSettings {
+        self.duplex = Some(duplex);
+        self
+    }
+
+    /// Set the threshold for black and white scanning.
+    pub fn with_threshold(mut self, threshold: u8) -> ScanSettings {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Set the resolution of overview scans.
+    pub fn with_overview_resolution(mut self, resolution: u64) -> ScanSettings {
+        self.overview_resolution = Some(resolution);
+        self
+    }
+
+    /// Set how scans are transferred.
+    pub fn with_transfer_mode(mut self, transfer_mode: ICScannerTransferMode) -> ScanSettings {
+        self.transfer_mode = Some(transfer_mode);
+        self
+    }
+
+    /// Set the directory and name of file based scans.
+    pub fn with_document<P: Into<PathBuf>>(mut self, directory: P, name: &str) -> ScanSettings {
+        self.downloads_directory = Some(directory.into());
+        self.document_name = Some(name.to_owned());
+        self
+    }
+
+    /// Set the Uniform Type Identifier of file based scans.
+    pub fn with_document_uti(mut self, uti: &str) -> ScanSettings {
+        self.document_uti = Some(uti.to_owned());
+        self
+    }
+
+    /// Set the largest size of the bands of memory based scans.
+    pub fn with_max_memory_band_size(mut self, size: u32) -> ScanSettings {
+        self.max_memory_band_size = Some(size);
+        self
+    }
+
+    /// Set the value of a vendor specific feature.
+    pub fn with_feature(mut self, name: &str, value: FeatureValue) -> ScanSettings {
+        self.features.push((name.to_owned(), value));
+        self
+    }
+}
+
+/// Something that happened on a backend, in answer to a request or on a device.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A device was found.
+    DeviceAdded(DeviceInfo),
+    /// A device went away.
+    DeviceRemoved(DeviceId),
+    /// A request to open a session finished.
+    SessionOpened {
+        /// The device.
+        device: DeviceId,
+        /// Whether the session was opened.
+        result: Result<()>,
+    },
+    /// A request to close a session finished.
+    SessionClosed {
+        /// The device.
+        device: DeviceId,
+        /// Whether the session was closed.
+        result: Result<()>,
+    },
+    /// A device with an open session is ready to use.
+    DeviceReady(DeviceId),
+    /// A device reported its status, such as `"ICScannerStatusWarmingUp"`.
+    Status {
+        /// The device.
+        device: DeviceId,
+        /// The status notification.
+        message: String,
+    },
+    /// A device reported an error that does not belong to a request, mirroring
+    /// `device:didEncounterError:`. Backends also report work that fails after the outcome of its
+    /// request was reported, such as deleting a file after downloading it.
+    DeviceError {
+        /// The device.
+        device: DeviceId,
+        /// The error.
+        error: Error,
+    },
+    /// Items were added to the catalog of a camera.
+    ItemsAdded {
+        /// The camera.
+        device: DeviceId,
+        /// The new items.
+        items: Vec<CameraItem>,
+    },
+    /// Items were removed from the catalog of a camera.
+    ItemsRemoved {
+        /// The camera.
+        device: DeviceId,
+        /// The removed items.
+        items: Vec<ItemId>,
+    },
+    /// The catalog of a camera is complete.
+    CatalogCompleted(DeviceId),
+    /// An Apple device started or stopped restricting access to its contents.
+    AccessRestrictionChanged {
+        /// The camera.
+        device: DeviceId,
+        /// Indicates whether access is now restricted.
+        restricted: bool,
+    },
+    /// A download finished.
+    Downloaded {
+        /// The camera.
+        device: DeviceId,
+        /// The downloaded item.
+        item: ItemId,
+        /// The path of the saved file.
+        result: Result<PathBuf>,
+    },
+    /// A request to read data from a file finished.
+    DataRead {
+        /// The camera.
+        device: DeviceId,
+        /// The file.
+        item: ItemId,
+        /// Offset of the data in the file.
+        offset: u64,
+        /// The data, which is shorter than requested at the end of the file.
+        result: Result<Vec<u8>>,
+    },
+    /// An upload finished. The uploaded file is reported with `Event::ItemsAdded`.
+    Uploaded {
+        /// The camera.
+        device: DeviceId,
+        /// Path of the uploaded file.
+        path: PathBuf,
+        /// Whether the file was uploaded.
+        result: Result<()>,
+    },
+    /// A request to select a functional unit finished.
+    FunctionalUnitSelected {
+        /// The scanner.
+        device: DeviceId,
+        /// The selected unit type.
+        result: Result<ICScannerFunctionalUnitType>,
+    },
+    /// A memory based scan delivered a band of image data.
+    ScannedBand {
+        /// The scanner.
+        device: DeviceId,
+        /// The band.
+        band: ScannerBand,
+    },
+    /// A file based scan saved a file.
+    ScannedFile {
+        /// The scanner.
+        device: DeviceId,
+        /// Path of the file.
+        path: PathBuf,
+    },
+    /// An overview scan finished. The image is available from `Backend::overview_image`.
+    OverviewCompleted {
+        /// The scanner.
+        device: DeviceId,
+        /// Whether the overview scan succeeded.
+        result: Result<()>,
+    },
+    /// A scan finished.
+    ScanCompleted {
+        /// The scanner.
+        device: DeviceId,
+        /// Whether the scan succeeded.
+        result: Result<()>,
+    },
+}
+
+impl Event {
+    /// The device the event is about.
+    pub fn device(&self) -> &DeviceId {
+        match self {
+            Event::DeviceAdded(info) => &info.id,
+            Event::DeviceRemoved(device)
+            | Event::DeviceReady(device)
+            | Event::CatalogCompleted(device) => device,
+            Event::SessionOpened { device, .. }
+            | Event::SessionClosed { device, .. }
+            | Event::Status { device, .. }
+            | Event::DeviceError { device, .. }
+            | Event::ItemsAdded { device, .. }
+            | Event::ItemsRemoved { device, .. }
+            | Event::AccessRestrictionChanged { device, .. }
+            | Event::Downloaded { device, .. }
+            | Event::DataRead { device, .. }
+            | Event::Uploaded { device, .. }
+            | Event::FunctionalUnitSelected { device, .. }
+            | Event::ScannedBand { device, .. }
+            | Event::ScannedFile { device, .. }
+            | Event::OverviewCompleted { device, .. }
+            | Event::ScanCompleted { device, .. } => device,
+        }
+    }
+}
+
+/// A source of cameras and scanners, such as the ImageCaptureCore framework.
+///
+/// Requests are asynchronous like those of ImageCaptureCore: a method that starts a request
+/// returns once the request is sent, and its outcome arrives later as an `Event`. Methods fail
+/// with `ICReturnInvalidParam` for devices the backend does not know or for requests that do not
+/// apply to the device.
+pub trait Backend {
+    /// Start looking for devices of the given kinds. Devices are reported with
+    /// `Event::DeviceAdded`.
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()>;
+
+    /// Stop looking for devices.
+    fn stop_browsing(&mut self) -> Result<()>;
+
+    /// The devices found so far.
+    fn devices(&self) -> Vec<DeviceInfo>;
+
+    /// Wait up to `timeout` for the next event.
+    fn next_event(&mut self, timeout: Duration) -> Option<Event>;
+
+    /// Request a session on a device. A session must be open to use the device.
+    fn open_session(&mut self, device: &DeviceId) -> Result<()>;
+
+    /// Request to close the session on a device.
+    fn close_session(&mut self, device: &DeviceId) -> Result<()>;
+
+    /// Indicates whether a session is open on a device.
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool>;
+
+    /// The files and folders catalogued on a camera so far.
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>>;
+
+    /// Request to download a file from a camera.
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()>;
+
+    /// The state of a camera.
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus>;
+
+    /// Request to read `length` bytes at `offset` from a file on a camera.
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()>;
+
+    /// Request to delete files from a camera. Deleted files are reported with
+    /// `Event::ItemsRemoved`. Fails without deleting anything if an item is a folder or locked.
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()>;
+
+    /// Request to upload a file to a camera.
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()>;
+
+    /// Request to enable or disable tethered capture on a camera.
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()>;
+
+    /// Request a camera with tethered capture enabled to take a picture. The picture is reported
+    /// with `Event::ItemsAdded`.
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()>;
+
+    /// The types of the functional units of a scanner.
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>>;
+
+    /// Request to select a functional unit of a scanner.
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()>;
+
+    /// The configuration of the selected functional unit of a scanner.
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit>;
+
+    /// Apply settings to the selected functional unit of a scanner and return the resulting
+    /// configuration. Fails without changing anything if the settings are not valid for the unit.
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit>;
+
+    /// Request an overview scan of the selected functional unit.
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()>;
+
+    /// The image of the last overview scan, if there is one.
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>>;
+
+    /// Request a scan with the selected functional unit.
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()>;
+
+    /// Cancel the scan in progress.
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()>;
+}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        (**self).start_browsing(kinds)
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        (**self).stop_browsing()
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        (**self).devices()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        (**self).next_event(timeout)
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).open_session(device)
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).close_session(device)
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        (**self).has_open_session(device)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        (**self).camera_items(device)
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        (**self).download_file(device, item, options)
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        (**self).camera_status(device)
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        (**self).read_file(device, item, offset, length)
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        (**self).delete_files(device, items)
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()> {
+        (**self).upload_file(device, path)
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()> {
+        (**self).set_tethering(device, enabled)
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).take_picture(device)
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        (**self).functional_unit_types(device)
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        (**self).select_functional_unit(device, unit_type)
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        (**self).functional_unit(device)
+    }
+
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit> {
+        (**self).configure(device, settings)
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).request_overview_scan(device)
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        (**self).overview_image(device)
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).request_scan(device)
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        (**self).cancel_scan(device)
+    }
+}
+
+/// Extensions of sidecar files, which belong to the image with the same name.
+const SIDECAR_EXTENSIONS: [&str; 3] = ["xmp", "thm", "aae"];
+
+/// The Uniform Type Identifier of a file, from the extension of its name.
+pub(crate) fn uti_for_name(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "public.jpeg",
+        "png" => "public.png",
+        "tif" | "tiff" => "public.tiff",
+        "heic" => "public.heic",
+        "dng" => "com.adobe.raw-image",
+        "crw" => "com.canon.crw-raw-image",
+        "cr2" => "com.canon.cr2-raw-image",
+        "cr3" => "com.canon.cr3-raw-image",
+        "nef" => "com.nikon.raw-image",
+        "nrw" => "com.nikon.nrw-raw-image",
+        "arw" => "com.sony.arw-raw-image",
+        "raf" => "com.fuji.raw-image",
+        "orf" => "com.olympus.raw-image",
+        "rw2" => "com.panasonic.rw2-raw-image",
+        "pef" => "com.pentax.raw-image",
+        "srw" => "com.samsung.raw-image",
+        "mov" => "com.apple.quicktime-movie",
+        "mp4" => "public.mpeg-4",
+        "xmp" => "com.adobe.xmp",
+        _ => "public.data",
+    }
+}
+
+/// Indicates whether a Uniform Type Identifier is that of a raw image.
+pub(crate) fn is_raw_uti(uti: &str) -> bool {
+    uti.ends_with("raw-image")
+}
+
+/// The name of the last component of a path.
+pub(crate) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Indicates whether a path is a sidecar file, which belongs to the image with the same name.
+pub(crate) fn is_sidecar(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().map_or(false, |extension| {
+            let extension = extension.to_string_lossy().to_ascii_lowercase();
+            SIDECAR_EXTENSIONS.contains(&extension.as_str())
+        })
+}
+
+/// Indicates whether two paths have the same name without extension, ignoring case.
+pub(crate) fn same_stem(a: &Path, b: &Path) -> bool {
+    match (a.file_stem(), b.file_stem()) {
+        (Some(a), Some(b)) => a
+            .to_string_lossy()
+            .eq_ignore_ascii_case(&b.to_string_lossy()),
+        _ => false,
+    }
+}
+
+/// `path`, or unless existing files may be replaced, the first of `name 1.ext`, `name 2.ext` and
+/// so on that does not exist.
+pub(crate) fn destination(path: &Path, overwrite: bool) -> PathBuf {
+    if overwrite || !path.exists() {
+        return path.to_owned();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|number| path.with_file_name(format!("{} {}{}", stem, number, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("some name is free")
+}
+
+/// A new empty directory for the files of a test, named after the test and the process so that
+/// concurrent runs do not share it. Whatever an earlier run left behind is removed.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "image-capture-core-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICEXIFOrientationType::ICEXIFOrientation1;
+    use crate::constants::ICScannerBitDepth::*;
+    use crate::constants::ICScannerDocumentType::*;
+    use crate::constants::ICScannerFunctionalUnitType::ICScannerFunctionalUnitTypeFlatbed;
+    use crate::constants::ICScannerMeasurementUnit::*;
+    use crate::constants::ICScannerPixelDataType::ICScannerPixelDataTypeRGB;
+    use crate::constants::ICScannerTransferMode::ICScannerTransferModeMemoryBased;
+    use std::fs;
+
+    /// A letter sized flatbed at 75 and 300 dpi.
+    fn flatbed() -> FunctionalUnit {
+        FunctionalUnit {
+            unit_type: ICScannerFunctionalUnitTypeFlatbed,
+            measurement_unit: ICScannerMeasurementUnitInches,
+            physical_size: (8.5, 11.0),
+            supported_resolutions: vec![75, 300],
+            resolution: 75,
+            supported_bit_depths: vec![ICScannerBitDepth8Bits],
+            bit_depth: ICScannerBitDepth8Bits,
+            pixel_data_type: ICScannerPixelDataTypeRGB,
+            scan_area: ScanRect::new(0.0, 0.0, 8.5, 11.0, ICScannerMeasurementUnitInches),
+            scan_area_orientation: ICEXIFOrientation1,
+            supported_document_types: vec![ICScannerDocumentTypeDefault],
+            document_type: ICScannerDocumentTypeDefault,
+            can_perform_overview_scan: true,
+            overview_resolution: 75,
+            accepts_threshold: false,
+            threshold: None,
+            duplex: None,
+            transfer_mode: ICScannerTransferModeMemoryBased,
+            downloads_directory: None,
+            document_name: None,
+            document_uti: None,
+            max_memory_band_size: 65536,
+            vendor_features: vec![ScannerFeature::range("gamma", 1.0, 3.0, 0.2, 1.8)],
+        }
+    }
+
+    #[test]
+    fn range_features_snap_to_steps() {
+        let mut feature = ScannerFeature::range("gamma", 1.0, 3.0, 0.5, 2.0);
+        feature.set_value(&FeatureValue::Range(2.2)).unwrap();
+        assert_eq!(feature.value(), FeatureValue::Range(2.0));
+        feature.set_value(&FeatureValue::Range(2.9)).unwrap();
+        assert_eq!(feature.value(), FeatureValue::Range(3.0));
+    }
+
+    #[test]
+    fn features_reject_foreign_values() {
+        let mut feature = ScannerFeature::enumeration("mode", &["fast", "fine"], "fast");
+        let invalid = Err(Error::ReturnCode(ICReturnInvalidParam));
+        assert_eq!(
+            feature.set_value(&FeatureValue::Enumeration("slow".to_owned())),
+            invalid
+        );
+        assert_eq!(feature.set_value(&FeatureValue::Boolean(true)), invalid);
+        assert_eq!(
+            feature.value(),
+            FeatureValue::Enumeration("fast".to_owned())
+        );
+    }
+
+    #[test]
+    fn validate_accepts_supported_settings() {
+        let settings = ScanSettings::new()
+            .with_resolution(300)
+            .with_scan_area(ScanRect::new(
+                0.0,
+                0.0,
+                2550.0,
+                3300.0,
+                ICScannerMeasurementUnitPixels,
+            ))
+            .with_feature("gamma", FeatureValue::Range(2.2));
+        assert_eq!(flatbed().validate(&settings), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_unsupported_settings() {
+        let unit = flatbed();
+        let invalid = Err(Error::ReturnCode(ICReturnInvalidParam));
+        for settings in &[
+            ScanSettings::new().with_resolution(150),
+            ScanSettings::new().with_bit_depth(ICScannerBitDepth16Bits),
+            ScanSettings::new().with_document_type(ICScannerDocumentTypeA4),
+            ScanSettings::new().with_duplex(true),
+            ScanSettings::new().with_threshold(128),
+            ScanSettings::new().with_max_memory_band_size(0),
+            ScanSettings::new().with_feature("gamma", FeatureValue::Range(4.0)),
+            ScanSettings::new().with_scan_area(ScanRect::new(
+                1.0,
+                1.0,
+                8.0,
+                1.0,
+                ICScannerMeasurementUnitInches,
+            )),
+            // Pixels at the default 75 dpi, which make an area wider than the bed.
+            ScanSettings::new().with_scan_area(ScanRect::new(
+                0.0,
+                0.0,
+                2550.0,
+                3300.0,
+                ICScannerMeasurementUnitPixels,
+            )),
+        ] {
+            assert_eq!(unit.validate(settings), invalid, "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn utis_follow_extensions() {
+        assert_eq!(uti_for_name("IMG_0001.JPG"), "public.jpeg");
+        assert_eq!(uti_for_name("notes"), "public.data");
+        assert!(is_raw_uti(uti_for_name("DSC_0001.NEF")));
+        assert!(!is_raw_uti(uti_for_name("DSC_0001.xmp")));
+    }
+
+    #[test]
+    fn stems_ignore_case_and_extension() {
+        assert!(same_stem(
+            Path::new("a/IMG_0001.CR2"),
+            Path::new("b/img_0001.xmp")
+        ));
+        assert!(!same_stem(
+            Path::new("IMG_0001.CR2"),
+            Path::new("IMG_0002.CR2")
+        ));
+    }
+
+    #[test]
+    fn destinations_are_numbered_unless_overwriting() {
+        let directory = temp_dir("backend");
+        let path = directory.join("scan.tiff");
+        assert_eq!(destination(&path, false), path);
+        fs::write(&path, b"").unwrap();
+        fs::write(directory.join("scan 1.tiff"), b"").unwrap();
+        assert_eq!(destination(&path, true), path);
+        assert_eq!(destination(&path, false), directory.join("scan 2.tiff"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
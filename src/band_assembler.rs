@@ -0,0 +1,545 @@
+use crate::constants::ICReturnCode::{ICReturnInvalidParam, ICReturnScannerFailedToCompleteScan};
+use crate::constants::ICScannerColorDataFormatType::{
+    self, ICScannerColorDataFormatTypeChunky, ICScannerColorDataFormatTypePlanar,
+};
+use crate::constants::ICScannerPixelDataType;
+use crate::error::{Error, Result};
+use crate::image::{min_bytes_per_row, valid_bits_per_pixel, ImageBuffer};
+use std::ops::Range;
+
+#[cfg(target_os = "macos")]
+use crate::scanner_band_data::ICScannerBandData;
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use cocoa::foundation::NSUInteger;
+#[cfg(target_os = "macos")]
+use objc::*;
+
+/// A band of image data delivered by a scanner in `ICScannerTransferModeMemoryBased` mode.
+/// This mirrors the properties of `ICScannerBandData`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScannerBand {
+    /// Width of the full image in pixels.
+    pub full_image_width: usize,
+    /// Height of the full image in pixels.
+    pub full_image_height: usize,
+    /// Number of bits per pixel.
+    pub bits_per_pixel: usize,
+    /// Number of bits per component.
+    pub bits_per_component: usize,
+    /// Number of components per pixel.
+    pub num_components: usize,
+    /// Indicates whether multi-byte components are big endian.
+    pub is_big_endian: bool,
+    /// Type of pixel data contained in the band.
+    pub pixel_data_type: ICScannerPixelDataType,
+    /// Layout of the channels in the band data.
+    ///
+    /// In planar data each row of the band holds a single channel of an image row. All rows of
+    /// the first channel come first, followed by all rows of the next channel, so
+    /// `data_start_row` and `data_num_rows` count channel rows, from 0 to
+    /// `full_image_height * num_components`.
+    pub color_data_format: ICScannerColorDataFormatType,
+    /// Path to the color profile matching the band data.
+    pub color_sync_profile_path: Option<String>,
+    /// Number of bytes in each row of the band, including padding.
+    pub bytes_per_row: usize,
+    /// Index of the first row of the band in the full image.
+    pub data_start_row: usize,
+    /// Number of rows in the band.
+    pub data_num_rows: usize,
+    /// The band data.
+    pub data: Vec<u8>,
+}
+
+impl ScannerBand {
+    /// Copy the properties and data of an `ICScannerBandData` object.
+    /// `ICScannerBandData` does not describe its channel layout, so the band is taken to be chunky.
+    /// Fails if the pixel data type is not known to this crate.
+    ///
+    /// # Safety
+    ///
+    /// `band` must be a valid `ICScannerBandData` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn from_band_data(band: id) -> Result<ScannerBand> {
+        let buffer = band.dataBuffer();
+        let data = if buffer == nil {
+            Vec::new()
+        } else {
+            let bytes: *const u8 = msg_send![buffer, bytes];
+            let length: NSUInteger = msg_send![buffer, length];
+            let length = (length as usize).min(band.dataSize() as usize);
+            if bytes.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(bytes, length).to_vec()
+            }
+        };
+        let path = band.colorSyncProfilePath();
+        let color_sync_profile_path = if path == nil {
+            None
+        } else {
+            let path: *const libc::c_char = msg_send![path, UTF8String];
+            if path.is_null() {
+                None
+            } else {
+                Some(
+                    std::ffi::CStr::from_ptr(path)
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            }
+        };
+        Ok(ScannerBand {
+            full_image_width: band.fullImageWidth() as usize,
+            full_image_height: band.fullImageHeight() as usize,
+            bits_per_pixel: band.bitsPerPixel() as usize,
+            bits_per_component: band.bitsPerComponent() as usize,
+            num_components: band.numComponents() as usize,
+            is_big_endian: band.isBigEndian() != 0,
+            pixel_data_type: band.pixelDataType()?,
+            color_data_format: ICScannerColorDataFormatTypeChunky,
+            color_sync_profile_path,
+            bytes_per_row: band.bytesPerRow() as usize,
+            data_start_row: band.dataStartRow() as usize,
+            data_num_rows: band.dataNumRows() as usize,
+            data,
+        })
+    }
+
+    /// Indicates whether the band holds planar data.
+    pub fn is_planar(&self) -> bool {
+        self.color_data_format == ICScannerColorDataFormatTypePlanar
+    }
+
+    /// Number of rows in the full image, counting channel rows for planar data.
+    pub fn total_rows(&self) -> usize {
+        if self.is_planar() {
+            self.full_image_height * self.num_components
+        } else {
+            self.full_image_height
+        }
+    }
+
+    /// Number of bytes in a row of the band, without padding.
+    pub fn row_bytes(&self) -> usize {
+        if self.is_planar() {
+            min_bytes_per_row(self.full_image_width, self.bits_per_component)
+        } else {
+            min_bytes_per_row(self.full_image_width, self.bits_per_pixel)
+        }
+    }
+
+    /// Number of complete rows contained in the band data.
+    /// This is less than `data_num_rows` if the band data is truncated.
+    pub fn available_rows(&self) -> usize {
+        let row_bytes = self.row_bytes();
+        if self.bytes_per_row == 0 || self.data.len() < row_bytes {
+            return 0;
+        }
+        // The last row of a band does not need to carry its padding.
+        let rows = (self.data.len() - row_bytes) / self.bytes_per_row + 1;
+        rows.min(self.data_num_rows)
+    }
+
+    /// The bytes of row `row` of the band, without padding.
+    pub fn row(&self, row: usize) -> &[u8] {
+        let start = row * self.bytes_per_row;
+        &self.data[start..start + self.row_bytes()]
+    }
+}
+
+/// Progress of the assembly of a banded image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AssemblyProgress {
+    /// Number of distinct rows received so far.
+    pub rows_received: usize,
+    /// Number of rows in the full image, counting channel rows for planar data.
+    pub total_rows: usize,
+}
+
+impl AssemblyProgress {
+    /// Percentage of rows received, from 0 to 100.
+    pub fn percent_done(&self) -> f64 {
+        if self.total_rows == 0 {
+            100.0
+        } else {
+            self.rows_received as f64 * 100.0 / self.total_rows as f64
+        }
+    }
+
+    /// Indicates whether every row has been received.
+    pub fn is_complete(&self) -> bool {
+        self.rows_received == self.total_rows
+    }
+}
+
+/// Reconstructs a full image from the bands of a memory based scan.
+///
+/// Bands may arrive in any order and may overlap; rows received more than once take the data of
+/// the most recent band. Row padding is removed, so the assembled image has no padding.
+///
+/// Planar bands are assembled channel by channel and interleaved when the image is finished,
+/// so a planar scan produces the same image as the equivalent chunky scan.
+#[derive(Clone, Debug)]
+pub struct BandAssembler {
+    image: ImageBuffer,
+    planar: Option<PlanarLayout>,
+    received: Vec<bool>,
+    rows_received: usize,
+}
+
+impl BandAssembler {
+    /// Create an assembler for an image of the given geometry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_pixel` is not 1, 2, 4 or a non-zero multiple of 8.
+    pub fn new(width: usize, height: usize, bits_per_pixel: usize) -> BandAssembler {
+        BandAssembler {
+            image: ImageBuffer::new(width, height, bits_per_pixel),
+            planar: None,
+            received: vec![false; height],
+            rows_received: 0,
+        }
+    }
+
+    /// Create an assembler for an image delivered in planar bands.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout cannot be interleaved into pixels of `bits_per_pixel` bits, as
+    /// checked by `interleave_planes`.
+    pub fn new_planar(
+        width: usize,
+        height: usize,
+        bits_per_pixel: usize,
+        bits_per_component: usize,
+        num_components: usize,
+    ) -> BandAssembler {
+        assert!(
+            valid_planar_layout(bits_per_pixel, bits_per_component, num_components),
+            "unsupported planar layout: {} components of {} bits in {} bits per pixel",
+            num_components,
+            bits_per_component,
+            bits_per_pixel
+        );
+        let rows = height * num_components;
+        BandAssembler {
+            image: ImageBuffer::new(width, rows, bits_per_component),
+            planar: Some(PlanarLayout {
+                bits_per_pixel,
+                num_components,
+            }),
+            received: vec![false; rows],
+            rows_received: 0,
+        }
+    }
+
+    /// Create an assembler for the image a band belongs to.
+    pub fn for_band(band: &ScannerBand) -> Result<BandAssembler> {
+        match band.color_data_format {
+            ICScannerColorDataFormatTypeChunky if valid_bits_per_pixel(band.bits_per_pixel) => {
+                Ok(BandAssembler::new(
+                    band.full_image_width,
+                    band.full_image_height,
+                    band.bits_per_pixel,
+                ))
+            }
+            ICScannerColorDataFormatTypePlanar
+                if valid_planar_layout(
+                    band.bits_per_pixel,
+                    band.bits_per_component,
+                    band.num_components,
+                ) =>
+            {
+                Ok(BandAssembler::new_planar(
+                    band.full_image_width,
+                    band.full_image_height,
+                    band.bits_per_pixel,
+                    band.bits_per_component,
+                    band.num_components,
+                ))
+            }
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    /// Width of the image in pixels.
+    pub fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    /// Height of the image in pixels.
+    pub fn height(&self) -> usize {
+        match self.planar {
+            Some(layout) => self.image.height() / layout.num_components,
+            None => self.image.height(),
+        }
+    }
+
+    /// Number of bits per pixel of the assembled image.
+    pub fn bits_per_pixel(&self) -> usize {
+        match self.planar {
+            Some(layout) => layout.bits_per_pixel,
+            None => self.image.bits_per_pixel(),
+        }
+    }
+
+    /// Indicates whether the assembler expects planar bands.
+    pub fn is_planar(&self) -> bool {
+        self.planar.is_some()
+    }
+
+    fn accepts(&self, band: &ScannerBand) -> bool {
+        let layout_matches = match self.planar {
+            Some(layout) => {
+                band.is_planar()
+                    && band.bits_per_component == self.image.bits_per_pixel()
+                    && band.num_components == layout.num_components
+            }
+            None => !band.is_planar(),
+        };
+        layout_matches
+            && band.full_image_width == self.width()
+            && band.full_image_height == self.height()
+            && band.bits_per_pixel == self.bits_per_pixel()
+            && band.bytes_per_row >= band.row_bytes()
+    }
+
+    /// Copy the rows of a band into the image.
+    ///
+    /// Fails if the band describes an image of a different geometry or channel layout, or if its
+    /// rows are shorter than a row of pixels. Rows past the end of the image and rows missing from
+    /// truncated band data are ignored.
+    pub fn add_band(&mut self, band: &ScannerBand) -> Result<AssemblyProgress> {
+        if !self.accepts(band) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let end = (band.data_start_row + band.available_rows()).min(self.image.height());
+        for y in band.data_start_row..end {
+            self.image
+                .row_mut(y)
+                .copy_from_slice(band.row(y - band.data_start_row));
+            if !self.received[y] {
+                self.received[y] = true;
+                self.rows_received += 1;
+            }
+        }
+        Ok(self.progress())
+    }
+
+    /// The progress of the assembly.
+    pub fn progress(&self) -> AssemblyProgress {
+        AssemblyProgress {
+            rows_received: self.rows_received,
+            total_rows: self.image.height(),
+        }
+    }
+
+    /// Indicates whether every row has been received.
+    pub fn is_complete(&self) -> bool {
+        self.progress().is_complete()
+    }
+
+    /// Ranges of rows that have not been received yet, counting channel rows for planar data.
+    pub fn missing_rows(&self) -> Vec<Range<usize>> {
+        let mut missing = Vec::new();
+        let mut start = None;
+        for (y, &received) in self.received.iter().enumerate() {
+            match (received, start) {
+                (false, None) => start = Some(y),
+                (true, Some(first)) => {
+                    missing.push(first..y);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start {
+            missing.push(first..self.received.len());
+        }
+        missing
+    }
+
+    /// The data assembled so far, in the channel layout of the bands.
+    /// Rows that have not been received are zero.
+    pub fn image(&self) -> &ImageBuffer {
+        &self.image
+    }
+
+    /// The assembled image, with planar data interleaved into chunky pixels.
+    /// Fails with `ICReturnScannerFailedToCompleteScan` if rows are missing.
+    pub fn finish(self) -> Result<ImageBuffer> {
+        if !self.is_complete() {
+            return Err(Error::ReturnCode(ICReturnScannerFailedToCompleteScan));
+        }
+        self.into_chunky()
+    }
+
+    fn into_chunky(self) -> Result<ImageBuffer> {
+        match self.planar {
+            Some(layout) => {
+                interleave_planes(&self.image, layout.num_components, layout.bits_per_pixel)
+            }
+            None => Ok(self.image),
+        }
+    }
+
+    /// The assembled image, with every byte of missing rows set to `fill`.
+    /// For planar data only the missing channel rows are filled.
+    pub fn finish_with_fill(mut self, fill: u8) -> ImageBuffer {
+        for range in self.missing_rows() {
+            for y in range {
+                for byte in self.image.row_mut(y) {
+                    *byte = fill;
+                }
+            }
+        }
+        self.into_chunky()
+            .expect("planar layout is validated on creation")
+    }
+}
+
+/// Channel layout of a planar assembler. The bits per component are those of its buffer.
+#[derive(Clone, Copy, Debug)]
+struct PlanarLayout {
+    bits_per_pixel: usize,
+    num_components: usize,
+}
+
+fn valid_planar_layout(
+    bits_per_pixel: usize,
+    bits_per_component: usize,
+    num_components: usize,
+) -> bool {
+    num_components > 0
+        && valid_bits_per_pixel(bits_per_pixel)
+        && valid_bits_per_pixel(bits_per_component)
+        && bits_per_component * num_components <= bits_per_pixel
+}
+
+/// Interleave planar image data into chunky pixels.
+///
+/// `planes` holds the rows of each channel in turn: rows `0..height` of the first channel,
+/// followed by the rows of the next channel, where `height` is the height of `planes` divided
+/// by `num_components`. Each pixel of `planes` is one component. Components are placed in the
+/// most significant bits of each pixel, in channel order, and any remaining bits are zero.
+///
+/// Fails if the height of `planes` is not a multiple of `num_components`, if `bits_per_pixel` is
+/// unsupported, or if the components do not fit in `bits_per_pixel`.
+pub fn interleave_planes(
+    planes: &ImageBuffer,
+    num_components: usize,
+    bits_per_pixel: usize,
+) -> Result<ImageBuffer> {
+    let bits_per_component = planes.bits_per_pixel();
+    if !valid_planar_layout(bits_per_pixel, bits_per_component, num_components)
+        || planes.height() % num_components != 0
+    {
+        return Err(Error::ReturnCode(ICReturnInvalidParam));
+    }
+    let (width, height) = (planes.width(), planes.height() / num_components);
+    let mut image = ImageBuffer::new(width, height, bits_per_pixel);
+    for y in 0..height {
+        for component in 0..num_components {
+            let plane = planes.row(component * height + y);
+            let row = image.row_mut(y);
+            if bits_per_component >= 8 {
+                let (size, stride) = (bits_per_component / 8, bits_per_pixel / 8);
+                for x in 0..width {
+                    let to = x * stride + component * size;
+                    row[to..to + size].copy_from_slice(&plane[x * size..(x + 1) * size]);
+                }
+            } else {
+                // Components narrower than a byte never straddle a byte boundary, because
+                // the bits per pixel are a multiple of the bits per component.
+                let mask = (1u8 << bits_per_component) - 1;
+                for x in 0..width {
+                    let from = x * bits_per_component;
+                    let value = (plane[from / 8] >> (8 - bits_per_component - from % 8)) & mask;
+                    let to = x * bits_per_pixel + component * bits_per_component;
+                    row[to / 8] |= value << (8 - bits_per_component - to % 8);
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICScannerPixelDataType::ICScannerPixelDataTypeRGB;
+
+    /// A planar RGB band of a 2 by 2 image, holding channel rows `start..start + rows` of
+    /// `planes`, padded to 4 bytes per row.
+    fn planar_band(planes: &[[u8; 2]], start: usize, rows: usize) -> ScannerBand {
+        let mut data = Vec::new();
+        for row in &planes[start..start + rows] {
+            data.extend_from_slice(row);
+            data.extend_from_slice(&[0xee, 0xee]);
+        }
+        ScannerBand {
+            full_image_width: 2,
+            full_image_height: 2,
+            bits_per_pixel: 24,
+            bits_per_component: 8,
+            num_components: 3,
+            is_big_endian: false,
+            pixel_data_type: ICScannerPixelDataTypeRGB,
+            color_data_format: ICScannerColorDataFormatTypePlanar,
+            color_sync_profile_path: None,
+            bytes_per_row: 4,
+            data_start_row: start,
+            data_num_rows: rows,
+            data,
+        }
+    }
+
+    #[test]
+    fn planar_bands_are_interleaved() {
+        // Red rows, then green rows, then blue rows.
+        let planes = [[1, 2], [3, 4], [10, 20], [30, 40], [100, 200], [150, 250]];
+        let mut assembler = BandAssembler::for_band(&planar_band(&planes, 0, 4)).unwrap();
+        assert!(assembler.is_planar());
+        assert_eq!(assembler.height(), 2);
+        let progress = assembler.add_band(&planar_band(&planes, 0, 4)).unwrap();
+        assert_eq!(
+            progress,
+            AssemblyProgress {
+                rows_received: 4,
+                total_rows: 6,
+            }
+        );
+        assert_eq!(assembler.missing_rows(), vec![Range { start: 4, end: 6 }]);
+        assembler.add_band(&planar_band(&planes, 4, 2)).unwrap();
+        let image = assembler.finish().unwrap();
+        assert_eq!(image.bits_per_pixel(), 24);
+        assert_eq!(image.row(0), [1, 10, 100, 2, 20, 200]);
+        assert_eq!(image.row(1), [3, 30, 150, 4, 40, 250]);
+    }
+
+    #[test]
+    fn narrow_components_are_packed_from_the_top_bits() {
+        // Two 2 bit components per 4 bit pixel.
+        let planes =
+            ImageBuffer::from_data(4, 2, 2, 1, vec![0b00_01_10_11, 0b11_10_01_00]).unwrap();
+        let image = interleave_planes(&planes, 2, 4).unwrap();
+        assert_eq!(image.row(0), [0b0011_0110, 0b1001_1100]);
+        assert_eq!(
+            interleave_planes(&planes, 3, 4),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn missing_planar_rows_are_filled() {
+        let planes = [[1, 2], [3, 4], [10, 20], [30, 40], [100, 200], [150, 250]];
+        let mut assembler = BandAssembler::for_band(&planar_band(&planes, 0, 2)).unwrap();
+        assembler.add_band(&planar_band(&planes, 0, 2)).unwrap();
+        let image = assembler.finish_with_fill(0xff);
+        assert_eq!(image.row(1), [3, 0xff, 0xff, 4, 0xff, 0xff]);
+    }
+}
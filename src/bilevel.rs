@@ -0,0 +1,411 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+use crate::feeder_job::{FeederPage, PageAction, PageProcessor, PageReport};
+use crate::image::{Image, PixelFormat};
+
+#[cfg(target_os = "macos")]
+use crate::scanner_functional_units::ICScannerFunctionalUnit;
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, NO};
+
+/// How a gray image is turned into black and white.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binarization {
+    /// A fixed threshold from 0 to 255, with the meaning of `thresholdForBlackAndWhiteScanning`:
+    /// samples at or above the threshold become white and darker samples become black. 16 bit
+    /// samples are compared with the threshold scaled to their range.
+    Threshold(u8),
+    /// A threshold for the whole image chosen with Otsu's method, which separates the histogram
+    /// into the two classes with the largest variance between them.
+    Otsu,
+    /// A threshold for each pixel from the mean and standard deviation of the `window` by `window`
+    /// pixels around it, with Sauvola's method. `k`, typically from 0.2 to 0.5, sets how far below
+    /// the local mean the threshold drops where the contrast is low. This copes with uneven
+    /// lighting and stained paper.
+    Sauvola {
+        /// Width and height of the window in pixels.
+        window: usize,
+        /// Sensitivity to the local contrast.
+        k: f64,
+    },
+    /// Floyd–Steinberg error diffusion around the middle gray, which renders shades of gray as
+    /// patterns of black and white pixels. This suits photographs rather than text.
+    FloydSteinberg,
+}
+
+/// Converts gray scans to black and white, for scanners that ignore the threshold for black and
+/// white scanning, or to scan in gray and binarise the same way on every scanner.
+///
+/// The result is a `PixelFormat::Bilevel` image, packed eight pixels to a byte with 1 for white.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BilevelConverter {
+    binarization: Binarization,
+}
+
+impl Default for BilevelConverter {
+    fn default() -> BilevelConverter {
+        BilevelConverter::new()
+    }
+}
+
+impl BilevelConverter {
+    /// Create a converter with a fixed threshold of 128.
+    pub fn new() -> BilevelConverter {
+        BilevelConverter {
+            binarization: Binarization::Threshold(128),
+        }
+    }
+
+    /// Create a converter with the threshold for black and white scanning of a functional unit,
+    /// or its default threshold if the unit does not use a threshold.
+    ///
+    /// # Safety
+    ///
+    /// `functional_unit` must be a valid `ICScannerFunctionalUnit` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn for_functional_unit(functional_unit: id) -> BilevelConverter {
+        let threshold = if functional_unit.usesThresholdForBlackAndWhiteScanning() != NO {
+            functional_unit.thresholdForBlackAndWhiteScanning()
+        } else {
+            functional_unit.defaultThresholdForBlackAndWhiteScanning()
+        };
+        BilevelConverter::new().with_binarization(Binarization::Threshold(threshold))
+    }
+
+    /// Set how images are binarised.
+    pub fn with_binarization(mut self, binarization: Binarization) -> BilevelConverter {
+        self.binarization = binarization;
+        self
+    }
+
+    /// The binarisation method.
+    pub fn binarization(&self) -> Binarization {
+        self.binarization
+    }
+
+    /// The threshold from 0 to 255 the whole of an image is binarised with, for a fixed threshold
+    /// or Otsu's method, or `None` for methods that do not use a single threshold.
+    /// Fails if the image is not `Gray8` or `Gray16`.
+    pub fn threshold(&self, image: &Image) -> Result<Option<f64>> {
+        let bits = gray_bits(image)?;
+        Ok(self
+            .global_threshold(image)
+            .map(|threshold| threshold as f64 * 255.0 / ((1u32 << bits) - 1) as f64))
+    }
+
+    /// Convert a gray image to black and white.
+    /// Fails if the image is not `Gray8` or `Gray16`.
+    pub fn convert(&self, image: &Image) -> Result<Image> {
+        gray_bits(image)?;
+        let (width, height) = (image.width(), image.height());
+        let mut bilevel = Image::new(PixelFormat::Bilevel, width, height);
+        let mut set_white = |x: usize, y: usize| bilevel.row_mut(y)[x / 8] |= 0x80 >> (x % 8);
+
+        match self.binarization {
+            Binarization::Threshold(_) | Binarization::Otsu => {
+                let threshold = self.global_threshold(image).unwrap_or(0);
+                for y in 0..height {
+                    for x in 0..width {
+                        if sample(image, x, y) >= threshold {
+                            set_white(x, y);
+                        }
+                    }
+                }
+            }
+            Binarization::Sauvola { window, k } => {
+                let max = f64::from(max_sample(image));
+                let (sums, squares) = integral_images(image);
+                let radius = window.max(1) / 2;
+                let stride = width + 1;
+                for y in 0..height {
+                    let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+                    for x in 0..width {
+                        let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+                        let area = |table: &[f64]| {
+                            table[y1 * stride + x1]
+                                - table[y0 * stride + x1]
+                                - table[y1 * stride + x0]
+                                + table[y0 * stride + x0]
+                        };
+                        let count = ((x1 - x0) * (y1 - y0)) as f64;
+                        let mean = area(&sums) / count;
+                        let variance = (area(&squares) / count - mean * mean).max(0.0);
+                        // Sauvola's dynamic range of the standard deviation is half the range.
+                        let threshold = mean * (1.0 + k * (variance.sqrt() / (max / 2.0) - 1.0));
+                        if f64::from(sample(image, x, y)) >= threshold {
+                            set_white(x, y);
+                        }
+                    }
+                }
+            }
+            Binarization::FloydSteinberg => {
+                let max = f64::from(max_sample(image));
+                // The errors carried to the current and the next row, with a pixel of padding on
+                // each side.
+                let mut current = vec![0.0f64; width + 2];
+                let mut next = vec![0.0f64; width + 2];
+                for y in 0..height {
+                    for x in 0..width {
+                        let value = f64::from(sample(image, x, y)) / max + current[x + 1];
+                        let white = value >= 0.5;
+                        if white {
+                            set_white(x, y);
+                        }
+                        let error = value - if white { 1.0 } else { 0.0 };
+                        current[x + 2] += error * 7.0 / 16.0;
+                        next[x] += error * 3.0 / 16.0;
+                        next[x + 1] += error * 5.0 / 16.0;
+                        next[x + 2] += error / 16.0;
+                    }
+                    std::mem::swap(&mut current, &mut next);
+                    next.iter_mut().for_each(|error| *error = 0.0);
+                }
+            }
+        }
+        Ok(bilevel)
+    }
+
+    /// The threshold in sample values for a fixed threshold or Otsu's method.
+    fn global_threshold(&self, image: &Image) -> Option<u16> {
+        match self.binarization {
+            Binarization::Threshold(threshold) => Some(if image.format() == PixelFormat::Gray16 {
+                u16::from(threshold) * 257
+            } else {
+                u16::from(threshold)
+            }),
+            Binarization::Otsu => Some(otsu(image)),
+            _ => None,
+        }
+    }
+}
+
+impl PageProcessor for BilevelConverter {
+    /// Convert the page to black and white and report the threshold from 0 to 255, if there is a
+    /// single one. Pages that are already black and white are left alone.
+    fn process(&mut self, page: &mut FeederPage) -> Result<PageAction> {
+        if page.image().format() == PixelFormat::Bilevel {
+            return Ok(PageAction::Keep);
+        }
+        let threshold = self.threshold(page.image())?;
+        *page.image_mut() = self.convert(page.image())?;
+        page.add_report(PageReport {
+            processor: "bilevel",
+            detected: true,
+            confidence: 1.0,
+            value: threshold,
+            flagged: false,
+        });
+        Ok(PageAction::Keep)
+    }
+}
+
+/// The number of bits of the samples of a gray image. Fails for other formats.
+fn gray_bits(image: &Image) -> Result<usize> {
+    match image.format() {
+        PixelFormat::Gray8 => Ok(8),
+        PixelFormat::Gray16 => Ok(16),
+        _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+    }
+}
+
+/// The largest sample value of a gray image.
+fn max_sample(image: &Image) -> u16 {
+    if image.format() == PixelFormat::Gray16 {
+        u16::MAX
+    } else {
+        u16::from(u8::MAX)
+    }
+}
+
+/// The sample of a gray image at `x`, `y`.
+fn sample(image: &Image, x: usize, y: usize) -> u16 {
+    let row = image.row(y);
+    if image.format() == PixelFormat::Gray16 {
+        u16::from_be_bytes([row[2 * x], row[2 * x + 1]])
+    } else {
+        u16::from(row[x])
+    }
+}
+
+/// The threshold chosen with Otsu's method. Samples at or above it belong to the brighter class.
+fn otsu(image: &Image) -> u16 {
+    let mut histogram = vec![0u64; usize::from(max_sample(image)) + 1];
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            histogram[usize::from(sample(image, x, y))] += 1;
+        }
+    }
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+
+    // Move samples into the dark class one value at a time and keep the split with the largest
+    // variance between the classes.
+    let (mut dark, mut dark_sum) = (0u64, 0.0);
+    let (mut best, mut best_variance) = (0usize, -1.0);
+    for (value, &count) in histogram.iter().enumerate() {
+        dark += count;
+        dark_sum += value as f64 * count as f64;
+        let light = total - dark;
+        if dark == 0 {
+            continue;
+        }
+        if light == 0 {
+            break;
+        }
+        let dark_mean = dark_sum / dark as f64;
+        let light_mean = (sum - dark_sum) / light as f64;
+        let variance = dark as f64 * light as f64 * (dark_mean - light_mean).powi(2);
+        if variance > best_variance {
+            best = value;
+            best_variance = variance;
+        }
+    }
+    (best + 1).min(histogram.len() - 1) as u16
+}
+
+/// Summed area tables of the samples and their squares, with a row and column of zeros in front.
+fn integral_images(image: &Image) -> (Vec<f64>, Vec<f64>) {
+    let (width, height) = (image.width(), image.height());
+    let stride = width + 1;
+    let mut sums = vec![0.0; stride * (height + 1)];
+    let mut squares = vec![0.0; stride * (height + 1)];
+    for y in 0..height {
+        let (mut row_sum, mut row_squares) = (0.0, 0.0);
+        for x in 0..width {
+            let value = f64::from(sample(image, x, y));
+            row_sum += value;
+            row_squares += value * value;
+            let index = (y + 1) * stride + x + 1;
+            sums[index] = sums[index - stride] + row_sum;
+            squares[index] = squares[index - stride] + row_squares;
+        }
+    }
+    (sums, squares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feeder_job::FeederJob;
+
+    /// A gray image with the given rows of 8 bit samples.
+    fn gray(rows: &[&[u8]]) -> Image {
+        let mut image = Image::new(PixelFormat::Gray8, rows[0].len(), rows.len());
+        for (y, samples) in rows.iter().enumerate() {
+            image.row_mut(y).copy_from_slice(samples);
+        }
+        image
+    }
+
+    /// The pixels of a bilevel image, with `true` for white.
+    fn pixels(image: &Image) -> Vec<Vec<bool>> {
+        (0..image.height())
+            .map(|y| {
+                let row = image.row(y);
+                (0..image.width())
+                    .map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn otsu_separates_two_levels() {
+        let image = gray(&[&[40, 200, 40, 200, 200], &[200, 40, 200, 200, 200]]);
+        let converter = BilevelConverter::new().with_binarization(Binarization::Otsu);
+        // Every split between the levels separates them equally well, and the first is kept.
+        assert_eq!(converter.threshold(&image), Ok(Some(41.0)));
+        let bilevel = converter.convert(&image).unwrap();
+        assert_eq!(bilevel.format(), PixelFormat::Bilevel);
+        assert_eq!(
+            pixels(&bilevel),
+            [
+                [false, true, false, true, true],
+                [true, false, true, true, true],
+            ]
+        );
+
+        let mut wide = Image::new(PixelFormat::Gray16, 2, 1);
+        wide.row_mut(0)
+            .copy_from_slice(&[(40 * 257u16).to_be_bytes(), (200 * 257u16).to_be_bytes()].concat());
+        let threshold = converter.threshold(&wide).unwrap().unwrap();
+        assert!((threshold - 40.0).abs() < 0.01, "{}", threshold);
+        assert_eq!(pixels(&converter.convert(&wide).unwrap()), [[false, true]]);
+    }
+
+    #[test]
+    fn fixed_thresholds_are_inclusive() {
+        let image = gray(&[&[0, 127, 128, 255]]);
+        let converter = BilevelConverter::new();
+        assert_eq!(converter.threshold(&image), Ok(Some(128.0)));
+        assert_eq!(
+            pixels(&converter.convert(&image).unwrap()),
+            [[false, false, true, true]]
+        );
+        assert_eq!(
+            converter.convert(&Image::new(PixelFormat::Rgb8, 4, 1)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn sauvola_copes_with_uneven_lighting() {
+        // A page that darkens from left to right, with a line of text darker than the page
+        // around it.
+        let rows: Vec<Vec<u8>> = (0..9)
+            .map(|y| {
+                (0..64)
+                    .map(|x| {
+                        let paper = 240 - 2 * x as u8;
+                        if y == 4 {
+                            paper - 60
+                        } else {
+                            paper
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let rows: Vec<&[u8]> = rows.iter().map(|row| row.as_slice()).collect();
+        let image = gray(&rows);
+        let converter =
+            BilevelConverter::new().with_binarization(Binarization::Sauvola { window: 7, k: 0.2 });
+        assert_eq!(converter.threshold(&image), Ok(None));
+        let result = pixels(&converter.convert(&image).unwrap());
+        for (y, row) in result.iter().enumerate() {
+            assert!(row.iter().all(|&white| white == (y != 4)), "{}", y);
+        }
+        // A single threshold either loses the text on the left or blackens the paper on the
+        // right.
+        let fixed = pixels(&BilevelConverter::new().convert(&image).unwrap());
+        assert!(fixed[4][0] && !fixed[0][63]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_mean_gray() {
+        let rows = vec![[64u8; 16]; 16];
+        let rows: Vec<&[u8]> = rows.iter().map(|row| &row[..]).collect();
+        let converter = BilevelConverter::new().with_binarization(Binarization::FloydSteinberg);
+        let white = pixels(&converter.convert(&gray(&rows)).unwrap())
+            .iter()
+            .flatten()
+            .filter(|&&white| white)
+            .count();
+        assert!((60..=68).contains(&white), "{}", white);
+    }
+
+    #[test]
+    fn feeder_pages_are_converted() {
+        let mut job = FeederJob::new(false)
+            .with_processor(BilevelConverter::new().with_binarization(Binarization::Otsu));
+        job.add_page(gray(&[&[40, 200]])).unwrap();
+        let pages = job.finish();
+        assert_eq!(pages[0].image().format(), PixelFormat::Bilevel);
+        let report = &pages[0].reports()[0];
+        assert_eq!((report.processor, report.value), ("bilevel", Some(41.0)));
+    }
+}
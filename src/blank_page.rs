@@ -0,0 +1,223 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+use crate::feeder_job::{FeederPage, PageAction, PageProcessor, PageReport};
+use crate::image::Image;
+
+/// What a `BlankPageDetector` does with the blank pages of a feeder job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlankPageAction {
+    /// Drop blank pages from the document.
+    Drop,
+    /// Keep blank pages, flagged for review.
+    Flag,
+}
+
+/// The result of looking for ink on a page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlankPageAnalysis {
+    /// Fraction of the examined area covered with ink, from 0 to 1.
+    pub ink_coverage: f64,
+    /// Indicates whether the page is blank.
+    pub is_blank: bool,
+    /// Confidence in `is_blank`, from 0.5 for a page on the coverage threshold to 1.
+    pub confidence: f64,
+}
+
+/// Detects blank pages, such as the empty backs of a duplex scan.
+///
+/// A pixel is ink if it is darker than the noise threshold and touches at least one other ink
+/// pixel, so that paper texture, sensor noise and isolated specks of dust are ignored. A page is
+/// blank if the fraction of ink pixels inside the margins does not exceed the coverage threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlankPageDetector {
+    coverage_threshold: f64,
+    noise_threshold: f64,
+    margins: (f64, f64, f64, f64),
+    action: BlankPageAction,
+}
+
+impl Default for BlankPageDetector {
+    fn default() -> BlankPageDetector {
+        BlankPageDetector::new()
+    }
+}
+
+impl BlankPageDetector {
+    /// Create a detector that drops pages with at most 0.2% ink coverage, counting pixels darker
+    /// than 25% as ink and ignoring margins of 5% on each side.
+    pub fn new() -> BlankPageDetector {
+        BlankPageDetector {
+            coverage_threshold: 0.002,
+            noise_threshold: 0.25,
+            margins: (0.05, 0.05, 0.05, 0.05),
+            action: BlankPageAction::Drop,
+        }
+    }
+
+    /// Set the largest fraction of ink coverage, from 0 to 1, a blank page may have.
+    pub fn with_coverage_threshold(mut self, coverage: f64) -> BlankPageDetector {
+        self.coverage_threshold = coverage.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set how dark a pixel must be to count as ink, from 0 for white to 1 for black.
+    pub fn with_noise_threshold(mut self, darkness: f64) -> BlankPageDetector {
+        self.noise_threshold = darkness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the margins to ignore on the left, top, right and bottom, as fractions of the width
+    /// and height of the page. Margins often show the shadows of the sheet edges or punch holes.
+    pub fn with_margins(
+        mut self,
+        left: f64,
+        top: f64,
+        right: f64,
+        bottom: f64,
+    ) -> BlankPageDetector {
+        let fraction = |value: f64| value.clamp(0.0, 1.0);
+        self.margins = (
+            fraction(left),
+            fraction(top),
+            fraction(right),
+            fraction(bottom),
+        );
+        self
+    }
+
+    /// Set what to do with blank pages when the detector processes a feeder job.
+    pub fn with_action(mut self, action: BlankPageAction) -> BlankPageDetector {
+        self.action = action;
+        self
+    }
+
+    /// Look for ink on an image. Fails if the margins leave nothing to examine.
+    pub fn analyze(&self, image: &Image) -> Result<BlankPageAnalysis> {
+        let (width, height) = (image.width(), image.height());
+        let (left, top, right, bottom) = self.margins;
+        let x0 = (width as f64 * left).round() as usize;
+        let y0 = (height as f64 * top).round() as usize;
+        let x1 = width.saturating_sub((width as f64 * right).round() as usize);
+        let y1 = height.saturating_sub((height as f64 * bottom).round() as usize);
+        if x0 >= x1 || y0 >= y1 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        // Pixels with a lower luma are dark enough to be ink.
+        let level = ((1.0 - self.noise_threshold) * 255.0).round() as u8;
+        let dark_row =
+            |y: usize| -> Vec<bool> { (x0..x1).map(|x| image.luma(x, y) < level).collect() };
+
+        let mut ink = 0usize;
+        let empty = vec![false; x1 - x0];
+        let mut previous = empty.clone();
+        let mut current = dark_row(y0);
+        for y in y0..y1 {
+            let next = if y + 1 < y1 {
+                dark_row(y + 1)
+            } else {
+                empty.clone()
+            };
+            for x in 0..current.len() {
+                if !current[x] {
+                    continue;
+                }
+                let from = x.saturating_sub(1);
+                let to = (x + 2).min(current.len());
+                let touching = previous[from..to].contains(&true)
+                    || next[from..to].contains(&true)
+                    || (x > 0 && current[x - 1])
+                    || (x + 1 < current.len() && current[x + 1]);
+                if touching {
+                    ink += 1;
+                }
+            }
+            previous = current;
+            current = next;
+        }
+
+        let ink_coverage = ink as f64 / ((x1 - x0) * (y1 - y0)) as f64;
+        let is_blank = ink_coverage <= self.coverage_threshold;
+        let confidence = if self.coverage_threshold == 0.0 {
+            1.0
+        } else if is_blank {
+            1.0 - ink_coverage / (2.0 * self.coverage_threshold)
+        } else {
+            1.0 - self.coverage_threshold / (2.0 * ink_coverage)
+        };
+        Ok(BlankPageAnalysis {
+            ink_coverage,
+            is_blank,
+            confidence,
+        })
+    }
+}
+
+impl PageProcessor for BlankPageDetector {
+    /// Report whether the page is blank, and drop it or flag it if it is.
+    fn process(&mut self, page: &mut FeederPage) -> Result<PageAction> {
+        let analysis = self.analyze(page.image())?;
+        let flag = analysis.is_blank && self.action == BlankPageAction::Flag;
+        page.add_report(PageReport {
+            processor: "blank page",
+            detected: analysis.is_blank,
+            confidence: analysis.confidence,
+            value: Some(analysis.ink_coverage),
+            flagged: flag,
+        });
+        if analysis.is_blank && self.action == BlankPageAction::Drop {
+            Ok(PageAction::Drop)
+        } else {
+            Ok(PageAction::Keep)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::PixelFormat;
+
+    /// A white page with `dark` pixels set to black.
+    fn page(dark: &[(usize, usize)]) -> Image {
+        let mut image = Image::new(PixelFormat::Gray8, 100, 100);
+        for y in 0..100 {
+            image.row_mut(y).iter_mut().for_each(|pixel| *pixel = 250);
+        }
+        for &(x, y) in dark {
+            image.row_mut(y)[x] = 0;
+        }
+        image
+    }
+
+    #[test]
+    fn specks_and_margins_are_not_ink() {
+        // Isolated specks inside the margins, and a solid bar in the top margin.
+        let mut dark: Vec<(usize, usize)> = (0..10).map(|i| (10 + 8 * i, 50)).collect();
+        dark.extend((0..100).flat_map(|x| (0..3).map(move |y| (x, y))));
+        let analysis = BlankPageDetector::new().analyze(&page(&dark)).unwrap();
+        assert_eq!(analysis.ink_coverage, 0.0);
+        assert!(analysis.is_blank);
+        assert_eq!(analysis.confidence, 1.0);
+    }
+
+    #[test]
+    fn text_is_ink() {
+        // A line of text, 40 by 2 pixels.
+        let dark: Vec<(usize, usize)> = (30..70).flat_map(|x| vec![(x, 40), (x, 41)]).collect();
+        let analysis = BlankPageDetector::new().analyze(&page(&dark)).unwrap();
+        assert_eq!(analysis.ink_coverage, 80.0 / 8100.0);
+        assert!(!analysis.is_blank);
+        assert!(analysis.confidence > 0.5 && analysis.confidence < 1.0);
+        let lenient = BlankPageDetector::new().with_coverage_threshold(0.02);
+        assert!(lenient.analyze(&page(&dark)).unwrap().is_blank);
+    }
+
+    #[test]
+    fn margins_must_leave_an_area() {
+        let detector = BlankPageDetector::new().with_margins(0.5, 0.0, 0.5, 0.0);
+        assert_eq!(
+            detector.analyze(&page(&[])),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+}
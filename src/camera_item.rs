@@ -1,9 +1,11 @@
 use cocoa::base::{id, BOOL};
-use crate::constants::ICEXIFOrientationType;
+use cocoa::foundation::NSUInteger;
+use crate::constants::{ICEXIFOrientationType, UnknownValue};
 use core_graphics::image::CGImageRef;
 use libc::c_uint;
 use libc::{c_double, off_t};
 use objc::*;
+use std::convert::TryFrom;
 
 /// ICCameraItem is an abstract class that represents an item in an ICCameraDevice object
 pub trait ICCameraItem: Sized {
@@ -124,7 +126,7 @@ pub trait ICCameraFile: Sized {
     /// Size of file in bytes.
     unsafe fn fileSize(self) -> off_t;
     /// Desired orientation of image to use when it is downloaded.
-    unsafe fn orientation(self) -> ICEXIFOrientationType;
+    unsafe fn orientation(self) -> Result<ICEXIFOrientationType, UnknownValue>;
     /// Duration of audio/video file in seconds.
     unsafe fn duration(self) -> c_double;
     /// This property is NULL if there are no sidecar files associated with this file.
@@ -138,8 +140,9 @@ impl ICCameraFile for id {
         msg_send![self, fileSize]
     }
 
-    unsafe fn orientation(self) -> ICEXIFOrientationType {
-        msg_send![self, orientation]
+    unsafe fn orientation(self) -> Result<ICEXIFOrientationType, UnknownValue> {
+        let orientation: NSUInteger = msg_send![self, orientation];
+        ICEXIFOrientationType::try_from(orientation)
     }
 
     unsafe fn duration(self) -> c_double {
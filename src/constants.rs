@@ -0,0 +1,312 @@
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+
+/// A raw value reported by ImageCaptureCore that is not known to this crate, such as a value
+/// added by a newer version of the framework.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownValue(pub u64);
+
+impl fmt::Display for UnknownValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown value {}", self.0)
+    }
+}
+
+impl error::Error for UnknownValue {}
+
+/// Declares an enumeration of raw values reported by ImageCaptureCore.
+///
+/// The enum is represented as a `u64`, so a variant can be cast with `as u64` or converted with
+/// `From`. Raw values are converted with `TryFrom<u64>`, which returns values not listed in the
+/// declaration as an `UnknownValue` error, so a value returned by a newer version of the
+/// framework never has to be transmuted into the enum.
+macro_rules! ic_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(u64)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant = $value,)+
+        }
+
+        impl TryFrom<u64> for $name {
+            type Error = UnknownValue;
+
+            fn try_from(value: u64) -> Result<$name, UnknownValue> {
+                match value {
+                    $($value => Ok($name::$variant),)+
+                    value => Err(UnknownValue(value)),
+                }
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(value: $name) -> u64 {
+                value as u64
+            }
+        }
+    };
+}
+
+ic_enum! {
+    /// Type representing EXIF Orientation tag value
+    pub enum ICEXIFOrientationType {
+        /// Normal
+        ICEXIFOrientation1 = 1,
+        /// Flipped horizontally
+        ICEXIFOrientation2 = 2,
+        /// Rotated 180°
+        ICEXIFOrientation3 = 3,
+        /// Flipped vertically
+        ICEXIFOrientation4 = 4,
+        /// Rotated 90° CCW and flipped vertically
+        ICEXIFOrientation5 = 5,
+        /// Rotated 90° CCW
+        ICEXIFOrientation6 = 6,
+        /// Rotated 90° CW and flipped vertically
+        ICEXIFOrientation7 = 7,
+        /// Rotated 90° CW
+        ICEXIFOrientation8 = 8,
+    }
+}
+
+/// Definition of codes returned by APIs in ImageCaptureCore framework
+#[repr(i64)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ICReturnCode {
+    ICReturnSuccess = 0,
+    ICReturnInvalidParam = -9922,
+    ICReturnCommunicationTimedOut = -9923,
+    ICReturnScanOperationCanceled = -9924,
+    ICReturnScannerInUseByLocalUser = -9925,
+    ICReturnScannerInUseByRemoteUser = -9926,
+    ICReturnDeviceFailedToOpenSession = -9927,
+    ICReturnDeviceFailedToCloseSession = -9928,
+    ICReturnScannerFailedToSelectFunctionalUnit = -9929,
+    ICReturnScannerFailedToCompleteOverviewScan = -9930,
+    ICReturnScannerFailedToCompleteScan = -9931,
+    ICReturnReceivedUnsolicitedScannerStatusInfo = -9932,
+    ICReturnReceivedUnsolicitedScannerErrorInfo = -9933,
+    ICReturnDownloadFailed = -9934,
+    ICReturnUploadFailed = -9935,
+    ICReturnFailedToCompletePassThroughCommand = -9936,
+    ICReturnDownloadCanceled = -9937,
+    ICReturnFailedToEnabeTethering = -9938,
+    ICReturnFailedToDisabeTethering = -9939,
+    ICReturnFailedToCompleteSendMessageRequest = -9940,
+    ICReturnDeleteFilesFailed = -9941,
+    ICReturnDeleteFilesCanceled = -9942,
+    ICReturnDeviceIsPasscodeLocked = -9943,
+    ICReturnDeviceFailedToTakePicture = -9944,
+    ICReturnDeviceSoftwareNotInstalled = -9945,
+    ICReturnDeviceSoftwareIsBeingInstalled = -9946,
+    ICReturnDeviceSoftwareInstallationCompleted = -9947,
+    ICReturnDeviceSoftwareInstallationCanceled = -9948,
+    ICReturnDeviceSoftwareInstallationFailed = -9949,
+    ICReturnDeviceSoftwareNotAvailable = -9950,
+    ICReturnDeviceCouldNotPair = -9951,
+    ICReturnDeviceCouldNotUnpair = -9952,
+    ICReturnDeviceNeedsCredentials = -9953,
+    ICReturnDeviceIsBusyEnumerating = -9954,
+    ICReturnDeviceCommandGeneralFailure = -9955,
+}
+
+ic_enum! {
+    /// Scanner Functional Unit Types
+    pub enum ICScannerFunctionalUnitType {
+        /// Flatbed functional unit.
+        ICScannerFunctionalUnitTypeFlatbed = 0,
+        /// Transparency functional unit for scanning positives.
+        ICScannerFunctionalUnitTypePositiveTransparency = 1,
+        /// Transparency functional unit for scanning negatives.
+        ICScannerFunctionalUnitTypeNegativeTransparency = 2,
+        /// Document feeder functional unit.
+        ICScannerFunctionalUnitTypeDocumentFeeder = 3,
+    }
+}
+
+ic_enum! {
+    /// Unit of measurement used by the scanner.
+    /// This corresponds to values used for ICAP_UNITS as defined in the TWAIN Specification.
+    pub enum ICScannerMeasurementUnit {
+        ICScannerMeasurementUnitInches = 0,
+        ICScannerMeasurementUnitCentimeters = 1,
+        ICScannerMeasurementUnitPicas = 2,
+        ICScannerMeasurementUnitPoints = 3,
+        ICScannerMeasurementUnitTwips = 4,
+        ICScannerMeasurementUnitPixels = 5,
+    }
+}
+
+ic_enum! {
+    /// Bits per channel in the scanned image.
+    /// This corresponds to values used for ICAP_UNITS as defined in the TWAIN Specification.
+    pub enum ICScannerBitDepth {
+        ICScannerBitDepth1Bit = 1,
+        ICScannerBitDepth8Bits = 8,
+        ICScannerBitDepth16Bits = 16,
+    }
+}
+
+ic_enum! {
+    /// Pixel data types.
+    /// Corresponds to "ICAP_PIXELTYPE" of the TWAIN Specification.
+    pub enum ICScannerPixelDataType {
+        /// Monochrome 1 bit pixel image.
+        ICScannerPixelDataTypeBW = 0,
+        /// 8 bit pixel Gray color space.
+        ICScannerPixelDataTypeGray = 1,
+        /// Color image RGB color space.
+        ICScannerPixelDataTypeRGB = 2,
+        /// Indexed Color image.
+        ICScannerPixelDataTypePalette = 3,
+        /// Color image in CMY color space.
+        ICScannerPixelDataTypeCMY = 4,
+        /// Color image in CMYK color space.
+        ICScannerPixelDataTypeCMYK = 5,
+        /// Color image in YUV color space.
+        ICScannerPixelDataTypeYUV = 6,
+        /// Color image in YUVK color space.
+        ICScannerPixelDataTypeYUVK = 7,
+        /// Color image in CIEXYZ color space.
+        ICScannerPixelDataTypeCIEXYZ = 8,
+    }
+}
+
+ic_enum! {
+    /// Layout of the channels of multi-channel image data.
+    pub enum ICScannerColorDataFormatType {
+        /// For multi-channel data (e.g., RGB) data from all channels are interleaved.
+        ICScannerColorDataFormatTypeChunky = 0,
+        /// For multi-channel data (e.g., RGB) each channel is transferred sequentially.
+        ICScannerColorDataFormatTypePlanar = 1,
+    }
+}
+
+ic_enum! {
+    /// Document size types.
+    /// Corresponds to "ICAP_SUPPORTEDSIZES" used by the Image Catpure scanner modules.
+    pub enum ICScannerDocumentType {
+        ICScannerDocumentTypeDefault = 0,
+        ICScannerDocumentTypeA4 = 1,
+        ICScannerDocumentTypeB5 = 2,
+        ICScannerDocumentTypeUSLetter = 3,
+        ICScannerDocumentTypeUSLegal = 4,
+        ICScannerDocumentTypeA5 = 5,
+        ICScannerDocumentTypeISOB4 = 6,
+        ICScannerDocumentTypeISOB6 = 7,
+        ICScannerDocumentTypeUSLedger = 9,
+        ICScannerDocumentTypeUSExecutive = 10,
+        ICScannerDocumentTypeA3 = 11,
+        ICScannerDocumentTypeISOB3 = 12,
+        ICScannerDocumentTypeA6 = 13,
+        ICScannerDocumentTypeC4 = 14,
+        ICScannerDocumentTypeC5 = 15,
+        ICScannerDocumentTypeC6 = 16,
+        ICScannerDocumentType4A0 = 17,
+        ICScannerDocumentType2A0 = 18,
+        ICScannerDocumentTypeA0 = 19,
+        ICScannerDocumentTypeA1 = 20,
+        ICScannerDocumentTypeA2 = 21,
+        ICScannerDocumentTypeA7 = 22,
+        ICScannerDocumentTypeA8 = 23,
+        ICScannerDocumentTypeA9 = 24,
+        ICScannerDocumentType10 = 25,
+        ICScannerDocumentTypeISOB0 = 26,
+        ICScannerDocumentTypeISOB1 = 27,
+        ICScannerDocumentTypeISOB2 = 28,
+        ICScannerDocumentTypeISOB5 = 29,
+        ICScannerDocumentTypeISOB7 = 30,
+        ICScannerDocumentTypeISOB8 = 31,
+        ICScannerDocumentTypeISOB9 = 32,
+        ICScannerDocumentTypeISOB10 = 33,
+        ICScannerDocumentTypeJISB0 = 34,
+        ICScannerDocumentTypeJISB1 = 35,
+        ICScannerDocumentTypeJISB2 = 36,
+        ICScannerDocumentTypeJISB3 = 37,
+        ICScannerDocumentTypeJISB4 = 38,
+        ICScannerDocumentTypeJISB6 = 39,
+        ICScannerDocumentTypeJISB7 = 40,
+        ICScannerDocumentTypeJISB8 = 41,
+        ICScannerDocumentTypeJISB9 = 42,
+        ICScannerDocumentTypeJISB10 = 43,
+        ICScannerDocumentTypeC0 = 44,
+        ICScannerDocumentTypeC1 = 45,
+        ICScannerDocumentTypeC2 = 46,
+        ICScannerDocumentTypeC3 = 47,
+        ICScannerDocumentTypeC7 = 48,
+        ICScannerDocumentTypeC8 = 49,
+        ICScannerDocumentTypeC9 = 50,
+        ICScannerDocumentTypeC10 = 51,
+        ICScannerDocumentTypeUSStatement = 52,
+        ICScannerDocumentTypeBusinessCard = 53,
+        ICScannerDocumentTypeE = 60,
+        ICScannerDocumentType3R = 61,
+        ICScannerDocumentType4R = 62,
+        ICScannerDocumentType5R = 63,
+        ICScannerDocumentType6R = 64,
+        ICScannerDocumentType8R = 65,
+        ICScannerDocumentTypeS8R = 66,
+        ICScannerDocumentType10R = 67,
+        ICScannerDocumentTypeS10R = 68,
+        ICScannerDocumentType11R = 69,
+        ICScannerDocumentType12R = 70,
+        ICScannerDocumentTypeS12R = 71,
+        ICScannerDocumentType110 = 72,
+        ICScannerDocumentTypeAPSH = 73,
+        ICScannerDocumentTypeAPSC = 74,
+        ICScannerDocumentTypeAPSP = 75,
+        ICScannerDocumentType135 = 76,
+        ICScannerDocumentTypeMF = 77,
+        ICScannerDocumentTypeLF = 78,
+    }
+}
+
+pub use self::functional_unit_state::ICScannerFunctionalUnitState;
+
+/// The functional unit state flags, whose constants keep the names used by ImageCaptureCore.
+#[allow(non_upper_case_globals)]
+mod functional_unit_state {
+    use bitflags::bitflags;
+
+    bitflags! {
+        /// A flag to indicate the scanner functional unit's state
+        pub struct ICScannerFunctionalUnitState: u64 {
+            /// The scanner functional unit is ready for operation.
+            const ICScannerFunctionalUnitStateReady = 1 << 0;
+            /// The scanner functional unit is performing a scan.
+            const ICScannerFunctionalUnitStateScanInProgress = 1 << 1;
+            /// The scanner functional unit is performing an overview scan.
+            const ICScannerFunctionalUnitStateOverviewScanInProgress = 1 << 2;
+        }
+    }
+}
+
+ic_enum! {
+    /// Scanner Feature Types
+    pub enum ICScannerFeatureType {
+        /// This feature can have one of several discrete values, strings or numbers.
+        ICScannerFeatureTypeEnumeration = 0,
+        /// This value of this feature lies within a range.
+        ICScannerFeatureTypeRange = 1,
+        /// The value of this feature can be YES or NO.
+        ICScannerFeatureTypeBoolean = 2,
+        ICScannerFeatureTypeTemplate = 3,
+    }
+}
+
+ic_enum! {
+    /// Transfer mode to be used when transferring scan data from the scanner functional unit.
+    pub enum ICScannerTransferMode {
+        /// Save the scan as a file.
+        ICScannerTransferModeFileBased = 0,
+        /// Transfer the scan as data.
+        ICScannerTransferModeMemoryBased = 1,
+    }
+}
@@ -0,0 +1,334 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+use crate::feeder_job::{FeederPage, PageAction, PageProcessor, PageReport};
+use crate::image::{Image, PixelFormat};
+
+/// Largest number of dark pixels sampled to estimate the skew of a page.
+const MAX_SAMPLES: usize = 200_000;
+
+/// The skew of a page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkewEstimate {
+    /// Angle in degrees the content is rotated by, positive for counterclockwise.
+    pub angle: f64,
+    /// Confidence in the angle, from 0 for a page without lines of text or other straight
+    /// structure to 1.
+    pub confidence: f64,
+}
+
+/// Straightens skewed pages, such as pages that went through a document feeder at an angle.
+///
+/// The skew is estimated with projection profiles: dark pixels are projected onto the vertical
+/// axis at each candidate angle, and the angle that lines them up into the sharpest rows wins.
+/// The page is then rotated about its centre, keeping its size, with bilinear interpolation for
+/// gray and colour images and nearest neighbour sampling for bilevel images. Corners that
+/// rotate into the page are filled with white.
+///
+/// Scanned pages are straightened by adding the deskewer to a `FeederJob` as a page processor.
+/// Imported pages, which the application decodes itself, are straightened with `deskew`. Files
+/// downloaded from cameras are saved as they are, as this crate does not decode image files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deskewer {
+    max_angle: f64,
+    precision: f64,
+    min_angle: f64,
+}
+
+impl Default for Deskewer {
+    fn default() -> Deskewer {
+        Deskewer::new()
+    }
+}
+
+impl Deskewer {
+    /// Create a deskewer that looks for skew of up to 5°, to a precision of 0.05°, and leaves
+    /// pages skewed by less than 0.1° alone.
+    pub fn new() -> Deskewer {
+        Deskewer {
+            max_angle: 5.0,
+            precision: 0.05,
+            min_angle: 0.1,
+        }
+    }
+
+    /// Set the largest skew to look for, in degrees.
+    pub fn with_max_angle(mut self, degrees: f64) -> Deskewer {
+        self.max_angle = degrees.abs().min(45.0);
+        self
+    }
+
+    /// Set the precision of the estimated angle, in degrees.
+    pub fn with_precision(mut self, degrees: f64) -> Deskewer {
+        self.precision = degrees.abs().max(0.001);
+        self
+    }
+
+    /// Set the smallest skew that is corrected, in degrees.
+    pub fn with_min_angle(mut self, degrees: f64) -> Deskewer {
+        self.min_angle = degrees.abs();
+        self
+    }
+
+    /// Estimate the skew of an image.
+    pub fn estimate(&self, image: &Image) -> SkewEstimate {
+        let (width, height) = (image.width(), image.height());
+        let step = ((width * height) as f64 / (4 * MAX_SAMPLES) as f64)
+            .sqrt()
+            .ceil()
+            .max(1.0) as usize;
+        let mut points = Vec::new();
+        for y in (0..height).step_by(step) {
+            for x in (0..width).step_by(step) {
+                if image.luma(x, y) < 128 {
+                    points.push(((x / step) as f64, (y / step) as f64));
+                }
+            }
+        }
+        if points.is_empty() {
+            return SkewEstimate {
+                angle: 0.0,
+                confidence: 0.0,
+            };
+        }
+        if points.len() > MAX_SAMPLES {
+            let keep = (points.len() + MAX_SAMPLES - 1) / MAX_SAMPLES;
+            points = points.into_iter().step_by(keep).collect();
+        }
+
+        let rows = (width + height) / step + 2;
+        let offset = (width / step) as f64;
+        let mut profile = vec![0u32; rows];
+        let mut score = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            profile.iter_mut().for_each(|count| *count = 0);
+            for &(x, y) in &points {
+                let row = (y * cos + x * sin + offset).round();
+                if row >= 0.0 && (row as usize) < rows {
+                    profile[row as usize] += 1;
+                }
+            }
+            profile
+                .iter()
+                .map(|&count| f64::from(count) * f64::from(count))
+                .sum::<f64>()
+        };
+
+        // Search coarsely over the whole range, then refine around the best angle.
+        let mut scores = Vec::new();
+        let mut best = (0.0, score(0.0));
+        let mut search = |from: f64, to: f64, step: f64, best: &mut (f64, f64)| {
+            let steps = ((to - from) / step).round() as i64;
+            for i in 0..=steps {
+                let angle = from + i as f64 * step;
+                let value = score(angle);
+                scores.push(value);
+                if value > best.1 {
+                    *best = (angle, value);
+                }
+            }
+        };
+        let coarse = self.precision.max(0.5);
+        search(-self.max_angle, self.max_angle, coarse, &mut best);
+        let mut step = coarse;
+        while step > self.precision {
+            let center = best.0;
+            step = (step / 4.0).max(self.precision);
+            search(center - 4.0 * step, center + 4.0 * step, step, &mut best);
+        }
+
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        SkewEstimate {
+            angle: best.0.clamp(-self.max_angle, self.max_angle),
+            confidence: if best.1 > 0.0 {
+                ((best.1 - mean) / best.1).clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// Estimate the skew of an image and straighten it. Returns the straightened image, which is
+    /// a copy of the image if the skew is too small to correct, and the skew that was found.
+    /// Fails if the image has no pixels.
+    pub fn deskew(&self, image: &Image) -> Result<(Image, SkewEstimate)> {
+        if image.width() == 0 || image.height() == 0 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let estimate = self.estimate(image);
+        if estimate.angle.abs() < self.min_angle {
+            return Ok((image.clone(), estimate));
+        }
+        Ok((rotate(image, -estimate.angle), estimate))
+    }
+}
+
+impl PageProcessor for Deskewer {
+    /// Straighten the page and report the skew angle in degrees.
+    fn process(&mut self, page: &mut FeederPage) -> Result<PageAction> {
+        let (image, estimate) = self.deskew(page.image())?;
+        *page.image_mut() = image;
+        page.add_report(PageReport {
+            processor: "deskew",
+            detected: estimate.angle.abs() >= self.min_angle,
+            confidence: estimate.confidence,
+            value: Some(estimate.angle),
+            flagged: false,
+        });
+        Ok(PageAction::Keep)
+    }
+}
+
+/// Straighten an image with the default settings of `Deskewer`, for pages that are not scanned
+/// through a `FeederJob`, such as imported pages. Fails if the image has no pixels.
+pub fn deskew(image: &Image) -> Result<Image> {
+    Deskewer::new().deskew(image).map(|(image, _)| image)
+}
+
+/// Rotate an image about its centre by `degrees`, counterclockwise for positive angles, keeping
+/// its size. Gray and colour images are interpolated bilinearly and bilevel images use the
+/// nearest pixel. Areas outside the original image are white.
+pub fn rotate(image: &Image, degrees: f64) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+    resample(image, width, height, |x, y| {
+        // The source of an output pixel is the output pixel rotated back.
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+    })
+}
+
+/// Create a `width` by `height` image in the format of `image`, taking each output pixel from the
+/// position in `image` that `source` maps it to, in pixels with pixel centres on whole numbers.
+/// Gray and colour images are interpolated bilinearly and bilevel images use the nearest pixel.
+/// Positions outside `image` are white.
+pub(crate) fn resample<F>(image: &Image, width: usize, height: usize, source: F) -> Image
+where
+    F: Fn(usize, usize) -> (f64, f64),
+{
+    let format = image.format();
+    let (image_width, image_height) = (image.width() as i64, image.height() as i64);
+    let mut resampled = Image::new(format, width, height);
+    let components = format.components();
+    let bytes = format.bits_per_component() / 8;
+    let white = if bytes == 2 { 0xffff } else { 0xff };
+    let sample = |x: i64, y: i64, component: usize| -> u32 {
+        if x < 0 || y < 0 || x >= image_width || y >= image_height {
+            return white;
+        }
+        let row = image.row(y as usize);
+        let index = (x as usize * components + component) * bytes;
+        if bytes == 2 {
+            u32::from(u16::from_be_bytes([row[index], row[index + 1]]))
+        } else {
+            u32::from(row[index])
+        }
+    };
+
+    for y in 0..height {
+        let row = resampled.row_mut(y);
+        for x in 0..width {
+            let (sx, sy) = source(x, y);
+            if format == PixelFormat::Bilevel {
+                let (nx, ny) = (sx.round() as i64, sy.round() as i64);
+                let inside = nx >= 0 && ny >= 0 && nx < image_width && ny < image_height;
+                if !inside || image.luma(nx as usize, ny as usize) != 0 {
+                    row[x / 8] |= 0x80 >> (x % 8);
+                }
+                continue;
+            }
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            for component in 0..components {
+                let top = sample(x0, y0, component) as f64 * (1.0 - fx)
+                    + sample(x0 + 1, y0, component) as f64 * fx;
+                let bottom = sample(x0, y0 + 1, component) as f64 * (1.0 - fx)
+                    + sample(x0 + 1, y0 + 1, component) as f64 * fx;
+                let value = (top * (1.0 - fy) + bottom * fy).round() as u32;
+                let index = (x * components + component) * bytes;
+                if bytes == 2 {
+                    row[index..index + 2].copy_from_slice(&(value as u16).to_be_bytes());
+                } else {
+                    row[index] = value as u8;
+                }
+            }
+        }
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A white page with lines of text rotated counterclockwise by `degrees`, as a page fed in at
+    /// an angle.
+    fn skewed_page(format: PixelFormat, degrees: f64) -> Image {
+        let mut page = Image::new(format, 400, 300);
+        let slope = degrees.to_radians().tan();
+        for y in 0..300 {
+            for x in 0..400 {
+                // Rows are counted downwards, so a counterclockwise line rises to the right.
+                let line_y = y as f64 + slope * (x as f64 - 200.0);
+                let ink = (40..360).contains(&x) && (line_y - 30.0).rem_euclid(40.0) < 4.0;
+                if format == PixelFormat::Bilevel {
+                    if !ink {
+                        page.row_mut(y)[x / 8] |= 0x80 >> (x % 8);
+                    }
+                } else if !ink {
+                    page.row_mut(y)[x] = 255;
+                }
+            }
+        }
+        page
+    }
+
+    #[test]
+    fn counterclockwise_skew_is_positive() {
+        let deskewer = Deskewer::new();
+        let estimate = deskewer.estimate(&skewed_page(PixelFormat::Gray8, 2.0));
+        assert!((estimate.angle - 2.0).abs() <= 0.1, "{:?}", estimate);
+        assert!(estimate.confidence > 0.2, "{:?}", estimate);
+        let estimate = deskewer.estimate(&skewed_page(PixelFormat::Gray8, -2.0));
+        assert!((estimate.angle + 2.0).abs() <= 0.1, "{:?}", estimate);
+    }
+
+    #[test]
+    fn deskewed_pages_are_straight() {
+        for &format in &[PixelFormat::Gray8, PixelFormat::Bilevel] {
+            let (straight, estimate) = Deskewer::new().deskew(&skewed_page(format, 2.0)).unwrap();
+            assert!((estimate.angle - 2.0).abs() <= 0.1);
+            assert_eq!(straight.format(), format);
+            assert_eq!((straight.width(), straight.height()), (400, 300));
+            let remaining = Deskewer::new().estimate(&straight).angle;
+            assert!(remaining.abs() <= 0.15, "{:?} {}", format, remaining);
+        }
+    }
+
+    #[test]
+    fn blank_and_empty_pages() {
+        let blank = skewed_page(PixelFormat::Gray8, 0.0);
+        let mut white = blank.clone();
+        for y in 0..300 {
+            white.row_mut(y).iter_mut().for_each(|pixel| *pixel = 255);
+        }
+        let (image, estimate) = Deskewer::new().deskew(&white).unwrap();
+        assert_eq!(image, white);
+        assert_eq!(estimate.confidence, 0.0);
+        assert_eq!(
+            deskew(&Image::new(PixelFormat::Gray8, 0, 10)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn feeder_pages_are_straightened() {
+        let mut job = crate::feeder_job::FeederJob::new(false).with_processor(Deskewer::new());
+        job.add_page(skewed_page(PixelFormat::Gray8, 1.5)).unwrap();
+        let report = &job.pages()[0].reports()[0];
+        assert_eq!(report.processor, "deskew");
+        assert!(report.detected);
+        assert!((report.value.unwrap() - 1.5).abs() <= 0.1);
+    }
+}
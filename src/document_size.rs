@@ -0,0 +1,262 @@
+use crate::constants::ICScannerDocumentType;
+use crate::constants::ICScannerDocumentType::*;
+
+/// Millimetres per inch.
+pub const MILLIMETERS_PER_INCH: f64 = 25.4;
+
+/// Physical size of a document.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DocumentSize {
+    width: f64,
+    height: f64,
+}
+
+/// Orientation of a document relative to its standard portrait form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DocumentOrientation {
+    /// The short side is the width.
+    Portrait,
+    /// The long side is the width.
+    Landscape,
+}
+
+/// Result of looking up the standard document size nearest to a measured size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DocumentMatch {
+    /// The matching document type.
+    pub document_type: ICScannerDocumentType,
+    /// Orientation in which the measured size matches the document type.
+    pub orientation: DocumentOrientation,
+    /// Largest difference between a measured side and the matching standard side, in millimetres.
+    pub deviation: f64,
+}
+
+impl DocumentSize {
+    /// Create a size from a width and height in millimetres.
+    pub fn from_millimeters(width: f64, height: f64) -> DocumentSize {
+        DocumentSize { width, height }
+    }
+
+    /// Create a size from a width and height in inches.
+    pub fn from_inches(width: f64, height: f64) -> DocumentSize {
+        DocumentSize {
+            width: width * MILLIMETERS_PER_INCH,
+            height: height * MILLIMETERS_PER_INCH,
+        }
+    }
+
+    /// Width in millimetres.
+    pub fn width_mm(self) -> f64 {
+        self.width
+    }
+
+    /// Height in millimetres.
+    pub fn height_mm(self) -> f64 {
+        self.height
+    }
+
+    /// Width in inches.
+    pub fn width_inches(self) -> f64 {
+        self.width / MILLIMETERS_PER_INCH
+    }
+
+    /// Height in inches.
+    pub fn height_inches(self) -> f64 {
+        self.height / MILLIMETERS_PER_INCH
+    }
+
+    /// The orientation of this size. Square sizes are reported as portrait.
+    pub fn orientation(self) -> DocumentOrientation {
+        if self.width > self.height {
+            DocumentOrientation::Landscape
+        } else {
+            DocumentOrientation::Portrait
+        }
+    }
+
+    /// The same size with the short side as the width.
+    pub fn portrait(self) -> DocumentSize {
+        if self.width > self.height {
+            self.rotated()
+        } else {
+            self
+        }
+    }
+
+    /// The same size with the long side as the width.
+    pub fn landscape(self) -> DocumentSize {
+        if self.width < self.height {
+            self.rotated()
+        } else {
+            self
+        }
+    }
+
+    /// The same size in the given orientation.
+    pub fn oriented(self, orientation: DocumentOrientation) -> DocumentSize {
+        match orientation {
+            DocumentOrientation::Portrait => self.portrait(),
+            DocumentOrientation::Landscape => self.landscape(),
+        }
+    }
+
+    /// The size with width and height swapped.
+    pub fn rotated(self) -> DocumentSize {
+        DocumentSize {
+            width: self.height,
+            height: self.width,
+        }
+    }
+
+    /// Indicates whether this size fits within `area` without rotating it.
+    pub fn fits_within(self, area: DocumentSize) -> bool {
+        self.width <= area.width && self.height <= area.height
+    }
+}
+
+/// Unit a standard size is defined in.
+#[derive(Clone, Copy)]
+enum Unit {
+    Millimeters,
+    Inches,
+}
+
+/// Standard sizes in portrait form, in the unit the standard defines them in.
+#[rustfmt::skip]
+const DOCUMENT_SIZES: &[(ICScannerDocumentType, &str, f64, f64, Unit)] = &[
+    (ICScannerDocumentTypeA4, "A4", 210.0, 297.0, Unit::Millimeters),
+    (ICScannerDocumentTypeB5, "JIS B5", 182.0, 257.0, Unit::Millimeters),
+    (ICScannerDocumentTypeUSLetter, "US Letter", 8.5, 11.0, Unit::Inches),
+    (ICScannerDocumentTypeUSLegal, "US Legal", 8.5, 14.0, Unit::Inches),
+    (ICScannerDocumentTypeA5, "A5", 148.0, 210.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB4, "ISO B4", 250.0, 353.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB6, "ISO B6", 125.0, 176.0, Unit::Millimeters),
+    (ICScannerDocumentTypeUSLedger, "US Ledger", 11.0, 17.0, Unit::Inches),
+    (ICScannerDocumentTypeUSExecutive, "US Executive", 7.25, 10.5, Unit::Inches),
+    (ICScannerDocumentTypeA3, "A3", 297.0, 420.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB3, "ISO B3", 353.0, 500.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA6, "A6", 105.0, 148.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC4, "C4", 229.0, 324.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC5, "C5", 162.0, 229.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC6, "C6", 114.0, 162.0, Unit::Millimeters),
+    (ICScannerDocumentType4A0, "4A0", 1682.0, 2378.0, Unit::Millimeters),
+    (ICScannerDocumentType2A0, "2A0", 1189.0, 1682.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA0, "A0", 841.0, 1189.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA1, "A1", 594.0, 841.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA2, "A2", 420.0, 594.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA7, "A7", 74.0, 105.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA8, "A8", 52.0, 74.0, Unit::Millimeters),
+    (ICScannerDocumentTypeA9, "A9", 37.0, 52.0, Unit::Millimeters),
+    (ICScannerDocumentType10, "A10", 26.0, 37.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB0, "ISO B0", 1000.0, 1414.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB1, "ISO B1", 707.0, 1000.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB2, "ISO B2", 500.0, 707.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB5, "ISO B5", 176.0, 250.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB7, "ISO B7", 88.0, 125.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB8, "ISO B8", 62.0, 88.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB9, "ISO B9", 44.0, 62.0, Unit::Millimeters),
+    (ICScannerDocumentTypeISOB10, "ISO B10", 31.0, 44.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB0, "JIS B0", 1030.0, 1456.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB1, "JIS B1", 728.0, 1030.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB2, "JIS B2", 515.0, 728.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB3, "JIS B3", 364.0, 515.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB4, "JIS B4", 257.0, 364.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB6, "JIS B6", 128.0, 182.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB7, "JIS B7", 91.0, 128.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB8, "JIS B8", 64.0, 91.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB9, "JIS B9", 45.0, 64.0, Unit::Millimeters),
+    (ICScannerDocumentTypeJISB10, "JIS B10", 32.0, 45.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC0, "C0", 917.0, 1297.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC1, "C1", 648.0, 917.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC2, "C2", 458.0, 648.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC3, "C3", 324.0, 458.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC7, "C7", 81.0, 114.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC8, "C8", 57.0, 81.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC9, "C9", 40.0, 57.0, Unit::Millimeters),
+    (ICScannerDocumentTypeC10, "C10", 28.0, 40.0, Unit::Millimeters),
+    (ICScannerDocumentTypeUSStatement, "US Statement", 5.5, 8.5, Unit::Inches),
+    (ICScannerDocumentTypeBusinessCard, "Business Card", 55.0, 90.0, Unit::Millimeters),
+    (ICScannerDocumentTypeE, "Japanese E", 3.25, 4.75, Unit::Inches),
+    (ICScannerDocumentType3R, "3R", 3.5, 5.0, Unit::Inches),
+    (ICScannerDocumentType4R, "4R", 4.0, 6.0, Unit::Inches),
+    (ICScannerDocumentType5R, "5R", 5.0, 7.0, Unit::Inches),
+    (ICScannerDocumentType6R, "6R", 6.0, 8.0, Unit::Inches),
+    (ICScannerDocumentType8R, "8R", 8.0, 10.0, Unit::Inches),
+    (ICScannerDocumentTypeS8R, "S8R", 8.0, 12.0, Unit::Inches),
+    (ICScannerDocumentType10R, "10R", 10.0, 12.0, Unit::Inches),
+    (ICScannerDocumentTypeS10R, "S10R", 10.0, 15.0, Unit::Inches),
+    (ICScannerDocumentType11R, "11R", 11.0, 14.0, Unit::Inches),
+    (ICScannerDocumentType12R, "12R", 12.0, 15.0, Unit::Inches),
+    (ICScannerDocumentTypeS12R, "S12R", 12.0, 18.0, Unit::Inches),
+    (ICScannerDocumentType110, "110 Film", 13.0, 17.0, Unit::Millimeters),
+    (ICScannerDocumentTypeAPSH, "APS-H", 16.7, 30.2, Unit::Millimeters),
+    (ICScannerDocumentTypeAPSC, "APS-C", 16.7, 25.1, Unit::Millimeters),
+    (ICScannerDocumentTypeAPSP, "APS-P", 9.5, 30.2, Unit::Millimeters),
+    (ICScannerDocumentType135, "135 Film", 24.0, 36.0, Unit::Millimeters),
+    (ICScannerDocumentTypeMF, "Medium Format", 60.0, 60.0, Unit::Millimeters),
+    (ICScannerDocumentTypeLF, "Large Format", 100.0, 120.0, Unit::Millimeters),
+];
+
+fn document_size(document_type: ICScannerDocumentType) -> Option<(&'static str, DocumentSize)> {
+    DOCUMENT_SIZES
+        .iter()
+        .find(|entry| entry.0 == document_type)
+        .map(|&(_, name, width, height, unit)| (name, size_in(unit, width, height)))
+}
+
+fn size_in(unit: Unit, width: f64, height: f64) -> DocumentSize {
+    match unit {
+        Unit::Millimeters => DocumentSize::from_millimeters(width, height),
+        Unit::Inches => DocumentSize::from_inches(width, height),
+    }
+}
+
+impl ICScannerDocumentType {
+    /// The physical size of the document type in portrait form.
+    /// Returns `None` for `ICScannerDocumentTypeDefault`.
+    pub fn size(self) -> Option<DocumentSize> {
+        document_size(self).map(|(_, size)| size)
+    }
+
+    /// The common name of the document type, such as "A4" or "US Letter".
+    pub fn name(self) -> Option<&'static str> {
+        document_size(self).map(|(name, _)| name)
+    }
+
+    /// All document types with a known physical size.
+    pub fn standard_types() -> impl Iterator<Item = ICScannerDocumentType> {
+        DOCUMENT_SIZES.iter().map(|entry| entry.0)
+    }
+
+    /// Find the standard document type nearest to a measured size, in either orientation.
+    /// A document type matches if neither side differs from the measured side by more than `tolerance` millimetres.
+    pub fn nearest(size: DocumentSize, tolerance: f64) -> Option<DocumentMatch> {
+        let mut best: Option<DocumentMatch> = None;
+        for &(document_type, _, width, height, unit) in DOCUMENT_SIZES {
+            let standard = size_in(unit, width, height);
+            for &orientation in &[
+                DocumentOrientation::Portrait,
+                DocumentOrientation::Landscape,
+            ] {
+                let candidate = standard.oriented(orientation);
+                let deviation = (candidate.width - size.width)
+                    .abs()
+                    .max((candidate.height - size.height).abs());
+                if deviation > tolerance {
+                    continue;
+                }
+                match best {
+                    Some(best) if best.deviation <= deviation => {}
+                    _ => {
+                        best = Some(DocumentMatch {
+                            document_type,
+                            orientation,
+                            deviation,
+                        })
+                    }
+                }
+            }
+        }
+        best
+    }
+}
@@ -0,0 +1,340 @@
+use crate::constants::{ICReturnCode, UnknownValue};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io;
+
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use objc::*;
+
+/// The NSError domain used by ImageCaptureCore for errors carrying an `ICReturnCode`.
+pub const IC_ERROR_DOMAIN: &str = "com.apple.ImageCaptureCore";
+
+/// The Cocoa error domain. Errors in this domain with code `NSUserCancelledError` are reported as cancellations.
+pub const NS_COCOA_ERROR_DOMAIN: &str = "NSCocoaErrorDomain";
+
+/// `NSUserCancelledError` code in `NSCocoaErrorDomain`.
+pub const NS_USER_CANCELLED_ERROR: i64 = 3072;
+
+/// Errors reported by the ImageCaptureCore framework.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A failure described by one of the known `ICReturnCode` values.
+    ReturnCode(ICReturnCode),
+    /// A code in the ImageCaptureCore error domain that is not known to this crate.
+    UnknownReturnCode(i64),
+    /// A raw value reported by ImageCaptureCore that is not known to this crate, such as a
+    /// pixel data type or measurement unit added by a newer version of the framework.
+    UnknownValue(u64),
+    /// An NSError from a domain other than ImageCaptureCore.
+    Domain {
+        /// The NSError domain.
+        domain: String,
+        /// The NSError code.
+        code: i64,
+    },
+    /// An I/O failure while reading or writing image data.
+    Io {
+        /// The kind of I/O error.
+        kind: io::ErrorKind,
+        /// The operating system error code, or 0 if there is none.
+        code: i64,
+        /// A description of the failure.
+        message: String,
+    },
+}
+
+/// Result type used throughout this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl ICReturnCode {
+    /// A human readable description of the code.
+    pub fn description(self) -> &'static str {
+        use ICReturnCode::*;
+        match self {
+            ICReturnSuccess => "operation completed successfully",
+            ICReturnInvalidParam => "an invalid parameter was supplied",
+            ICReturnCommunicationTimedOut => "communication with the device timed out",
+            ICReturnScanOperationCanceled => "the scan operation was canceled",
+            ICReturnScannerInUseByLocalUser => "the scanner is in use by a local user",
+            ICReturnScannerInUseByRemoteUser => "the scanner is in use by a remote user",
+            ICReturnDeviceFailedToOpenSession => "failed to open a session on the device",
+            ICReturnDeviceFailedToCloseSession => "failed to close the session on the device",
+            ICReturnScannerFailedToSelectFunctionalUnit => {
+                "the scanner failed to select the functional unit"
+            }
+            ICReturnScannerFailedToCompleteOverviewScan => {
+                "the scanner failed to complete the overview scan"
+            }
+            ICReturnScannerFailedToCompleteScan => "the scanner failed to complete the scan",
+            ICReturnReceivedUnsolicitedScannerStatusInfo => {
+                "received unsolicited status information from the scanner"
+            }
+            ICReturnReceivedUnsolicitedScannerErrorInfo => {
+                "received unsolicited error information from the scanner"
+            }
+            ICReturnDownloadFailed => "the download failed",
+            ICReturnUploadFailed => "the upload failed",
+            ICReturnFailedToCompletePassThroughCommand => {
+                "failed to complete the pass-through command"
+            }
+            ICReturnDownloadCanceled => "the download was canceled",
+            ICReturnFailedToEnabeTethering => "failed to enable tethered capture",
+            ICReturnFailedToDisabeTethering => "failed to disable tethered capture",
+            ICReturnFailedToCompleteSendMessageRequest => {
+                "failed to complete the send message request"
+            }
+            ICReturnDeleteFilesFailed => "failed to delete files",
+            ICReturnDeleteFilesCanceled => "deleting files was canceled",
+            ICReturnDeviceIsPasscodeLocked => "the device is locked with a passcode",
+            ICReturnDeviceFailedToTakePicture => "the device failed to take a picture",
+            ICReturnDeviceSoftwareNotInstalled => "the device software is not installed",
+            ICReturnDeviceSoftwareIsBeingInstalled => "the device software is being installed",
+            ICReturnDeviceSoftwareInstallationCompleted => {
+                "the device software installation completed"
+            }
+            ICReturnDeviceSoftwareInstallationCanceled => {
+                "the device software installation was canceled"
+            }
+            ICReturnDeviceSoftwareInstallationFailed => "the device software installation failed",
+            ICReturnDeviceSoftwareNotAvailable => "the device software is not available",
+            ICReturnDeviceCouldNotPair => "the device could not be paired",
+            ICReturnDeviceCouldNotUnpair => "the device could not be unpaired",
+            ICReturnDeviceNeedsCredentials => "the device requires credentials",
+            ICReturnDeviceIsBusyEnumerating => "the device is busy enumerating its contents",
+            ICReturnDeviceCommandGeneralFailure => "the device command failed",
+        }
+    }
+
+    /// Indicates whether the code reports an operation canceled by the user or the client.
+    pub fn is_cancellation(self) -> bool {
+        use ICReturnCode::*;
+        matches!(
+            self,
+            ICReturnScanOperationCanceled
+                | ICReturnDownloadCanceled
+                | ICReturnDeleteFilesCanceled
+                | ICReturnDeviceSoftwareInstallationCanceled
+        )
+    }
+
+    /// Indicates whether the failure is transient and the request may succeed if it is sent again later.
+    pub fn is_retryable(self) -> bool {
+        use ICReturnCode::*;
+        matches!(
+            self,
+            ICReturnCommunicationTimedOut
+                | ICReturnScannerInUseByLocalUser
+                | ICReturnScannerInUseByRemoteUser
+                | ICReturnDeviceSoftwareIsBeingInstalled
+                | ICReturnDeviceIsBusyEnumerating
+        )
+    }
+
+    /// Indicates whether the user has to act on the device or the host before the request can succeed.
+    pub fn requires_user_action(self) -> bool {
+        use ICReturnCode::*;
+        matches!(
+            self,
+            ICReturnDeviceIsPasscodeLocked
+                | ICReturnDeviceNeedsCredentials
+                | ICReturnDeviceSoftwareNotInstalled
+                | ICReturnDeviceCouldNotPair
+        )
+    }
+}
+
+impl TryFrom<i64> for ICReturnCode {
+    type Error = Error;
+
+    fn try_from(code: i64) -> Result<Self> {
+        use ICReturnCode::*;
+        Ok(match code {
+            0 => ICReturnSuccess,
+            -9922 => ICReturnInvalidParam,
+            -9923 => ICReturnCommunicationTimedOut,
+            -9924 => ICReturnScanOperationCanceled,
+            -9925 => ICReturnScannerInUseByLocalUser,
+            -9926 => ICReturnScannerInUseByRemoteUser,
+            -9927 => ICReturnDeviceFailedToOpenSession,
+            -9928 => ICReturnDeviceFailedToCloseSession,
+            -9929 => ICReturnScannerFailedToSelectFunctionalUnit,
+            -9930 => ICReturnScannerFailedToCompleteOverviewScan,
+            -9931 => ICReturnScannerFailedToCompleteScan,
+            -9932 => ICReturnReceivedUnsolicitedScannerStatusInfo,
+            -9933 => ICReturnReceivedUnsolicitedScannerErrorInfo,
+            -9934 => ICReturnDownloadFailed,
+            -9935 => ICReturnUploadFailed,
+            -9936 => ICReturnFailedToCompletePassThroughCommand,
+            -9937 => ICReturnDownloadCanceled,
+            -9938 => ICReturnFailedToEnabeTethering,
+            -9939 => ICReturnFailedToDisabeTethering,
+            -9940 => ICReturnFailedToCompleteSendMessageRequest,
+            -9941 => ICReturnDeleteFilesFailed,
+            -9942 => ICReturnDeleteFilesCanceled,
+            -9943 => ICReturnDeviceIsPasscodeLocked,
+            -9944 => ICReturnDeviceFailedToTakePicture,
+            -9945 => ICReturnDeviceSoftwareNotInstalled,
+            -9946 => ICReturnDeviceSoftwareIsBeingInstalled,
+            -9947 => ICReturnDeviceSoftwareInstallationCompleted,
+            -9948 => ICReturnDeviceSoftwareInstallationCanceled,
+            -9949 => ICReturnDeviceSoftwareInstallationFailed,
+            -9950 => ICReturnDeviceSoftwareNotAvailable,
+            -9951 => ICReturnDeviceCouldNotPair,
+            -9952 => ICReturnDeviceCouldNotUnpair,
+            -9953 => ICReturnDeviceNeedsCredentials,
+            -9954 => ICReturnDeviceIsBusyEnumerating,
+            -9955 => ICReturnDeviceCommandGeneralFailure,
+            _ => return Err(Error::UnknownReturnCode(code)),
+        })
+    }
+}
+
+impl From<ICReturnCode> for i64 {
+    fn from(code: ICReturnCode) -> i64 {
+        code as i64
+    }
+}
+
+impl fmt::Display for ICReturnCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error {
+    /// Convert a raw code returned by ImageCaptureCore into a result. `ICReturnSuccess` maps to `Ok(())`.
+    pub fn check(code: i64) -> Result<()> {
+        match ICReturnCode::try_from(code)? {
+            ICReturnCode::ICReturnSuccess => Ok(()),
+            code => Err(Error::ReturnCode(code)),
+        }
+    }
+
+    /// Create an error from the domain and code of an NSError.
+    /// Codes in the ImageCaptureCore domain are mapped to `ICReturnCode` values.
+    pub fn from_domain(domain: &str, code: i64) -> Error {
+        if domain == IC_ERROR_DOMAIN {
+            match ICReturnCode::try_from(code) {
+                Ok(code) => Error::ReturnCode(code),
+                Err(error) => error,
+            }
+        } else {
+            Error::Domain {
+                domain: domain.to_owned(),
+                code,
+            }
+        }
+    }
+
+    /// Create an error from an NSError object. Returns `None` if `error` is nil. An error without
+    /// a domain is reported with an empty domain and its code.
+    ///
+    /// # Safety
+    ///
+    /// `error` must be nil or a valid `NSError` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn from_ns_error(error: id) -> Option<Error> {
+        if error == nil {
+            return None;
+        }
+        let code: i64 = msg_send![error, code];
+        let domain: id = msg_send![error, domain];
+        if domain == nil {
+            return Some(Error::from_domain("", code));
+        }
+        let domain: *const libc::c_char = msg_send![domain, UTF8String];
+        if domain.is_null() {
+            return Some(Error::from_domain("", code));
+        }
+        let domain = std::ffi::CStr::from_ptr(domain).to_string_lossy();
+        Some(Error::from_domain(&domain, code))
+    }
+
+    /// The raw code of the error, or 0 for an unknown value, which has no code.
+    pub fn code(&self) -> i64 {
+        match *self {
+            Error::ReturnCode(code) => code.into(),
+            Error::UnknownReturnCode(code) => code,
+            Error::UnknownValue(_) => 0,
+            Error::Domain { code, .. } => code,
+            Error::Io { code, .. } => code,
+        }
+    }
+
+    /// The `ICReturnCode` of the error, if it is a known ImageCaptureCore code.
+    pub fn return_code(&self) -> Option<ICReturnCode> {
+        match *self {
+            Error::ReturnCode(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Indicates whether the error reports an operation canceled by the user or the client.
+    pub fn is_cancellation(&self) -> bool {
+        match *self {
+            Error::ReturnCode(code) => code.is_cancellation(),
+            Error::UnknownReturnCode(_) | Error::UnknownValue(_) | Error::Io { .. } => false,
+            Error::Domain { ref domain, code } => {
+                domain == NS_COCOA_ERROR_DOMAIN && code == NS_USER_CANCELLED_ERROR
+            }
+        }
+    }
+
+    /// Indicates whether the failure is transient and the request may succeed if it is sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Io { kind, .. } => matches!(
+                kind,
+                io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ),
+            _ => self.return_code().map_or(false, ICReturnCode::is_retryable),
+        }
+    }
+
+    /// Indicates whether the user has to act on the device or the host before the request can succeed.
+    pub fn requires_user_action(&self) -> bool {
+        self.return_code()
+            .map_or(false, ICReturnCode::requires_user_action)
+    }
+}
+
+impl From<ICReturnCode> for Error {
+    fn from(code: ICReturnCode) -> Error {
+        Error::ReturnCode(code)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io {
+            kind: error.kind(),
+            code: error.raw_os_error().map_or(0, i64::from),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<UnknownValue> for Error {
+    fn from(value: UnknownValue) -> Error {
+        Error::UnknownValue(value.0)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ReturnCode(code) => write!(f, "{} ({})", code, code as i64),
+            Error::UnknownReturnCode(code) => {
+                write!(f, "unknown ImageCaptureCore error ({})", code)
+            }
+            Error::UnknownValue(value) => write!(f, "unknown ImageCaptureCore value ({})", value),
+            Error::Domain { ref domain, code } => write!(f, "{} error {}", domain, code),
+            Error::Io { ref message, .. } => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,785 @@
+use crate::backend::{
+    Backend, CameraItem, CameraStatus, DeviceId, DeviceInfo, DeviceKind, DownloadOptions, Event,
+    FunctionalUnit, ItemId, ScanSettings,
+};
+use crate::constants::ICReturnCode::{self, ICReturnDeviceIsPasscodeLocked, ICReturnInvalidParam};
+use crate::constants::ICScannerFunctionalUnitType;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A request to a backend that can be made to fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Request {
+    /// `Backend::open_session`.
+    OpenSession,
+    /// `Backend::close_session`.
+    CloseSession,
+    /// `Backend::download_file`.
+    Download,
+    /// `Backend::read_file`.
+    ReadFile,
+    /// `Backend::delete_files`.
+    DeleteFiles,
+    /// `Backend::upload_file`.
+    Upload,
+    /// `Backend::set_tethering`.
+    SetTethering,
+    /// `Backend::take_picture`.
+    TakePicture,
+    /// `Backend::select_functional_unit`.
+    SelectFunctionalUnit,
+    /// `Backend::configure`.
+    Configure,
+    /// `Backend::request_overview_scan`.
+    OverviewScan,
+    /// `Backend::request_scan`.
+    Scan,
+    /// `Backend::cancel_scan`.
+    CancelScan,
+}
+
+/// Which occurrences of a request or event a fault applies to. Occurrences are counted from 1
+/// over all devices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// Only the given occurrence.
+    Nth(usize),
+    /// The given occurrence and all later ones.
+    From(usize),
+    /// Each occurrence with a probability from 0 to 1, drawn from the random numbers of the
+    /// scenario.
+    Probability(f64),
+}
+
+/// A fault of a scenario.
+#[derive(Clone, Debug, PartialEq)]
+enum Fault {
+    /// Fail a request with a return code.
+    Fail {
+        request: Request,
+        trigger: Trigger,
+        code: ICReturnCode,
+    },
+    /// Remove a device after a transfer event from it.
+    Disconnect(Trigger),
+    /// Cut the data of bands short.
+    TruncateBands(Trigger),
+}
+
+/// A reproducible set of faults to inject into a backend with a `FaultInjector`.
+///
+/// Faults that happen by chance and the jitter of the latency are drawn from a pseudorandom
+/// sequence started from the seed, so the same scenario run against the same requests injects
+/// the same faults. A scenario can be cloned to run the same faults against several backends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultScenario {
+    seed: u64,
+    faults: Vec<Fault>,
+    latency: Duration,
+    jitter: Duration,
+}
+
+impl Default for FaultScenario {
+    fn default() -> FaultScenario {
+        FaultScenario::new(0)
+    }
+}
+
+impl FaultScenario {
+    /// Create a scenario without faults, with random numbers drawn from `seed`.
+    pub fn new(seed: u64) -> FaultScenario {
+        FaultScenario {
+            seed,
+            faults: Vec::new(),
+            latency: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+        }
+    }
+
+    /// The seed of the random numbers.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Fail the `trigger` occurrences of `request` with `code`.
+    ///
+    /// The request is not passed to the backend. For requests whose outcome arrives as an event,
+    /// such as `Event::SessionOpened` or `Event::Downloaded`, the method succeeds and the event
+    /// reports the error. It may arrive before events of earlier requests that the backend has
+    /// not delivered yet. Other requests fail with the error right away.
+    pub fn with_failure(
+        mut self,
+        request: Request,
+        trigger: Trigger,
+        code: ICReturnCode,
+    ) -> FaultScenario {
+        self.faults.push(Fault::Fail {
+            request,
+            trigger,
+            code,
+        });
+        self
+    }
+
+    /// Disconnect a device after the `trigger` occurrences of transfer events: scanned bands and
+    /// files, downloads and reads. The transfer event is delivered, followed by
+    /// `Event::DeviceRemoved`. Later events from the device are dropped and requests to it fail
+    /// with `ICReturnInvalidParam`, as for a device the backend does not know.
+    pub fn with_disconnect(mut self, trigger: Trigger) -> FaultScenario {
+        self.faults.push(Fault::Disconnect(trigger));
+        self
+    }
+
+    /// Cut the data of the `trigger` occurrences of scanned bands to a random length shorter than
+    /// `bytes_per_row * data_num_rows`, as a transfer that breaks off would.
+    pub fn with_truncated_bands(mut self, trigger: Trigger) -> FaultScenario {
+        self.faults.push(Fault::TruncateBands(trigger));
+        self
+    }
+
+    /// Delay every event by `latency` plus a random part of `jitter`. Events keep their order.
+    pub fn with_latency(mut self, latency: Duration, jitter: Duration) -> FaultScenario {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+}
+
+/// The SplitMix64 pseudorandom number generator.
+#[derive(Clone, Debug)]
+struct Random(u64);
+
+impl Random {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to but not including 1.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// An event held back until its delivery time.
+#[derive(Clone, Debug)]
+struct Pending {
+    due: Instant,
+    event: Event,
+}
+
+/// A backend that passes requests and events through to another backend and injects the faults
+/// of a `FaultScenario`, to test how an application copes with failing devices. It wraps any
+/// backend, such as a `SimulatedScanner` or a `SimulatedCamera`.
+///
+/// Besides the faults of the scenario, a camera can be made to restrict access or be locked with
+/// a passcode while the injector runs. A camera that is locked with a passcode restricts access,
+/// and opening a session or transferring files fails with `ICReturnDeviceIsPasscodeLocked`.
+#[derive(Debug)]
+pub struct FaultInjector<B: Backend> {
+    backend: B,
+    scenario: FaultScenario,
+    random: Random,
+    requests: HashMap<Request, usize>,
+    transfers: usize,
+    bands: usize,
+    disconnected: HashSet<DeviceId>,
+    restricted: HashMap<DeviceId, bool>,
+    locked: HashSet<DeviceId>,
+    pending: VecDeque<Pending>,
+}
+
+impl<B: Backend> FaultInjector<B> {
+    /// Inject the faults of `scenario` into `backend`.
+    pub fn new(backend: B, scenario: FaultScenario) -> FaultInjector<B> {
+        FaultInjector {
+            backend,
+            random: Random(scenario.seed),
+            scenario,
+            requests: HashMap::new(),
+            transfers: 0,
+            bands: 0,
+            disconnected: HashSet::new(),
+            restricted: HashMap::new(),
+            locked: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The scenario.
+    pub fn scenario(&self) -> &FaultScenario {
+        &self.scenario
+    }
+
+    /// The backend faults are injected into.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// The backend faults are injected into, for changing it while the injector runs.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Stop injecting faults and return the backend. Events that are held back are lost.
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+
+    /// Make a camera restrict access to its contents or stop restricting it, as reported by
+    /// `CameraStatus::access_restricted`. A change is reported with
+    /// `Event::AccessRestrictionChanged`.
+    pub fn set_access_restricted(&mut self, device: &DeviceId, restricted: bool) {
+        if self.restricted.get(device) != Some(&restricted) {
+            self.restricted.insert(device.clone(), restricted);
+            self.schedule(Event::AccessRestrictionChanged {
+                device: device.clone(),
+                restricted,
+            });
+        }
+    }
+
+    /// Lock a camera with a passcode or unlock it. Locking it restricts access and unlocking it
+    /// removes the restriction.
+    pub fn set_passcode_locked(&mut self, device: &DeviceId, locked: bool) {
+        if locked {
+            self.locked.insert(device.clone());
+        } else {
+            self.locked.remove(device);
+        }
+        self.set_access_restricted(device, locked);
+    }
+
+    /// Indicates whether a device has been disconnected by the scenario.
+    pub fn is_disconnected(&self, device: &DeviceId) -> bool {
+        self.disconnected.contains(device)
+    }
+
+    /// Decide whether the next occurrence counted by `count` is faulty.
+    fn fires(random: &mut Random, trigger: Trigger, count: usize) -> bool {
+        match trigger {
+            Trigger::Nth(n) => count == n,
+            Trigger::From(n) => count >= n,
+            Trigger::Probability(p) => random.next_f64() < p,
+        }
+    }
+
+    /// Count a request to a device and return the error it fails with, if any.
+    fn fault(&mut self, device: &DeviceId, request: Request) -> Option<Error> {
+        if self.disconnected.contains(device) {
+            return Some(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let count = self.requests.entry(request).or_insert(0);
+        *count += 1;
+        let count = *count;
+
+        let random = &mut self.random;
+        let code = self.scenario.faults.iter().find_map(|fault| match *fault {
+            Fault::Fail {
+                request: faulty,
+                trigger,
+                code,
+            } if faulty == request && FaultInjector::<B>::fires(random, trigger, count) => {
+                Some(code)
+            }
+            _ => None,
+        });
+        if code.is_some() {
+            return code.map(Error::ReturnCode);
+        }
+
+        let locked = match request {
+            Request::OpenSession
+            | Request::Download
+            | Request::ReadFile
+            | Request::DeleteFiles
+            | Request::Upload
+            | Request::TakePicture => self.locked.contains(device),
+            _ => false,
+        };
+        if locked {
+            Some(Error::ReturnCode(ICReturnDeviceIsPasscodeLocked))
+        } else {
+            None
+        }
+    }
+
+    /// Make a request whose outcome arrives as an event. If the request fails, `failure` makes
+    /// the event with the error instead of passing the request on.
+    fn request<F, G>(
+        &mut self,
+        device: &DeviceId,
+        request: Request,
+        failure: F,
+        forward: G,
+    ) -> Result<()>
+    where
+        F: FnOnce(Error) -> Event,
+        G: FnOnce(&mut B) -> Result<()>,
+    {
+        if self.disconnected.contains(device) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        match self.fault(device, request) {
+            Some(error) => {
+                self.schedule(failure(error));
+                Ok(())
+            }
+            None => forward(&mut self.backend),
+        }
+    }
+
+    /// Make a request whose outcome is returned right away.
+    fn request_now<T, G>(&mut self, device: &DeviceId, request: Request, forward: G) -> Result<T>
+    where
+        G: FnOnce(&mut B) -> Result<T>,
+    {
+        match self.fault(device, request) {
+            Some(error) => Err(error),
+            None => forward(&mut self.backend),
+        }
+    }
+
+    /// Fail with `ICReturnInvalidParam` for a device that has been disconnected.
+    fn check_connected(&self, device: &DeviceId) -> Result<()> {
+        if self.disconnected.contains(device) {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply the faults to an event from the backend and hold the result back for delivery.
+    fn receive(&mut self, mut event: Event) {
+        let device = event.device().clone();
+        if self.disconnected.contains(&device) {
+            return;
+        }
+
+        if let Event::ScannedBand { band, .. } = &mut event {
+            self.bands += 1;
+            let (random, bands) = (&mut self.random, self.bands);
+            let truncate = self.scenario.faults.iter().any(|fault| match *fault {
+                Fault::TruncateBands(trigger) => FaultInjector::<B>::fires(random, trigger, bands),
+                _ => false,
+            });
+            if truncate {
+                let length = (band.bytes_per_row * band.data_num_rows).min(band.data.len());
+                let keep = (self.random.next_f64() * length as f64) as usize;
+                band.data.truncate(keep);
+            }
+        }
+
+        let transfer = matches!(
+            event,
+            Event::ScannedBand { .. }
+                | Event::ScannedFile { .. }
+                | Event::Downloaded { .. }
+                | Event::DataRead { .. }
+        );
+        let mut disconnect = false;
+        if transfer {
+            self.transfers += 1;
+            let (random, transfers) = (&mut self.random, self.transfers);
+            disconnect = self.scenario.faults.iter().any(|fault| match *fault {
+                Fault::Disconnect(trigger) => FaultInjector::<B>::fires(random, trigger, transfers),
+                _ => false,
+            });
+        }
+
+        self.schedule(event);
+        if disconnect {
+            self.disconnected.insert(device.clone());
+            self.schedule(Event::DeviceRemoved(device));
+        }
+    }
+
+    /// Hold an event back for the latency of the scenario, after the events before it.
+    fn schedule(&mut self, event: Event) {
+        let jitter = self.scenario.jitter.mul_f64(self.random.next_f64());
+        let mut due = Instant::now() + self.scenario.latency + jitter;
+        if let Some(last) = self.pending.back() {
+            due = due.max(last.due);
+        }
+        self.pending.push_back(Pending { due, event });
+    }
+}
+
+impl<B: Backend> Backend for FaultInjector<B> {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        self.backend.start_browsing(kinds)
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        self.backend.stop_browsing()
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.backend
+            .devices()
+            .into_iter()
+            .filter(|info| !self.disconnected.contains(&info.id))
+            .collect()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if self
+                .pending
+                .front()
+                .map_or(false, |pending| pending.due <= now)
+            {
+                return self.pending.pop_front().map(|pending| pending.event);
+            }
+            let until = self
+                .pending
+                .front()
+                .map_or(deadline, |pending| pending.due.min(deadline));
+            match self
+                .backend
+                .next_event(until.saturating_duration_since(now))
+            {
+                Some(event) => self.receive(event),
+                None => {
+                    let now = Instant::now();
+                    match self.pending.front() {
+                        Some(pending) if pending.due <= deadline => {
+                            thread::sleep(pending.due.saturating_duration_since(now))
+                        }
+                        // Wait out the timeout for an event that is due later, as the backend
+                        // would for an event that has not arrived.
+                        Some(_) => {
+                            thread::sleep(deadline.saturating_duration_since(now));
+                            return None;
+                        }
+                        None => return None,
+                    }
+                }
+            }
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::OpenSession,
+            |error| Event::SessionOpened {
+                device: event_device,
+                result: Err(error),
+            },
+            |backend| backend.open_session(device),
+        )
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::CloseSession,
+            |error| Event::SessionClosed {
+                device: event_device,
+                result: Err(error),
+            },
+            |backend| backend.close_session(device),
+        )
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        self.check_connected(device)?;
+        self.backend.has_open_session(device)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        self.check_connected(device)?;
+        self.backend.camera_items(device)
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let (event_device, event_item) = (device.clone(), item.clone());
+        self.request(
+            device,
+            Request::Download,
+            |error| Event::Downloaded {
+                device: event_device,
+                item: event_item,
+                result: Err(error),
+            },
+            |backend| backend.download_file(device, item, options),
+        )
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        self.check_connected(device)?;
+        let mut status = self.backend.camera_status(device)?;
+        if let Some(&restricted) = self.restricted.get(device) {
+            status.access_restricted = restricted;
+        }
+        Ok(status)
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let (event_device, event_item) = (device.clone(), item.clone());
+        self.request(
+            device,
+            Request::ReadFile,
+            |error| Event::DataRead {
+                device: event_device,
+                item: event_item,
+                offset,
+                result: Err(error),
+            },
+            |backend| backend.read_file(device, item, offset, length),
+        )
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        self.request_now(device, Request::DeleteFiles, |backend| {
+            backend.delete_files(device, items)
+        })
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::Upload,
+            |error| Event::Uploaded {
+                device: event_device,
+                path: path.to_owned(),
+                result: Err(error),
+            },
+            |backend| backend.upload_file(device, path),
+        )
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()> {
+        self.request_now(device, Request::SetTethering, |backend| {
+            backend.set_tethering(device, enabled)
+        })
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        self.request_now(device, Request::TakePicture, |backend| {
+            backend.take_picture(device)
+        })
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        self.check_connected(device)?;
+        self.backend.functional_unit_types(device)
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::SelectFunctionalUnit,
+            |error| Event::FunctionalUnitSelected {
+                device: event_device,
+                result: Err(error),
+            },
+            |backend| backend.select_functional_unit(device, unit_type),
+        )
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        self.check_connected(device)?;
+        self.backend.functional_unit(device)
+    }
+
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit> {
+        self.request_now(device, Request::Configure, |backend| {
+            backend.configure(device, settings)
+        })
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::OverviewScan,
+            |error| Event::OverviewCompleted {
+                device: event_device,
+                result: Err(error),
+            },
+            |backend| backend.request_overview_scan(device),
+        )
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        self.check_connected(device)?;
+        self.backend.overview_image(device)
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let event_device = device.clone();
+        self.request(
+            device,
+            Request::Scan,
+            |error| Event::ScanCompleted {
+                device: event_device,
+                result: Err(error),
+            },
+            |backend| backend.request_scan(device),
+        )
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.request_now(device, Request::CancelScan, |backend| {
+            backend.cancel_scan(device)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICReturnCode::ICReturnDeviceFailedToOpenSession;
+    use crate::constants::ICScannerMeasurementUnit::ICScannerMeasurementUnitInches;
+    use crate::measurement::ScanRect;
+    use crate::simulated_camera::{SimulatedCamera, SimulatedFile, SimulatedFolder};
+    use crate::simulated_scanner::SimulatedScanner;
+
+    fn events<B: Backend>(backend: &mut B) -> Vec<Event> {
+        std::iter::from_fn(|| backend.next_event(Duration::from_secs(0))).collect()
+    }
+
+    fn open_results(events: &[Event]) -> Vec<Result<()>> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::SessionOpened { result, .. } => Some(result.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A scanner with an open session, scanning a square inch in bands of 10 rows at 75 dpi.
+    fn scanner(scenario: FaultScenario) -> FaultInjector<SimulatedScanner> {
+        let mut injector = FaultInjector::new(SimulatedScanner::default(), scenario);
+        let id = injector.backend().id().clone();
+        injector.start_browsing(&[DeviceKind::Scanner]).unwrap();
+        injector.open_session(&id).unwrap();
+        let area = ScanRect::new(0.0, 0.0, 1.0, 1.0, ICScannerMeasurementUnitInches);
+        let settings = ScanSettings::new()
+            .with_resolution(75)
+            .with_scan_area(area)
+            .with_max_memory_band_size(2250);
+        injector.backend_mut().configure(&id, &settings).unwrap();
+        events(&mut injector);
+        injector
+    }
+
+    #[test]
+    fn failed_requests_report_their_outcome_as_events() {
+        let scenario = FaultScenario::new(1).with_failure(
+            Request::OpenSession,
+            Trigger::Nth(1),
+            ICReturnDeviceFailedToOpenSession,
+        );
+        let mut injector = FaultInjector::new(SimulatedCamera::default(), scenario);
+        let id = injector.backend().id().clone();
+        injector.start_browsing(&[DeviceKind::Camera]).unwrap();
+        injector.open_session(&id).unwrap();
+        assert_eq!(injector.has_open_session(&id), Ok(false));
+        injector.open_session(&id).unwrap();
+        assert_eq!(
+            open_results(&events(&mut injector)),
+            vec![
+                Err(Error::ReturnCode(ICReturnDeviceFailedToOpenSession)),
+                Ok(())
+            ]
+        );
+    }
+
+    #[test]
+    fn disconnected_devices_are_gone_after_the_transfer() {
+        let scenario = FaultScenario::new(2).with_disconnect(Trigger::Nth(1));
+        let storage =
+            SimulatedFolder::new("Card").with_file(SimulatedFile::new("a.jpg", b"a".to_vec()));
+        let camera = SimulatedCamera::default().with_storage(storage);
+        let mut injector = FaultInjector::new(camera, scenario);
+        let id = injector.backend().id().clone();
+        injector.start_browsing(&[DeviceKind::Camera]).unwrap();
+        injector.open_session(&id).unwrap();
+        events(&mut injector);
+        let file = injector.camera_items(&id).unwrap()[1].id.clone();
+
+        injector.read_file(&id, &file, 0, 1).unwrap();
+        let events = events(&mut injector);
+        assert!(matches!(
+            events.as_slice(),
+            [Event::DataRead { .. }, Event::DeviceRemoved(_)]
+        ));
+        assert!(injector.is_disconnected(&id));
+        assert!(injector.devices().is_empty());
+        assert_eq!(
+            injector.read_file(&id, &file, 0, 1),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn truncated_bands_lose_data() {
+        let mut injector = scanner(FaultScenario::new(3).with_truncated_bands(Trigger::From(2)));
+        let id = injector.backend().id().clone();
+        injector.request_scan(&id).unwrap();
+        let complete: Vec<bool> = events(&mut injector)
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::ScannedBand { band, .. } => {
+                    Some(band.data.len() == band.bytes_per_row * band.data_num_rows)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            complete,
+            vec![true, false, false, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn scenarios_with_the_same_seed_inject_the_same_faults() {
+        let scenario = FaultScenario::new(42).with_failure(
+            Request::Configure,
+            Trigger::Probability(0.5),
+            ICReturnInvalidParam,
+        );
+        let outcomes = |scenario: FaultScenario| {
+            let mut injector = scanner(scenario);
+            let id = injector.backend().id().clone();
+            (0..32)
+                .map(|_| injector.configure(&id, &ScanSettings::new()).is_ok())
+                .collect::<Vec<bool>>()
+        };
+        let first = outcomes(scenario.clone());
+        assert_eq!(outcomes(scenario), first);
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::constants::ICEXIFOrientationType::{self, ICEXIFOrientation1};
+use crate::error::Result;
+use crate::image::Image;
+
+#[cfg(target_os = "macos")]
+use crate::scanner_functional_units::ICScannerFunctionalUnitDocumentFeeder;
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, NO};
+
+/// Side of a sheet a page was scanned from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SheetSide {
+    /// The front of the sheet. Every page of a simplex scan is a front.
+    Front,
+    /// The back of the sheet, scanned by a duplex feeder.
+    Back,
+}
+
+/// What a processor found on a page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageReport {
+    /// Name of the processor, such as `"blank page"`.
+    pub processor: &'static str,
+    /// Indicates whether the processor found what it looks for, such as a blank page.
+    pub detected: bool,
+    /// Confidence of the processor in `detected`, from 0 to 1.
+    pub confidence: f64,
+    /// The value the processor measured, such as the ink coverage.
+    pub value: Option<f64>,
+    /// Indicates whether the processor flagged the page for review.
+    pub flagged: bool,
+}
+
+/// A page of a document feeder scan.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeederPage {
+    image: Image,
+    index: usize,
+    sheet: usize,
+    side: SheetSide,
+    orientation: ICEXIFOrientationType,
+    reports: Vec<PageReport>,
+}
+
+impl FeederPage {
+    /// The scanned image.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// The mutable scanned image, for processors that correct it.
+    pub fn image_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
+
+    /// Consume the page and return its image.
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+    /// Position of the page in the order it was scanned, starting at 0. Dropped pages keep
+    /// their position.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Number of the sheet the page was scanned from, starting at 1.
+    pub fn sheet(&self) -> usize {
+        self.sheet
+    }
+
+    /// Side of the sheet the page was scanned from.
+    pub fn side(&self) -> SheetSide {
+        self.side
+    }
+
+    /// Orientation of the page, from the odd or even page orientation of the feeder.
+    pub fn orientation(&self) -> ICEXIFOrientationType {
+        self.orientation
+    }
+
+    /// Reports of the processors that looked at the page, in the order they ran.
+    pub fn reports(&self) -> &[PageReport] {
+        &self.reports
+    }
+
+    /// Add a report to the page.
+    pub fn add_report(&mut self, report: PageReport) {
+        self.reports.push(report);
+    }
+
+    /// Indicates whether a processor flagged the page.
+    pub fn is_flagged(&self) -> bool {
+        self.reports.iter().any(|report| report.flagged)
+    }
+}
+
+/// What to do with a page after processing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PageAction {
+    /// Keep the page and pass it to the next processor.
+    Keep,
+    /// Drop the page from the document.
+    Drop,
+}
+
+/// A step of the processing a feeder job applies to each scanned page.
+pub trait PageProcessor {
+    /// Process a page. Processors may change the image and add reports to the page.
+    fn process(&mut self, page: &mut FeederPage) -> Result<PageAction>;
+}
+
+/// Collects the pages of a document feeder scan and runs them through page processors, such
+/// as a `BlankPageDetector`, as they arrive.
+///
+/// With duplex scanning pages alternate between the front and the back of each sheet, starting
+/// with the front.
+pub struct FeederJob {
+    duplex: bool,
+    odd_page_orientation: ICEXIFOrientationType,
+    even_page_orientation: ICEXIFOrientationType,
+    processors: Vec<Box<dyn PageProcessor>>,
+    scanned: usize,
+    pages: Vec<FeederPage>,
+    dropped: Vec<FeederPage>,
+}
+
+impl FeederJob {
+    /// Create a job for a simplex or duplex scan.
+    pub fn new(duplex: bool) -> FeederJob {
+        FeederJob {
+            duplex,
+            odd_page_orientation: ICEXIFOrientation1,
+            even_page_orientation: ICEXIFOrientation1,
+            processors: Vec::new(),
+            scanned: 0,
+            pages: Vec::new(),
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Create a job for a scan with the duplex setting and page orientations of a document
+    /// feeder functional unit.
+    ///
+    /// # Safety
+    ///
+    /// `feeder` must be a valid `ICScannerFunctionalUnitDocumentFeeder` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn for_document_feeder(feeder: id) -> FeederJob {
+        // Unknown orientations are taken to be upright, as EXIF readers do.
+        FeederJob::new(feeder.duplexScanningEnabled() != NO).with_page_orientations(
+            feeder.oddPageOrientation().unwrap_or(ICEXIFOrientation1),
+            feeder.evenPageOrientation().unwrap_or(ICEXIFOrientation1),
+        )
+    }
+
+    /// Set the orientations of odd and even pages. The first page is odd.
+    pub fn with_page_orientations(
+        mut self,
+        odd_page_orientation: ICEXIFOrientationType,
+        even_page_orientation: ICEXIFOrientationType,
+    ) -> FeederJob {
+        self.odd_page_orientation = odd_page_orientation;
+        self.even_page_orientation = even_page_orientation;
+        self
+    }
+
+    /// Add a processor. Processors run in the order they are added, and a dropped page is not
+    /// passed to the processors after the one that dropped it.
+    pub fn with_processor<P: PageProcessor + 'static>(mut self, processor: P) -> FeederJob {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Indicates whether the job is a duplex scan.
+    pub fn is_duplex(&self) -> bool {
+        self.duplex
+    }
+
+    /// Number of pages scanned so far, including dropped pages.
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+
+    /// Add the next scanned page and run it through the processors.
+    /// Returns whether the page was kept. Fails if a processor fails, in which case the page is
+    /// neither kept nor dropped.
+    pub fn add_page(&mut self, image: Image) -> Result<PageAction> {
+        let index = self.scanned;
+        self.scanned += 1;
+        let mut page = FeederPage {
+            image,
+            index,
+            sheet: if self.duplex {
+                index / 2 + 1
+            } else {
+                index + 1
+            },
+            side: if self.duplex && index % 2 != 0 {
+                SheetSide::Back
+            } else {
+                SheetSide::Front
+            },
+            orientation: if index % 2 == 0 {
+                self.odd_page_orientation
+            } else {
+                self.even_page_orientation
+            },
+            reports: Vec::new(),
+        };
+        for processor in &mut self.processors {
+            if processor.process(&mut page)? == PageAction::Drop {
+                self.dropped.push(page);
+                return Ok(PageAction::Drop);
+            }
+        }
+        self.pages.push(page);
+        Ok(PageAction::Keep)
+    }
+
+    /// The pages kept so far.
+    pub fn pages(&self) -> &[FeederPage] {
+        &self.pages
+    }
+
+    /// The pages dropped so far.
+    pub fn dropped_pages(&self) -> &[FeederPage] {
+        &self.dropped
+    }
+
+    /// Finish the job and return the kept pages.
+    pub fn finish(self) -> Vec<FeederPage> {
+        self.pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blank_page::{BlankPageAction, BlankPageDetector};
+    use crate::constants::ICEXIFOrientationType::ICEXIFOrientation3;
+    use crate::image::PixelFormat;
+
+    /// A white page, with a black square if it has `text`.
+    fn page(text: bool) -> Image {
+        let mut image = Image::new(PixelFormat::Gray8, 40, 40);
+        for y in 0..40 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                let ink = text && (10..20).contains(&x) && (10..20).contains(&y);
+                *pixel = if ink { 0 } else { 255 };
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn blank_backs_are_dropped_from_duplex_jobs() {
+        let mut job = FeederJob::new(true)
+            .with_page_orientations(ICEXIFOrientation1, ICEXIFOrientation3)
+            .with_processor(BlankPageDetector::new());
+        for &text in &[true, false, true, true] {
+            let expected = if text {
+                PageAction::Keep
+            } else {
+                PageAction::Drop
+            };
+            assert_eq!(job.add_page(page(text)), Ok(expected));
+        }
+        assert_eq!(job.scanned(), 4);
+        let dropped = job.dropped_pages();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(
+            (dropped[0].sheet(), dropped[0].side()),
+            (1, SheetSide::Back)
+        );
+        let pages = job.finish();
+        let sides: Vec<(usize, usize, SheetSide, ICEXIFOrientationType)> = pages
+            .iter()
+            .map(|page| (page.index(), page.sheet(), page.side(), page.orientation()))
+            .collect();
+        assert_eq!(
+            sides,
+            [
+                (0, 1, SheetSide::Front, ICEXIFOrientation1),
+                (2, 2, SheetSide::Front, ICEXIFOrientation1),
+                (3, 2, SheetSide::Back, ICEXIFOrientation3),
+            ]
+        );
+        assert_eq!(pages[0].reports()[0].processor, "blank page");
+        assert!(!pages[0].is_flagged());
+    }
+
+    #[test]
+    fn flagged_pages_are_kept() {
+        let mut job = FeederJob::new(false)
+            .with_processor(BlankPageDetector::new().with_action(BlankPageAction::Flag));
+        assert_eq!(job.add_page(page(false)), Ok(PageAction::Keep));
+        let pages = job.finish();
+        assert_eq!((pages[0].sheet(), pages[0].side()), (1, SheetSide::Front));
+        assert!(pages[0].is_flagged());
+        assert!(pages[0].reports()[0].detected);
+    }
+}
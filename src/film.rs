@@ -0,0 +1,453 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerFunctionalUnitType::{
+    self, ICScannerFunctionalUnitTypeNegativeTransparency,
+    ICScannerFunctionalUnitTypePositiveTransparency,
+};
+use crate::error::{Error, Result};
+use crate::image::{Image, PixelFormat};
+
+#[cfg(target_os = "macos")]
+use crate::scanner_functional_units::ICScannerFunctionalUnit;
+#[cfg(target_os = "macos")]
+use cocoa::base::id;
+
+/// The kind of film on a transparency unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FilmType {
+    /// Colour negative film, with an orange mask in the film base.
+    ColorNegative,
+    /// Black and white negative film.
+    BlackAndWhiteNegative,
+    /// Slide or other positive film.
+    Positive,
+}
+
+impl FilmType {
+    /// The film scanned by a functional unit of `functional_unit_type`, or `None` for units that
+    /// do not scan film. Negative transparency units are taken to hold colour negatives.
+    pub fn for_functional_unit_type(
+        functional_unit_type: ICScannerFunctionalUnitType,
+    ) -> Option<FilmType> {
+        match functional_unit_type {
+            ICScannerFunctionalUnitTypeNegativeTransparency => Some(FilmType::ColorNegative),
+            ICScannerFunctionalUnitTypePositiveTransparency => Some(FilmType::Positive),
+            _ => None,
+        }
+    }
+
+    /// Indicates whether the film is a negative.
+    pub fn is_negative(self) -> bool {
+        self != FilmType::Positive
+    }
+}
+
+/// The colour of the unexposed film base of a negative, which holds the orange mask of colour
+/// negatives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilmBase {
+    /// Estimate the film base from the brightest parts of the negative, which are its least
+    /// exposed areas. This works best on scans cropped to the frame, without sprocket holes or
+    /// other areas outside the film.
+    Auto,
+    /// The red, green and blue values of the film base, from 0 to 1, as measured on a scan of the
+    /// film between frames.
+    Sample([f64; 3]),
+}
+
+impl FilmBase {
+    /// Measure the film base as the mean colour of a `width` by `height` rectangle of a negative
+    /// at `x`, `y`, such as an unexposed strip between frames.
+    /// Fails if the rectangle is empty or outside the image, or the image is bilevel.
+    pub fn sample(
+        image: &Image,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<FilmBase> {
+        let format = image.format();
+        if format == PixelFormat::Bilevel
+            || width == 0
+            || height == 0
+            || x + width > image.width()
+            || y + height > image.height()
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let channels = channels(format);
+        let max = max_value(format) as f64;
+        let mut sums = [0.0; 3];
+        for row in y..y + height {
+            for column in x..x + width {
+                for (channel, sum) in sums.iter_mut().enumerate().take(channels) {
+                    *sum += f64::from(component(image, column, row, channel));
+                }
+            }
+        }
+        let count = (width * height) as f64;
+        let mut color = [0.0; 3];
+        for channel in 0..3 {
+            color[channel] = sums[channel.min(channels - 1)] / count / max;
+        }
+        Ok(FilmBase::Sample(color))
+    }
+}
+
+/// Levels applied to a channel: input values up to `black` become black, values from `white`
+/// become white, and the values in between are stretched and then raised to `1 / gamma`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Levels {
+    /// Input value, from 0 to 1, that becomes black.
+    pub black: f64,
+    /// Input value, from 0 to 1, that becomes white.
+    pub white: f64,
+    /// Gamma applied to the midtones. Values above 1 brighten them.
+    pub gamma: f64,
+}
+
+impl Default for Levels {
+    fn default() -> Levels {
+        Levels::new(0.0, 1.0, 1.0)
+    }
+}
+
+impl Levels {
+    /// Create levels.
+    pub fn new(black: f64, white: f64, gamma: f64) -> Levels {
+        Levels {
+            black,
+            white,
+            gamma,
+        }
+    }
+
+    /// Apply the levels to a value from 0 to 1.
+    pub fn apply(&self, value: f64) -> f64 {
+        let range = self.white - self.black;
+        let stretched = if range > 0.0 {
+            ((value - self.black) / range).clamp(0.0, 1.0)
+        } else if value >= self.white {
+            1.0
+        } else {
+            0.0
+        };
+        if self.gamma > 0.0 {
+            stretched.powf(1.0 / self.gamma)
+        } else {
+            stretched
+        }
+    }
+}
+
+/// Turns scans of film into positive images.
+///
+/// Negatives are converted to densities above the film base, which removes the orange mask of
+/// colour negatives, and the densities become the brightness of the positive. The darkest and
+/// brightest values of each channel are then stretched to the full range, which also balances
+/// the different contrasts of the colour layers. Positives only have their darkest and brightest
+/// values stretched, by the same amount for all channels to keep their colours.
+///
+/// Each channel is mapped through a lookup table computed at the full precision of the scan, so
+/// 16 bit scans keep all their levels and only the clipped fraction of the values at either end
+/// of the range is lost. Per-channel levels are applied last. Alpha is left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilmProcessor {
+    film_type: FilmType,
+    film_base: FilmBase,
+    clip: f64,
+    levels: [Levels; 3],
+}
+
+impl FilmProcessor {
+    /// Create a processor for `film_type`, with an automatic film base, 0.1% of the values
+    /// clipped at each end of the range, and no levels.
+    pub fn new(film_type: FilmType) -> FilmProcessor {
+        FilmProcessor {
+            film_type,
+            film_base: FilmBase::Auto,
+            clip: 0.001,
+            levels: [Levels::default(); 3],
+        }
+    }
+
+    /// Create a processor for the film scanned by a functional unit, or `None` if the unit does
+    /// not scan film.
+    ///
+    /// # Safety
+    ///
+    /// `functional_unit` must be a valid `ICScannerFunctionalUnit` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn for_functional_unit(functional_unit: id) -> Option<FilmProcessor> {
+        let unit_type = functional_unit.type_().ok()?;
+        FilmType::for_functional_unit_type(unit_type).map(FilmProcessor::new)
+    }
+
+    /// Set the film base of negatives.
+    pub fn with_film_base(mut self, film_base: FilmBase) -> FilmProcessor {
+        self.film_base = film_base;
+        self
+    }
+
+    /// Set the fraction of values, from 0 to 0.5, clipped at each end of the range when it is
+    /// stretched.
+    pub fn with_clip(mut self, fraction: f64) -> FilmProcessor {
+        self.clip = fraction.clamp(0.0, 0.5);
+        self
+    }
+
+    /// Set the levels of all channels.
+    pub fn with_levels(mut self, levels: Levels) -> FilmProcessor {
+        self.levels = [levels; 3];
+        self
+    }
+
+    /// Set the levels of the red, green and blue channels. Gray images use the red levels.
+    pub fn with_channel_levels(
+        mut self,
+        red: Levels,
+        green: Levels,
+        blue: Levels,
+    ) -> FilmProcessor {
+        self.levels = [red, green, blue];
+        self
+    }
+
+    /// The film type.
+    pub fn film_type(&self) -> FilmType {
+        self.film_type
+    }
+
+    /// The red, green and blue values of the film base, from 0 to 1, for a scan of a negative.
+    /// With `FilmBase::Auto` this is the brightest value of each channel, ignoring the clipped
+    /// fraction of values. The channels of black and white negatives are averaged.
+    /// Fails if the image is bilevel.
+    pub fn film_base(&self, image: &Image) -> Result<[f64; 3]> {
+        let histograms = histograms(image)?;
+        Ok(self.base(image.format(), &histograms))
+    }
+
+    /// Turn a scan of film into a positive image in the same pixel format.
+    /// Fails if the image is bilevel.
+    pub fn process(&self, image: &Image) -> Result<Image> {
+        let format = image.format();
+        let histograms = histograms(image)?;
+        let max = max_value(format);
+        let channels = channels(format);
+
+        // The value of each input level before it is stretched, increasing with brightness.
+        let base = self.base(format, &histograms);
+        let transfer = |channel: usize, value: usize| -> f64 {
+            if self.film_type.is_negative() {
+                let value = (value as f64).max(0.5) / max as f64;
+                (base[channel] / value).log10().max(0.0)
+            } else {
+                value as f64 / max as f64
+            }
+        };
+        let mut ranges: Vec<(f64, f64)> = (0..channels)
+            .map(|channel| {
+                let (low, high) = percentiles(&histograms[channel], self.clip);
+                let (a, b) = (transfer(channel, low), transfer(channel, high));
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        if self.film_type != FilmType::ColorNegative {
+            let low = ranges.iter().map(|range| range.0).fold(f64::MAX, f64::min);
+            let high = ranges.iter().map(|range| range.1).fold(f64::MIN, f64::max);
+            ranges.iter_mut().for_each(|range| *range = (low, high));
+        }
+
+        let tables: Vec<Vec<u16>> = ranges
+            .iter()
+            .enumerate()
+            .map(|(channel, &(low, high))| {
+                let stretch = Levels::new(low, high, 1.0);
+                (0..=max)
+                    .map(|value| {
+                        let stretched = stretch.apply(transfer(channel, value));
+                        let leveled = self.levels[channel].apply(stretched);
+                        (leveled * max as f64).round() as u16
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut positive = image.clone();
+        let components = format.components();
+        let wide = format.bits_per_component() == 16;
+        for y in 0..image.height() {
+            let row = positive.row_mut(y);
+            for x in 0..image.width() {
+                for (channel, table) in tables.iter().enumerate() {
+                    let index = x * components + channel;
+                    if wide {
+                        let value = u16::from_be_bytes([row[2 * index], row[2 * index + 1]]);
+                        row[2 * index..2 * index + 2]
+                            .copy_from_slice(&table[usize::from(value)].to_be_bytes());
+                    } else {
+                        row[index] = table[usize::from(row[index])] as u8;
+                    }
+                }
+            }
+        }
+        Ok(positive)
+    }
+
+    /// The film base from a sample, or estimated from the histograms of a negative.
+    fn base(&self, format: PixelFormat, histograms: &[Vec<u64>]) -> [f64; 3] {
+        let max = max_value(format) as f64;
+        let mut base = match self.film_base {
+            FilmBase::Sample(color) => color,
+            FilmBase::Auto => {
+                let mut color = [0.0; 3];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    let histogram = &histograms[channel.min(histograms.len() - 1)];
+                    *value = percentiles(histogram, self.clip).1 as f64 / max;
+                }
+                color
+            }
+        };
+        if self.film_type == FilmType::BlackAndWhiteNegative || !format.is_color() {
+            let mean = base.iter().sum::<f64>() / 3.0;
+            base = [mean; 3];
+        }
+        base
+    }
+}
+
+/// Number of channels processed: the colour components, without alpha.
+fn channels(format: PixelFormat) -> usize {
+    if format.is_color() {
+        3
+    } else {
+        1
+    }
+}
+
+/// The largest value of a component.
+fn max_value(format: PixelFormat) -> usize {
+    (1 << format.bits_per_component()) - 1
+}
+
+/// The value of a channel of a pixel.
+fn component(image: &Image, x: usize, y: usize, channel: usize) -> u16 {
+    let format = image.format();
+    let index = x * format.components() + channel;
+    let row = image.row(y);
+    if format.bits_per_component() == 16 {
+        u16::from_be_bytes([row[2 * index], row[2 * index + 1]])
+    } else {
+        u16::from(row[index])
+    }
+}
+
+/// The number of pixels with each value of each channel. Fails for bilevel images.
+fn histograms(image: &Image) -> Result<Vec<Vec<u64>>> {
+    let format = image.format();
+    if format == PixelFormat::Bilevel {
+        return Err(Error::ReturnCode(ICReturnInvalidParam));
+    }
+    let mut histograms = vec![vec![0u64; max_value(format) + 1]; channels(format)];
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            for (channel, histogram) in histograms.iter_mut().enumerate() {
+                histogram[usize::from(component(image, x, y, channel))] += 1;
+            }
+        }
+    }
+    Ok(histograms)
+}
+
+/// The lowest and highest value of a histogram after leaving out `clip` of the values at each
+/// end.
+fn percentiles(histogram: &[u64], clip: f64) -> (usize, usize) {
+    let total: u64 = histogram.iter().sum();
+    let skip = (total as f64 * clip).floor() as u64;
+    let find = |values: &mut dyn Iterator<Item = (usize, &u64)>| {
+        let mut seen = 0;
+        for (value, &count) in values {
+            seen += count;
+            if seen > skip {
+                return Some(value);
+            }
+        }
+        None
+    };
+    let low = find(&mut histogram.iter().enumerate()).unwrap_or(0);
+    let high = find(&mut histogram.iter().enumerate().rev()).unwrap_or(histogram.len() - 1);
+    (low, high.max(low))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one row image of the given RGB pixels.
+    fn rgb(pixels: &[[u8; 3]]) -> Image {
+        let mut image = Image::new(PixelFormat::Rgb8, pixels.len(), 1);
+        for (pixel, value) in image.row_mut(0).chunks_mut(3).zip(pixels) {
+            pixel.copy_from_slice(value);
+        }
+        image
+    }
+
+    #[test]
+    fn color_negatives_lose_their_mask() {
+        // The orange film base, and the base at densities of 0.5 and 1 above it.
+        let negative = rgb(&[[230, 150, 90], [73, 47, 28], [23, 15, 9]]);
+        let base = [230.0 / 255.0, 150.0 / 255.0, 90.0 / 255.0];
+        assert_eq!(
+            FilmBase::sample(&negative, 0, 0, 1, 1),
+            Ok(FilmBase::Sample(base))
+        );
+        let processor = FilmProcessor::new(FilmType::ColorNegative).with_clip(0.0);
+        assert_eq!(processor.film_base(&negative), Ok(base));
+
+        let positive = processor.process(&negative).unwrap();
+        let row = positive.row(0);
+        assert_eq!(&row[0..3], &[0, 0, 0]);
+        assert_eq!(&row[6..9], &[255, 255, 255]);
+        // Equal densities give a neutral gray.
+        for &value in &row[3..6] {
+            assert!((125..=131).contains(&value), "{:?}", row);
+        }
+
+        let sampled = processor.with_film_base(FilmBase::Sample(base));
+        assert_eq!(sampled.process(&negative).unwrap(), positive);
+    }
+
+    #[test]
+    fn black_and_white_negatives_are_inverted() {
+        let mut negative = Image::new(PixelFormat::Gray8, 3, 1);
+        negative.row_mut(0).copy_from_slice(&[200, 63, 20]);
+        let processor = FilmProcessor::new(FilmType::BlackAndWhiteNegative).with_clip(0.0);
+        assert_eq!(processor.film_base(&negative), Ok([200.0 / 255.0; 3]));
+        let positive = processor.process(&negative).unwrap();
+        assert_eq!(positive.row(0), &[0, 128, 255]);
+
+        // Brightening the midtones.
+        let brighter = processor.with_levels(Levels::new(0.0, 1.0, 2.0));
+        assert_eq!(brighter.process(&negative).unwrap().row(0), &[0, 181, 255]);
+    }
+
+    #[test]
+    fn positives_keep_their_colors() {
+        let slide = rgb(&[[50, 100, 150], [100, 150, 200]]);
+        let processor = FilmProcessor::new(FilmType::Positive).with_clip(0.0);
+        let positive = processor.process(&slide).unwrap();
+        assert_eq!(positive.row(0), &[0, 85, 170, 85, 170, 255]);
+        assert_eq!(
+            processor.process(&Image::new(PixelFormat::Bilevel, 8, 1)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn levels_stretch_and_brighten() {
+        let levels = Levels::new(0.2, 0.8, 1.0);
+        assert_eq!(levels.apply(0.1), 0.0);
+        assert!((levels.apply(0.5) - 0.5).abs() < 1e-12);
+        assert_eq!(levels.apply(0.9), 1.0);
+        assert_eq!(Levels::new(0.0, 1.0, 2.0).apply(0.25), 0.5);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::Path;
+
+/// Size of the ICC profile header.
+const HEADER_SIZE: usize = 128;
+
+/// Colour spaces of the data an ICC profile describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IccColorSpace {
+    /// Gray, one component.
+    Gray,
+    /// RGB, three components.
+    Rgb,
+    /// CMYK, four components.
+    Cmyk,
+    /// Any other colour space, with its signature.
+    Other([u8; 4]),
+}
+
+impl IccColorSpace {
+    /// Number of components, if the colour space is one of the known ones.
+    pub fn components(self) -> Option<usize> {
+        match self {
+            IccColorSpace::Gray => Some(1),
+            IccColorSpace::Rgb => Some(3),
+            IccColorSpace::Cmyk => Some(4),
+            IccColorSpace::Other(_) => None,
+        }
+    }
+}
+
+/// An ICC colour profile, such as the profile ColorSync reports for scanner band data.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IccProfile {
+    data: Vec<u8>,
+}
+
+impl IccProfile {
+    /// Wrap the bytes of a profile. Fails if they do not start with a valid ICC profile header.
+    pub fn from_bytes(data: Vec<u8>) -> Result<IccProfile> {
+        let valid = data.len() >= HEADER_SIZE + 4
+            && &data[36..40] == b"acsp"
+            && read_u32(&data, 0) as usize <= data.len();
+        if !valid {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok(IccProfile { data })
+    }
+
+    /// Read a profile from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<IccProfile> {
+        IccProfile::from_bytes(fs::read(path)?)
+    }
+
+    /// The profile of a scanner band, read from `color_sync_profile_path`, or the sRGB profile if
+    /// the band has none.
+    pub fn for_band(band: &ScannerBand) -> Result<IccProfile> {
+        match band.color_sync_profile_path {
+            Some(ref path) => IccProfile::from_file(path),
+            None => Ok(IccProfile::srgb()),
+        }
+    }
+
+    /// An sRGB profile (IEC 61966-2-1) with a sampled tone curve.
+    pub fn srgb() -> IccProfile {
+        IccProfile {
+            data: srgb_profile(),
+        }
+    }
+
+    /// The bytes of the profile.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consume the profile and return its bytes.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The colour space of the data the profile describes.
+    pub fn color_space(&self) -> IccColorSpace {
+        match &self.data[16..20] {
+            b"GRAY" => IccColorSpace::Gray,
+            b"RGB " => IccColorSpace::Rgb,
+            b"CMYK" => IccColorSpace::Cmyk,
+            other => IccColorSpace::Other([other[0], other[1], other[2], other[3]]),
+        }
+    }
+
+    /// The major and minor version of the profile format.
+    pub fn version(&self) -> (u8, u8) {
+        (self.data[8], self.data[9] >> 4)
+    }
+
+    /// The ASCII description of the profile, if it has a version 2 `desc` tag.
+    pub fn description(&self) -> Option<String> {
+        let count = read_u32(&self.data, HEADER_SIZE) as usize;
+        (0..count)
+            .map(|i| HEADER_SIZE + 4 + 12 * i)
+            .take_while(|&entry| entry + 12 <= self.data.len())
+            .find(|&entry| &self.data[entry..entry + 4] == b"desc")
+            .and_then(|entry| {
+                let offset = read_u32(&self.data, entry + 4) as usize;
+                let tag = self.data.get(offset..offset + 12)?;
+                if &tag[..4] != b"desc" {
+                    return None;
+                }
+                let length = read_u32(tag, 8) as usize;
+                let text = self.data.get(offset + 12..offset + 12 + length)?;
+                let text = text.split(|&byte| byte == 0).next()?;
+                Some(String::from_utf8_lossy(text).into_owned())
+            })
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Build a version 2.1 sRGB display profile.
+fn srgb_profile() -> Vec<u8> {
+    fn s15_fixed16(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for &value in &[x, y, z] {
+            tag.extend_from_slice(&s15_fixed16(value));
+        }
+        tag
+    }
+
+    let description = b"sRGB IEC61966-2.1\0";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(description.len() as u32).to_be_bytes());
+    desc.extend_from_slice(description);
+    // No Unicode or ScriptCode descriptions.
+    desc.extend_from_slice(&[0; 8]);
+    desc.extend_from_slice(&[0; 3]);
+    desc.extend_from_slice(&[0; 67]);
+
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend_from_slice(b"No copyright, use freely\0");
+
+    const CURVE_POINTS: usize = 1024;
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend_from_slice(&(CURVE_POINTS as u32).to_be_bytes());
+    for i in 0..CURVE_POINTS {
+        let encoded = i as f64 / (CURVE_POINTS - 1) as f64;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        curve.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    // Colorants adapted to the D50 profile connection space.
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", desc),
+        (b"cprt", cprt),
+        (b"wtpt", xyz(0.964_203, 1.0, 0.824_905)),
+        (b"rXYZ", xyz(0.436_066, 0.222_488, 0.013_916)),
+        (b"gXYZ", xyz(0.385_147, 0.716_873, 0.097_076)),
+        (b"bXYZ", xyz(0.143_066, 0.060_608, 0.714_096)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let mut offset = HEADER_SIZE + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend_from_slice(&signature[..]);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+        // Tag data starts on a four byte boundary.
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        offset = HEADER_SIZE + 4 + 12 * tags.len() + data.len();
+    }
+
+    let mut header = vec![0; HEADER_SIZE];
+    let size = (HEADER_SIZE + table.len() + data.len()) as u32;
+    header[0..4].copy_from_slice(&size.to_be_bytes());
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    // Creation date: 2019-01-01 00:00:00.
+    header[24..30].copy_from_slice(&[0x07, 0xe3, 0, 1, 0, 1]);
+    header[36..40].copy_from_slice(b"acsp");
+    header[68..72].copy_from_slice(&s15_fixed16(0.964_2));
+    header[72..76].copy_from_slice(&s15_fixed16(1.0));
+    header[76..80].copy_from_slice(&s15_fixed16(0.824_9));
+
+    let mut profile = header;
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
@@ -0,0 +1,327 @@
+use crate::constants::ICEXIFOrientationType;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+
+/// An in-memory raster image.
+///
+/// Pixels are stored row by row, starting with the top row. Pixels narrower than a byte are packed
+/// with the leftmost pixel in the most significant bits, as in scanner band data. Rows may be
+/// padded, so `bytes_per_row` can be larger than the number of bytes the pixels of a row occupy.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageBuffer {
+    width: usize,
+    height: usize,
+    bits_per_pixel: usize,
+    bytes_per_row: usize,
+    data: Vec<u8>,
+}
+
+/// Number of bytes needed to hold `width` pixels of `bits_per_pixel` bits.
+pub fn min_bytes_per_row(width: usize, bits_per_pixel: usize) -> usize {
+    (width * bits_per_pixel + 7) / 8
+}
+
+pub(crate) fn valid_bits_per_pixel(bits_per_pixel: usize) -> bool {
+    match bits_per_pixel {
+        1 | 2 | 4 => true,
+        bits => bits > 0 && bits % 8 == 0,
+    }
+}
+
+impl ImageBuffer {
+    /// Create an image with all bits set to zero and no row padding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_pixel` is not 1, 2, 4 or a non-zero multiple of 8.
+    pub fn new(width: usize, height: usize, bits_per_pixel: usize) -> ImageBuffer {
+        assert!(
+            valid_bits_per_pixel(bits_per_pixel),
+            "unsupported bits per pixel: {}",
+            bits_per_pixel
+        );
+        let bytes_per_row = min_bytes_per_row(width, bits_per_pixel);
+        ImageBuffer {
+            width,
+            height,
+            bits_per_pixel,
+            bytes_per_row,
+            data: vec![0; bytes_per_row * height],
+        }
+    }
+
+    /// Create an image from existing pixel data.
+    /// Fails if `bits_per_pixel` is unsupported, if `bytes_per_row` cannot hold a row of pixels,
+    /// or if `data` is shorter than `bytes_per_row * height`.
+    pub fn from_data(
+        width: usize,
+        height: usize,
+        bits_per_pixel: usize,
+        bytes_per_row: usize,
+        mut data: Vec<u8>,
+    ) -> Result<ImageBuffer> {
+        if !valid_bits_per_pixel(bits_per_pixel)
+            || bytes_per_row < min_bytes_per_row(width, bits_per_pixel)
+            || data.len() < bytes_per_row * height
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        data.truncate(bytes_per_row * height);
+        Ok(ImageBuffer {
+            width,
+            height,
+            bits_per_pixel,
+            bytes_per_row,
+            data,
+        })
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of bits per pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bits_per_pixel
+    }
+
+    /// Number of bytes per row, including padding.
+    pub fn bytes_per_row(&self) -> usize {
+        self.bytes_per_row
+    }
+
+    /// The pixel data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The mutable pixel data.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Consume the image and return its pixel data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The bytes of row `y`, without padding.
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.bytes_per_row;
+        &self.data[start..start + min_bytes_per_row(self.width, self.bits_per_pixel)]
+    }
+
+    /// The mutable bytes of row `y`, without padding.
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.bytes_per_row;
+        let len = min_bytes_per_row(self.width, self.bits_per_pixel);
+        &mut self.data[start..start + len]
+    }
+
+    /// A copy of the image without row padding.
+    pub fn to_packed(&self) -> ImageBuffer {
+        let mut packed = ImageBuffer::new(self.width, self.height, self.bits_per_pixel);
+        for y in 0..self.height {
+            packed.row_mut(y).copy_from_slice(self.row(y));
+        }
+        packed
+    }
+
+    /// The value of a pixel narrower than a byte.
+    pub(crate) fn packed_pixel(&self, x: usize, y: usize) -> u8 {
+        let bit = x * self.bits_per_pixel;
+        let byte = self.data[y * self.bytes_per_row + bit / 8];
+        let shift = 8 - self.bits_per_pixel - bit % 8;
+        (byte >> shift) & ((1 << self.bits_per_pixel) - 1)
+    }
+
+    /// Set the value of a pixel narrower than a byte.
+    pub(crate) fn set_packed_pixel(&mut self, x: usize, y: usize, value: u8) {
+        let bit = x * self.bits_per_pixel;
+        let index = y * self.bytes_per_row + bit / 8;
+        let shift = 8 - self.bits_per_pixel - bit % 8;
+        let mask = ((1u8 << self.bits_per_pixel) - 1) << shift;
+        self.data[index] = (self.data[index] & !mask) | ((value << shift) & mask);
+    }
+
+    /// Copy pixel `(sx, sy)` of `source` to pixel `(x, y)` of this image.
+    /// Both images must have the same number of bits per pixel.
+    pub(crate) fn copy_pixel(
+        &mut self,
+        x: usize,
+        y: usize,
+        source: &ImageBuffer,
+        sx: usize,
+        sy: usize,
+    ) {
+        if self.bits_per_pixel < 8 {
+            let value = source.packed_pixel(sx, sy);
+            self.set_packed_pixel(x, y, value);
+        } else {
+            let bytes = self.bits_per_pixel / 8;
+            let from = sy * source.bytes_per_row + sx * bytes;
+            let to = y * self.bytes_per_row + x * bytes;
+            self.data[to..to + bytes].copy_from_slice(&source.data[from..from + bytes]);
+        }
+    }
+}
+
+/// Canonical pixel formats that scanner data is normalised to.
+///
+/// Samples of 16 bit formats are stored big endian. Bilevel pixels are packed eight to a byte,
+/// leftmost pixel in the most significant bit, with 0 for black and 1 for white.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// 1 bit per pixel, 0 is black and 1 is white.
+    Bilevel,
+    /// 8 bit gray.
+    Gray8,
+    /// 16 bit gray.
+    Gray16,
+    /// 8 bits per component RGB.
+    Rgb8,
+    /// 16 bits per component RGB.
+    Rgb16,
+    /// 8 bits per component RGB with alpha.
+    Rgba8,
+}
+
+impl PixelFormat {
+    /// Number of bits per pixel.
+    pub fn bits_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Bilevel => 1,
+            PixelFormat::Gray8 => 8,
+            PixelFormat::Gray16 => 16,
+            PixelFormat::Rgb8 => 24,
+            PixelFormat::Rgb16 => 48,
+            PixelFormat::Rgba8 => 32,
+        }
+    }
+
+    /// Number of bits per component.
+    pub fn bits_per_component(self) -> usize {
+        match self {
+            PixelFormat::Bilevel => 1,
+            PixelFormat::Gray8 | PixelFormat::Rgb8 | PixelFormat::Rgba8 => 8,
+            PixelFormat::Gray16 | PixelFormat::Rgb16 => 16,
+        }
+    }
+
+    /// Number of components per pixel.
+    pub fn components(self) -> usize {
+        match self {
+            PixelFormat::Bilevel | PixelFormat::Gray8 | PixelFormat::Gray16 => 1,
+            PixelFormat::Rgb8 | PixelFormat::Rgb16 => 3,
+            PixelFormat::Rgba8 => 4,
+        }
+    }
+
+    /// Indicates whether the format has colour components.
+    pub fn is_color(self) -> bool {
+        self.components() > 1
+    }
+}
+
+/// An image in one of the canonical pixel formats.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Image {
+    format: PixelFormat,
+    buffer: ImageBuffer,
+}
+
+impl Image {
+    /// Create an image with all bits set to zero.
+    pub fn new(format: PixelFormat, width: usize, height: usize) -> Image {
+        Image {
+            format,
+            buffer: ImageBuffer::new(width, height, format.bits_per_pixel()),
+        }
+    }
+
+    /// Wrap a buffer holding pixels of `format`.
+    /// Fails if the bits per pixel of the buffer do not match the format.
+    pub fn from_buffer(format: PixelFormat, buffer: ImageBuffer) -> Result<Image> {
+        if buffer.bits_per_pixel() != format.bits_per_pixel() {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok(Image { format, buffer })
+    }
+
+    /// The pixel format.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.buffer.width()
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.buffer.height()
+    }
+
+    /// The pixel buffer.
+    pub fn buffer(&self) -> &ImageBuffer {
+        &self.buffer
+    }
+
+    /// The mutable pixel buffer.
+    pub fn buffer_mut(&mut self) -> &mut ImageBuffer {
+        &mut self.buffer
+    }
+
+    /// Consume the image and return its pixel buffer.
+    pub fn into_buffer(self) -> ImageBuffer {
+        self.buffer
+    }
+
+    /// The bytes of row `y`, without padding.
+    pub fn row(&self, y: usize) -> &[u8] {
+        self.buffer.row(y)
+    }
+
+    /// The mutable bytes of row `y`, without padding.
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        self.buffer.row_mut(y)
+    }
+
+    /// The image turned upright for an image stored with `orientation`.
+    pub fn oriented(&self, orientation: ICEXIFOrientationType) -> Image {
+        Image {
+            format: self.format,
+            buffer: orientation.apply(&self.buffer),
+        }
+    }
+
+    /// The BT.601 luma of the pixel at `x`, `y`, from 0 for black to 255 for white.
+    /// The low byte of 16 bit samples and alpha are ignored.
+    pub fn luma(&self, x: usize, y: usize) -> u8 {
+        let row = self.row(y);
+        let weighted = |r: u8, g: u8, b: u8| {
+            ((299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b) + 500) / 1000) as u8
+        };
+        match self.format {
+            PixelFormat::Bilevel => {
+                if row[x / 8] & (0x80 >> (x % 8)) == 0 {
+                    0
+                } else {
+                    255
+                }
+            }
+            PixelFormat::Gray8 => row[x],
+            PixelFormat::Gray16 => row[2 * x],
+            PixelFormat::Rgb8 => weighted(row[3 * x], row[3 * x + 1], row[3 * x + 2]),
+            PixelFormat::Rgb16 => weighted(row[6 * x], row[6 * x + 2], row[6 * x + 4]),
+            PixelFormat::Rgba8 => weighted(row[4 * x], row[4 * x + 1], row[4 * x + 2]),
+        }
+    }
+}
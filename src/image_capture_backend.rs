@@ -0,0 +1,1421 @@
+use crate::backend::{
+    Backend, CameraItem, CameraStatus, DeviceId, DeviceInfo, DeviceKind, DownloadOptions, Event,
+    FeatureKind, FeatureValue, FunctionalUnit, ItemId, ScanSettings, ScannerFeature,
+};
+use crate::band_assembler::ScannerBand;
+use crate::camera_device::{
+    ICCameraDevice, ICDeleteAfterSuccessfulDownload, ICDownloadSidecarFiles,
+    ICDownloadsDirectoryURL, ICOverwrite, ICSaveAsFilename, ICSavedFilename,
+};
+use crate::camera_item::{ICCameraFile, ICCameraItem};
+use crate::constants::ICEXIFOrientationType::ICEXIFOrientation1;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerFeatureType::{
+    ICScannerFeatureTypeBoolean, ICScannerFeatureTypeEnumeration, ICScannerFeatureTypeRange,
+};
+use crate::constants::ICScannerFunctionalUnitType::{
+    self, ICScannerFunctionalUnitTypeDocumentFeeder,
+};
+use crate::constants::{ICScannerBitDepth, ICScannerDocumentType};
+use crate::device::{
+    ICDevice, ICDeviceLocationTypeMask, ICDeviceType, ICDeviceTypeMask, ICStatusNotificationKey,
+};
+use crate::device_browser::ICDeviceBrowser;
+use crate::error::{Error, Result};
+use crate::image::{Image, PixelFormat};
+use crate::measurement::ScanRect;
+use crate::scanner_device::ICScannerDevice;
+use crate::scanner_functional_units::{
+    ICScannerFeature, ICScannerFeatureBoolean, ICScannerFeatureEnumeration, ICScannerFeatureRange,
+    ICScannerFunctionalUnit, ICScannerFunctionalUnitDocumentFeeder, ICScannerFunctionalUnitFlatbed,
+};
+use cocoa::base::{id, nil, BOOL, NO, YES};
+use cocoa::foundation::{NSRect, NSUInteger};
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::*;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Name of the Objective-C class that receives the delegate messages of the framework.
+const DELEGATE_CLASS: &str = "ImageCaptureCoreRsBackendDelegate";
+
+/// Name of the instance variable of the delegate that points to the shared state.
+const SHARED_IVAR: &str = "rustShared";
+
+/// `NSNotFound`, returned by `NSIndexSet` when there are no more indexes.
+const NS_NOT_FOUND: NSUInteger = i64::MAX as NSUInteger;
+
+/// `kCGImageAlphaNoneSkipLast`: RGB pixels followed by an unused byte.
+const CG_IMAGE_ALPHA_NONE_SKIP_LAST: u32 = 5;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGImageGetWidth(image: *mut c_void) -> usize;
+    fn CGImageGetHeight(image: *mut c_void) -> usize;
+    fn CGColorSpaceCreateDeviceRGB() -> *mut c_void;
+    fn CGColorSpaceRelease(space: *mut c_void);
+    fn CGBitmapContextCreate(
+        data: *mut c_void,
+        width: usize,
+        height: usize,
+        bits_per_component: usize,
+        bytes_per_row: usize,
+        space: *mut c_void,
+        bitmap_info: u32,
+    ) -> *mut c_void;
+    fn CGContextDrawImage(context: *mut c_void, rect: NSRect, image: *mut c_void);
+    fn CGContextRelease(context: *mut c_void);
+}
+
+/// State shared between the backend and the delegate, which the framework calls while the run
+/// loop runs.
+#[derive(Default)]
+struct Shared {
+    events: VecDeque<Event>,
+    /// Retained devices.
+    devices: HashMap<DeviceId, id>,
+    /// Retained camera items and the camera they belong to.
+    items: HashMap<ItemId, (DeviceId, id)>,
+    /// Cameras of uploads in progress, by the number passed as the context of the upload.
+    uploads: HashMap<usize, DeviceId>,
+    /// Number of the next upload.
+    next_upload: usize,
+    /// Errors of bands that could not be read, reported as the result of the scan.
+    band_errors: HashMap<DeviceId, Error>,
+}
+
+impl Shared {
+    unsafe fn add_item(&mut self, device: &DeviceId, item: id) -> ItemId {
+        let item_id = item_id(item);
+        if !self.items.contains_key(&item_id) {
+            let _: id = msg_send![item, retain];
+            self.items.insert(item_id.clone(), (device.clone(), item));
+        }
+        item_id
+    }
+
+    unsafe fn remove_item(&mut self, item: &ItemId) {
+        if let Some((_, item)) = self.items.remove(item) {
+            let _: () = msg_send![item, release];
+        }
+    }
+}
+
+/// The shared state, with the delegate calls that found it borrowed by the backend.
+///
+/// The framework can call the delegate while the backend holds the state, so such calls are
+/// queued and run in order the next time the state is borrowed.
+#[derive(Default)]
+struct SharedCell {
+    state: RefCell<Shared>,
+    deferred: RefCell<VecDeque<DeferredCall>>,
+}
+
+/// A delegate call that is waiting for the shared state.
+type DeferredCall = Box<dyn FnOnce(&mut Shared)>;
+
+impl SharedCell {
+    fn borrow(&self) -> Ref<Shared> {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            self.run_deferred(&mut state);
+        }
+        self.state.borrow()
+    }
+
+    fn borrow_mut(&self) -> RefMut<Shared> {
+        let mut state = self.state.borrow_mut();
+        self.run_deferred(&mut state);
+        state
+    }
+
+    fn run_deferred(&self, state: &mut Shared) {
+        // The queue is not borrowed while a call runs, as the call can queue more.
+        loop {
+            let call = self.deferred.borrow_mut().pop_front();
+            match call {
+                Some(call) => call(state),
+                None => break,
+            }
+        }
+    }
+}
+
+/// A `Backend` on the ImageCaptureCore framework.
+///
+/// The backend must be created and used on a thread with a run loop, normally the main thread.
+/// The framework reports results to a delegate while `next_event` runs the run loop, and the
+/// delegate turns them into events. Devices, camera items and functional units are looked up by
+/// their identifiers on each request, so the objects of the framework never leave the backend.
+pub struct ImageCaptureBackend {
+    browser: id,
+    delegate: id,
+    shared: Box<SharedCell>,
+}
+
+impl Default for ImageCaptureBackend {
+    fn default() -> ImageCaptureBackend {
+        ImageCaptureBackend::new()
+    }
+}
+
+impl ImageCaptureBackend {
+    /// Create a backend with a device browser that is not browsing yet.
+    pub fn new() -> ImageCaptureBackend {
+        unsafe {
+            let shared = Box::new(SharedCell::default());
+            let delegate: id = msg_send![delegate_class(), new];
+            (*delegate).set_ivar::<*mut c_void>(
+                SHARED_IVAR,
+                &*shared as *const SharedCell as *mut c_void,
+            );
+            let browser = <id as ICDeviceBrowser>::new(nil);
+            ICDeviceBrowser::setDelegate(browser, delegate);
+            ImageCaptureBackend {
+                browser,
+                delegate,
+                shared,
+            }
+        }
+    }
+
+    /// The device with an identifier.
+    fn device(&self, device: &DeviceId) -> Result<id> {
+        self.shared
+            .borrow()
+            .devices
+            .get(device)
+            .copied()
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    /// The device with an identifier, if it is of `kind`.
+    fn device_of_kind(&self, device: &DeviceId, kind: DeviceKind) -> Result<id> {
+        let device = self.device(device)?;
+        if unsafe { device_kind(device) } == kind {
+            Ok(device)
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// A camera and one of its files.
+    fn camera_file(&self, device: &DeviceId, item: &ItemId) -> Result<(id, id)> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        let file = match self.shared.borrow().items.get(item) {
+            Some((owner, file)) if owner == device => *file,
+            _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        };
+        if unsafe { is_folder(file) } {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok((camera, file))
+    }
+
+    /// The selected functional unit of a scanner.
+    fn selected_unit(&self, device: &DeviceId) -> Result<(id, id)> {
+        let scanner = self.device_of_kind(device, DeviceKind::Scanner)?;
+        let unit = unsafe { scanner.selectedFunctionalUnit() };
+        if unit == nil {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok((scanner, unit))
+    }
+}
+
+impl Drop for ImageCaptureBackend {
+    fn drop(&mut self) {
+        unsafe {
+            self.browser.stop();
+            ICDeviceBrowser::setDelegate(self.browser, nil);
+            let mut shared = self.shared.borrow_mut();
+            for (_, device) in shared.devices.drain() {
+                ICDevice::setDelegate(device, nil);
+                let _: () = msg_send![device, release];
+            }
+            for (_, (_, item)) in shared.items.drain() {
+                let _: () = msg_send![item, release];
+            }
+            (*self.delegate).set_ivar::<*mut c_void>(SHARED_IVAR, std::ptr::null_mut());
+            let _: () = msg_send![self.delegate, release];
+            let _: () = msg_send![self.browser, release];
+        }
+    }
+}
+
+impl Backend for ImageCaptureBackend {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        let mut mask = ICDeviceLocationTypeMask::ICDeviceLocationTypeMaskLocal.bits()
+            | ICDeviceLocationTypeMask::ICDeviceLocationTypeMaskRemote.bits();
+        for kind in kinds {
+            mask |= match kind {
+                DeviceKind::Camera => ICDeviceTypeMask::ICDeviceTypeMaskCamera.bits(),
+                DeviceKind::Scanner => ICDeviceTypeMask::ICDeviceTypeMaskScanner.bits(),
+            };
+        }
+        unsafe {
+            self.browser.setBrowsedDeviceTypeMask(mask);
+            if self.browser.isBrowsing() == NO {
+                self.browser.start();
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        unsafe { self.browser.stop() };
+        Ok(())
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        let devices: Vec<id> = self.shared.borrow().devices.values().copied().collect();
+        let mut devices: Vec<DeviceInfo> = autoreleased(|| {
+            devices
+                .into_iter()
+                .map(|device| unsafe { device_info(device) })
+                .collect()
+        });
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        devices
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.shared.borrow_mut().events.pop_front() {
+                return Some(event);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            // Run the run loop until a source was handled or the time is up, so that the
+            // framework can call the delegate.
+            autoreleased(|| unsafe {
+                let seconds = (deadline - now).as_secs_f64();
+                let date: id = msg_send![class!(NSDate), dateWithTimeIntervalSinceNow: seconds];
+                let run_loop: id = msg_send![class!(NSRunLoop), currentRunLoop];
+                let mode = ns_string("kCFRunLoopDefaultMode");
+                let _: BOOL = msg_send![run_loop, runMode:mode beforeDate:date];
+            });
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        let device = self.device(device)?;
+        unsafe { ICDevice::requestOpenSession(device) };
+        Ok(())
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        let device = self.device(device)?;
+        unsafe { ICDevice::requestCloseSession(device) };
+        Ok(())
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        let device = self.device(device)?;
+        Ok(unsafe { ICDevice::hasOpenSession(device) } != NO)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        Ok(autoreleased(|| unsafe {
+            // Walk the folders depth first, registering each item so that it can be downloaded.
+            let mut items = Vec::new();
+            let mut pending: Vec<id> = array(ICCameraDevice::contents(camera));
+            pending.reverse();
+            while let Some(item) = pending.pop() {
+                self.shared.borrow_mut().add_item(device, item);
+                items.push(camera_item(item));
+                if is_folder(item) {
+                    let mut contents = array(crate::camera_item::ICCameraFolder::contents(item));
+                    contents.reverse();
+                    pending.extend(contents);
+                }
+            }
+            items
+        }))
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let (camera, file) = self.camera_file(device, item)?;
+        autoreleased(|| unsafe {
+            let dictionary: id = msg_send![class!(NSMutableDictionary), dictionary];
+            let set = |key: id, value: id| {
+                let _: () = msg_send![dictionary, setObject:value forKey:key];
+            };
+            set(ICDownloadsDirectoryURL, file_url(&options.directory));
+            if let Some(ref name) = options.file_name {
+                set(ICSaveAsFilename, ns_string(name));
+            }
+            set(ICOverwrite, ns_bool(options.overwrite));
+            set(
+                ICDeleteAfterSuccessfulDownload,
+                ns_bool(options.delete_after_download),
+            );
+            set(ICDownloadSidecarFiles, ns_bool(options.sidecar_files));
+            // The binding types the selector as an object, so the message is sent directly.
+            let _: () = msg_send![camera, requestDownloadFile:file
+                                                      options:dictionary
+                                             downloadDelegate:self.delegate
+                                          didDownloadSelector:sel!(didDownloadFile:error:options:contextInfo:)
+                                                  contextInfo:std::ptr::null_mut::<c_void>()];
+        });
+        Ok(())
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        unsafe {
+            Ok(CameraStatus {
+                battery_level: if camera.batteryLevelAvailable() != NO {
+                    Some(camera.batteryLevel().min(100) as u8)
+                } else {
+                    None
+                },
+                catalog_percent_completed: camera.contentCatalogPercentCompleted().min(100) as u8,
+                tethered_capture_enabled: camera.tetheredCaptureEnabled() != NO,
+                access_restricted: camera.isAccessRestrictedAppleDevice() != NO,
+            })
+        }
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let (camera, file) = self.camera_file(device, item)?;
+        let (offset, length) = (offset as libc::off_t, length as libc::off_t);
+        // The offset travels in the context, as the delegate is not told it. The binding types
+        // the selector as an object, so the message is sent directly.
+        let _: () = unsafe {
+            msg_send![camera, requestReadDataFromFile:file
+                                             atOffset:offset
+                                               length:length
+                                         readDelegate:self.delegate
+                                  didReadDataSelector:sel!(didReadData:fromFile:error:contextInfo:)
+                                          contextInfo:offset as usize as *mut c_void]
+        };
+        Ok(())
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        let mut files = Vec::with_capacity(items.len());
+        for item in items {
+            let (camera, file) = self.camera_file(device, item)?;
+            if unsafe { ICCameraItem::isLocked(file) } != NO {
+                return Err(Error::ReturnCode(
+                    crate::constants::ICReturnCode::ICReturnDeleteFilesFailed,
+                ));
+            }
+            files.push((camera, file));
+        }
+        if let Some(&(camera, _)) = files.first() {
+            autoreleased(|| unsafe {
+                let files: Vec<id> = files.iter().map(|&(_, file)| file).collect();
+                let array: id = msg_send![class!(NSArray), arrayWithObjects:files.as_ptr()
+                                                                      count:files.len() as NSUInteger];
+                camera.requestDeleteFiles(array);
+            });
+        }
+        Ok(())
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        let upload = {
+            let mut shared = self.shared.borrow_mut();
+            let upload = shared.next_upload;
+            shared.next_upload += 1;
+            shared.uploads.insert(upload, device.clone());
+            upload
+        };
+        autoreleased(|| unsafe {
+            let options: id = msg_send![class!(NSDictionary), dictionary];
+            // The binding types the selector as an object, so the message is sent directly.
+            let _: () = msg_send![camera, requestUploadFile:file_url(path)
+                                                    options:options
+                                             uploadDelegate:self.delegate
+                                          didUploadSelector:sel!(didUploadFile:error:contextInfo:)
+                                                contextInfo:upload as *mut c_void];
+        });
+        Ok(())
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        unsafe {
+            if enabled {
+                camera.requestEnableTethering();
+            } else {
+                camera.requestDisableTethering();
+            }
+        }
+        Ok(())
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        let camera = self.device_of_kind(device, DeviceKind::Camera)?;
+        unsafe { camera.requestTakePicture() };
+        Ok(())
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        let scanner = self.device_of_kind(device, DeviceKind::Scanner)?;
+        Ok(autoreleased(|| unsafe {
+            array(scanner.availableFunctionalUnitTypes())
+                .into_iter()
+                .filter_map(|number| {
+                    let value: NSUInteger = msg_send![number, unsignedIntegerValue];
+                    ICScannerFunctionalUnitType::try_from(value).ok()
+                })
+                .collect()
+        }))
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        let scanner = self.device_of_kind(device, DeviceKind::Scanner)?;
+        // The binding types the unit type as an object, so the message is sent directly.
+        let unit_type = NSUInteger::from(unit_type);
+        let _: () = unsafe { msg_send![scanner, requestSelectFunctionalUnit: unit_type] };
+        Ok(())
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        let (scanner, unit) = self.selected_unit(device)?;
+        autoreleased(|| unsafe { functional_unit(scanner, unit) })
+    }
+
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit> {
+        let (scanner, unit) = self.selected_unit(device)?;
+        let current = self.functional_unit(device)?;
+        current.validate(settings)?;
+        autoreleased(|| unsafe { configure(scanner, unit, &current, settings) })?;
+        self.functional_unit(device)
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let (scanner, unit) = self.selected_unit(device)?;
+        unsafe {
+            if unit.canPerformOverviewScan() == NO {
+                return Err(Error::ReturnCode(ICReturnInvalidParam));
+            }
+            scanner.requestOverviewScan();
+        }
+        Ok(())
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        let (_, unit) = self.selected_unit(device)?;
+        unsafe {
+            // The binding returns the image as a `CGImageRef` value, so the message is sent
+            // directly for a pointer.
+            let image: *mut c_void = msg_send![unit, overviewImage];
+            if image.is_null() {
+                return Ok(None);
+            }
+            Ok(Some(render(image)))
+        }
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let (scanner, _) = self.selected_unit(device)?;
+        unsafe { scanner.requestScan() };
+        Ok(())
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let scanner = self.device_of_kind(device, DeviceKind::Scanner)?;
+        unsafe { scanner.cancelScan() };
+        Ok(())
+    }
+}
+
+/// Run `f` with an autorelease pool that is drained afterwards.
+fn autoreleased<T, F: FnOnce() -> T>(f: F) -> T {
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let result = f();
+        let _: () = msg_send![pool, drain];
+        result
+    }
+}
+
+/// The delegate class, registered with the Objective-C runtime on first use.
+fn delegate_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        let mut decl = ClassDecl::new(DELEGATE_CLASS, class!(NSObject))
+            .expect("the delegate class is registered once");
+        decl.add_ivar::<*mut c_void>(SHARED_IVAR);
+
+        // ICDeviceBrowserDelegate
+        decl.add_method(
+            sel!(deviceBrowser:didAddDevice:moreComing:),
+            browser_did_add_device as extern "C" fn(&Object, Sel, id, id, BOOL),
+        );
+        decl.add_method(
+            sel!(deviceBrowser:didRemoveDevice:moreGoing:),
+            browser_did_remove_device as extern "C" fn(&Object, Sel, id, id, BOOL),
+        );
+
+        // ICDeviceDelegate
+        decl.add_method(
+            sel!(didRemoveDevice:),
+            did_remove_device as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(device:didOpenSessionWithError:),
+            did_open_session as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(device:didCloseSessionWithError:),
+            did_close_session as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(deviceDidBecomeReady:),
+            did_become_ready as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(device:didReceiveStatusInformation:),
+            did_receive_status as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(device:didEncounterError:),
+            did_encounter_error as extern "C" fn(&Object, Sel, id, id),
+        );
+
+        // ICCameraDeviceDelegate and ICCameraDeviceDownloadDelegate
+        decl.add_method(
+            sel!(cameraDevice:didAddItems:),
+            camera_did_add_items as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(cameraDevice:didRemoveItems:),
+            camera_did_remove_items as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(deviceDidBecomeReadyWithCompleteContentCatalog:),
+            did_complete_catalog as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(didDownloadFile:error:options:contextInfo:),
+            did_download_file as extern "C" fn(&Object, Sel, id, id, id, *mut c_void),
+        );
+        decl.add_method(
+            sel!(didReadData:fromFile:error:contextInfo:),
+            did_read_data as extern "C" fn(&Object, Sel, id, id, id, *mut c_void),
+        );
+        decl.add_method(
+            sel!(didUploadFile:error:contextInfo:),
+            did_upload_file as extern "C" fn(&Object, Sel, id, id, *mut c_void),
+        );
+
+        // ICScannerDeviceDelegate
+        decl.add_method(
+            sel!(scannerDevice:didSelectFunctionalUnit:error:),
+            did_select_functional_unit as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        decl.add_method(
+            sel!(scannerDevice:didScanToURL:),
+            did_scan_to_url as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(scannerDevice:didScanToBandData:),
+            did_scan_to_band_data as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(scannerDevice:didCompleteOverviewScanWithError:),
+            did_complete_overview_scan as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(scannerDevice:didCompleteScanWithError:),
+            did_complete_scan as extern "C" fn(&Object, Sel, id, id),
+        );
+
+        decl.add_method(
+            sel!(cameraDeviceDidEnableAccessRestriction:),
+            did_enable_access_restriction as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(cameraDeviceDidRemoveAccessRestriction:),
+            did_remove_access_restriction as extern "C" fn(&Object, Sel, id),
+        );
+
+        // Required delegate methods without an event.
+        for &selector in &[
+            sel!(cameraDeviceDidChangeCapability:),
+            sel!(scannerDeviceDidBecomeAvailable:),
+        ] {
+            decl.add_method(selector, ignore_1 as extern "C" fn(&Object, Sel, id));
+        }
+        for &selector in &[
+            sel!(cameraDevice:didRenameItems:),
+            sel!(cameraDevice:didReceivePTPEvent:),
+        ] {
+            decl.add_method(selector, ignore_2 as extern "C" fn(&Object, Sel, id, id));
+        }
+        for &selector in &[
+            sel!(cameraDevice:didReceiveThumbnail:forItem:error:),
+            sel!(cameraDevice:didReceiveMetadata:forItem:error:),
+        ] {
+            decl.add_method(
+                selector,
+                ignore_4 as extern "C" fn(&Object, Sel, id, id, id, id),
+            );
+        }
+        decl.register();
+    });
+    Class::get(DELEGATE_CLASS).expect("the delegate class is registered")
+}
+
+/// Run `f` on the shared state of the backend that owns a delegate, unless the backend is gone.
+/// If the backend holds the state, `f` runs when the backend next borrows it.
+unsafe fn with_shared<F: FnOnce(&mut Shared) + 'static>(delegate: &Object, f: F) {
+    let shared = *delegate.get_ivar::<*mut c_void>(SHARED_IVAR);
+    if shared.is_null() {
+        return;
+    }
+    let shared = &*(shared as *const SharedCell);
+    match shared.state.try_borrow_mut() {
+        Ok(mut state) => {
+            shared.run_deferred(&mut state);
+            f(&mut state);
+        }
+        Err(_) => shared.deferred.borrow_mut().push_back(Box::new(f)),
+    }
+}
+
+/// An object retained for a delegate call that may be deferred, and released when the call is
+/// done with it or dropped without running.
+struct Retained(id);
+
+impl Retained {
+    unsafe fn new(object: id) -> Retained {
+        let _: id = msg_send![object, retain];
+        Retained(object)
+    }
+}
+
+impl Drop for Retained {
+    fn drop(&mut self) {
+        unsafe {
+            let _: () = msg_send![self.0, release];
+        }
+    }
+}
+
+/// The result of a request that reports an `NSError` or nil.
+unsafe fn result(error: id) -> Result<()> {
+    match Error::from_ns_error(error) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+extern "C" fn browser_did_add_device(this: &Object, _: Sel, _: id, device: id, _: BOOL) {
+    unsafe {
+        let info = device_info(device);
+        let delegate = this as *const Object as id;
+        let retained = Retained::new(device);
+        with_shared(this, move |shared| {
+            let device = retained.0;
+            if shared.devices.contains_key(&info.id) {
+                return;
+            }
+            let _: id = msg_send![device, retain];
+            ICDevice::setDelegate(device, delegate);
+            shared.devices.insert(info.id.clone(), device);
+            shared.events.push_back(Event::DeviceAdded(info));
+        });
+    }
+}
+
+extern "C" fn browser_did_remove_device(this: &Object, _: Sel, _: id, device: id, _: BOOL) {
+    did_remove_device(this, sel!(didRemoveDevice:), device);
+}
+
+extern "C" fn did_remove_device(this: &Object, _: Sel, device: id) {
+    unsafe {
+        let device_id = device_id(device);
+        with_shared(this, |shared| {
+            if let Some(device) = shared.devices.remove(&device_id) {
+                ICDevice::setDelegate(device, nil);
+                let _: () = msg_send![device, release];
+                let items: Vec<ItemId> = shared
+                    .items
+                    .iter()
+                    .filter(|(_, (owner, _))| *owner == device_id)
+                    .map(|(item, _)| item.clone())
+                    .collect();
+                for item in &items {
+                    shared.remove_item(item);
+                }
+                shared.events.push_back(Event::DeviceRemoved(device_id));
+            }
+        });
+    }
+}
+
+extern "C" fn did_open_session(this: &Object, _: Sel, device: id, error: id) {
+    unsafe {
+        let event = Event::SessionOpened {
+            device: device_id(device),
+            result: result(error),
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_close_session(this: &Object, _: Sel, device: id, error: id) {
+    unsafe {
+        let event = Event::SessionClosed {
+            device: device_id(device),
+            result: result(error),
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_become_ready(this: &Object, _: Sel, device: id) {
+    unsafe {
+        let event = Event::DeviceReady(device_id(device));
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_receive_status(this: &Object, _: Sel, device: id, status: id) {
+    unsafe {
+        let message: id = msg_send![status, objectForKey: ICStatusNotificationKey];
+        if let Some(message) = string(message) {
+            let event = Event::Status {
+                device: device_id(device),
+                message,
+            };
+            with_shared(this, |shared| shared.events.push_back(event));
+        }
+    }
+}
+
+extern "C" fn did_encounter_error(this: &Object, _: Sel, device: id, error: id) {
+    unsafe {
+        if let Some(error) = Error::from_ns_error(error) {
+            let event = Event::DeviceError {
+                device: device_id(device),
+                error,
+            };
+            with_shared(this, |shared| shared.events.push_back(event));
+        }
+    }
+}
+
+extern "C" fn camera_did_add_items(this: &Object, _: Sel, camera: id, items: id) {
+    unsafe {
+        let device = device_id(camera);
+        let items: Vec<Retained> = array(items)
+            .into_iter()
+            .map(|item| Retained::new(item))
+            .collect();
+        let added: Vec<CameraItem> = items.iter().map(|item| camera_item(item.0)).collect();
+        with_shared(this, move |shared| {
+            for item in &items {
+                shared.add_item(&device, item.0);
+            }
+            shared.events.push_back(Event::ItemsAdded {
+                device,
+                items: added,
+            });
+        });
+    }
+}
+
+extern "C" fn camera_did_remove_items(this: &Object, _: Sel, camera: id, items: id) {
+    unsafe {
+        let device = device_id(camera);
+        let removed: Vec<ItemId> = array(items).into_iter().map(item_id).collect();
+        with_shared(this, |shared| {
+            for item in &removed {
+                shared.remove_item(item);
+            }
+            shared.events.push_back(Event::ItemsRemoved {
+                device,
+                items: removed,
+            });
+        });
+    }
+}
+
+extern "C" fn did_complete_catalog(this: &Object, _: Sel, device: id) {
+    unsafe {
+        let event = Event::CatalogCompleted(device_id(device));
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_enable_access_restriction(this: &Object, _: Sel, device: id) {
+    unsafe {
+        let event = Event::AccessRestrictionChanged {
+            device: device_id(device),
+            restricted: true,
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_remove_access_restriction(this: &Object, _: Sel, device: id) {
+    unsafe {
+        let event = Event::AccessRestrictionChanged {
+            device: device_id(device),
+            restricted: false,
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_download_file(
+    this: &Object,
+    _: Sel,
+    file: id,
+    error: id,
+    options: id,
+    _: *mut c_void,
+) {
+    unsafe {
+        let saved = || -> Option<PathBuf> {
+            let directory: id = msg_send![options, objectForKey: ICDownloadsDirectoryURL];
+            let name: id = msg_send![options, objectForKey: ICSavedFilename];
+            Some(url_path(directory)?.join(string(name)?))
+        };
+        let result = result(error).and_then(|_| {
+            saved().ok_or(Error::ReturnCode(
+                crate::constants::ICReturnCode::ICReturnDownloadFailed,
+            ))
+        });
+        let event = Event::Downloaded {
+            device: device_id(ICCameraItem::device(file)),
+            item: item_id(file),
+            result,
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_read_data(
+    this: &Object,
+    _: Sel,
+    data: id,
+    file: id,
+    error: id,
+    offset: *mut c_void,
+) {
+    unsafe {
+        let result = result(error).map(|_| {
+            if data == nil {
+                return Vec::new();
+            }
+            let bytes: *const u8 = msg_send![data, bytes];
+            let length: NSUInteger = msg_send![data, length];
+            if bytes.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(bytes, length as usize).to_vec()
+            }
+        });
+        let event = Event::DataRead {
+            device: device_id(ICCameraItem::device(file)),
+            item: item_id(file),
+            offset: offset as usize as u64,
+            result,
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_upload_file(this: &Object, _: Sel, url: id, error: id, upload: *mut c_void) {
+    unsafe {
+        let path = url_path(url).unwrap_or_default();
+        let result = result(error);
+        with_shared(this, move |shared| {
+            // The delegate is not told the camera, so the upload number finds it.
+            if let Some(device) = shared.uploads.remove(&(upload as usize)) {
+                shared.events.push_back(Event::Uploaded {
+                    device,
+                    path,
+                    result,
+                });
+            }
+        });
+    }
+}
+
+extern "C" fn did_select_functional_unit(this: &Object, _: Sel, scanner: id, unit: id, error: id) {
+    unsafe {
+        let result = result(error).and_then(|_| {
+            if unit == nil {
+                Err(Error::ReturnCode(
+                    crate::constants::ICReturnCode::ICReturnScannerFailedToSelectFunctionalUnit,
+                ))
+            } else {
+                ICScannerFunctionalUnit::type_(unit).map_err(Error::from)
+            }
+        });
+        let event = Event::FunctionalUnitSelected {
+            device: device_id(scanner),
+            result,
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_scan_to_url(this: &Object, _: Sel, scanner: id, url: id) {
+    unsafe {
+        if let Some(path) = url_path(url) {
+            let event = Event::ScannedFile {
+                device: device_id(scanner),
+                path,
+            };
+            with_shared(this, |shared| shared.events.push_back(event));
+        }
+    }
+}
+
+extern "C" fn did_scan_to_band_data(this: &Object, _: Sel, scanner: id, band: id) {
+    unsafe {
+        let device = device_id(scanner);
+        match ScannerBand::from_band_data(band) {
+            Ok(band) => {
+                let event = Event::ScannedBand { device, band };
+                with_shared(this, |shared| shared.events.push_back(event));
+            }
+            Err(error) => with_shared(this, |shared| {
+                shared.band_errors.insert(device, error);
+            }),
+        }
+    }
+}
+
+extern "C" fn did_complete_overview_scan(this: &Object, _: Sel, scanner: id, error: id) {
+    unsafe {
+        let event = Event::OverviewCompleted {
+            device: device_id(scanner),
+            result: result(error),
+        };
+        with_shared(this, |shared| shared.events.push_back(event));
+    }
+}
+
+extern "C" fn did_complete_scan(this: &Object, _: Sel, scanner: id, error: id) {
+    unsafe {
+        let device = device_id(scanner);
+        let result = result(error);
+        with_shared(this, |shared| {
+            let result = match shared.band_errors.remove(&device) {
+                Some(error) if result.is_ok() => Err(error),
+                _ => result,
+            };
+            shared
+                .events
+                .push_back(Event::ScanCompleted { device, result });
+        });
+    }
+}
+
+extern "C" fn ignore_1(_: &Object, _: Sel, _: id) {}
+
+extern "C" fn ignore_2(_: &Object, _: Sel, _: id, _: id) {}
+
+extern "C" fn ignore_4(_: &Object, _: Sel, _: id, _: id, _: id, _: id) {}
+
+/// The string value of an `NSString`, or `None` for nil.
+unsafe fn string(string: id) -> Option<String> {
+    if string == nil {
+        return None;
+    }
+    let string: *const libc::c_char = msg_send![string, UTF8String];
+    if string.is_null() {
+        return None;
+    }
+    Some(
+        std::ffi::CStr::from_ptr(string)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// The description of an object, which is the value of strings and the decimal representation
+/// of numbers.
+unsafe fn description(object: id) -> Option<String> {
+    if object == nil {
+        return None;
+    }
+    string(msg_send![object, description])
+}
+
+/// An autoreleased `NSString`.
+unsafe fn ns_string(value: &str) -> id {
+    let value = CString::new(value.replace('\0', "")).unwrap_or_default();
+    msg_send![class!(NSString), stringWithUTF8String: value.as_ptr()]
+}
+
+/// An autoreleased `NSNumber` holding a boolean.
+unsafe fn ns_bool(value: bool) -> id {
+    let value: BOOL = if value { YES } else { NO };
+    msg_send![class!(NSNumber), numberWithBool: value]
+}
+
+/// An autoreleased file URL.
+unsafe fn file_url(path: &Path) -> id {
+    let path = ns_string(&path.to_string_lossy());
+    msg_send![class!(NSURL), fileURLWithPath: path]
+}
+
+/// The path of a file URL.
+unsafe fn url_path(url: id) -> Option<PathBuf> {
+    if url == nil {
+        return None;
+    }
+    let path: id = msg_send![url, path];
+    string(path).map(PathBuf::from)
+}
+
+/// The objects of an `NSArray`, or nothing for nil.
+unsafe fn array(array: id) -> Vec<id> {
+    if array == nil {
+        return Vec::new();
+    }
+    let count: NSUInteger = msg_send![array, count];
+    (0..count)
+        .map(|index| msg_send![array, objectAtIndex: index])
+        .collect()
+}
+
+/// The indexes of an `NSIndexSet`, or nothing for nil.
+unsafe fn index_set(set: id) -> Vec<NSUInteger> {
+    let mut indexes = Vec::new();
+    if set == nil {
+        return indexes;
+    }
+    let mut index: NSUInteger = msg_send![set, firstIndex];
+    while index != NS_NOT_FOUND {
+        indexes.push(index);
+        index = msg_send![set, indexGreaterThanIndex: index];
+    }
+    indexes
+}
+
+/// The time of an `NSDate`.
+unsafe fn date(date: id) -> Option<SystemTime> {
+    if date == nil {
+        return None;
+    }
+    let seconds: f64 = msg_send![date, timeIntervalSince1970];
+    if seconds >= 0.0 {
+        Some(UNIX_EPOCH + Duration::from_secs_f64(seconds))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs_f64(-seconds))
+    }
+}
+
+/// The identifier of a device: its UUID, or its address if it has none.
+unsafe fn device_id(device: id) -> DeviceId {
+    DeviceId(string(device.UUIDString()).unwrap_or_else(|| format!("{:p}", device)))
+}
+
+/// The identifier of a camera item: its address, which stays the same while the item is
+/// retained.
+fn item_id(item: id) -> ItemId {
+    ItemId(format!("{:p}", item))
+}
+
+unsafe fn device_kind(device: id) -> DeviceKind {
+    if ICDevice::type_(device).contains(ICDeviceType::ICDeviceTypeScanner) {
+        DeviceKind::Scanner
+    } else {
+        DeviceKind::Camera
+    }
+}
+
+unsafe fn device_info(device: id) -> DeviceInfo {
+    DeviceInfo {
+        id: device_id(device),
+        kind: device_kind(device),
+        name: string(ICDevice::name(device)).unwrap_or_default(),
+        transport: string(device.transportType()),
+        serial_number: string(device.serialNumberString()),
+        is_remote: device.isRemote() != NO,
+        capabilities: array(device.capabilities())
+            .into_iter()
+            .filter_map(|capability| string(capability))
+            .collect(),
+    }
+}
+
+unsafe fn is_folder(item: id) -> bool {
+    let folder: BOOL = msg_send![item, isKindOfClass: class!(ICCameraFolder)];
+    folder != NO
+}
+
+unsafe fn camera_item(item: id) -> CameraItem {
+    let folder = is_folder(item);
+    let parent = ICCameraItem::parentFolder(item);
+    CameraItem {
+        id: item_id(item),
+        name: string(ICCameraItem::name(item)).unwrap_or_default(),
+        parent: if parent == nil {
+            None
+        } else {
+            Some(item_id(parent))
+        },
+        is_folder: folder,
+        uti: string(item.UTI()),
+        size: if folder {
+            0
+        } else {
+            item.fileSize().max(0) as u64
+        },
+        orientation: if folder {
+            ICEXIFOrientation1
+        } else {
+            // EXIF readers treat unknown orientations as upright.
+            item.orientation().unwrap_or(ICEXIFOrientation1)
+        },
+        is_raw: item.isRaw() != NO,
+        is_locked: item.isLocked() != NO,
+        creation_date: date(item.creationDate()),
+        modification_date: date(item.modificationDate()),
+        ptp_object_handle: ICCameraItem::ptpObjectHandle(item),
+        sidecar_files: if folder {
+            Vec::new()
+        } else {
+            array(item.sidecarFiles())
+                .into_iter()
+                .filter_map(|sidecar| string(ICCameraItem::name(sidecar)))
+                .collect()
+        },
+        added_after_catalog: item.wasAddedAfterContentCatalogCompleted() != NO,
+    }
+}
+
+/// The configuration of the selected functional unit of a scanner.
+unsafe fn functional_unit(scanner: id, unit: id) -> Result<FunctionalUnit> {
+    let unit_type = ICScannerFunctionalUnit::type_(unit)?;
+    let measurement_unit = unit.measurementUnit()?;
+    let size = unit.physicalSize();
+    let feeder = unit_type == ICScannerFunctionalUnitTypeDocumentFeeder;
+    let downloads_directory = url_path(scanner.downloadsDirectory());
+    Ok(FunctionalUnit {
+        unit_type,
+        measurement_unit,
+        physical_size: (size.width, size.height),
+        supported_resolutions: index_set(unit.supportedResolutions()),
+        resolution: unit.resolution(),
+        supported_bit_depths: index_set(unit.supportedBitDepths())
+            .into_iter()
+            .filter_map(|depth| ICScannerBitDepth::try_from(depth).ok())
+            .collect(),
+        bit_depth: unit.bitDepth()?,
+        pixel_data_type: unit.pixelDataType()?,
+        scan_area: ScanRect::from_ns_rect(unit.scanArea(), measurement_unit),
+        scan_area_orientation: unit.scanAreaOrientation()?,
+        // All concrete functional units have document types.
+        supported_document_types: index_set(
+            ICScannerFunctionalUnitFlatbed::supportedDocumentTypes(unit),
+        )
+        .into_iter()
+        .filter_map(|document_type| ICScannerDocumentType::try_from(document_type).ok())
+        .collect(),
+        document_type: ICScannerFunctionalUnitFlatbed::documentType(unit)?,
+        can_perform_overview_scan: unit.canPerformOverviewScan() != NO,
+        overview_resolution: unit.overviewResolution(),
+        accepts_threshold: unit.acceptsThresholdForBlackAndWhiteScanning() != NO,
+        threshold: if unit.usesThresholdForBlackAndWhiteScanning() != NO {
+            Some(unit.thresholdForBlackAndWhiteScanning())
+        } else {
+            None
+        },
+        duplex: if feeder && unit.supportsDuplexScanning() != NO {
+            Some(unit.duplexScanningEnabled() != NO)
+        } else {
+            None
+        },
+        transfer_mode: scanner.transferMode()?,
+        downloads_directory,
+        document_name: string(scanner.documentName()),
+        document_uti: string(scanner.documentUTI()),
+        max_memory_band_size: scanner.maxMemoryBandSize(),
+        vendor_features: array(unit.vendorFeatures())
+            .into_iter()
+            .filter_map(|feature| scanner_feature(feature))
+            .collect(),
+    })
+}
+
+/// A vendor feature of a functional unit, or `None` for templates.
+unsafe fn scanner_feature(feature: id) -> Option<ScannerFeature> {
+    let kind = match ICScannerFeature::type_(feature) {
+        Ok(ICScannerFeatureTypeEnumeration) => FeatureKind::Enumeration {
+            values: array(feature.values())
+                .into_iter()
+                .filter_map(|value| description(value))
+                .collect(),
+            current: description(ICScannerFeatureEnumeration::currentValue(feature))
+                .unwrap_or_default(),
+        },
+        Ok(ICScannerFeatureTypeRange) => FeatureKind::Range {
+            min: feature.minValue(),
+            max: feature.maxValue(),
+            step: feature.stepSize(),
+            current: ICScannerFeatureRange::currentValue(feature),
+        },
+        Ok(ICScannerFeatureTypeBoolean) => FeatureKind::Boolean(feature.value() != NO),
+        _ => return None,
+    };
+    Some(ScannerFeature {
+        name: string(feature.internalName())?,
+        label: string(feature.humanReadableName()),
+        kind,
+    })
+}
+
+/// Set the value of the vendor feature of a functional unit with an internal name.
+unsafe fn set_feature(unit: id, name: &str, value: &FeatureValue) {
+    let feature = array(unit.vendorFeatures())
+        .into_iter()
+        .find(|&feature| string(feature.internalName()).as_deref() == Some(name));
+    let feature = match feature {
+        Some(feature) => feature,
+        None => return,
+    };
+    match *value {
+        FeatureValue::Enumeration(ref value) => {
+            let object = array(feature.values())
+                .into_iter()
+                .find(|&object| description(object).as_ref() == Some(value));
+            if let Some(object) = object {
+                ICScannerFeatureEnumeration::setCurrentValue(feature, object);
+            }
+        }
+        FeatureValue::Range(value) => ICScannerFeatureRange::setCurrentValue(feature, value),
+        FeatureValue::Boolean(value) => feature.setValue(if value { YES } else { NO }),
+    }
+}
+
+/// Apply validated settings to a scanner and its selected functional unit.
+unsafe fn configure(
+    scanner: id,
+    unit: id,
+    current: &FunctionalUnit,
+    settings: &ScanSettings,
+) -> Result<()> {
+    let measurement_unit = settings
+        .measurement_unit
+        .unwrap_or(current.measurement_unit);
+    let resolution = settings.resolution.unwrap_or(current.resolution);
+    if let Some(measurement_unit) = settings.measurement_unit {
+        unit.setMeasurementUnit(measurement_unit);
+    }
+    if let Some(resolution) = settings.resolution {
+        unit.setResolution(resolution);
+    }
+    if let Some(bit_depth) = settings.bit_depth {
+        unit.setBitDepth(bit_depth);
+    }
+    if let Some(pixel_data_type) = settings.pixel_data_type {
+        unit.setPixelDataType(pixel_data_type);
+    }
+    if let Some(document_type) = settings.document_type {
+        ICScannerFunctionalUnitFlatbed::setDocumentType(unit, document_type);
+    }
+    if let Some(area) = settings.scan_area {
+        // Pixels on either side of the conversion are at the new scan resolution.
+        unit.setScanArea(area.to(measurement_unit, resolution)?.to_ns_rect());
+    }
+    if let Some(orientation) = settings.scan_area_orientation {
+        unit.setScanAreaOrientation(orientation);
+    }
+    if let Some(duplex) = settings.duplex {
+        if current.duplex.is_some() {
+            unit.setDuplexScanningEnabled(if duplex { YES } else { NO });
+        }
+    }
+    if let Some(threshold) = settings.threshold {
+        unit.setUsesThresholdForBlackAndWhiteScanning(YES);
+        unit.setThresholdForBlackAndWhiteScanning(threshold);
+    }
+    if let Some(resolution) = settings.overview_resolution {
+        unit.setOverviewResolution(resolution);
+    }
+    if let Some(transfer_mode) = settings.transfer_mode {
+        scanner.setTransferMode(transfer_mode);
+    }
+    if let Some(ref directory) = settings.downloads_directory {
+        scanner.setDownloadsDirectory(file_url(directory));
+    }
+    if let Some(ref name) = settings.document_name {
+        scanner.setDocumentName(ns_string(name));
+    }
+    if let Some(ref uti) = settings.document_uti {
+        scanner.setDocumentUTI(ns_string(uti));
+    }
+    if let Some(size) = settings.max_memory_band_size {
+        scanner.setMaxMemoryBandSize(size);
+    }
+    for (name, value) in &settings.features {
+        set_feature(unit, name, value);
+    }
+    Ok(())
+}
+
+/// Draw a `CGImage` into an RGB image.
+unsafe fn render(image: *mut c_void) -> Image {
+    let (width, height) = (CGImageGetWidth(image), CGImageGetHeight(image));
+    let mut pixels = vec![0u8; width * height * 4];
+    let space = CGColorSpaceCreateDeviceRGB();
+    let context = CGBitmapContextCreate(
+        pixels.as_mut_ptr() as *mut c_void,
+        width,
+        height,
+        8,
+        width * 4,
+        space,
+        CG_IMAGE_ALPHA_NONE_SKIP_LAST,
+    );
+    CGColorSpaceRelease(space);
+    let mut rgb = Image::new(PixelFormat::Rgb8, width, height);
+    if context.is_null() {
+        return rgb;
+    }
+    let rect = NSRect::new(
+        cocoa::foundation::NSPoint::new(0.0, 0.0),
+        cocoa::foundation::NSSize::new(width as f64, height as f64),
+    );
+    CGContextDrawImage(context, rect, image);
+    CGContextRelease(context);
+    for y in 0..height {
+        let row = rgb.row_mut(y);
+        for x in 0..width {
+            let pixel = (y * width + x) * 4;
+            row[3 * x..3 * x + 3].copy_from_slice(&pixels[pixel..pixel + 3]);
+        }
+    }
+    rgb
+}
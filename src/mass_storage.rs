@@ -0,0 +1,900 @@
+use crate::backend::{
+    destination, file_name, is_raw_uti, is_sidecar, same_stem, uti_for_name, Backend, CameraItem,
+    CameraStatus, DeviceId, DeviceInfo, DeviceKind, DownloadOptions, Event, FunctionalUnit, ItemId,
+    ScanSettings,
+};
+use crate::constants::ICEXIFOrientationType::ICEXIFOrientation1;
+use crate::constants::ICReturnCode::{
+    ICReturnDeleteFilesFailed, ICReturnDeviceFailedToCloseSession,
+    ICReturnDeviceFailedToOpenSession, ICReturnInvalidParam,
+};
+use crate::constants::ICScannerFunctionalUnitType;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Name of the DCF folder that holds the images of a volume.
+const DCIM: &str = "DCIM";
+
+/// A file or folder of a volume.
+#[derive(Clone, Debug)]
+struct Entry {
+    item: CameraItem,
+    path: PathBuf,
+    sidecars: Vec<PathBuf>,
+}
+
+/// A volume found by the backend.
+#[derive(Clone, Debug)]
+struct Volume {
+    info: DeviceInfo,
+    root: PathBuf,
+    session: bool,
+    /// The catalog, which is complete while a session is open.
+    entries: Vec<Entry>,
+    /// New files that are not reported yet, with their size and modification date when they were
+    /// last seen.
+    unsettled: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+/// A backend that presents mounted volumes with a DCF `DCIM` folder, such as memory cards in a
+/// card reader, as cameras. These are the mass storage cameras of ImageCaptureCore, whose
+/// `mountPoint` is the volume and whose items have a `fileSystemPath`.
+///
+/// The backend looks for volumes among the directories it is given and the subdirectories of
+/// volume roots, such as `/media/<user>` on Linux or `/Volumes` on macOS. Items are the `DCIM`
+/// folder, which stands for the storage, and the folders and files below it, identified by their
+/// path relative to the volume. Sidecar files such as XMP files belong to the image with the same
+/// name. Files that are write protected are locked.
+///
+/// While browsing or while a session is open, `next_event` polls the file system: volumes that
+/// appear or go away are reported as devices, and files added to or removed from a volume with an
+/// open session are reported as items. A new file is reported once its size and modification date
+/// are the same in two polls in a row, so files that are still being written are not imported.
+#[derive(Clone, Debug)]
+pub struct MassStorageBackend {
+    volume_paths: Vec<PathBuf>,
+    volume_roots: Vec<PathBuf>,
+    poll_interval: Duration,
+    browsing: bool,
+    next_poll: Option<Instant>,
+    volumes: Vec<Volume>,
+    events: VecDeque<Event>,
+}
+
+impl Default for MassStorageBackend {
+    fn default() -> MassStorageBackend {
+        MassStorageBackend::new()
+    }
+}
+
+impl MassStorageBackend {
+    /// Create a backend without volumes that polls every second.
+    pub fn new() -> MassStorageBackend {
+        MassStorageBackend {
+            volume_paths: Vec::new(),
+            volume_roots: Vec::new(),
+            poll_interval: Duration::from_secs(1),
+            browsing: false,
+            next_poll: None,
+            volumes: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Add a directory that is a camera whenever it has a `DCIM` folder.
+    pub fn with_volume<P: Into<PathBuf>>(mut self, path: P) -> MassStorageBackend {
+        self.volume_paths.push(path.into());
+        self
+    }
+
+    /// Add a directory whose subdirectories are cameras whenever they have a `DCIM` folder.
+    pub fn with_volume_root<P: Into<PathBuf>>(mut self, path: P) -> MassStorageBackend {
+        self.volume_roots.push(path.into());
+        self
+    }
+
+    /// Set how often the file system is polled for volumes and files.
+    pub fn with_poll_interval(mut self, interval: Duration) -> MassStorageBackend {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The directory of a volume, like `ICCameraDevice::mountPoint`.
+    pub fn mount_point(&self, device: &DeviceId) -> Option<&Path> {
+        self.volume(device).ok().map(|volume| volume.root.as_path())
+    }
+
+    /// The path of an item of a volume with an open session, like `ICCameraItem::fileSystemPath`.
+    pub fn file_system_path(&self, device: &DeviceId, item: &ItemId) -> Option<&Path> {
+        self.volume(device)
+            .ok()?
+            .entries
+            .iter()
+            .find(|entry| entry.item.id == *item)
+            .map(|entry| entry.path.as_path())
+    }
+
+    fn volume(&self, device: &DeviceId) -> Result<&Volume> {
+        self.volumes
+            .iter()
+            .find(|volume| volume.info.id == *device)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn volume_mut(&mut self, device: &DeviceId) -> Result<&mut Volume> {
+        self.volumes
+            .iter_mut()
+            .find(|volume| volume.info.id == *device)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    /// The file with an identifier on a volume with an open session.
+    fn file(&self, device: &DeviceId, item: &ItemId) -> Result<&Entry> {
+        let volume = self.volume(device)?;
+        if !volume.session {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        volume
+            .entries
+            .iter()
+            .find(|entry| entry.item.id == *item && !entry.item.is_folder)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    /// The directories that are volumes with a `DCIM` folder now.
+    fn find_volumes(&self) -> Vec<PathBuf> {
+        let mut candidates = self.volume_paths.clone();
+        for root in &self.volume_roots {
+            if let Ok(entries) = fs::read_dir(root) {
+                let mut paths: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .collect();
+                paths.sort();
+                candidates.extend(paths);
+            }
+        }
+        let mut volumes = Vec::new();
+        for path in candidates {
+            if dcim_folder(&path).is_some() && !volumes.contains(&path) {
+                volumes.push(path);
+            }
+        }
+        volumes
+    }
+
+    /// Report volumes that appeared or went away, and files added to or removed from volumes with
+    /// an open session.
+    fn poll(&mut self) {
+        let found = self.find_volumes();
+        let (kept, gone): (Vec<Volume>, Vec<Volume>) = self
+            .volumes
+            .drain(..)
+            .partition(|volume| found.contains(&volume.root));
+        self.volumes = kept;
+        for volume in gone {
+            self.events.push_back(Event::DeviceRemoved(volume.info.id));
+        }
+        if self.browsing {
+            for root in found {
+                if !self.volumes.iter().any(|volume| volume.root == root) {
+                    let volume = Volume {
+                        info: device_info(&root),
+                        root,
+                        session: false,
+                        entries: Vec::new(),
+                        unsettled: HashMap::new(),
+                    };
+                    self.events
+                        .push_back(Event::DeviceAdded(volume.info.clone()));
+                    self.volumes.push(volume);
+                }
+            }
+        }
+
+        for volume in self.volumes.iter_mut().filter(|volume| volume.session) {
+            let current = match catalog(&volume.root) {
+                Ok(current) => current,
+                Err(_) => continue,
+            };
+            let device = volume.info.id.clone();
+            let present: HashSet<&ItemId> = current.iter().map(|entry| &entry.item.id).collect();
+
+            let removed: Vec<ItemId> = volume
+                .entries
+                .iter()
+                .map(|entry| entry.item.id.clone())
+                .filter(|id| !present.contains(id))
+                .collect();
+            volume
+                .entries
+                .retain(|entry| present.contains(&entry.item.id));
+            volume
+                .unsettled
+                .retain(|path, _| current.iter().any(|entry| entry.path == *path));
+
+            let mut added = Vec::new();
+            for mut entry in current {
+                if let Some(old) = volume
+                    .entries
+                    .iter_mut()
+                    .find(|old| old.item.id == entry.item.id)
+                {
+                    // Keep sizes, dates and sidecars up to date without reporting them.
+                    entry.item.added_after_catalog = old.item.added_after_catalog;
+                    *old = entry;
+                    continue;
+                }
+                if !entry.item.is_folder {
+                    let seen = (entry.item.size, entry.item.modification_date);
+                    if volume.unsettled.insert(entry.path.clone(), seen) != Some(seen) {
+                        continue;
+                    }
+                    volume.unsettled.remove(&entry.path);
+                }
+                entry.item.added_after_catalog = true;
+                added.push(entry.item.clone());
+                volume.entries.push(entry);
+            }
+
+            if !removed.is_empty() {
+                self.events.push_back(Event::ItemsRemoved {
+                    device: device.clone(),
+                    items: removed,
+                });
+            }
+            if !added.is_empty() {
+                self.events.push_back(Event::ItemsAdded {
+                    device,
+                    items: added,
+                });
+            }
+        }
+    }
+
+    /// Copy a file and its sidecars for a download, and return the path of the copy.
+    fn save(entry: &Entry, options: &DownloadOptions) -> Result<PathBuf> {
+        let name = options.file_name.as_deref().unwrap_or(&entry.item.name);
+        let path = destination(&options.directory.join(name), options.overwrite);
+        copy(&entry.path, &path)?;
+        if options.sidecar_files {
+            for sidecar in &entry.sidecars {
+                let extension = sidecar.extension().unwrap_or_default();
+                copy(
+                    sidecar,
+                    &destination(&path.with_extension(extension), options.overwrite),
+                )?;
+            }
+        }
+        Ok(path)
+    }
+
+    /// Remove files and their sidecars from a volume and report them.
+    fn remove(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        let volume = self.volume_mut(device)?;
+        let mut removed = Vec::new();
+        let mut result = Ok(());
+        for entry in volume
+            .entries
+            .iter()
+            .filter(|entry| items.contains(&entry.item.id))
+        {
+            // Sidecars go first, so that a file is only reported removed once it is all gone.
+            let outcome = entry
+                .sidecars
+                .iter()
+                .try_for_each(fs::remove_file)
+                .and_then(|_| fs::remove_file(&entry.path));
+            match outcome {
+                Ok(()) => removed.push(entry.item.id.clone()),
+                Err(error) => result = result.and(Err(error.into())),
+            }
+        }
+        volume
+            .entries
+            .retain(|entry| !removed.contains(&entry.item.id));
+        if !removed.is_empty() {
+            self.events.push_back(Event::ItemsRemoved {
+                device: device.clone(),
+                items: removed,
+            });
+        }
+        result
+    }
+}
+
+impl Backend for MassStorageBackend {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        if kinds.contains(&DeviceKind::Camera) && !self.browsing {
+            self.browsing = true;
+            self.poll();
+            self.next_poll = Some(Instant::now() + self.poll_interval);
+        }
+        Ok(())
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        self.browsing = false;
+        Ok(())
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.volumes
+            .iter()
+            .map(|volume| volume.info.clone())
+            .collect()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(event);
+            }
+            if !self.browsing && !self.volumes.iter().any(|volume| volume.session) {
+                return None;
+            }
+            let now = Instant::now();
+            let next_poll = self.next_poll.unwrap_or(now);
+            if next_poll <= now {
+                self.poll();
+                self.next_poll = Some(now + self.poll_interval);
+            } else if next_poll > deadline {
+                thread::sleep(deadline.saturating_duration_since(now));
+                return self.events.pop_front();
+            } else {
+                thread::sleep(next_poll - now);
+            }
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        let volume = self.volume_mut(device)?;
+        let device = device.clone();
+        let result = if volume.session {
+            Err(Error::ReturnCode(ICReturnDeviceFailedToOpenSession))
+        } else {
+            catalog(&volume.root).map_err(Error::from)
+        };
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.events.push_back(Event::SessionOpened {
+                    device,
+                    result: Err(error),
+                });
+                return Ok(());
+            }
+        };
+        volume.session = true;
+        volume.unsettled.clear();
+        let items: Vec<CameraItem> = entries.iter().map(|entry| entry.item.clone()).collect();
+        volume.entries = entries;
+
+        self.events.push_back(Event::SessionOpened {
+            device: device.clone(),
+            result: Ok(()),
+        });
+        self.events.push_back(Event::DeviceReady(device.clone()));
+        if !items.is_empty() {
+            self.events.push_back(Event::ItemsAdded {
+                device: device.clone(),
+                items,
+            });
+        }
+        self.events.push_back(Event::CatalogCompleted(device));
+        if self.next_poll.is_none() {
+            self.next_poll = Some(Instant::now() + self.poll_interval);
+        }
+        Ok(())
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        let volume = self.volume_mut(device)?;
+        let result = if volume.session {
+            volume.session = false;
+            volume.entries.clear();
+            volume.unsettled.clear();
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnDeviceFailedToCloseSession))
+        };
+        self.events.push_back(Event::SessionClosed {
+            device: device.clone(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        Ok(self.volume(device)?.session)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        Ok(self
+            .volume(device)?
+            .entries
+            .iter()
+            .map(|entry| entry.item.clone())
+            .collect())
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let entry = self.file(device, item)?;
+        let result = MassStorageBackend::save(entry, options);
+        let delete = result.is_ok() && options.delete_after_download && !entry.item.is_locked;
+        self.events.push_back(Event::Downloaded {
+            device: device.clone(),
+            item: item.clone(),
+            result,
+        });
+        if delete {
+            // A file that cannot be deleted stays in the catalog.
+            if let Err(error) = self.remove(device, std::slice::from_ref(item)) {
+                self.events.push_back(Event::DeviceError {
+                    device: device.clone(),
+                    error,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        let volume = self.volume(device)?;
+        Ok(CameraStatus {
+            battery_level: None,
+            catalog_percent_completed: if volume.session { 100 } else { 0 },
+            tethered_capture_enabled: false,
+            access_restricted: false,
+        })
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let entry = self.file(device, item)?;
+        let result = (|| {
+            let mut file = fs::File::open(&entry.path)?;
+            if offset > file.metadata()?.len() {
+                return Err(Error::ReturnCode(ICReturnInvalidParam));
+            }
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::new();
+            file.take(length).read_to_end(&mut data)?;
+            Ok(data)
+        })();
+        self.events.push_back(Event::DataRead {
+            device: device.clone(),
+            item: item.clone(),
+            offset,
+            result,
+        });
+        Ok(())
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        for item in items {
+            if self.file(device, item)?.item.is_locked {
+                return Err(Error::ReturnCode(ICReturnDeleteFilesFailed));
+            }
+        }
+        self.remove(device, items)
+    }
+
+    fn upload_file(&mut self, _device: &DeviceId, _path: &Path) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn set_tethering(&mut self, _device: &DeviceId, _enabled: bool) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn take_picture(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn functional_unit_types(
+        &self,
+        _device: &DeviceId,
+    ) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        _device: &DeviceId,
+        _unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn functional_unit(&self, _device: &DeviceId) -> Result<FunctionalUnit> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn configure(
+        &mut self,
+        _device: &DeviceId,
+        _settings: &ScanSettings,
+    ) -> Result<FunctionalUnit> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn request_overview_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn overview_image(&self, _device: &DeviceId) -> Result<Option<Image>> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn request_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn cancel_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+}
+
+fn device_info(root: &Path) -> DeviceInfo {
+    DeviceInfo {
+        id: DeviceId(format!("mass-storage:{}", root.display())),
+        kind: DeviceKind::Camera,
+        name: root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| root.display().to_string()),
+        transport: Some("ICTransportTypeMassStorage".to_owned()),
+        serial_number: None,
+        is_remote: false,
+        capabilities: vec!["ICCameraDeviceCanDeleteOneFile".to_owned()],
+    }
+}
+
+/// The `DCIM` folder of a volume, whatever the case of its name.
+fn dcim_folder(root: &Path) -> Option<PathBuf> {
+    fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(DCIM)
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+}
+
+/// The items of a volume: the `DCIM` folder, followed by each folder's files and then its
+/// subfolders.
+fn catalog(root: &Path) -> io::Result<Vec<Entry>> {
+    let dcim = dcim_folder(root).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let mut entries = Vec::new();
+    add_folder(root, &dcim, None, &mut entries)?;
+    Ok(entries)
+}
+
+fn add_folder(
+    root: &Path,
+    path: &Path,
+    parent: Option<ItemId>,
+    entries: &mut Vec<Entry>,
+) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    let id = item_id(root, path);
+    entries.push(Entry {
+        item: CameraItem {
+            is_folder: true,
+            uti: Some("public.folder".to_owned()),
+            ..item(id.clone(), path, parent, &metadata)
+        },
+        path: path.to_owned(),
+        sidecars: Vec::new(),
+    });
+
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    // Hidden files, such as the resource forks macOS writes to FAT volumes, are not images.
+    paths.retain(|path| !file_name(path).starts_with('.'));
+    paths.sort();
+
+    let (folders, files): (Vec<PathBuf>, Vec<PathBuf>) =
+        paths.into_iter().partition(|path| path.is_dir());
+    let (sidecars, files): (Vec<PathBuf>, Vec<PathBuf>) =
+        files.into_iter().partition(|path| is_sidecar(path));
+    let mut orphans = Vec::new();
+    let mut attached: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for sidecar in sidecars {
+        match files.iter().find(|file| same_stem(file, &sidecar)) {
+            Some(file) => attached.entry(file.clone()).or_default().push(sidecar),
+            None => orphans.push(sidecar),
+        }
+    }
+
+    let mut files: Vec<PathBuf> = files
+        .into_iter()
+        .chain(orphans)
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    for file in files {
+        let metadata = match fs::metadata(&file) {
+            Ok(metadata) => metadata,
+            // The file went away while the folder was read.
+            Err(_) => continue,
+        };
+        let sidecars = attached.remove(&file).unwrap_or_default();
+        let name = file_name(&file);
+        let uti = uti_for_name(&name);
+        entries.push(Entry {
+            item: CameraItem {
+                uti: Some(uti.to_owned()),
+                size: metadata.len(),
+                is_raw: is_raw_uti(uti),
+                is_locked: metadata.permissions().readonly(),
+                sidecar_files: sidecars.iter().map(|sidecar| file_name(sidecar)).collect(),
+                ..item(item_id(root, &file), &file, Some(id.clone()), &metadata)
+            },
+            path: file,
+            sidecars,
+        });
+    }
+    for folder in folders {
+        add_folder(root, &folder, Some(id.clone()), entries)?;
+    }
+    Ok(())
+}
+
+/// An item with the name and dates of a file.
+fn item(id: ItemId, path: &Path, parent: Option<ItemId>, metadata: &fs::Metadata) -> CameraItem {
+    CameraItem {
+        id,
+        name: file_name(path),
+        parent,
+        is_folder: false,
+        uti: None,
+        size: 0,
+        orientation: ICEXIFOrientation1,
+        is_raw: false,
+        is_locked: false,
+        creation_date: metadata.created().ok(),
+        modification_date: metadata.modified().ok(),
+        ptp_object_handle: 0,
+        sidecar_files: Vec::new(),
+        added_after_catalog: false,
+    }
+}
+
+/// The identifier of an item: its path relative to the volume, with `/` between components.
+fn item_id(root: &Path, path: &Path) -> ItemId {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    ItemId(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Copy a file, keeping its modification date where the platform allows it.
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to)?;
+    if let Ok(modified) = fs::metadata(from).and_then(|metadata| metadata.modified()) {
+        set_modified(to, modified)?;
+    }
+    Ok(())
+}
+
+/// Set the access and modification dates of a file, to the microsecond.
+#[cfg(unix)]
+fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let since_epoch = match modified.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch,
+        Err(_) => return Ok(()),
+    };
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let time = libc::timeval {
+        tv_sec: since_epoch.as_secs() as libc::time_t,
+        tv_usec: since_epoch.subsec_micros() as libc::suseconds_t,
+    };
+    if unsafe { libc::utimes(path.as_ptr(), [time, time].as_ptr()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_modified(_path: &Path, _modified: SystemTime) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::temp_dir;
+
+    /// A volume in a new temporary directory with a raw image and its sidecar, and a JPEG image.
+    fn volume(name: &str) -> PathBuf {
+        let root = temp_dir(name);
+        let folder = root.join(DCIM).join("100CANON");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("IMG_0001.CR2"), b"raw").unwrap();
+        fs::write(folder.join("IMG_0001.XMP"), b"xmp").unwrap();
+        fs::write(folder.join("IMG_0002.JPG"), b"jpeg").unwrap();
+        root
+    }
+
+    /// A backend with an open session on a volume, with its events drained.
+    fn open(root: &Path) -> (MassStorageBackend, DeviceId) {
+        let mut backend = MassStorageBackend::new()
+            .with_volume(root)
+            .with_poll_interval(Duration::from_millis(5));
+        backend.start_browsing(&[DeviceKind::Camera]).unwrap();
+        let id = backend.devices()[0].id.clone();
+        backend.open_session(&id).unwrap();
+        backend.events.clear();
+        (backend, id)
+    }
+
+    fn id(path: &str) -> ItemId {
+        ItemId(path.to_owned())
+    }
+
+    #[test]
+    fn catalog_attaches_sidecars_to_images() {
+        let root = volume("mass-storage-catalog");
+        let (backend, device) = open(&root);
+        let items = backend.camera_items(&device).unwrap();
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![DCIM, "100CANON", "IMG_0001.CR2", "IMG_0002.JPG"]
+        );
+
+        let raw = &items[2];
+        assert_eq!(raw.id, id("DCIM/100CANON/IMG_0001.CR2"));
+        assert_eq!(raw.parent, Some(id("DCIM/100CANON")));
+        assert!(raw.is_raw);
+        assert_eq!(raw.sidecar_files, vec!["IMG_0001.XMP".to_owned()]);
+        assert!(!items[3].is_raw);
+        assert_eq!(backend.mount_point(&device), Some(root.as_path()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn new_files_are_reported_once_they_settle() {
+        let root = volume("mass-storage-watch");
+        let (mut backend, device) = open(&root);
+        let path = root.join(DCIM).join("100CANON").join("IMG_0003.JPG");
+        fs::write(&path, b"partial").unwrap();
+
+        let poll = |backend: &mut MassStorageBackend| {
+            thread::sleep(Duration::from_millis(10));
+            backend.next_event(Duration::from_secs(0))
+        };
+        assert_eq!(poll(&mut backend), None);
+        fs::write(&path, b"complete file").unwrap();
+        assert_eq!(poll(&mut backend), None);
+        match poll(&mut backend) {
+            Some(Event::ItemsAdded { items, .. }) => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].id, id("DCIM/100CANON/IMG_0003.JPG"));
+                assert_eq!(items[0].size, 13);
+                assert!(items[0].added_after_catalog);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            poll(&mut backend),
+            Some(Event::ItemsRemoved {
+                device,
+                items: vec![id("DCIM/100CANON/IMG_0003.JPG")],
+            })
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn downloads_copy_sidecars_and_delete_the_originals() {
+        let root = volume("mass-storage-download");
+        let target = root.join("imported");
+        fs::create_dir_all(&target).unwrap();
+        let (mut backend, device) = open(&root);
+        let raw = id("DCIM/100CANON/IMG_0001.CR2");
+        let taken = backend.camera_items(&device).unwrap()[2]
+            .modification_date
+            .unwrap();
+        let options = DownloadOptions::new(&target)
+            .with_sidecar_files(true)
+            .with_delete_after_download(true);
+        backend.download_file(&device, &raw, &options).unwrap();
+
+        let events: Vec<Event> = backend.events.drain(..).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Downloaded {
+                    device: device.clone(),
+                    item: raw.clone(),
+                    result: Ok(target.join("IMG_0001.CR2")),
+                },
+                Event::ItemsRemoved {
+                    device: device.clone(),
+                    items: vec![raw],
+                },
+            ]
+        );
+        assert_eq!(fs::read(target.join("IMG_0001.CR2")).unwrap(), b"raw");
+        assert_eq!(fs::read(target.join("IMG_0001.XMP")).unwrap(), b"xmp");
+        if cfg!(unix) {
+            let copied = fs::metadata(target.join("IMG_0001.CR2"))
+                .and_then(|metadata| metadata.modified())
+                .unwrap();
+            let difference = copied
+                .duration_since(taken)
+                .or_else(|_| taken.duration_since(copied))
+                .unwrap();
+            assert!(difference < Duration::from_millis(1));
+        }
+        let folder = root.join(DCIM).join("100CANON");
+        assert!(!folder.join("IMG_0001.CR2").exists());
+        assert!(!folder.join("IMG_0001.XMP").exists());
+        assert_eq!(backend.camera_items(&device).unwrap().len(), 3);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failed_deletes_after_download_are_reported() {
+        let root = volume("mass-storage-delete");
+        let target = root.join("imported");
+        fs::create_dir_all(&target).unwrap();
+        let (mut backend, device) = open(&root);
+        let folder = root.join(DCIM).join("100CANON");
+        // The sidecar goes away behind the back of the backend, so it cannot be deleted.
+        fs::remove_file(folder.join("IMG_0001.XMP")).unwrap();
+        let raw = id("DCIM/100CANON/IMG_0001.CR2");
+        let options = DownloadOptions::new(&target).with_delete_after_download(true);
+        backend.download_file(&device, &raw, &options).unwrap();
+
+        let events: Vec<Event> = backend.events.drain(..).collect();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                Event::Downloaded { result: Ok(_), .. },
+                Event::DeviceError {
+                    error: Error::Io { .. },
+                    ..
+                },
+            ]
+        ));
+        assert!(folder.join("IMG_0001.CR2").exists());
+        assert!(backend.file_system_path(&device, &raw).is_some());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
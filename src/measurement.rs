@@ -0,0 +1,277 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerMeasurementUnit;
+use crate::constants::ICScannerMeasurementUnit::*;
+use crate::document_size::DocumentSize;
+use crate::error::{Error, Result};
+
+#[cfg(target_os = "macos")]
+use cocoa::foundation::{NSPoint, NSRect, NSSize};
+
+/// Distance below which a converted value is snapped to the nearest integer before rounding.
+/// This keeps values such as `2.9999999999999996` from rounding up or down to the wrong integer.
+const SNAP_EPSILON: f64 = 1e-9;
+
+/// How a converted value is rounded to an integer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rounding {
+    /// Round to the nearest integer, with halfway values rounded away from zero.
+    Nearest,
+    /// Round towards negative infinity.
+    Down,
+    /// Round towards positive infinity.
+    Up,
+}
+
+impl Rounding {
+    /// Round `value` to an integer. Values within `1e-9` of an integer are snapped to it first.
+    pub fn apply(self, value: f64) -> f64 {
+        let nearest = value.round();
+        if (value - nearest).abs() < SNAP_EPSILON {
+            return nearest;
+        }
+        match self {
+            Rounding::Nearest => nearest,
+            Rounding::Down => value.floor(),
+            Rounding::Up => value.ceil(),
+        }
+    }
+}
+
+/// The size of one unit as an exact fraction of a twip (1/1440 inch).
+fn twips_per_unit(unit: ICScannerMeasurementUnit, resolution: u64) -> Result<(u64, u64)> {
+    Ok(match unit {
+        ICScannerMeasurementUnitInches => (1440, 1),
+        ICScannerMeasurementUnitCentimeters => (72000, 127),
+        ICScannerMeasurementUnitPicas => (240, 1),
+        ICScannerMeasurementUnitPoints => (20, 1),
+        ICScannerMeasurementUnitTwips => (1, 1),
+        ICScannerMeasurementUnitPixels if resolution > 0 => (1440, resolution),
+        _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+    })
+}
+
+/// Convert `value` between units. `resolution` is the number of pixels per inch and is
+/// only used when either unit is `ICScannerMeasurementUnitPixels`.
+///
+/// The scale factor is reduced to lowest terms first, so conversions between units with a
+/// terminating ratio (for example inches to points, or centimetres to twips) are exact for
+/// values that can be represented exactly.
+pub fn convert(
+    value: f64,
+    from: ICScannerMeasurementUnit,
+    to: ICScannerMeasurementUnit,
+    resolution: u64,
+) -> Result<f64> {
+    if from == to {
+        return Ok(value);
+    }
+    let (from_num, from_den) = twips_per_unit(from, resolution)?;
+    let (to_num, to_den) = twips_per_unit(to, resolution)?;
+    let num = u128::from(from_num) * u128::from(to_den);
+    let den = u128::from(from_den) * u128::from(to_num);
+    let common = gcd(num, den);
+    let (num, den) = (num / common, den / common);
+    if num == 1 {
+        Ok(value / den as f64)
+    } else if den == 1 {
+        Ok(value * num as f64)
+    } else {
+        Ok(value * num as f64 / den as f64)
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// A length in one of the scanner measurement units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Length {
+    /// The length in `unit`.
+    pub value: f64,
+    /// The unit of `value`.
+    pub unit: ICScannerMeasurementUnit,
+}
+
+impl Length {
+    /// Create a length.
+    pub fn new(value: f64, unit: ICScannerMeasurementUnit) -> Length {
+        Length { value, unit }
+    }
+
+    /// Create a length from a value in millimetres.
+    pub fn from_millimeters(value: f64) -> Length {
+        Length::new(value / 10.0, ICScannerMeasurementUnitCentimeters)
+    }
+
+    /// Convert the length to another unit. `resolution` is used for `ICScannerMeasurementUnitPixels`.
+    pub fn to(self, unit: ICScannerMeasurementUnit, resolution: u64) -> Result<Length> {
+        Ok(Length::new(
+            convert(self.value, self.unit, unit, resolution)?,
+            unit,
+        ))
+    }
+
+    /// The length in millimetres. `resolution` is used for `ICScannerMeasurementUnitPixels`.
+    pub fn to_millimeters(self, resolution: u64) -> Result<f64> {
+        Ok(convert(
+            self.value,
+            self.unit,
+            ICScannerMeasurementUnitCentimeters,
+            resolution,
+        )? * 10.0)
+    }
+
+    /// The length as a whole number of pixels at `resolution`.
+    pub fn to_pixels(self, resolution: u64, rounding: Rounding) -> Result<i64> {
+        let pixels = convert(
+            self.value,
+            self.unit,
+            ICScannerMeasurementUnitPixels,
+            resolution,
+        )?;
+        Ok(rounding.apply(pixels) as i64)
+    }
+
+    /// The length with its value rounded to a whole number of its unit.
+    pub fn rounded(self, rounding: Rounding) -> Length {
+        Length::new(rounding.apply(self.value), self.unit)
+    }
+}
+
+/// A rectangle to be scanned, such as the scan area of a functional unit, in one of the scanner measurement units.
+/// The origin is the top left corner of the scan bed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanRect {
+    /// Distance of the left edge from the origin.
+    pub x: f64,
+    /// Distance of the top edge from the origin.
+    pub y: f64,
+    /// Width of the rectangle.
+    pub width: f64,
+    /// Height of the rectangle.
+    pub height: f64,
+    /// The unit of all four values.
+    pub unit: ICScannerMeasurementUnit,
+}
+
+/// A rectangle in whole pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PixelRect {
+    /// Left edge in pixels.
+    pub x: i64,
+    /// Top edge in pixels.
+    pub y: i64,
+    /// Width in pixels.
+    pub width: i64,
+    /// Height in pixels.
+    pub height: i64,
+}
+
+impl ScanRect {
+    /// Create a rectangle.
+    pub fn new(
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        unit: ICScannerMeasurementUnit,
+    ) -> ScanRect {
+        ScanRect {
+            x,
+            y,
+            width,
+            height,
+            unit,
+        }
+    }
+
+    /// Create a rectangle at the origin covering a document of the given size.
+    pub fn from_document_size(
+        size: DocumentSize,
+        unit: ICScannerMeasurementUnit,
+        resolution: u64,
+    ) -> Result<ScanRect> {
+        let width = Length::from_millimeters(size.width_mm()).to(unit, resolution)?;
+        let height = Length::from_millimeters(size.height_mm()).to(unit, resolution)?;
+        Ok(ScanRect::new(0.0, 0.0, width.value, height.value, unit))
+    }
+
+    /// Convert the rectangle to another unit. `resolution` is used for `ICScannerMeasurementUnitPixels`.
+    pub fn to(self, unit: ICScannerMeasurementUnit, resolution: u64) -> Result<ScanRect> {
+        let convert = |value| convert(value, self.unit, unit, resolution);
+        Ok(ScanRect::new(
+            convert(self.x)?,
+            convert(self.y)?,
+            convert(self.width)?,
+            convert(self.height)?,
+            unit,
+        ))
+    }
+
+    /// The rectangle in whole pixels at `resolution`.
+    ///
+    /// Each edge is rounded to the nearest pixel and the size is the distance between the rounded
+    /// edges, so adjacent rectangles map to adjacent pixel rectangles without gaps or overlap.
+    pub fn to_pixels(self, resolution: u64) -> Result<PixelRect> {
+        let rect = self.to(ICScannerMeasurementUnitPixels, resolution)?;
+        let left = Rounding::Nearest.apply(rect.x);
+        let top = Rounding::Nearest.apply(rect.y);
+        let right = Rounding::Nearest.apply(rect.x + rect.width);
+        let bottom = Rounding::Nearest.apply(rect.y + rect.height);
+        Ok(PixelRect {
+            x: left as i64,
+            y: top as i64,
+            width: (right - left) as i64,
+            height: (bottom - top) as i64,
+        })
+    }
+
+    /// The physical size of the rectangle.
+    pub fn document_size(self, resolution: u64) -> Result<DocumentSize> {
+        Ok(DocumentSize::from_millimeters(
+            Length::new(self.width, self.unit).to_millimeters(resolution)?,
+            Length::new(self.height, self.unit).to_millimeters(resolution)?,
+        ))
+    }
+
+    /// Create a rectangle from an `NSRect` expressed in `unit`.
+    #[cfg(target_os = "macos")]
+    pub fn from_ns_rect(rect: NSRect, unit: ICScannerMeasurementUnit) -> ScanRect {
+        ScanRect::new(
+            rect.origin.x,
+            rect.origin.y,
+            rect.size.width,
+            rect.size.height,
+            unit,
+        )
+    }
+
+    /// The rectangle as an `NSRect`, for use with `setScanArea`.
+    #[cfg(target_os = "macos")]
+    pub fn to_ns_rect(self) -> NSRect {
+        NSRect::new(
+            NSPoint::new(self.x, self.y),
+            NSSize::new(self.width, self.height),
+        )
+    }
+}
+
+impl PixelRect {
+    /// The rectangle in `unit` at `resolution`.
+    pub fn to_scan_rect(self, unit: ICScannerMeasurementUnit, resolution: u64) -> Result<ScanRect> {
+        ScanRect::new(
+            self.x as f64,
+            self.y as f64,
+            self.width as f64,
+            self.height as f64,
+            ICScannerMeasurementUnitPixels,
+        )
+        .to(unit, resolution)
+    }
+}
@@ -0,0 +1,492 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::error::{Error, Result};
+
+/// A word recognised on a scanned page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcrWord {
+    /// The text of the word.
+    pub text: String,
+    /// Left, top, right and bottom edges of the word, with the origin at the top left corner
+    /// of the page.
+    pub bounds: (f64, f64, f64, f64),
+    /// Vertical position of the baseline the word sits on, if the OCR engine reported one.
+    pub baseline: Option<f64>,
+}
+
+/// The text an OCR engine recognised on a scanned page, read from hOCR or ALTO.
+///
+/// Coordinates are those of the OCR document and refer to the page image as stored, before any
+/// orientation is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcrPage {
+    /// Width and height of the page in the coordinates of the words, if the document gives them.
+    /// Words are scaled from this size to the size of the image.
+    pub size: Option<(f64, f64)>,
+    /// Number of coordinate units per inch, for documents that measure in physical units. Used
+    /// with the scan resolution when the document does not give the page size. `None` means
+    /// coordinates are image pixels.
+    pub units_per_inch: Option<f64>,
+    /// The recognised words, in reading order.
+    pub words: Vec<OcrWord>,
+}
+
+impl OcrPage {
+    /// Read the first page of an hOCR document. HTML end tags may be left out, as in HTML.
+    /// Fails if a tag cannot be read.
+    pub fn from_hocr(hocr: &str) -> Result<OcrPage> {
+        let mut page = OcrPage {
+            size: None,
+            units_per_inch: None,
+            words: Vec::new(),
+        };
+        // The class of each open element, and the baseline of the innermost open line.
+        let mut open: Vec<(String, Option<Baseline>)> = Vec::new();
+        let mut word: Option<(usize, OcrWord)> = None;
+        let mut pages = 0;
+        for event in XmlReader::new(hocr) {
+            match event? {
+                XmlEvent::Start {
+                    name,
+                    attributes,
+                    empty,
+                } => {
+                    let class = attribute(&attributes, "class").unwrap_or("");
+                    let title = attribute(&attributes, "title").unwrap_or("");
+                    let classes: Vec<&str> = class.split_whitespace().collect();
+                    let bbox = title_property(title, "bbox").filter(|values| values.len() == 4);
+                    let mut line = open.last().and_then(|&(_, line)| line);
+                    if classes.contains(&"ocr_page") {
+                        pages += 1;
+                        if pages == 1 {
+                            page.size = bbox.as_ref().map(|b| (b[2], b[3]));
+                        }
+                    }
+                    let is_line = classes.iter().any(|class| {
+                        matches!(
+                            *class,
+                            "ocr_line"
+                                | "ocrx_line"
+                                | "ocr_caption"
+                                | "ocr_header"
+                                | "ocr_textfloat"
+                        )
+                    });
+                    if is_line {
+                        // `baseline slope offset` is relative to the bottom left corner of the line.
+                        line = match (&bbox, title_property(title, "baseline")) {
+                            (Some(b), Some(baseline)) if baseline.len() == 2 => {
+                                Some((b[0], b[3] + baseline[1], baseline[0]))
+                            }
+                            _ => None,
+                        };
+                    }
+                    if pages == 1 && word.is_none() && classes.contains(&"ocrx_word") {
+                        if let Some(b) = bbox {
+                            let baseline =
+                                line.map(|(x, y, slope)| y + slope * ((b[0] + b[2]) / 2.0 - x));
+                            word = Some((
+                                open.len(),
+                                OcrWord {
+                                    text: String::new(),
+                                    bounds: (b[0], b[1], b[2], b[3]),
+                                    baseline,
+                                },
+                            ));
+                        }
+                    }
+                    if empty || VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                        finish_word(&mut word, open.len(), &mut page.words);
+                    } else {
+                        open.push((name, line));
+                    }
+                }
+                XmlEvent::End { name } => {
+                    // Close any elements whose end tags were left out.
+                    if let Some(depth) = open.iter().rposition(|(open_name, _)| *open_name == name)
+                    {
+                        while open.len() > depth {
+                            open.pop();
+                            finish_word(&mut word, open.len(), &mut page.words);
+                        }
+                    }
+                }
+                XmlEvent::Text(text) => {
+                    if let Some((_, ref mut word)) = word {
+                        word.text.push_str(&text);
+                    }
+                }
+            }
+        }
+        while !open.is_empty() {
+            open.pop();
+            finish_word(&mut word, open.len(), &mut page.words);
+        }
+        Ok(page)
+    }
+
+    /// Read the first page of an ALTO document. Fails if the document is not well formed.
+    pub fn from_alto(alto: &str) -> Result<OcrPage> {
+        let mut page = OcrPage {
+            size: None,
+            units_per_inch: None,
+            words: Vec::new(),
+        };
+        let mut open: Vec<String> = Vec::new();
+        let mut unit = String::new();
+        let mut pages = 0;
+        let mut line_baseline = None;
+        for event in XmlReader::new(alto) {
+            match event? {
+                XmlEvent::Start {
+                    name,
+                    attributes,
+                    empty,
+                } => {
+                    let number = |key: &str| {
+                        attribute(&attributes, key).and_then(|value| value.trim().parse().ok())
+                    };
+                    match local_name(&name) {
+                        "Page" => {
+                            pages += 1;
+                            if pages == 1 {
+                                page.size = number("WIDTH").zip(number("HEIGHT"));
+                            }
+                        }
+                        // Older versions give the baseline as a single vertical position.
+                        "TextLine" => line_baseline = number("BASELINE"),
+                        "String" if pages == 1 => {
+                            let bounds = (
+                                number("HPOS"),
+                                number("VPOS"),
+                                number("WIDTH"),
+                                number("HEIGHT"),
+                            );
+                            let text = attribute(&attributes, "CONTENT").unwrap_or("");
+                            if let (Some(x), Some(y), Some(width), Some(height)) = bounds {
+                                page.words.push(OcrWord {
+                                    text: text.to_owned(),
+                                    bounds: (x, y, x + width, y + height),
+                                    baseline: line_baseline,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                    if !empty {
+                        open.push(name);
+                    }
+                }
+                XmlEvent::End { name } => {
+                    if open.pop().as_ref() != Some(&name) {
+                        return Err(Error::ReturnCode(ICReturnInvalidParam));
+                    }
+                }
+                XmlEvent::Text(text) => {
+                    if open.last().map(|name| local_name(name)) == Some("MeasurementUnit") {
+                        unit.push_str(&text);
+                    }
+                }
+            }
+        }
+        if !open.is_empty() {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        page.units_per_inch = match unit.trim() {
+            "mm10" => Some(254.0),
+            "inch1200" => Some(1200.0),
+            _ => None,
+        };
+        Ok(page)
+    }
+}
+
+/// The baseline of an hOCR line: the left edge of the line, the vertical position of the
+/// baseline there, and its slope.
+type Baseline = (f64, f64, f64);
+
+/// HTML elements that have no end tag.
+const VOID_ELEMENTS: [&str; 6] = ["br", "hr", "img", "input", "link", "meta"];
+
+fn finish_word(word: &mut Option<(usize, OcrWord)>, depth: usize, words: &mut Vec<OcrWord>) {
+    if let Some((word_depth, _)) = *word {
+        if word_depth == depth {
+            let (_, mut finished) = word.take().expect("word is open");
+            finished.text = finished.text.trim().to_owned();
+            if !finished.text.is_empty() {
+                words.push(finished);
+            }
+        }
+    }
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The numbers of a property of an hOCR `title`, such as `bbox 10 20 30 40; x_wconf 95`.
+fn title_property(title: &str, key: &str) -> Option<Vec<f64>> {
+    title.split(';').find_map(|property| {
+        let mut values = property.split_whitespace();
+        if values.next()? != key {
+            return None;
+        }
+        values.map(|value| value.parse().ok()).collect()
+    })
+}
+
+/// The name of an element without its namespace prefix.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// An event of `XmlReader`.
+#[derive(Debug)]
+enum XmlEvent {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        empty: bool,
+    },
+    End {
+        name: String,
+    },
+    Text(String),
+}
+
+/// A reader of the elements and text of an XML or XHTML document. Comments, processing
+/// instructions and the document type are skipped.
+struct XmlReader<'a> {
+    rest: &'a str,
+}
+
+impl<'a> XmlReader<'a> {
+    fn new(document: &'a str) -> XmlReader<'a> {
+        XmlReader { rest: document }
+    }
+
+    fn skip_to(&mut self, end: &str) -> Result<()> {
+        let position = self
+            .rest
+            .find(end)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        self.rest = &self.rest[position + end.len()..];
+        Ok(())
+    }
+
+    fn tag(&mut self) -> Result<XmlEvent> {
+        let invalid = || Error::ReturnCode(ICReturnInvalidParam);
+        let closing = self.rest.starts_with("</");
+        self.rest = &self.rest[if closing { 2 } else { 1 }..];
+        let name_end = self
+            .rest
+            .find(|ch: char| ch.is_whitespace() || ch == '>' || ch == '/')
+            .ok_or_else(invalid)?;
+        let name = self.rest[..name_end].to_owned();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        self.rest = &self.rest[name_end..];
+        let mut attributes = Vec::new();
+        loop {
+            self.rest = self.rest.trim_start();
+            if let Some(rest) = self.rest.strip_prefix("/>") {
+                self.rest = rest;
+                return Ok(XmlEvent::Start {
+                    name,
+                    attributes,
+                    empty: true,
+                });
+            }
+            if let Some(rest) = self.rest.strip_prefix('>') {
+                self.rest = rest;
+                return Ok(if closing {
+                    XmlEvent::End { name }
+                } else {
+                    XmlEvent::Start {
+                        name,
+                        attributes,
+                        empty: false,
+                    }
+                });
+            }
+            let equals = self.rest.find('=').ok_or_else(invalid)?;
+            let key = self.rest[..equals].trim().to_owned();
+            self.rest = self.rest[equals + 1..].trim_start();
+            let quote = self.rest.chars().next().ok_or_else(invalid)?;
+            if quote != '"' && quote != '\'' {
+                return Err(invalid());
+            }
+            let value_end = self.rest[1..].find(quote).ok_or_else(invalid)?;
+            attributes.push((key, unescape(&self.rest[1..1 + value_end])));
+            self.rest = &self.rest[value_end + 2..];
+        }
+    }
+}
+
+impl<'a> Iterator for XmlReader<'a> {
+    type Item = Result<XmlEvent>;
+
+    fn next(&mut self) -> Option<Result<XmlEvent>> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let skipped = if self.rest.starts_with("<!--") {
+                Some("-->")
+            } else if self.rest.starts_with("<?") {
+                Some("?>")
+            } else if self.rest.starts_with("<![CDATA[") {
+                let end = match self.rest.find("]]>") {
+                    Some(end) => end,
+                    None => return Some(Err(Error::ReturnCode(ICReturnInvalidParam))),
+                };
+                let text = self.rest[9..end].to_owned();
+                self.rest = &self.rest[end + 3..];
+                return Some(Ok(XmlEvent::Text(text)));
+            } else if self.rest.starts_with("<!") {
+                Some(">")
+            } else {
+                None
+            };
+            if let Some(end) = skipped {
+                if let Err(error) = self.skip_to(end) {
+                    return Some(Err(error));
+                }
+                continue;
+            }
+            if self.rest.starts_with('<') {
+                return Some(self.tag());
+            }
+            let end = self.rest.find('<').unwrap_or(self.rest.len());
+            let text = unescape(&self.rest[..end]);
+            self.rest = &self.rest[end..];
+            return Some(Ok(XmlEvent::Text(text)));
+        }
+    }
+}
+
+/// Replace the predefined and numeric character references of XML.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let ch = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match ch {
+            Some(ch) => {
+                unescaped.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hocr_words_take_bbox_titles() {
+        let hocr = r#"<html><body>
+            <div class="ocr_page" title="image scan.tiff; bbox 0 0 2480 3508; ppageno 0">
+              <span class="ocr_line" title="bbox 100 200 500 240; baseline 0.01 -8">
+                <span class="ocrx_word" title="bbox 100 200 260 240; x_wconf 96">Caf&#233; &amp;</span>
+                <span class="ocrx_word" title="bbox 300 205 500 238"><strong>Bar</strong></span>
+              </span>
+              <p><span class="ocrx_word" title="x_wconf 10">unplaced</span><br>
+            </div>
+            <div class="ocr_page" title="bbox 0 0 100 100">
+              <span class="ocrx_word" title="bbox 1 1 2 2">second</span>
+            </div>
+            </body></html>"#;
+        let page = OcrPage::from_hocr(hocr).unwrap();
+        assert_eq!(page.size, Some((2480.0, 3508.0)));
+        assert_eq!(page.units_per_inch, None);
+        let texts: Vec<&str> = page.words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, ["Café &", "Bar"]);
+        assert_eq!(page.words[0].bounds, (100.0, 200.0, 260.0, 240.0));
+        assert_eq!(page.words[1].bounds, (300.0, 205.0, 500.0, 238.0));
+        // The baseline is 8 pixels above the bottom of the line, rising to the right.
+        assert_eq!(page.words[0].baseline, Some(232.0 + 0.01 * 80.0));
+        assert_eq!(page.words[1].baseline, Some(232.0 + 0.01 * 300.0));
+    }
+
+    #[test]
+    fn alto_strings_take_positions_and_units() {
+        let alto = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <alto xmlns="http://www.loc.gov/standards/alto/ns-v4#">
+              <Description><MeasurementUnit>mm10</MeasurementUnit></Description>
+              <Layout>
+                <Page ID="p1" HEIGHT="2970" WIDTH="2100">
+                  <PrintSpace>
+                    <TextLine BASELINE="330">
+                      <String CONTENT="Hello" HPOS="100" VPOS="300" WIDTH="250" HEIGHT="40"/>
+                      <SP/>
+                      <String CONTENT="&quot;world&quot;" HPOS="380" VPOS="302" WIDTH="300"
+                              HEIGHT="38"/>
+                      <String CONTENT="nowhere"/>
+                    </TextLine>
+                  </PrintSpace>
+                </Page>
+              </Layout>
+            </alto>"#;
+        let page = OcrPage::from_alto(alto).unwrap();
+        assert_eq!(page.size, Some((2100.0, 2970.0)));
+        assert_eq!(page.units_per_inch, Some(254.0));
+        assert_eq!(
+            page.words,
+            [
+                OcrWord {
+                    text: "Hello".to_owned(),
+                    bounds: (100.0, 300.0, 350.0, 340.0),
+                    baseline: Some(330.0),
+                },
+                OcrWord {
+                    text: "\"world\"".to_owned(),
+                    bounds: (380.0, 302.0, 680.0, 340.0),
+                    baseline: Some(330.0),
+                },
+            ]
+        );
+
+        let pixels = "<alto><Description><MeasurementUnit>pixel</MeasurementUnit></Description>\
+                      </alto>";
+        assert_eq!(OcrPage::from_alto(pixels).unwrap().units_per_inch, None);
+        let inches = "<alto><Description><MeasurementUnit>inch1200</MeasurementUnit>\
+                      </Description></alto>";
+        assert_eq!(
+            OcrPage::from_alto(inches).unwrap().units_per_inch,
+            Some(1200.0)
+        );
+        assert!(OcrPage::from_alto("<alto><Layout></alto>").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::constants::ICEXIFOrientationType;
+use crate::constants::ICEXIFOrientationType::*;
+use crate::image::ImageBuffer;
+
+/// Clockwise rotation by a multiple of 90°.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    /// No rotation.
+    Rotate0,
+    /// Rotation by 90° clockwise.
+    Rotate90,
+    /// Rotation by 180°.
+    Rotate180,
+    /// Rotation by 270° clockwise, or 90° counter-clockwise.
+    Rotate270,
+}
+
+impl Rotation {
+    /// The rotation angle in degrees, clockwise.
+    pub fn degrees(self) -> u32 {
+        match self {
+            Rotation::Rotate0 => 0,
+            Rotation::Rotate90 => 90,
+            Rotation::Rotate180 => 180,
+            Rotation::Rotate270 => 270,
+        }
+    }
+}
+
+/// The operations that turn an image stored with a given orientation into an upright image:
+/// an optional horizontal flip, followed by a clockwise rotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OrientationTransform {
+    /// Mirror the image left to right before rotating it.
+    pub flip_horizontal: bool,
+    /// Rotation applied after the optional flip.
+    pub rotation: Rotation,
+}
+
+/// A 2x3 affine matrix `[[a, b, tx], [c, d, ty]]` that maps a point `(x, y)` to
+/// `(a * x + b * y + tx, c * x + d * y + ty)`.
+pub type AffineMatrix = [[f64; 3]; 2];
+
+/// Orientations with the matrix that maps centred stored coordinates to centred upright
+/// coordinates, with y increasing downwards.
+const MATRICES: [(ICEXIFOrientationType, [[i8; 2]; 2]); 8] = [
+    (ICEXIFOrientation1, [[1, 0], [0, 1]]),
+    (ICEXIFOrientation2, [[-1, 0], [0, 1]]),
+    (ICEXIFOrientation3, [[-1, 0], [0, -1]]),
+    (ICEXIFOrientation4, [[1, 0], [0, -1]]),
+    (ICEXIFOrientation5, [[0, 1], [1, 0]]),
+    (ICEXIFOrientation6, [[0, -1], [1, 0]]),
+    (ICEXIFOrientation7, [[0, -1], [-1, 0]]),
+    (ICEXIFOrientation8, [[0, 1], [-1, 0]]),
+];
+
+fn multiply(a: [[i8; 2]; 2], b: [[i8; 2]; 2]) -> [[i8; 2]; 2] {
+    [
+        [
+            a[0][0] * b[0][0] + a[0][1] * b[1][0],
+            a[0][0] * b[0][1] + a[0][1] * b[1][1],
+        ],
+        [
+            a[1][0] * b[0][0] + a[1][1] * b[1][0],
+            a[1][0] * b[0][1] + a[1][1] * b[1][1],
+        ],
+    ]
+}
+
+fn from_matrix(matrix: [[i8; 2]; 2]) -> ICEXIFOrientationType {
+    MATRICES
+        .iter()
+        .find(|entry| entry.1 == matrix)
+        .map(|entry| entry.0)
+        .expect("orientation matrices form a closed group")
+}
+
+impl ICEXIFOrientationType {
+    /// The matrix that maps centred stored coordinates to centred upright coordinates.
+    fn matrix(self) -> [[i8; 2]; 2] {
+        MATRICES
+            .iter()
+            .find(|entry| entry.0 == self)
+            .map(|entry| entry.1)
+            .expect("every orientation has a matrix")
+    }
+
+    /// Indicates whether the upright image has the width and height of the stored image swapped.
+    pub fn swaps_dimensions(self) -> bool {
+        self.matrix()[0][0] == 0
+    }
+
+    /// The orientation equivalent to correcting an image for `self` and then for `next`.
+    pub fn compose(self, next: ICEXIFOrientationType) -> ICEXIFOrientationType {
+        from_matrix(multiply(next.matrix(), self.matrix()))
+    }
+
+    /// The orientation that undoes this one.
+    pub fn inverse(self) -> ICEXIFOrientationType {
+        let m = self.matrix();
+        from_matrix([[m[0][0], m[1][0]], [m[0][1], m[1][1]]])
+    }
+
+    /// The flip and rotation that turn an image stored with this orientation upright.
+    pub fn transform(self) -> OrientationTransform {
+        let (flip_horizontal, rotation) = match self {
+            ICEXIFOrientation1 => (false, Rotation::Rotate0),
+            ICEXIFOrientation2 => (true, Rotation::Rotate0),
+            ICEXIFOrientation3 => (false, Rotation::Rotate180),
+            ICEXIFOrientation4 => (true, Rotation::Rotate180),
+            ICEXIFOrientation5 => (true, Rotation::Rotate270),
+            ICEXIFOrientation6 => (false, Rotation::Rotate90),
+            ICEXIFOrientation7 => (true, Rotation::Rotate90),
+            ICEXIFOrientation8 => (false, Rotation::Rotate270),
+        };
+        OrientationTransform {
+            flip_horizontal,
+            rotation,
+        }
+    }
+
+    /// The orientation described by a flip and rotation.
+    pub fn from_transform(transform: OrientationTransform) -> ICEXIFOrientationType {
+        [
+            ICEXIFOrientation1,
+            ICEXIFOrientation2,
+            ICEXIFOrientation3,
+            ICEXIFOrientation4,
+            ICEXIFOrientation5,
+            ICEXIFOrientation6,
+            ICEXIFOrientation7,
+            ICEXIFOrientation8,
+        ]
+        .iter()
+        .cloned()
+        .find(|orientation| orientation.transform() == transform)
+        .expect("every flip and rotation has an orientation")
+    }
+
+    /// The affine matrix that maps a point of an image stored with this orientation, in a
+    /// `width` x `height` coordinate space with the origin at the top left corner, to the
+    /// corresponding point of the upright image.
+    pub fn affine_matrix(self, width: f64, height: f64) -> AffineMatrix {
+        let m = self.matrix();
+        let (upright_width, upright_height) = if self.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let (a, b) = (f64::from(m[0][0]), f64::from(m[0][1]));
+        let (c, d) = (f64::from(m[1][0]), f64::from(m[1][1]));
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        [
+            [a, b, upright_width / 2.0 - (a * cx + b * cy)],
+            [c, d, upright_height / 2.0 - (c * cx + d * cy)],
+        ]
+    }
+
+    /// Turn an image stored with this orientation upright.
+    pub fn apply(self, image: &ImageBuffer) -> ImageBuffer {
+        let m = self.matrix();
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let (upright_width, upright_height) = if self.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let mut upright = ImageBuffer::new(
+            upright_width as usize,
+            upright_height as usize,
+            image.bits_per_pixel(),
+        );
+        // Work in doubled coordinates relative to the image centres so that pixel centres
+        // land on integers: the stored point is the transpose (inverse) of the matrix applied
+        // to the upright point.
+        let (m00, m01) = (i64::from(m[0][0]), i64::from(m[0][1]));
+        let (m10, m11) = (i64::from(m[1][0]), i64::from(m[1][1]));
+        for y in 0..upright_height {
+            let uy = 2 * y + 1 - upright_height;
+            for x in 0..upright_width {
+                let ux = 2 * x + 1 - upright_width;
+                let sx = (m00 * ux + m10 * uy + width - 1) / 2;
+                let sy = (m01 * ux + m11 * uy + height - 1) / 2;
+                upright.copy_pixel(x as usize, y as usize, image, sx as usize, sy as usize);
+            }
+        }
+        upright
+    }
+}
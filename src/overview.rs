@@ -0,0 +1,263 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerMeasurementUnit;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use crate::measurement::{Length, ScanRect};
+
+#[cfg(target_os = "macos")]
+use crate::scanner_functional_units::ICScannerFunctionalUnit;
+#[cfg(target_os = "macos")]
+use cocoa::base::id;
+
+/// Finds the document on the overview scan of a flatbed, to set the scan area to it.
+///
+/// The lid background is sampled along the right and bottom edges of the overview, away from
+/// the origin corner documents are placed against. Pixels that differ from it by more than the
+/// threshold belong to the document, and rows and columns with only a few of them are ignored
+/// as dust. The bounding rectangle of the document is padded and clamped to the scan bed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverviewAnalyzer {
+    threshold: f64,
+    padding: Length,
+    min_width: Length,
+    min_height: Length,
+}
+
+impl Default for OverviewAnalyzer {
+    fn default() -> OverviewAnalyzer {
+        OverviewAnalyzer::new()
+    }
+}
+
+impl OverviewAnalyzer {
+    /// Create an analyzer with a threshold of 8%, padding of 2 mm and a minimum document size of
+    /// 20 mm by 20 mm.
+    pub fn new() -> OverviewAnalyzer {
+        OverviewAnalyzer {
+            threshold: 0.08,
+            padding: Length::from_millimeters(2.0),
+            min_width: Length::from_millimeters(20.0),
+            min_height: Length::from_millimeters(20.0),
+        }
+    }
+
+    /// Set how much a pixel must differ from the background to belong to the document, as a
+    /// fraction of the full range of a component.
+    pub fn with_threshold(mut self, threshold: f64) -> OverviewAnalyzer {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the padding added around the document on each side.
+    pub fn with_padding(mut self, padding: Length) -> OverviewAnalyzer {
+        self.padding = padding;
+        self
+    }
+
+    /// Set the smallest document that is found. Smaller objects are taken for debris.
+    pub fn with_minimum_size(mut self, width: Length, height: Length) -> OverviewAnalyzer {
+        self.min_width = width;
+        self.min_height = height;
+        self
+    }
+
+    /// Find the document on an overview image of a scan bed of `physical_width` by
+    /// `physical_height` in `unit`, and return the area to scan in `unit`, or `None` if the bed
+    /// is empty. `resolution` is used for `ICScannerMeasurementUnitPixels`.
+    /// Fails if the overview is empty or the physical size is not positive.
+    pub fn find_document(
+        &self,
+        overview: &Image,
+        physical_width: f64,
+        physical_height: f64,
+        unit: ICScannerMeasurementUnit,
+        resolution: u64,
+    ) -> Result<Option<ScanRect>> {
+        let (width, height) = (overview.width(), overview.height());
+        if width == 0 || height == 0 || !(physical_width > 0.0 && physical_height > 0.0) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let background = background(overview);
+        let limit = self.threshold * 255.0;
+        let mut rows = vec![0usize; height];
+        let mut columns = vec![0usize; width];
+        for (y, row) in rows.iter_mut().enumerate() {
+            for (x, column) in columns.iter_mut().enumerate() {
+                let difference = color(overview, x, y)
+                    .iter()
+                    .zip(background.iter())
+                    .map(|(&a, &b)| (f64::from(a) - f64::from(b)).abs())
+                    .fold(0.0, f64::max);
+                if difference > limit {
+                    *row += 1;
+                    *column += 1;
+                }
+            }
+        }
+
+        // A row or column belongs to the document if more than 0.5% of it differs from the
+        // background.
+        let span = |counts: &[usize], length: usize| {
+            let noise = length / 200;
+            let first = counts.iter().position(|&count| count > noise)?;
+            let last = counts.iter().rposition(|&count| count > noise)?;
+            Some((first, last + 1))
+        };
+        let (left, right) = match span(&columns, height) {
+            Some(span) => span,
+            None => return Ok(None),
+        };
+        let (top, bottom) = match span(&rows, width) {
+            Some(span) => span,
+            None => return Ok(None),
+        };
+
+        let scale_x = physical_width / width as f64;
+        let scale_y = physical_height / height as f64;
+        let min_width = self.min_width.to(unit, resolution)?.value;
+        let min_height = self.min_height.to(unit, resolution)?.value;
+        if ((right - left) as f64 * scale_x) < min_width
+            || ((bottom - top) as f64 * scale_y) < min_height
+        {
+            return Ok(None);
+        }
+        let padding = self.padding.to(unit, resolution)?.value;
+        let x = (left as f64 * scale_x - padding).max(0.0);
+        let y = (top as f64 * scale_y - padding).max(0.0);
+        let x_end = (right as f64 * scale_x + padding).min(physical_width);
+        let y_end = (bottom as f64 * scale_y + padding).min(physical_height);
+        Ok(Some(ScanRect::new(x, y, x_end - x, y_end - y, unit)))
+    }
+
+    /// Find the document on an overview image made by a functional unit, with the physical
+    /// size, measurement unit and overview resolution of the functional unit. The result can be
+    /// passed to `setScanArea` with `ScanRect::to_ns_rect`.
+    ///
+    /// # Safety
+    ///
+    /// `functional_unit` must be a valid `ICScannerFunctionalUnit` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn find_document_for_unit(
+        &self,
+        overview: &Image,
+        functional_unit: id,
+    ) -> Result<Option<ScanRect>> {
+        let size = functional_unit.physicalSize();
+        self.find_document(
+            overview,
+            size.width,
+            size.height,
+            functional_unit.measurementUnit()?,
+            functional_unit.overviewResolution(),
+        )
+    }
+}
+
+/// The 8 bit RGB colour of a pixel. Gray pixels have three equal components.
+pub(crate) fn color(image: &Image, x: usize, y: usize) -> [u8; 3] {
+    let format = image.format();
+    if !format.is_color() {
+        let luma = image.luma(x, y);
+        return [luma; 3];
+    }
+    let row = image.row(y);
+    let bytes = format.bits_per_component() / 8;
+    let pixel = x * format.components() * bytes;
+    [row[pixel], row[pixel + bytes], row[pixel + 2 * bytes]]
+}
+
+/// The median colour along the right and bottom edges of an image.
+pub(crate) fn background(image: &Image) -> [u8; 3] {
+    let (width, height) = (image.width(), image.height());
+    let mut samples: Vec<[u8; 3]> = (0..height)
+        .map(|y| color(image, width - 1, y))
+        .chain((0..width).map(|x| color(image, x, height - 1)))
+        .collect();
+    let mut median = [0; 3];
+    for (component, value) in median.iter_mut().enumerate() {
+        samples.sort_unstable_by_key(|sample| sample[component]);
+        *value = samples[samples.len() / 2][component];
+    }
+    median
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICScannerMeasurementUnit::*;
+    use crate::image::PixelFormat;
+
+    /// A 10 dpi overview of a letter sized bed with a white lid, with a dark `width` by `height`
+    /// rectangle at `x`, `y` in pixels.
+    fn overview(x: usize, y: usize, width: usize, height: usize) -> Image {
+        let mut image = Image::new(PixelFormat::Rgb8, 85, 110);
+        for row in 0..110 {
+            for (column, pixel) in image.row_mut(row).chunks_mut(3).enumerate() {
+                let dark = (x..x + width).contains(&column) && (y..y + height).contains(&row);
+                let value = if dark { 40 } else { 245 };
+                pixel.copy_from_slice(&[value, value, value]);
+            }
+        }
+        image
+    }
+
+    fn assert_close(actual: ScanRect, expected: (f64, f64, f64, f64)) {
+        let actual_values = [actual.x, actual.y, actual.width, actual.height];
+        let expected_values = [expected.0, expected.1, expected.2, expected.3];
+        for (a, e) in actual_values.iter().zip(expected_values.iter()) {
+            assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn finds_a_dark_document_on_a_white_bed() {
+        let analyzer = OverviewAnalyzer::new().with_padding(Length::from_millimeters(0.0));
+        let area = analyzer
+            .find_document(
+                &overview(10, 20, 40, 60),
+                8.5,
+                11.0,
+                ICScannerMeasurementUnitInches,
+                10,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(area.unit, ICScannerMeasurementUnitInches);
+        assert_close(area, (1.0, 2.0, 4.0, 6.0));
+
+        // Padding is clamped to the bed.
+        let padded = OverviewAnalyzer::new()
+            .with_padding(Length::new(0.5, ICScannerMeasurementUnitInches))
+            .find_document(
+                &overview(0, 20, 40, 60),
+                8.5,
+                11.0,
+                ICScannerMeasurementUnitInches,
+                10,
+            )
+            .unwrap()
+            .unwrap();
+        assert_close(padded, (0.0, 1.5, 4.5, 7.0));
+    }
+
+    #[test]
+    fn empty_beds_and_debris_are_not_documents() {
+        let analyzer = OverviewAnalyzer::new();
+        let find = |image: &Image| {
+            analyzer.find_document(image, 8.5, 11.0, ICScannerMeasurementUnitInches, 10)
+        };
+        assert_eq!(find(&overview(0, 0, 0, 0)), Ok(None));
+        // 5 mm by 5 mm.
+        assert_eq!(find(&overview(30, 30, 2, 2)), Ok(None));
+        assert_eq!(
+            analyzer.find_document(
+                &overview(0, 0, 0, 0),
+                0.0,
+                11.0,
+                ICScannerMeasurementUnitInches,
+                10
+            ),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+}
@@ -0,0 +1,991 @@
+use crate::ccitt::encode_group4;
+use crate::constants::ICEXIFOrientationType::{self, ICEXIFOrientation1};
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerDocumentType::{self, ICScannerDocumentTypeDefault};
+use crate::constants::ICScannerMeasurementUnit::{self, *};
+use crate::document_size::{DocumentOrientation, DocumentSize, MILLIMETERS_PER_INCH};
+use crate::error::{Error, Result};
+use crate::icc::{IccColorSpace, IccProfile};
+use crate::image::{Image, PixelFormat};
+use crate::measurement::convert;
+use crate::ocr::OcrPage;
+use jpeg_encoder::{ColorType, Encoder};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_os = "macos")]
+use crate::device::ICDevice;
+#[cfg(target_os = "macos")]
+use crate::scanner_device::ICScannerDevice;
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use objc::*;
+
+/// Number of PDF points per inch.
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+/// A scanned page to be written to a PDF document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdfPage {
+    image: Image,
+    resolution: (f64, f64, ICScannerMeasurementUnit),
+    document_type: Option<ICScannerDocumentType>,
+    orientation: Option<ICEXIFOrientationType>,
+    text: Option<OcrPage>,
+}
+
+impl PdfPage {
+    /// Create a page showing `image` at 72 pixels per inch.
+    pub fn new(image: Image) -> PdfPage {
+        PdfPage {
+            image,
+            resolution: (72.0, 72.0, ICScannerMeasurementUnitInches),
+            document_type: None,
+            orientation: None,
+            text: None,
+        }
+    }
+
+    /// Set the resolution the image was scanned at, in pixels per `unit`.
+    /// The page size is the size of the image at this resolution.
+    pub fn with_resolution(
+        mut self,
+        x_resolution: f64,
+        y_resolution: f64,
+        unit: ICScannerMeasurementUnit,
+    ) -> PdfPage {
+        self.resolution = (x_resolution, y_resolution, unit);
+        self
+    }
+
+    /// Set the document type the page was scanned as. The page takes the physical size of the
+    /// document type, in the orientation that matches the image, and the image is scaled to fill
+    /// it. `ICScannerDocumentTypeDefault` leaves the size to the resolution.
+    pub fn with_document_type(mut self, document_type: ICScannerDocumentType) -> PdfPage {
+        self.document_type = Some(document_type);
+        self
+    }
+
+    /// Set the orientation the image is stored with. The page is shown upright.
+    /// Pages without an orientation use the orientation of the writer for odd or even pages.
+    pub fn with_orientation(mut self, orientation: ICEXIFOrientationType) -> PdfPage {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    /// Lay the words an OCR engine recognised in the image over it as invisible text, so the page
+    /// can be searched and its text selected. The words are placed by their coordinates in the
+    /// image as stored, so they follow the resolution and the orientation of the page.
+    pub fn with_text_layer(mut self, text: OcrPage) -> PdfPage {
+        self.text = Some(text);
+        self
+    }
+
+    /// The image shown on the page.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// The orientation set on the page, if any.
+    pub fn orientation(&self) -> Option<ICEXIFOrientationType> {
+        self.orientation
+    }
+
+    /// The text layer of the page, if any.
+    pub fn text_layer(&self) -> Option<&OcrPage> {
+        self.text.as_ref()
+    }
+
+    /// The size of the upright page in points, for an image stored with `orientation`.
+    pub fn media_box(&self, orientation: ICEXIFOrientationType) -> Result<(f64, f64)> {
+        let (width, height) = if orientation.swaps_dimensions() {
+            (self.image.height(), self.image.width())
+        } else {
+            (self.image.width(), self.image.height())
+        };
+        if width == 0 || height == 0 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let size = self
+            .document_type
+            .filter(|&document_type| document_type != ICScannerDocumentTypeDefault)
+            .and_then(ICScannerDocumentType::size);
+        if let Some(size) = size {
+            let wanted = if width > height {
+                DocumentOrientation::Landscape
+            } else {
+                DocumentOrientation::Portrait
+            };
+            let size = size.oriented(wanted);
+            return Ok((to_points(size.width_mm()), to_points(size.height_mm())));
+        }
+        let (x, y) = self.pixels_per_inch()?;
+        let (x, y) = if orientation.swaps_dimensions() {
+            (y, x)
+        } else {
+            (x, y)
+        };
+        Ok((
+            width as f64 / x * POINTS_PER_INCH,
+            height as f64 / y * POINTS_PER_INCH,
+        ))
+    }
+
+    /// The resolution of the stored image in pixels per inch.
+    fn pixels_per_inch(&self) -> Result<(f64, f64)> {
+        let (x, y, unit) = self.resolution;
+        if !(x > 0.0 && y > 0.0) || unit == ICScannerMeasurementUnitPixels {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let per_inch = convert(1.0, ICScannerMeasurementUnitInches, unit, 0)?;
+        Ok((x * per_inch, y * per_inch))
+    }
+
+    /// The physical size of the upright page.
+    pub fn document_size(&self, orientation: ICEXIFOrientationType) -> Result<DocumentSize> {
+        let (width, height) = self.media_box(orientation)?;
+        Ok(DocumentSize::from_inches(
+            width / POINTS_PER_INCH,
+            height / POINTS_PER_INCH,
+        ))
+    }
+}
+
+fn to_points(millimeters: f64) -> f64 {
+    millimeters / MILLIMETERS_PER_INCH * POINTS_PER_INCH
+}
+
+/// Document information written to the PDF Info dictionary.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdfMetadata {
+    /// Title of the document.
+    pub title: Option<String>,
+    /// Author of the document.
+    pub author: Option<String>,
+    /// Application that created the original document, such as the scanning application.
+    pub creator: Option<String>,
+    /// Application that produced the PDF.
+    pub producer: Option<String>,
+    /// Date the document was scanned.
+    pub creation_date: Option<SystemTime>,
+    /// Name of the scanner, written to the XMP metadata of PDF/A documents.
+    pub device_name: Option<String>,
+    /// Serial number of the scanner, written to the XMP metadata of PDF/A documents.
+    pub device_serial_number: Option<String>,
+}
+
+impl Default for PdfMetadata {
+    fn default() -> PdfMetadata {
+        PdfMetadata {
+            title: None,
+            author: None,
+            creator: None,
+            producer: Some(format!(
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            creation_date: Some(SystemTime::now()),
+            device_name: None,
+            device_serial_number: None,
+        }
+    }
+}
+
+impl PdfMetadata {
+    /// Metadata for a document scanned now, titled with the document name of a scanner and
+    /// carrying its name and serial number.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be a valid `ICScannerDevice` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn from_scanner_device(device: id) -> PdfMetadata {
+        PdfMetadata {
+            title: string(device.documentName()),
+            device_name: string(device.name()),
+            device_serial_number: string(device.serialNumberString()),
+            ..PdfMetadata::default()
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn string(string: id) -> Option<String> {
+    if string == nil {
+        return None;
+    }
+    let string: *const libc::c_char = msg_send![string, UTF8String];
+    Some(
+        std::ffi::CStr::from_ptr(string)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// The Catalog, Pages and Info objects have fixed numbers, so that pages can refer to them
+/// before they are written.
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const INFO: usize = 3;
+
+/// The output intent of a PDF/A document.
+struct OutputIntent {
+    /// Object number of the embedded output profile.
+    profile: usize,
+    condition: String,
+    /// Object number of an embedded sRGB profile for colour images, if the output profile is
+    /// not an RGB profile.
+    srgb_profile: Option<usize>,
+}
+
+/// Writes scanned pages to a PDF document.
+///
+/// Each page is written as soon as it is added. Colour and gray images are compressed with JPEG,
+/// with 16 bit samples reduced to 8 bits and alpha discarded, and bilevel images are compressed with
+/// CCITT Group 4. The page tree, catalog and cross-reference table are written by `finish`.
+///
+/// With `with_pdf_a` the document conforms to PDF/A-2b: it embeds an output intent profile and
+/// XMP metadata, and colour images are calibrated. `check_pdf_a` reports why a file does not.
+pub struct PdfWriter<W: Write> {
+    writer: W,
+    position: u64,
+    /// FNV-1a hash of the bytes written so far, used as the file identifier.
+    digest: u128,
+    /// Byte offsets of the objects, indexed by object number.
+    offsets: Vec<Option<u64>>,
+    pages: Vec<usize>,
+    metadata: PdfMetadata,
+    odd_page_orientation: ICEXIFOrientationType,
+    even_page_orientation: ICEXIFOrientationType,
+    jpeg_quality: u8,
+    output_intent: Option<OutputIntent>,
+}
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+const FNV_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+impl<W: Write> PdfWriter<W> {
+    /// Start a document by writing the PDF header.
+    pub fn new(writer: W, metadata: PdfMetadata) -> Result<PdfWriter<W>> {
+        let mut pdf = PdfWriter {
+            writer,
+            position: 0,
+            digest: FNV_OFFSET_BASIS,
+            offsets: vec![None; INFO + 1],
+            pages: Vec::new(),
+            metadata,
+            odd_page_orientation: ICEXIFOrientation1,
+            even_page_orientation: ICEXIFOrientation1,
+            jpeg_quality: 85,
+            output_intent: None,
+        };
+        // The comment with bytes above 127 marks the file as binary.
+        pdf.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(pdf)
+    }
+
+    /// Set the orientations used for pages that do not set their own, as configured with
+    /// `oddPageOrientation` and `evenPageOrientation` of a document feeder. The first page is odd.
+    pub fn with_feeder_orientations(
+        mut self,
+        odd_page_orientation: ICEXIFOrientationType,
+        even_page_orientation: ICEXIFOrientationType,
+    ) -> PdfWriter<W> {
+        self.odd_page_orientation = odd_page_orientation;
+        self.even_page_orientation = even_page_orientation;
+        self
+    }
+
+    /// Set the JPEG quality of colour and gray pages, from 1 to 100. The default is 85.
+    pub fn with_jpeg_quality(mut self, quality: u8) -> PdfWriter<W> {
+        self.jpeg_quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Make the document conform to PDF/A-2b, with `output_profile` as the profile of the output
+    /// intent, such as the ColorSync profile of the scanner from `IccProfile::for_band`, or
+    /// `IccProfile::srgb`. Colour images are tagged as sRGB unless the output profile is an RGB
+    /// profile. Fails if pages have already been added or if the profile is not a gray, RGB or
+    /// CMYK profile.
+    pub fn with_pdf_a(mut self, output_profile: IccProfile) -> Result<PdfWriter<W>> {
+        let color_space = output_profile.color_space();
+        let components = color_space
+            .components()
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        if !self.pages.is_empty() || self.output_intent.is_some() {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let condition = output_profile
+            .description()
+            .unwrap_or_else(|| "Custom".to_owned());
+        let profile = self.allocate();
+        self.write_profile(profile, &output_profile, components)?;
+        let srgb_profile = if color_space == IccColorSpace::Rgb {
+            None
+        } else {
+            let id = self.allocate();
+            self.write_profile(id, &IccProfile::srgb(), 3)?;
+            Some(id)
+        };
+        self.output_intent = Some(OutputIntent {
+            profile,
+            condition,
+            srgb_profile,
+        });
+        Ok(self)
+    }
+
+    /// Number of pages added so far.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Add a page to the document.
+    /// Fails if the page size cannot be determined or the image cannot be encoded, in which case
+    /// the document is left as it was and further pages can be added.
+    pub fn add_page(&mut self, page: &PdfPage) -> Result<()> {
+        let objects = self.offsets.len();
+        let result = self.write_page(page);
+        if result.is_err() {
+            // Forget the objects of the page, so `finish` does not list them. Any that were
+            // written before the error are left unreferenced.
+            self.offsets.truncate(objects);
+        }
+        result
+    }
+
+    fn write_page(&mut self, page: &PdfPage) -> Result<()> {
+        let orientation = page.orientation.unwrap_or(if self.pages.len() % 2 == 0 {
+            self.odd_page_orientation
+        } else {
+            self.even_page_orientation
+        });
+        let (page_width, page_height) = page.media_box(orientation)?;
+        let image = &page.image;
+        let (width, height) = (image.width() as f64, image.height() as f64);
+
+        let (color_space, bits, filter, data) = self.encode_image(image)?;
+        // Text is drawn in the pixel space of the stored image, with the origin at its top left
+        // corner.
+        let text_scale = match page.text {
+            Some(ref text) => Some(match (text.size, text.units_per_inch) {
+                (Some((w, h)), _) if w > 0.0 && h > 0.0 => (width / w, height / h),
+                (_, Some(units)) if units > 0.0 => {
+                    let (x, y) = page.pixels_per_inch()?;
+                    (x / units, y / units)
+                }
+                _ => (1.0, 1.0),
+            }),
+            None => None,
+        };
+        let decode_parms = if image.format() == PixelFormat::Bilevel {
+            format!(
+                " /DecodeParms << /K -1 /Columns {} /Rows {} >>",
+                image.width(),
+                image.height()
+            )
+        } else {
+            String::new()
+        };
+        let dictionary = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} \
+             /BitsPerComponent {} /Filter {}{} /Length {} >>",
+            image.width(),
+            image.height(),
+            color_space,
+            bits,
+            filter,
+            decode_parms,
+            data.len()
+        );
+        let image_id = self.allocate();
+        self.write_stream(image_id, &dictionary, &data)?;
+
+        // Map the image unit square onto the upright page. Image space has its origin at the
+        // bottom left corner of the stored image, so rows are flipped on the way.
+        let m = orientation.affine_matrix(width, height);
+        let (upright_width, upright_height) = if orientation.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let (sx, sy) = (page_width / upright_width, page_height / upright_height);
+        let (a, b, tx) = (m[0][0], m[0][1], m[0][2]);
+        let (c, d, ty) = (m[1][0], m[1][1], m[1][2]);
+        let mut content = format!(
+            "q {} {} {} {} {} {} cm /Im0 Do Q\n",
+            number(sx * a * width),
+            number(-sy * c * width),
+            number(-sx * b * height),
+            number(sy * d * height),
+            number(sx * (b * height + tx)),
+            number(page_height - sy * (d * height + ty)),
+        );
+        let mut fonts = String::new();
+        if let (Some(text), Some(scale)) = (page.text.as_ref(), text_scale) {
+            let _ = writeln!(
+                content,
+                "q {} {} {} {} {} {} cm",
+                number(sx * a),
+                number(-sy * c),
+                number(sx * b),
+                number(-sy * d),
+                number(sx * tx),
+                number(page_height - sy * ty),
+            );
+            fonts = self.write_text_layer(text, scale, &mut content)?;
+            content.push_str("Q\n");
+        }
+        let content_id = self.allocate();
+        self.write_stream(
+            content_id,
+            &format!("<< /Length {} >>", content.len()),
+            content.as_bytes(),
+        )?;
+
+        let page_id = self.allocate();
+        self.write_object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /XObject << /Im0 {} 0 R >>{} >> /Contents {} 0 R >>",
+                PAGES,
+                number(page_width),
+                number(page_height),
+                image_id,
+                fonts,
+                content_id
+            ),
+        )?;
+        self.pages.push(page_id);
+        Ok(())
+    }
+
+    /// Finish the document and return the writer. Fails if no page has been added.
+    pub fn finish(mut self) -> Result<W> {
+        if self.pages.is_empty() {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let kids: Vec<String> = self.pages.iter().map(|id| format!("{} 0 R", id)).collect();
+        self.write_object(
+            PAGES,
+            &format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            ),
+        )?;
+        let mut catalog = format!("<< /Type /Catalog /Pages {} 0 R", PAGES);
+        let intent = self
+            .output_intent
+            .as_ref()
+            .map(|intent| (text_string(&intent.condition), intent.profile));
+        if let Some((condition, profile)) = intent {
+            let _ = write!(
+                catalog,
+                " /OutputIntents [<< /Type /OutputIntent /S /GTS_PDFA1 \
+                 /OutputConditionIdentifier {} /Info {} /DestOutputProfile {} 0 R >>]",
+                condition, condition, profile
+            );
+            let metadata = self.allocate();
+            let _ = write!(catalog, " /Metadata {} 0 R", metadata);
+            let xmp = self.xmp_packet();
+            // PDF/A does not allow the metadata stream to be compressed.
+            self.write_stream(
+                metadata,
+                &format!("<< /Type /Metadata /Subtype /XML /Length {} >>", xmp.len()),
+                xmp.as_bytes(),
+            )?;
+        }
+        catalog.push_str(" >>");
+        self.write_object(CATALOG, &catalog)?;
+        let info = self.info_dictionary();
+        self.write_object(INFO, &info)?;
+
+        let xref = self.position;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in &self.offsets[1..] {
+            let offset = offset.expect("every allocated object is written");
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R /ID [<{:032X}> <{:032X}>] >>\n\
+             startxref\n{}\n%%EOF\n",
+            self.offsets.len(),
+            CATALOG,
+            INFO,
+            self.digest,
+            self.digest,
+            xref
+        );
+        self.write(table.as_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn info_dictionary(&self) -> String {
+        let mut info = String::from("<<");
+        let fields = [
+            ("Title", &self.metadata.title),
+            ("Author", &self.metadata.author),
+            ("Creator", &self.metadata.creator),
+            ("Producer", &self.metadata.producer),
+        ];
+        for &(key, value) in fields.iter() {
+            if let Some(ref value) = *value {
+                let _ = write!(info, " /{} {}", key, text_string(value));
+            }
+        }
+        if let Some(date) = self.metadata.creation_date {
+            let _ = write!(info, " /CreationDate ({})", pdf_date(date));
+        }
+        info.push_str(" >>");
+        info
+    }
+
+    /// The XMP metadata of a PDF/A document, repeating the Info dictionary.
+    fn xmp_packet(&self) -> String {
+        let metadata = &self.metadata;
+        let mut xmp = String::from(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\"\n \
+             xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"\n \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n \
+             xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n \
+             xmlns:tiff=\"http://ns.adobe.com/tiff/1.0/\"\n \
+             xmlns:aux=\"http://ns.adobe.com/exif/1.0/aux/\">\n\
+             <pdfaid:part>2</pdfaid:part>\n\
+             <pdfaid:conformance>B</pdfaid:conformance>\n\
+             <dc:format>application/pdf</dc:format>\n",
+        );
+        if let Some(ref title) = metadata.title {
+            let _ = writeln!(
+                xmp,
+                "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+                xml_text(title)
+            );
+        }
+        if let Some(ref author) = metadata.author {
+            let _ = writeln!(
+                xmp,
+                "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+                xml_text(author)
+            );
+        }
+        let fields = [
+            ("xmp:CreatorTool", &metadata.creator),
+            ("pdf:Producer", &metadata.producer),
+            ("tiff:Model", &metadata.device_name),
+            ("aux:SerialNumber", &metadata.device_serial_number),
+        ];
+        for &(property, value) in fields.iter() {
+            if let Some(ref value) = *value {
+                let _ = writeln!(xmp, "<{0}>{1}</{0}>", property, xml_text(value));
+            }
+        }
+        if let Some(date) = metadata.creation_date {
+            let _ = writeln!(xmp, "<xmp:CreateDate>{}</xmp:CreateDate>", xmp_date(date));
+        }
+        xmp.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>");
+        xmp
+    }
+
+    /// Append invisible text for the words of `text` to a content stream in the pixel space of
+    /// the image, scaling word coordinates by `scale`, and write the fonts it uses. Returns the
+    /// font resources.
+    ///
+    /// The fonts are Type 3 fonts with a single empty glyph one em wide, and a `ToUnicode`
+    /// map to the characters they stand for. Each font holds up to 256 characters.
+    fn write_text_layer(
+        &mut self,
+        text: &OcrPage,
+        scale: (f64, f64),
+        content: &mut String,
+    ) -> Result<String> {
+        let mut fonts: Vec<Vec<char>> = Vec::new();
+        let mut codes: HashMap<char, (usize, u8)> = HashMap::new();
+        content.push_str("BT 3 Tr\n");
+        let mut current_font = None;
+        for word in &text.words {
+            let chars: Vec<char> = word.text.chars().collect();
+            let (x0, y0, x1, y1) = word.bounds;
+            let (x0, y0, x1, y1) = (x0 * scale.0, y0 * scale.1, x1 * scale.0, y1 * scale.1);
+            let (width, height) = (x1 - x0, y1 - y0);
+            if chars.is_empty() || !(width > 0.0 && height > 0.0) {
+                continue;
+            }
+            let baseline = word.baseline.map_or(y1, |baseline| baseline * scale.1);
+            // Stretch the glyphs so the word fills its box. The y axis of pixel space points
+            // down, so it is flipped to draw the text upright.
+            let _ = writeln!(
+                content,
+                "{} 0 0 {} {} {} Tm",
+                number(width / chars.len() as f64),
+                number(-height),
+                number(x0),
+                number(baseline)
+            );
+            let mut run = String::new();
+            for ch in chars {
+                let (font, code) = *codes.entry(ch).or_insert_with(|| {
+                    if fonts.last().map_or(true, |font| font.len() == 256) {
+                        fonts.push(Vec::new());
+                    }
+                    let font = fonts.len() - 1;
+                    fonts[font].push(ch);
+                    (font, (fonts[font].len() - 1) as u8)
+                });
+                if current_font != Some(font) {
+                    if !run.is_empty() {
+                        let _ = writeln!(content, "<{}> Tj", run);
+                        run.clear();
+                    }
+                    let _ = writeln!(content, "/F{} 1 Tf", font);
+                    current_font = Some(font);
+                }
+                let _ = write!(run, "{:02X}", code);
+            }
+            let _ = writeln!(content, "<{}> Tj", run);
+        }
+        content.push_str("ET\n");
+        if fonts.is_empty() {
+            return Ok(String::new());
+        }
+
+        let glyph = self.allocate();
+        let procedure = "1000 0 0 0 0 0 d1\n";
+        self.write_stream(
+            glyph,
+            &format!("<< /Length {} >>", procedure.len()),
+            procedure.as_bytes(),
+        )?;
+        let mut resources = String::from(" /Font <<");
+        for (index, chars) in fonts.iter().enumerate() {
+            let mut cmap = String::from(
+                "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+                 /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+                 /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+                 1 begincodespacerange\n<00> <FF>\nendcodespacerange\n",
+            );
+            for (block, chars) in chars.chunks(100).enumerate() {
+                let _ = writeln!(cmap, "{} beginbfchar", chars.len());
+                for (offset, ch) in chars.iter().enumerate() {
+                    let mut units = String::new();
+                    for unit in ch.encode_utf16(&mut [0; 2]) {
+                        let _ = write!(units, "{:04X}", unit);
+                    }
+                    let _ = writeln!(cmap, "<{:02X}> <{}>", block * 100 + offset, units);
+                }
+                cmap.push_str("endbfchar\n");
+            }
+            cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+            let to_unicode = self.allocate();
+            self.write_stream(
+                to_unicode,
+                &format!("<< /Length {} >>", cmap.len()),
+                cmap.as_bytes(),
+            )?;
+
+            // Glyph names follow the Adobe Glyph List, for readers that ignore `ToUnicode`. All
+            // glyphs share the empty procedure.
+            let names: Vec<String> = chars
+                .iter()
+                .map(|&ch| match u32::from(ch) {
+                    code @ 0..=0xffff => format!("/uni{:04X}", code),
+                    code => format!("/u{:X}", code),
+                })
+                .collect();
+            let procedures: Vec<String> = names
+                .iter()
+                .map(|name| format!("{} {} 0 R", name, glyph))
+                .collect();
+            let font = self.allocate();
+            self.write_object(
+                font,
+                &format!(
+                    "<< /Type /Font /Subtype /Type3 /FontBBox [0 0 1000 1000] \
+                     /FontMatrix [0.001 0 0 0.001 0 0] /CharProcs << {} >> \
+                     /Encoding << /Type /Encoding /Differences [0 {}] >> /FirstChar 0 \
+                     /LastChar {} /Widths [{}] /ToUnicode {} 0 R /Resources << >> >>",
+                    procedures.join(" "),
+                    names.join(" "),
+                    chars.len() - 1,
+                    vec!["1000"; chars.len()].join(" "),
+                    to_unicode
+                ),
+            )?;
+            let _ = write!(resources, " /F{} {} 0 R", index, font);
+        }
+        resources.push_str(" >>");
+        Ok(resources)
+    }
+
+    fn write_profile(&mut self, id: usize, profile: &IccProfile, components: usize) -> Result<()> {
+        let data = compress_to_vec_zlib(profile.data(), 6);
+        self.write_stream(
+            id,
+            &format!(
+                "<< /N {} /Filter /FlateDecode /Length {} >>",
+                components,
+                data.len()
+            ),
+            &data,
+        )
+    }
+
+    /// Compress an image, returning its colour space, bits per component, filter and data.
+    fn encode_image(&self, image: &Image) -> Result<(String, u8, &'static str, Vec<u8>)> {
+        let (width, height) = (image.width(), image.height());
+        if image.format() == PixelFormat::Bilevel {
+            let buffer = image.buffer();
+            let data = encode_group4(buffer.data(), width, height, buffer.bytes_per_row(), true);
+            return Ok(("/DeviceGray".to_owned(), 1, "/CCITTFaxDecode", data));
+        }
+        if width > usize::from(u16::MAX) || height > usize::from(u16::MAX) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let (color_type, color_space, components) = match image.format() {
+            PixelFormat::Gray8 | PixelFormat::Gray16 => (ColorType::Luma, "/DeviceGray", 1),
+            _ => (ColorType::Rgb, "/DeviceRGB", 3),
+        };
+        let step = image.format().bits_per_pixel() / 8 / image.format().components();
+        let stride = image.format().bits_per_pixel() / 8;
+        let mut samples = Vec::with_capacity(width * height * components);
+        for y in 0..height {
+            for pixel in image.row(y).chunks(stride) {
+                // Take the high byte of 16 bit samples and skip alpha.
+                samples.extend(pixel.iter().step_by(step).take(components));
+            }
+        }
+        let mut data = Vec::new();
+        Encoder::new(&mut data, self.jpeg_quality)
+            .encode(&samples, width as u16, height as u16, color_type)
+            .map_err(|error| {
+                Error::from(io::Error::new(io::ErrorKind::Other, error.to_string()))
+            })?;
+        // PDF/A only allows device colour that matches the output intent.
+        let srgb_profile = self
+            .output_intent
+            .as_ref()
+            .and_then(|intent| intent.srgb_profile);
+        let color_space = match srgb_profile {
+            Some(id) if components == 3 => format!("[/ICCBased {} 0 R]", id),
+            _ => color_space.to_owned(),
+        };
+        Ok((color_space, 8, "/DCTDecode", data))
+    }
+
+    fn allocate(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len() - 1
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.position += data.len() as u64;
+        for &byte in data {
+            self.digest = (self.digest ^ u128::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+        Ok(())
+    }
+
+    fn write_object(&mut self, id: usize, body: &str) -> Result<()> {
+        self.offsets[id] = Some(self.position);
+        self.write(format!("{} 0 obj\n{}\nendobj\n", id, body).as_bytes())
+    }
+
+    fn write_stream(&mut self, id: usize, dictionary: &str, data: &[u8]) -> Result<()> {
+        self.offsets[id] = Some(self.position);
+        self.write(format!("{} 0 obj\n{}\nstream\n", id, dictionary).as_bytes())?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+}
+
+/// Format a number for a PDF content stream or dictionary.
+fn number(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "" | "-0" => "0".to_owned(),
+        text => text.to_owned(),
+    }
+}
+
+/// Encode a string as a PDF text string: a literal string if it is printable ASCII, otherwise
+/// UTF-16BE with a byte order mark.
+fn text_string(text: &str) -> String {
+    if text.bytes().all(|byte| (0x20..0x7f).contains(&byte)) {
+        let mut literal = String::from("(");
+        for ch in text.chars() {
+            if ch == '(' || ch == ')' || ch == '\\' {
+                literal.push('\\');
+            }
+            literal.push(ch);
+        }
+        literal.push(')');
+        literal
+    } else {
+        let mut hex = String::from("<FEFF");
+        for unit in text.encode_utf16() {
+            let _ = write!(hex, "{:04X}", unit);
+        }
+        hex.push('>');
+        hex
+    }
+}
+
+/// Escape text for XML character data.
+fn xml_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Format a time as a PDF date in UTC, such as `D:20191231235959Z`.
+fn pdf_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    format!(
+        "D:{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Format a time as an XMP date in UTC, such as `2019-12-31T23:59:59Z`.
+fn xmp_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// The year, month, day, hour, minute and second of a time in UTC.
+fn civil_time(time: SystemTime) -> (i64, i64, i64, u64, u64, u64) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm.
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that every entry of the cross-reference table points at its object.
+    fn assert_cross_references(pdf: &[u8]) {
+        let find = |needle: &[u8]| {
+            pdf.windows(needle.len())
+                .rposition(|window| window == needle)
+                .unwrap()
+        };
+        let tail = String::from_utf8_lossy(&pdf[find(b"startxref\n")..]).into_owned();
+        let xref: usize = tail.lines().nth(1).unwrap().parse().unwrap();
+        let table = String::from_utf8_lossy(&pdf[xref..]).into_owned();
+        let mut lines = table.lines().skip(1);
+        let count: usize = lines.next().unwrap()[2..].parse().unwrap();
+        for (id, entry) in lines.skip(1).take(count - 1).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            let header = format!("{} 0 obj\n", id + 1);
+            assert!(
+                pdf[offset..].starts_with(header.as_bytes()),
+                "object {}",
+                id + 1
+            );
+        }
+    }
+
+    #[test]
+    fn failed_pages_leave_the_document_intact() {
+        let mut pdf = PdfWriter::new(Vec::new(), PdfMetadata::default()).unwrap();
+        let wide = PdfPage::new(Image::new(PixelFormat::Gray8, 70000, 1));
+        assert_eq!(
+            pdf.add_page(&wide),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+        // A text layer in inches needs a resolution, which a page in pixels does not have.
+        let text = OcrPage {
+            size: None,
+            units_per_inch: Some(1200.0),
+            words: Vec::new(),
+        };
+        let unmeasured = PdfPage::new(Image::new(PixelFormat::Gray8, 8, 8))
+            .with_document_type(ICScannerDocumentType::ICScannerDocumentTypeA4)
+            .with_resolution(1.0, 1.0, ICScannerMeasurementUnitPixels)
+            .with_text_layer(text);
+        assert!(pdf.add_page(&unmeasured).is_err());
+        assert_eq!(pdf.pages(), 0);
+        pdf.add_page(&PdfPage::new(Image::new(PixelFormat::Gray8, 8, 8)))
+            .unwrap();
+        let data = pdf.finish().unwrap();
+        assert!(String::from_utf8_lossy(&data).contains("/Count 1"));
+        assert_cross_references(&data);
+    }
+
+    #[test]
+    fn pages_follow_the_feeder_orientations() {
+        let mut pdf = PdfWriter::new(Vec::new(), PdfMetadata::default())
+            .unwrap()
+            .with_feeder_orientations(
+                ICEXIFOrientation1,
+                ICEXIFOrientationType::ICEXIFOrientation6,
+            );
+        let page = PdfPage::new(Image::new(PixelFormat::Bilevel, 144, 72));
+        pdf.add_page(&page).unwrap();
+        pdf.add_page(&page).unwrap();
+        let data = pdf.finish().unwrap();
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("/MediaBox [0 0 144 72]"));
+        assert!(text.contains("/MediaBox [0 0 72 144]"));
+        assert!(text.contains("/Filter /CCITTFaxDecode"));
+        assert_cross_references(&data);
+    }
+
+    #[test]
+    fn text_strings_escape_or_use_utf16() {
+        assert_eq!(text_string("a (b)"), "(a \\(b\\))");
+        assert_eq!(text_string("é"), "<FEFF00E9>");
+        assert_eq!(number(1.5), "1.5");
+        assert_eq!(number(-0.00001), "0");
+        assert_eq!(pdf_date(UNIX_EPOCH), "D:19700101000000Z");
+    }
+}
@@ -0,0 +1,1068 @@
+use crate::icc::IccProfile;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A reason a document does not conform to PDF/A-2b.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PdfAViolation {
+    /// The file does not start with a `%PDF-1.n` header.
+    MissingHeader,
+    /// The header is not followed by a comment with at least four bytes above 127.
+    MissingBinaryComment,
+    /// The file structure cannot be read.
+    Malformed(String),
+    /// The trailer has no `ID` file identifier.
+    MissingFileIdentifier,
+    /// The document is encrypted.
+    Encrypted,
+    /// The catalog has no `GTS_PDFA1` output intent.
+    MissingOutputIntent,
+    /// The output intent profile is missing or unusable.
+    InvalidOutputProfile(String),
+    /// The catalog has no XMP metadata stream.
+    MissingMetadata,
+    /// The XMP metadata stream is compressed.
+    FilteredMetadata,
+    /// The XMP metadata does not identify the document as PDF/A-2b.
+    MissingIdentification,
+    /// An Info dictionary entry has no equal XMP property.
+    MetadataMismatch(String),
+    /// A device colour space is used without a matching output intent.
+    UncalibratedColor { color_space: String, object: u32 },
+    /// A stream is compressed with LZW.
+    LzwCompression { object: u32 },
+    /// An action or script that PDF/A forbids.
+    ForbiddenAction { action: String, object: u32 },
+    /// An image asks to be interpolated.
+    Interpolation { object: u32 },
+    /// A font program is not embedded.
+    FontNotEmbedded { font: String, object: u32 },
+}
+
+impl fmt::Display for PdfAViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfAViolation::MissingHeader => write!(f, "the file does not start with a PDF header"),
+            PdfAViolation::MissingBinaryComment => {
+                write!(f, "the header is not followed by a binary comment")
+            }
+            PdfAViolation::Malformed(reason) => write!(f, "the file is malformed: {}", reason),
+            PdfAViolation::MissingFileIdentifier => {
+                write!(f, "the trailer has no file identifier")
+            }
+            PdfAViolation::Encrypted => write!(f, "the document is encrypted"),
+            PdfAViolation::MissingOutputIntent => {
+                write!(f, "the document has no PDF/A output intent")
+            }
+            PdfAViolation::InvalidOutputProfile(reason) => {
+                write!(f, "the output intent profile is invalid: {}", reason)
+            }
+            PdfAViolation::MissingMetadata => write!(f, "the document has no XMP metadata"),
+            PdfAViolation::FilteredMetadata => write!(f, "the XMP metadata stream is compressed"),
+            PdfAViolation::MissingIdentification => write!(
+                f,
+                "the XMP metadata does not identify the document as PDF/A-2b"
+            ),
+            PdfAViolation::MetadataMismatch(key) => write!(
+                f,
+                "the {} entry of the Info dictionary does not match the XMP metadata",
+                key
+            ),
+            PdfAViolation::UncalibratedColor {
+                color_space,
+                object,
+            } => write!(
+                f,
+                "object {} uses {} without a matching output intent",
+                object, color_space
+            ),
+            PdfAViolation::LzwCompression { object } => {
+                write!(f, "object {} is compressed with LZW", object)
+            }
+            PdfAViolation::ForbiddenAction { action, object } => {
+                write!(f, "object {} contains a {} action", object, action)
+            }
+            PdfAViolation::Interpolation { object } => {
+                write!(f, "image {} is interpolated", object)
+            }
+            PdfAViolation::FontNotEmbedded { font, object } => {
+                write!(f, "font {} in object {} is not embedded", font, object)
+            }
+        }
+    }
+}
+
+/// Actions PDF/A-2 does not allow.
+const FORBIDDEN_ACTIONS: [&str; 10] = [
+    "Launch",
+    "Sound",
+    "Movie",
+    "ResetForm",
+    "ImportData",
+    "JavaScript",
+    "Hide",
+    "SetOCGState",
+    "Rendition",
+    "GoTo3DView",
+];
+
+/// Check a PDF document against the requirements of PDF/A-2b that apply to scanned documents,
+/// and return the reasons it does not conform, which are empty for a conforming document.
+///
+/// This is a self-check for documents written by `PdfWriter`, not a full validator: objects in
+/// object streams, and content streams with filters other than Flate, are not looked into.
+pub fn check_pdf_a(data: &[u8]) -> Vec<PdfAViolation> {
+    let mut violations = Vec::new();
+    let header_ok =
+        data.len() >= 8 && data.starts_with(b"%PDF-1.") && (b'0'..=b'7').contains(&data[7]);
+    if !header_ok {
+        violations.push(PdfAViolation::MissingHeader);
+        return violations;
+    }
+    let second_line = data
+        .iter()
+        .position(|&byte| byte == b'\n' || byte == b'\r')
+        .map(|end| {
+            let start = end
+                + data[end..]
+                    .iter()
+                    .take_while(|&&byte| byte == b'\n' || byte == b'\r')
+                    .count();
+            let length = data[start..]
+                .iter()
+                .take_while(|&&byte| byte != b'\n' && byte != b'\r')
+                .count();
+            &data[start..start + length]
+        })
+        .unwrap_or(&[]);
+    let binary = second_line
+        .iter()
+        .skip(1)
+        .filter(|&&byte| byte > 127)
+        .count();
+    if second_line.first() != Some(&b'%') || binary < 4 {
+        violations.push(PdfAViolation::MissingBinaryComment);
+    }
+
+    let document = Document::parse(data);
+    let trailer = match document.trailer {
+        Some(ref trailer) => trailer,
+        None => {
+            violations.push(PdfAViolation::Malformed("no trailer".to_owned()));
+            return violations;
+        }
+    };
+    if !trailer.contains_key("ID") {
+        violations.push(PdfAViolation::MissingFileIdentifier);
+    }
+    if trailer.contains_key("Encrypt") {
+        violations.push(PdfAViolation::Encrypted);
+    }
+    let catalog = match trailer
+        .get("Root")
+        .and_then(|root| document.resolve(root).as_dictionary())
+    {
+        Some(catalog) => catalog,
+        None => {
+            violations.push(PdfAViolation::Malformed("no catalog".to_owned()));
+            return violations;
+        }
+    };
+
+    let intent = document.check_output_intent(catalog, &mut violations);
+    document.check_metadata(catalog, trailer, &mut violations);
+    document.check_objects(intent, &mut violations);
+    violations
+}
+
+/// Number of components of the output intent profile, if the document has a usable one.
+type Intent = Option<i64>;
+
+/// A PDF object.
+#[derive(Clone, Debug, PartialEq)]
+enum Object {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<Object>),
+    Dictionary(BTreeMap<String, Object>),
+    Stream(BTreeMap<String, Object>, Vec<u8>),
+    Reference(u32),
+}
+
+const NULL: Object = Object::Null;
+
+impl Object {
+    fn as_dictionary(&self) -> Option<&BTreeMap<String, Object>> {
+        match self {
+            Object::Dictionary(dictionary) | Object::Stream(dictionary, _) => Some(dictionary),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&str> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Object::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+/// The indirect objects and trailer of a PDF file.
+struct Document {
+    objects: BTreeMap<u32, Object>,
+    trailer: Option<BTreeMap<String, Object>>,
+}
+
+impl Document {
+    /// Find every indirect object by scanning the file, which also finds objects the
+    /// cross-reference table does not list.
+    fn parse(data: &[u8]) -> Document {
+        let mut objects = BTreeMap::new();
+        let mut trailer = None;
+        let mut line_start = 0;
+        while line_start < data.len() {
+            let mut parser = Parser::new(data, line_start);
+            if parser.keyword(b"trailer") {
+                if let Some(Object::Dictionary(dictionary)) = parser.object() {
+                    trailer = Some(dictionary);
+                }
+            } else if let Some(number) = parser.object_header() {
+                if let Some(object) = parser.object() {
+                    let object = match object {
+                        Object::Dictionary(dictionary) if parser.keyword(b"stream") => {
+                            let data = parser.stream_data(&dictionary);
+                            Object::Stream(dictionary, data)
+                        }
+                        object => object,
+                    };
+                    if let Object::Stream(ref dictionary, _) = object {
+                        // Cross-reference streams carry the trailer of files without one.
+                        if dictionary.get("Type").and_then(Object::as_name) == Some("XRef") {
+                            trailer = Some(dictionary.clone());
+                        }
+                    }
+                    objects.insert(number, object);
+                    line_start = parser.position.max(line_start + 1);
+                    continue;
+                }
+            }
+            line_start = match data[line_start..]
+                .iter()
+                .position(|&byte| byte == b'\n' || byte == b'\r')
+            {
+                Some(end) => line_start + end + 1,
+                None => data.len(),
+            };
+        }
+        Document { objects, trailer }
+    }
+
+    fn resolve<'a>(&'a self, mut object: &'a Object) -> &'a Object {
+        for _ in 0..32 {
+            match object {
+                Object::Reference(number) => {
+                    object = self.objects.get(number).unwrap_or(&NULL);
+                }
+                object => return object,
+            }
+        }
+        &NULL
+    }
+
+    fn get<'a>(&'a self, dictionary: &'a BTreeMap<String, Object>, key: &str) -> &'a Object {
+        dictionary
+            .get(key)
+            .map_or(&NULL, |object| self.resolve(object))
+    }
+
+    /// The decoded data of a stream, if it has no filter or only Flate.
+    fn stream_data(&self, object: &Object) -> Option<Vec<u8>> {
+        let (dictionary, data) = match object {
+            Object::Stream(dictionary, data) => (dictionary, data),
+            _ => return None,
+        };
+        let filters = match self.get(dictionary, "Filter") {
+            Object::Null => Vec::new(),
+            Object::Name(name) => vec![name.as_str()],
+            Object::Array(names) => names
+                .iter()
+                .map(|name| self.resolve(name).as_name().unwrap_or(""))
+                .collect(),
+            _ => return None,
+        };
+        match filters.as_slice() {
+            [] => Some(data.clone()),
+            ["FlateDecode"] => decompress_to_vec_zlib(data).ok(),
+            _ => None,
+        }
+    }
+
+    /// Check the output intent, returning the number of components of its profile.
+    fn check_output_intent(
+        &self,
+        catalog: &BTreeMap<String, Object>,
+        violations: &mut Vec<PdfAViolation>,
+    ) -> Intent {
+        let intents = match self.get(catalog, "OutputIntents") {
+            Object::Array(intents) => intents.as_slice(),
+            _ => &[],
+        };
+        let intent = intents
+            .iter()
+            .filter_map(|intent| self.resolve(intent).as_dictionary())
+            .find(|intent| self.get(intent, "S").as_name() == Some("GTS_PDFA1"));
+        let intent = match intent {
+            Some(intent) => intent,
+            None => {
+                violations.push(PdfAViolation::MissingOutputIntent);
+                return None;
+            }
+        };
+        let invalid = |reason: &str| PdfAViolation::InvalidOutputProfile(reason.to_owned());
+        let stream = self.get(intent, "DestOutputProfile");
+        let components = stream
+            .as_dictionary()
+            .and_then(|dictionary| self.get(dictionary, "N").as_number());
+        let data = match self.stream_data(stream) {
+            Some(data) => data,
+            None => {
+                violations.push(invalid("the profile is not embedded"));
+                return None;
+            }
+        };
+        let profile = match IccProfile::from_bytes(data) {
+            Ok(profile) => profile,
+            Err(_) => {
+                violations.push(invalid("the profile is not an ICC profile"));
+                return None;
+            }
+        };
+        let class = &profile.data()[12..16];
+        if class != b"mntr" && class != b"prtr" {
+            violations.push(invalid("the profile is not an output or display profile"));
+        }
+        if profile.version().0 > 4 {
+            violations.push(invalid("the profile version is newer than 4"));
+        }
+        let profile_components = profile.color_space().components();
+        if profile_components.is_none() {
+            violations.push(invalid("the profile is not a gray, RGB or CMYK profile"));
+        } else if profile_components.map(|n| n as f64) != components {
+            violations.push(invalid("N does not match the profile"));
+        }
+        profile_components.map(|n| n as i64)
+    }
+
+    fn check_metadata(
+        &self,
+        catalog: &BTreeMap<String, Object>,
+        trailer: &BTreeMap<String, Object>,
+        violations: &mut Vec<PdfAViolation>,
+    ) {
+        let stream = self.get(catalog, "Metadata");
+        let xmp = match stream {
+            Object::Stream(dictionary, data) => {
+                if dictionary.contains_key("Filter") {
+                    violations.push(PdfAViolation::FilteredMetadata);
+                    return;
+                }
+                String::from_utf8_lossy(data).into_owned()
+            }
+            _ => {
+                violations.push(PdfAViolation::MissingMetadata);
+                return;
+            }
+        };
+        let part = xmp_property(&xmp, "pdfaid:part");
+        let conformance = xmp_property(&xmp, "pdfaid:conformance");
+        if part.as_deref() != Some("2") || conformance.as_deref() != Some("B") {
+            violations.push(PdfAViolation::MissingIdentification);
+        }
+
+        let info = match trailer
+            .get("Info")
+            .and_then(|info| self.resolve(info).as_dictionary())
+        {
+            Some(info) => info,
+            None => return,
+        };
+        let properties = [
+            ("Title", "dc:title"),
+            ("Author", "dc:creator"),
+            ("Subject", "dc:description"),
+            ("Keywords", "pdf:Keywords"),
+            ("Creator", "xmp:CreatorTool"),
+            ("Producer", "pdf:Producer"),
+        ];
+        for &(key, property) in properties.iter() {
+            if let Object::String(value) = self.get(info, key) {
+                if xmp_property(&xmp, property) != Some(text(value)) {
+                    violations.push(PdfAViolation::MetadataMismatch(key.to_owned()));
+                }
+            }
+        }
+        for &(key, property) in [
+            ("CreationDate", "xmp:CreateDate"),
+            ("ModDate", "xmp:ModifyDate"),
+        ]
+        .iter()
+        {
+            if let Object::String(value) = self.get(info, key) {
+                let date = digits(&text(value), 14);
+                let xmp_date = xmp_property(&xmp, property).map(|date| digits(&date, 14));
+                if xmp_date != Some(date) {
+                    violations.push(PdfAViolation::MetadataMismatch(key.to_owned()));
+                }
+            }
+        }
+    }
+
+    fn check_objects(&self, intent: Intent, violations: &mut Vec<PdfAViolation>) {
+        let mut found = Vec::new();
+        let mut contents = Vec::new();
+        for (&number, object) in &self.objects {
+            let dictionary = match object.as_dictionary() {
+                Some(dictionary) => dictionary,
+                None => continue,
+            };
+            let kind = self.get(dictionary, "Type").as_name();
+            let subtype = self.get(dictionary, "Subtype").as_name();
+
+            if let Object::Stream(..) = object {
+                let filters = match self.get(dictionary, "Filter") {
+                    Object::Array(filters) => filters.clone(),
+                    filter => vec![filter.clone()],
+                };
+                if filters
+                    .iter()
+                    .any(|filter| self.resolve(filter).as_name() == Some("LZWDecode"))
+                {
+                    found.push(PdfAViolation::LzwCompression { object: number });
+                }
+            }
+
+            if let Some(action) = self.get(dictionary, "S").as_name() {
+                if FORBIDDEN_ACTIONS.contains(&action) {
+                    found.push(PdfAViolation::ForbiddenAction {
+                        action: action.to_owned(),
+                        object: number,
+                    });
+                }
+            }
+            if dictionary.contains_key("JS") || dictionary.contains_key("JavaScript") {
+                found.push(PdfAViolation::ForbiddenAction {
+                    action: "JavaScript".to_owned(),
+                    object: number,
+                });
+            }
+
+            if subtype == Some("Image") {
+                if self.get(dictionary, "Interpolate") == &Object::Boolean(true) {
+                    found.push(PdfAViolation::Interpolation { object: number });
+                }
+                let image_mask = self.get(dictionary, "ImageMask") == &Object::Boolean(true);
+                if !image_mask {
+                    self.check_color_space(
+                        self.get(dictionary, "ColorSpace"),
+                        number,
+                        intent,
+                        &mut found,
+                    );
+                }
+            }
+            if subtype == Some("Form") {
+                contents.push(number);
+            }
+            if kind == Some("Page") {
+                match dictionary.get("Contents") {
+                    Some(Object::Reference(content)) => contents.push(*content),
+                    Some(Object::Array(streams)) => {
+                        contents.extend(streams.iter().filter_map(|stream| match stream {
+                            Object::Reference(content) => Some(*content),
+                            _ => None,
+                        }))
+                    }
+                    _ => {}
+                }
+            }
+            if let Object::Dictionary(resources) = self.get(dictionary, "Resources") {
+                if let Object::Dictionary(spaces) = self.get(resources, "ColorSpace") {
+                    for space in spaces.values() {
+                        self.check_color_space(self.resolve(space), number, intent, &mut found);
+                    }
+                }
+            }
+
+            if kind == Some("Font") {
+                self.check_font(dictionary, number, &mut found);
+            }
+        }
+
+        for number in contents {
+            let data = match self.objects.get(&number).and_then(|s| self.stream_data(s)) {
+                Some(data) => data,
+                None => continue,
+            };
+            let mut parser = Parser::new(&data, 0);
+            while let Some(token) = parser.operator() {
+                let color_space = match token.as_slice() {
+                    b"g" | b"G" => "DeviceGray",
+                    b"rg" | b"RG" => "DeviceRGB",
+                    b"k" | b"K" => "DeviceCMYK",
+                    _ => continue,
+                };
+                check_device_color(color_space, number, intent, &mut found);
+            }
+        }
+
+        for violation in found {
+            if !violations.contains(&violation) {
+                violations.push(violation);
+            }
+        }
+    }
+
+    fn check_color_space(
+        &self,
+        color_space: &Object,
+        object: u32,
+        intent: Intent,
+        found: &mut Vec<PdfAViolation>,
+    ) {
+        match color_space {
+            Object::Name(name) => check_device_color(name, object, intent, found),
+            Object::Array(array) => {
+                // Indexed and separation spaces have device base and alternate spaces.
+                let family = array
+                    .first()
+                    .and_then(|family| self.resolve(family).as_name());
+                let base = match family {
+                    Some("Indexed") => array.get(1),
+                    Some("Separation") | Some("DeviceN") => array.get(2),
+                    _ => None,
+                };
+                if let Some(base) = base {
+                    self.check_color_space(self.resolve(base), object, intent, found);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_font(
+        &self,
+        font: &BTreeMap<String, Object>,
+        object: u32,
+        found: &mut Vec<PdfAViolation>,
+    ) {
+        let subtype = self.get(font, "Subtype").as_name();
+        if subtype == Some("Type3") || subtype == Some("Type0") {
+            // Type 3 glyphs are content streams, and composite fonts are checked through their
+            // descendant fonts.
+            return;
+        }
+        let embedded =
+            self.get(font, "FontDescriptor")
+                .as_dictionary()
+                .map_or(false, |descriptor| {
+                    ["FontFile", "FontFile2", "FontFile3"]
+                        .iter()
+                        .any(|key| descriptor.contains_key(*key))
+                });
+        if !embedded {
+            let name = self.get(font, "BaseFont").as_name().unwrap_or("unnamed");
+            found.push(PdfAViolation::FontNotEmbedded {
+                font: name.to_owned(),
+                object,
+            });
+        }
+    }
+}
+
+fn check_device_color(
+    color_space: &str,
+    object: u32,
+    intent: Intent,
+    found: &mut Vec<PdfAViolation>,
+) {
+    let allowed = match color_space {
+        "DeviceGray" => intent.is_some(),
+        "DeviceRGB" => intent == Some(3),
+        "DeviceCMYK" => intent == Some(4),
+        _ => return,
+    };
+    if !allowed {
+        found.push(PdfAViolation::UncalibratedColor {
+            color_space: color_space.to_owned(),
+            object,
+        });
+    }
+}
+
+/// Decode a PDF text string, either UTF-16BE with a byte order mark or PDFDocEncoding, which
+/// is read as Latin-1.
+fn text(value: &[u8]) -> String {
+    if value.starts_with(&[0xfe, 0xff]) {
+        let units: Vec<u16> = value[2..]
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        value.iter().map(|&byte| char::from(byte)).collect()
+    }
+}
+
+/// The first `count` digits of a date.
+fn digits(date: &str, count: usize) -> String {
+    date.chars()
+        .filter(char::is_ascii_digit)
+        .take(count)
+        .collect()
+}
+
+/// The value of a simple XMP property, written either as an element or as an attribute. For
+/// array values the first item is returned.
+fn xmp_property(xmp: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let mut search = 0;
+    while let Some(start) = xmp[search..].find(&open).map(|start| search + start) {
+        search = start + open.len();
+        let rest = &xmp[search..];
+        match rest.chars().next() {
+            Some('>') | Some(' ') | Some('\n') | Some('\r') | Some('\t') => {}
+            _ => continue,
+        }
+        let content_start = search + rest.find('>')? + 1;
+        let close = format!("</{}>", name);
+        let content_end = content_start + xmp[content_start..].find(&close)?;
+        let mut content = &xmp[content_start..content_end];
+        if let Some(item) = content.find("<rdf:li") {
+            let item = &content[item..];
+            let item_start = item.find('>')? + 1;
+            let item_end = item.find("</rdf:li>")?;
+            content = &item[item_start..item_end];
+        }
+        return Some(unescape(content.trim()));
+    }
+    for quote in &['"', '\''] {
+        let attribute = format!("{}={}", name, quote);
+        if let Some(start) = xmp.find(&attribute) {
+            let value = &xmp[start + attribute.len()..];
+            let end = value.find(*quote)?;
+            return Some(unescape(&value[..end]));
+        }
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A reader of PDF objects.
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8], position: usize) -> Parser<'a> {
+        Parser { data, position }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.position += 1;
+            } else if byte == b'%' {
+                while let Some(byte) = self.peek() {
+                    if byte == b'\n' || byte == b'\r' {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Read a regular token: a number, keyword or operator.
+    fn token(&mut self) -> &'a [u8] {
+        let start = self.position;
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) || is_delimiter(byte) {
+                break;
+            }
+            self.position += 1;
+        }
+        &self.data[start..self.position]
+    }
+
+    /// Consume `keyword` if it comes next.
+    fn keyword(&mut self, keyword: &[u8]) -> bool {
+        let start = self.position;
+        self.skip_whitespace();
+        if self.token() == keyword {
+            true
+        } else {
+            self.position = start;
+            false
+        }
+    }
+
+    fn integer(&mut self) -> Option<u32> {
+        self.skip_whitespace();
+        let token = self.token();
+        if token.is_empty() || !token.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(token).ok()?.parse().ok()
+    }
+
+    /// Read `number generation obj`, returning the object number.
+    fn object_header(&mut self) -> Option<u32> {
+        let number = self.integer()?;
+        self.integer()?;
+        if self.keyword(b"obj") {
+            Some(number)
+        } else {
+            None
+        }
+    }
+
+    /// Read the data of a stream after the `stream` keyword, using its length if it is direct.
+    fn stream_data(&mut self, dictionary: &BTreeMap<String, Object>) -> Vec<u8> {
+        if self.peek() == Some(b'\r') {
+            self.position += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.position += 1;
+        }
+        let start = self.position.min(self.data.len());
+        let rest = &self.data[start..];
+        let length = dictionary
+            .get("Length")
+            .and_then(Object::as_number)
+            .map(|length| length as usize)
+            .filter(|&length| {
+                length <= rest.len() && {
+                    let mut after = Parser::new(rest, length);
+                    after.keyword(b"endstream")
+                }
+            });
+        let length = match length {
+            Some(length) => length,
+            None => {
+                let end = rest
+                    .windows(9)
+                    .position(|window| window == b"endstream")
+                    .unwrap_or(rest.len());
+                let mut length = end;
+                while length > 0 && (rest[length - 1] == b'\n' || rest[length - 1] == b'\r') {
+                    length -= 1;
+                }
+                length
+            }
+        };
+        self.position = start + length;
+        self.keyword(b"endstream");
+        rest[..length].to_vec()
+    }
+
+    fn object(&mut self) -> Option<Object> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'/' => {
+                self.position += 1;
+                Some(Object::Name(self.name()))
+            }
+            b'(' => {
+                self.position += 1;
+                Some(Object::String(self.literal_string()))
+            }
+            b'<' if self.data.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                let mut dictionary = BTreeMap::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b'>' => {
+                            self.position += 2;
+                            return Some(Object::Dictionary(dictionary));
+                        }
+                        b'/' => {
+                            self.position += 1;
+                            let key = self.name();
+                            let value = self.object()?;
+                            dictionary.insert(key, value);
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            b'<' => {
+                self.position += 1;
+                Some(Object::String(self.hex_string()))
+            }
+            b'[' => {
+                self.position += 1;
+                let mut array = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b']' {
+                        self.position += 1;
+                        return Some(Object::Array(array));
+                    }
+                    array.push(self.object()?);
+                }
+            }
+            _ => {
+                let token = self.token();
+                match token {
+                    b"" => None,
+                    b"true" => Some(Object::Boolean(true)),
+                    b"false" => Some(Object::Boolean(false)),
+                    b"null" => Some(Object::Null),
+                    _ => {
+                        let number: f64 = std::str::from_utf8(token).ok()?.parse().ok()?;
+                        // An integer may start a reference `number generation R`.
+                        let start = self.position;
+                        if token.iter().all(u8::is_ascii_digit)
+                            && self.integer().is_some()
+                            && self.keyword(b"R")
+                        {
+                            return Some(Object::Reference(number as u32));
+                        }
+                        self.position = start;
+                        Some(Object::Number(number))
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let token = self.token();
+        let mut name = Vec::with_capacity(token.len());
+        let mut i = 0;
+        while i < token.len() {
+            let escaped = if token[i] == b'#' && i + 2 < token.len() {
+                std::str::from_utf8(&token[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            } else {
+                None
+            };
+            match escaped {
+                Some(byte) => {
+                    name.push(byte);
+                    i += 3;
+                }
+                None => {
+                    name.push(token[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&name).into_owned()
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut string = Vec::new();
+        let mut depth = 0;
+        while let Some(byte) = self.peek() {
+            self.position += 1;
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    string.push(byte);
+                }
+                b')' if depth == 0 => break,
+                b')' => {
+                    depth -= 1;
+                    string.push(byte);
+                }
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(escaped) => escaped,
+                        None => break,
+                    };
+                    self.position += 1;
+                    match escaped {
+                        b'n' => string.push(b'\n'),
+                        b'r' => string.push(b'\r'),
+                        b't' => string.push(b'\t'),
+                        b'b' => string.push(8),
+                        b'f' => string.push(12),
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.position += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            string.push(value as u8);
+                        }
+                        other => string.push(other),
+                    }
+                }
+                _ => string.push(byte),
+            }
+        }
+        string
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(byte) = self.peek() {
+            self.position += 1;
+            if byte == b'>' {
+                break;
+            }
+            if let Some(digit) = char::from(byte).to_digit(16) {
+                digits.push(digit as u8);
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(0);
+        }
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect()
+    }
+
+    /// Read the next operator of a content stream, skipping operands.
+    fn operator(&mut self) -> Option<Vec<u8>> {
+        loop {
+            self.skip_whitespace();
+            let byte = self.peek()?;
+            if is_delimiter(byte) || byte.is_ascii_digit() || byte == b'-' || byte == b'+' {
+                let start = self.position;
+                self.object();
+                if self.position == start {
+                    self.position += 1;
+                }
+                continue;
+            }
+            let token = self.token();
+            if token == b"BI" {
+                // Skip inline image data, which is binary.
+                let rest = &self.data[self.position..];
+                let end = rest
+                    .windows(3)
+                    .position(|window| is_whitespace(window[0]) && &window[1..] == b"EI")
+                    .map_or(rest.len(), |end| end + 3);
+                self.position += end;
+                continue;
+            }
+            return Some(token.to_vec());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICScannerMeasurementUnit::ICScannerMeasurementUnitInches;
+    use crate::image::{Image, PixelFormat};
+    use crate::ocr::{OcrPage, OcrWord};
+    use crate::pdf::{PdfMetadata, PdfPage, PdfWriter};
+
+    fn pages() -> Vec<PdfPage> {
+        let text = OcrPage {
+            size: Some((100.0, 50.0)),
+            units_per_inch: None,
+            words: vec![OcrWord {
+                text: "Größe ☃".to_owned(),
+                bounds: (10.0, 10.0, 90.0, 30.0),
+                baseline: Some(28.0),
+            }],
+        };
+        vec![
+            PdfPage::new(Image::new(PixelFormat::Rgb8, 200, 100))
+                .with_resolution(200.0, 200.0, ICScannerMeasurementUnitInches)
+                .with_text_layer(text),
+            PdfPage::new(Image::new(PixelFormat::Bilevel, 64, 64)),
+        ]
+    }
+
+    fn metadata() -> PdfMetadata {
+        PdfMetadata {
+            title: Some("Invoice (copy)".to_owned()),
+            author: Some("Zoë".to_owned()),
+            ..PdfMetadata::default()
+        }
+    }
+
+    #[test]
+    fn pdf_a_output_conforms() {
+        let mut pdf = PdfWriter::new(Vec::new(), metadata())
+            .unwrap()
+            .with_pdf_a(IccProfile::srgb())
+            .unwrap();
+        for page in &pages() {
+            pdf.add_page(page).unwrap();
+        }
+        let data = pdf.finish().unwrap();
+        assert_eq!(check_pdf_a(&data), []);
+    }
+
+    #[test]
+    fn plain_output_is_reported() {
+        let mut pdf = PdfWriter::new(Vec::new(), metadata()).unwrap();
+        for page in &pages() {
+            pdf.add_page(page).unwrap();
+        }
+        let violations = check_pdf_a(&pdf.finish().unwrap());
+        assert!(violations.contains(&PdfAViolation::MissingOutputIntent));
+        assert!(violations.contains(&PdfAViolation::MissingMetadata));
+        assert!(violations.iter().any(|violation| matches!(
+            violation,
+            PdfAViolation::UncalibratedColor { color_space, .. } if color_space == "DeviceRGB"
+        )));
+        assert_eq!(check_pdf_a(b"%!PS-Adobe"), [PdfAViolation::MissingHeader]);
+    }
+}
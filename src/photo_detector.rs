@@ -0,0 +1,570 @@
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerMeasurementUnit::{self, ICScannerMeasurementUnitPixels};
+use crate::deskew::resample;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use crate::measurement::{Length, ScanRect};
+use crate::overview::{background, color};
+
+#[cfg(target_os = "macos")]
+use crate::scanner_functional_units::ICScannerFunctionalUnit;
+#[cfg(target_os = "macos")]
+use cocoa::base::id;
+
+/// Rows of a connected component, as the row and the first and last column of the component in
+/// that row.
+type Extents = Vec<(usize, usize, usize)>;
+
+/// A rectangle enclosing a polygon with a side along one of its edges, as its area, the edge
+/// direction, and the smallest and largest position of the polygon along and across the edge.
+type Enclosure = (f64, (f64, f64), (f64, f64), (f64, f64));
+
+/// A photo found on an overview scan.
+///
+/// The geometry is kept in pixels of the overview. The photo can be scanned on its own by
+/// setting the scan area of the functional unit to `scan_area`, or cut out of a larger scan with
+/// `extract`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetectedPhoto {
+    center: (f64, f64),
+    size: (f64, f64),
+    angle: f64,
+    padding: f64,
+    bed: (f64, f64),
+    resolution: u64,
+}
+
+impl DetectedPhoto {
+    /// Centre of the photo, in pixels of the overview.
+    pub fn center(&self) -> (f64, f64) {
+        self.center
+    }
+
+    /// Width and height of the photo along its own edges, in pixels of the overview.
+    pub fn size(&self) -> (f64, f64) {
+        self.size
+    }
+
+    /// Angle in degrees the photo is rotated by on the scan bed, positive for counterclockwise,
+    /// from -45° to 45°.
+    pub fn angle(&self) -> f64 {
+        self.angle
+    }
+
+    /// Resolution of the overview the photo was found on, in pixels per inch.
+    pub fn resolution(&self) -> u64 {
+        self.resolution
+    }
+
+    /// Left and top edge, width and height of the upright rectangle enclosing the photo, in
+    /// pixels of the overview.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (width, height) = self.size;
+        let half_width = (width * cos.abs() + height * sin.abs()) / 2.0;
+        let half_height = (width * sin.abs() + height * cos.abs()) / 2.0;
+        (
+            self.center.0 - half_width,
+            self.center.1 - half_height,
+            2.0 * half_width,
+            2.0 * half_height,
+        )
+    }
+
+    /// The area to scan for the photo in `unit`: the upright rectangle enclosing it, padded and
+    /// clamped to the scan bed. The result can be passed to `setScanArea` with
+    /// `ScanRect::to_ns_rect`.
+    pub fn scan_area(&self, unit: ICScannerMeasurementUnit) -> Result<ScanRect> {
+        let (x, y, width, height) = self.bounds();
+        let left = (x - self.padding).max(0.0);
+        let top = (y - self.padding).max(0.0);
+        let right = (x + width + self.padding).min(self.bed.0);
+        let bottom = (y + height + self.padding).min(self.bed.1);
+        ScanRect::new(
+            left,
+            top,
+            right - left,
+            bottom - top,
+            ICScannerMeasurementUnitPixels,
+        )
+        .to(unit, self.resolution)
+    }
+
+    /// Cut the photo out of `scan` and straighten it. `area` is the part of the scan bed the scan
+    /// shows, such as `scan_area` for a scan of the photo alone, or the whole bed for a scan of
+    /// all photos at once. The photo keeps the resolution of the scan.
+    /// Fails if the scan or the area is empty.
+    pub fn extract(&self, scan: &Image, area: ScanRect) -> Result<Image> {
+        let area = area.to(ICScannerMeasurementUnitPixels, self.resolution)?;
+        if scan.width() == 0 || scan.height() == 0 || !(area.width > 0.0 && area.height > 0.0) {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let scale_x = scan.width() as f64 / area.width;
+        let scale_y = scan.height() as f64 / area.height;
+        let width = (self.size.0 * scale_x).round().max(1.0) as usize;
+        let height = (self.size.1 * scale_y).round().max(1.0) as usize;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (center_x, center_y) = self.center;
+        Ok(resample(scan, width, height, |x, y| {
+            // Offset of the output pixel from the centre of the photo, in overview pixels along
+            // the edges of the photo, turned by the angle of the photo onto the bed.
+            let dx = (x as f64 + 0.5) / scale_x - self.size.0 / 2.0;
+            let dy = (y as f64 + 0.5) / scale_y - self.size.1 / 2.0;
+            let bed_x = center_x + dx * cos + dy * sin;
+            let bed_y = center_y - dx * sin + dy * cos;
+            (
+                (bed_x - area.x) * scale_x - 0.5,
+                (bed_y - area.y) * scale_y - 0.5,
+            )
+        }))
+    }
+}
+
+/// Finds the separate photos on the overview scan of a flatbed, for scanning several prints at
+/// once.
+///
+/// As with `OverviewAnalyzer`, the lid background is sampled along the right and bottom edges
+/// of the overview, and pixels that differ from it by more than the threshold belong to a photo.
+/// Isolated pixels are ignored as dust. Pixels closer together than the minimum gap are grouped
+/// into one photo, so photos must lie at least that far apart. The smallest rectangle enclosing
+/// each group gives the position, size and rotation of the photo, and groups with a side shorter
+/// than the minimum size are ignored as debris.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhotoDetector {
+    threshold: f64,
+    min_gap: Length,
+    min_size: Length,
+    padding: Length,
+}
+
+impl Default for PhotoDetector {
+    fn default() -> PhotoDetector {
+        PhotoDetector::new()
+    }
+}
+
+impl PhotoDetector {
+    /// Create a detector with a threshold of 8%, a minimum gap of 5 mm between photos, a minimum
+    /// photo size of 25 mm and scan areas padded by 1 mm.
+    pub fn new() -> PhotoDetector {
+        PhotoDetector {
+            threshold: 0.08,
+            min_gap: Length::from_millimeters(5.0),
+            min_size: Length::from_millimeters(25.0),
+            padding: Length::from_millimeters(1.0),
+        }
+    }
+
+    /// Set how much a pixel must differ from the background to belong to a photo, as a fraction
+    /// of the full range of a component.
+    pub fn with_threshold(mut self, threshold: f64) -> PhotoDetector {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the smallest gap between two photos. Parts closer together are taken for one photo.
+    pub fn with_minimum_gap(mut self, gap: Length) -> PhotoDetector {
+        self.min_gap = gap;
+        self
+    }
+
+    /// Set the length of the shortest side of a photo. Smaller objects are taken for debris.
+    pub fn with_minimum_size(mut self, size: Length) -> PhotoDetector {
+        self.min_size = size;
+        self
+    }
+
+    /// Set the padding added around each photo on each side of its scan area.
+    pub fn with_padding(mut self, padding: Length) -> PhotoDetector {
+        self.padding = padding;
+        self
+    }
+
+    /// Find the photos on an overview image made at `resolution` pixels per inch, ordered by
+    /// their centres from top to bottom and then from left to right.
+    /// Fails if the overview is empty or the resolution is 0.
+    pub fn detect(&self, overview: &Image, resolution: u64) -> Result<Vec<DetectedPhoto>> {
+        let (width, height) = (overview.width(), overview.height());
+        if width == 0 || height == 0 || resolution == 0 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let pixels = |length: Length| -> Result<f64> {
+            Ok(length.to(ICScannerMeasurementUnitPixels, resolution)?.value)
+        };
+        let min_size = pixels(self.min_size)?;
+        let padding = pixels(self.padding)?.max(0.0);
+        let radius = (pixels(self.min_gap)? / 2.0).floor().max(0.0) as usize;
+
+        let mask = self.foreground(overview);
+        let grown = dilate(&mask, width, height, radius);
+        let (labels, count) = label(&grown, width, height);
+
+        // The extent of the foreground in each row of each group. Only the first and last pixel
+        // of a row can be corners of the enclosing rectangle.
+        let mut extents: Vec<Extents> = vec![Vec::new(); count];
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                if !mask[index] {
+                    continue;
+                }
+                let rows = &mut extents[labels[index] as usize - 1];
+                match rows.last_mut() {
+                    Some(last) if last.0 == y => last.2 = x,
+                    _ => rows.push((y, x, x)),
+                }
+            }
+        }
+
+        let mut photos: Vec<DetectedPhoto> = extents
+            .iter()
+            .filter(|rows| !rows.is_empty())
+            .map(|rows| {
+                let mut points: Vec<(i64, i64)> = Vec::with_capacity(rows.len() * 4);
+                for &(y, first, last) in rows {
+                    let (y, first, last) = (y as i64, first as i64, last as i64 + 1);
+                    points.extend_from_slice(&[
+                        (first, y),
+                        (first, y + 1),
+                        (last, y),
+                        (last, y + 1),
+                    ]);
+                }
+                let (center, size, angle) = enclosing_rectangle(&convex_hull(points));
+                DetectedPhoto {
+                    center,
+                    size,
+                    angle,
+                    padding,
+                    bed: (width as f64, height as f64),
+                    resolution,
+                }
+            })
+            .filter(|photo| photo.size.0 >= min_size && photo.size.1 >= min_size)
+            .collect();
+        photos.sort_by(|a, b| {
+            (a.center.1, a.center.0)
+                .partial_cmp(&(b.center.1, b.center.0))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(photos)
+    }
+
+    /// Find the photos on an overview image made by a functional unit at its overview
+    /// resolution.
+    ///
+    /// # Safety
+    ///
+    /// `functional_unit` must be a valid `ICScannerFunctionalUnit` object.
+    #[cfg(target_os = "macos")]
+    pub unsafe fn detect_for_unit(
+        &self,
+        overview: &Image,
+        functional_unit: id,
+    ) -> Result<Vec<DetectedPhoto>> {
+        self.detect(overview, functional_unit.overviewResolution())
+    }
+
+    /// The pixels that differ from the background and touch at least one other such pixel.
+    fn foreground(&self, overview: &Image) -> Vec<bool> {
+        let (width, height) = (overview.width(), overview.height());
+        let background = background(overview);
+        let limit = self.threshold * 255.0;
+        let mut mask = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let difference = color(overview, x, y)
+                    .iter()
+                    .zip(background.iter())
+                    .map(|(&a, &b)| (f64::from(a) - f64::from(b)).abs())
+                    .fold(0.0, f64::max);
+                mask.push(difference > limit);
+            }
+        }
+        let touching = |x: usize, y: usize| {
+            (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                (x.saturating_sub(1)..(x + 2).min(width))
+                    .any(|nx| (nx, ny) != (x, y) && mask[ny * width + nx])
+            })
+        };
+        (0..width * height)
+            .map(|index| mask[index] && touching(index % width, index / width))
+            .collect()
+    }
+}
+
+/// Grow the set pixels of a mask by `radius` pixels in each direction.
+fn dilate(mask: &[bool], width: usize, height: usize, radius: usize) -> Vec<bool> {
+    if radius == 0 {
+        return mask.to_vec();
+    }
+    // A pixel is set if the window around it contains a set pixel, which is counted with the
+    // running sums of a row or column, first horizontally and then vertically.
+    let grow = |get: &dyn Fn(usize) -> bool, length: usize, out: &mut dyn FnMut(usize, bool)| {
+        let mut sums = vec![0usize; length + 1];
+        for i in 0..length {
+            sums[i + 1] = sums[i] + usize::from(get(i));
+        }
+        for i in 0..length {
+            let from = i.saturating_sub(radius);
+            let to = (i + radius + 1).min(length);
+            out(i, sums[to] > sums[from]);
+        }
+    };
+    let mut rows = vec![false; width * height];
+    for y in 0..height {
+        let line = &mask[y * width..(y + 1) * width];
+        grow(&|x| line[x], width, &mut |x, value| {
+            rows[y * width + x] = value
+        });
+    }
+    let mut grown = vec![false; width * height];
+    for x in 0..width {
+        grow(&|y| rows[y * width + x], height, &mut |y, value| {
+            grown[y * width + x] = value
+        });
+    }
+    grown
+}
+
+/// Label the groups of set pixels of a mask that touch horizontally, vertically or diagonally.
+/// Returns the label of each pixel, 0 for unset pixels and from 1 for groups, and the number of
+/// groups.
+fn label(mask: &[bool], width: usize, height: usize) -> (Vec<u32>, usize) {
+    let mut labels = vec![0u32; width * height];
+    let mut count = 0;
+    let mut stack = Vec::new();
+    for start in 0..width * height {
+        if !mask[start] || labels[start] != 0 {
+            continue;
+        }
+        count += 1;
+        labels[start] = count as u32;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let neighbour = ny * width + nx;
+                    if mask[neighbour] && labels[neighbour] == 0 {
+                        labels[neighbour] = count as u32;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+    }
+    (labels, count)
+}
+
+/// The convex hull of a set of points, in order around the hull.
+fn convex_hull(mut points: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    points.sort_unstable();
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: (i64, i64), a: (i64, i64), b: (i64, i64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(i64, i64)> = Vec::with_capacity(points.len() * 2);
+    // The lower half of the hull from left to right, then the upper half from right to left.
+    for reverse in [false, true] {
+        let start = hull.len();
+        for i in 0..points.len() {
+            let point = points[if reverse { points.len() - 1 - i } else { i }];
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of each half is the first point of the other.
+        hull.pop();
+    }
+    hull
+}
+
+/// The smallest rectangle enclosing a convex polygon, as its centre, its width and height, and
+/// the angle in degrees it is rotated by, counterclockwise, from -45° to 45°.
+///
+/// One side of the smallest enclosing rectangle lies on an edge of the polygon, so each edge is
+/// tried in turn.
+fn enclosing_rectangle(hull: &[(i64, i64)]) -> ((f64, f64), (f64, f64), f64) {
+    let points: Vec<(f64, f64)> = hull.iter().map(|&(x, y)| (x as f64, y as f64)).collect();
+    let mut best: Option<Enclosure> = None;
+    for (i, &(x0, y0)) in points.iter().enumerate() {
+        let (x1, y1) = points[(i + 1) % points.len()];
+        let length = (x1 - x0).hypot(y1 - y0);
+        if length == 0.0 {
+            continue;
+        }
+        // The edge direction and its normal, which points down for a horizontal edge.
+        let edge = ((x1 - x0) / length, (y1 - y0) / length);
+        let normal = (-edge.1, edge.0);
+        let (mut along, mut across) = ((f64::MAX, f64::MIN), (f64::MAX, f64::MIN));
+        for &(x, y) in &points {
+            let a = x * edge.0 + y * edge.1;
+            let b = x * normal.0 + y * normal.1;
+            along = (along.0.min(a), along.1.max(a));
+            across = (across.0.min(b), across.1.max(b));
+        }
+        let area = (along.1 - along.0) * (across.1 - across.0);
+        if best.map_or(true, |(smallest, ..)| area < smallest) {
+            best = Some((area, edge, along, across));
+        }
+    }
+
+    let (edge, along, across) = match best {
+        Some((_, edge, along, across)) => (edge, along, across),
+        None => {
+            // A single point or a line has no area.
+            let (x, y) = points.first().copied().unwrap_or((0.0, 0.0));
+            return ((x, y), (0.0, 0.0), 0.0);
+        }
+    };
+    let normal = (-edge.1, edge.0);
+    let (a, b) = ((along.0 + along.1) / 2.0, (across.0 + across.1) / 2.0);
+    let center = (a * edge.0 + b * normal.0, a * edge.1 + b * normal.1);
+    let mut size = (along.1 - along.0, across.1 - across.0);
+    // Image rows run down, so the angle of the edge is negated to make counterclockwise on
+    // screen positive.
+    let mut angle = -edge.1.atan2(edge.0).to_degrees();
+    while angle > 45.0 {
+        angle -= 90.0;
+        size = (size.1, size.0);
+    }
+    while angle <= -45.0 {
+        angle += 90.0;
+        size = (size.1, size.0);
+    }
+    (center, size, angle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::PixelFormat;
+
+    /// A photo on the scan bed, as its centre, size and counterclockwise angle.
+    type Placement = ((f64, f64), (f64, f64), f64);
+
+    /// A 300 by 300 pixel overview of a white bed with dark photos and a speck of dust.
+    fn overview(photos: &[Placement]) -> Image {
+        let mut image = Image::new(PixelFormat::Gray8, 300, 300);
+        for y in 0..300 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                let (bed_x, bed_y) = (x as f64 + 0.5, y as f64 + 0.5);
+                let inside = photos.iter().any(|&(center, size, angle)| {
+                    let (sin, cos) = f64::to_radians(angle).sin_cos();
+                    let (x, y) = (bed_x - center.0, bed_y - center.1);
+                    let along = x * cos - y * sin;
+                    let across = x * sin + y * cos;
+                    along.abs() <= size.0 / 2.0 && across.abs() <= size.1 / 2.0
+                });
+                *pixel = if inside { 60 } else { 240 };
+            }
+        }
+        image.row_mut(20)[280] = 0;
+        image
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn finds_upright_and_rotated_photos() {
+        let image = overview(&[
+            ((200.0, 200.0), (80.0, 60.0), 10.0),
+            ((70.0, 65.0), (100.0, 70.0), 0.0),
+        ]);
+        let photos = PhotoDetector::new().detect(&image, 50).unwrap();
+        assert_eq!(photos.len(), 2);
+
+        let upright = photos[0];
+        assert_eq!(upright.center(), (70.0, 65.0));
+        assert_eq!(upright.size(), (100.0, 70.0));
+        assert_eq!(upright.angle(), 0.0);
+        assert_eq!(upright.bounds(), (20.0, 30.0, 100.0, 70.0));
+        assert_eq!(upright.resolution(), 50);
+        // Padded by 1 mm on each side.
+        let area = upright.scan_area(ICScannerMeasurementUnitPixels).unwrap();
+        let padding = 50.0 / 25.4;
+        assert_close(area.x, 20.0 - padding, 1e-9);
+        assert_close(area.y, 30.0 - padding, 1e-9);
+        assert_close(area.width, 100.0 + 2.0 * padding, 1e-9);
+        assert_close(area.height, 70.0 + 2.0 * padding, 1e-9);
+
+        let rotated = photos[1];
+        assert_close(rotated.center().0, 200.0, 1.0);
+        assert_close(rotated.center().1, 200.0, 1.0);
+        assert_close(rotated.size().0, 80.0, 2.0);
+        assert_close(rotated.size().1, 60.0, 2.0);
+        assert_close(rotated.angle(), 10.0, 1.0);
+    }
+
+    #[test]
+    fn photos_are_cut_out_and_straightened() {
+        let image = overview(&[((150.0, 150.0), (80.0, 60.0), -20.0)]);
+        let photos = PhotoDetector::new().detect(&image, 50).unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_close(photos[0].angle(), -20.0, 1.0);
+
+        // Cut out of a scan of the whole bed at twice the resolution of the overview.
+        let bed = ScanRect::new(
+            0.0,
+            0.0,
+            6.0,
+            6.0,
+            ICScannerMeasurementUnit::ICScannerMeasurementUnitInches,
+        );
+        let mut scan = Image::new(PixelFormat::Gray8, 600, 600);
+        for y in 0..600 {
+            let source = image.row(y / 2).to_vec();
+            for (x, pixel) in scan.row_mut(y).iter_mut().enumerate() {
+                *pixel = source[x / 2];
+            }
+        }
+        let photo = photos[0].extract(&scan, bed).unwrap();
+        assert_close(photo.width() as f64, 160.0, 4.0);
+        assert_close(photo.height() as f64, 120.0, 4.0);
+        // Away from its edges, the straightened photo is dark all over.
+        for y in 8..photo.height() - 8 {
+            for x in 8..photo.width() - 8 {
+                assert!(photo.luma(x, y) < 100, "{}, {}", x, y);
+            }
+        }
+        assert_eq!(
+            photos[0].extract(&scan, ScanRect::new(0.0, 0.0, 0.0, 6.0, bed.unit)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn close_parts_are_one_photo_and_debris_is_ignored() {
+        // Two halves 2 pixels apart, and a 10 by 10 pixel scrap.
+        let image = overview(&[
+            ((55.0, 80.0), (70.0, 100.0), 0.0),
+            ((127.0, 80.0), (70.0, 100.0), 0.0),
+            ((250.0, 250.0), (10.0, 10.0), 0.0),
+        ]);
+        let photos = PhotoDetector::new().detect(&image, 50).unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].bounds(), (20.0, 30.0, 142.0, 100.0));
+        let apart = PhotoDetector::new().with_minimum_gap(Length::from_millimeters(0.5));
+        assert_eq!(apart.detect(&image, 50).unwrap().len(), 2);
+
+        assert_eq!(PhotoDetector::new().detect(&overview(&[]), 50), Ok(vec![]));
+        assert_eq!(
+            PhotoDetector::new().detect(&image, 0),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+}
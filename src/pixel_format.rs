@@ -0,0 +1,419 @@
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerPixelDataType;
+use crate::constants::ICScannerPixelDataType::*;
+use crate::error::{Error, Result};
+use crate::image::{Image, ImageBuffer, PixelFormat};
+
+/// Layout of raw pixel data as delivered by a scanner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceFormat {
+    /// Type of the pixel data.
+    pub pixel_data_type: ICScannerPixelDataType,
+    /// Number of bits per component: 1, 2, 4, 8 or 16.
+    pub bits_per_component: usize,
+    /// Number of components per pixel.
+    pub num_components: usize,
+    /// Number of bits per pixel. This may be larger than the components occupy.
+    pub bits_per_pixel: usize,
+    /// Indicates whether 16 bit components are big endian.
+    pub is_big_endian: bool,
+}
+
+impl SourceFormat {
+    /// The format of the pixel data in a scanner band.
+    pub fn from_band(band: &ScannerBand) -> SourceFormat {
+        SourceFormat {
+            pixel_data_type: band.pixel_data_type,
+            bits_per_component: band.bits_per_component,
+            num_components: band.num_components,
+            bits_per_pixel: band.bits_per_pixel,
+            is_big_endian: band.is_big_endian,
+        }
+    }
+
+    /// Number of colour components the pixel data type requires.
+    fn required_components(self) -> usize {
+        match self.pixel_data_type {
+            ICScannerPixelDataTypeBW
+            | ICScannerPixelDataTypeGray
+            | ICScannerPixelDataTypePalette => 1,
+            ICScannerPixelDataTypeRGB
+            | ICScannerPixelDataTypeCMY
+            | ICScannerPixelDataTypeYUV
+            | ICScannerPixelDataTypeCIEXYZ => 3,
+            ICScannerPixelDataTypeCMYK | ICScannerPixelDataTypeYUVK => 4,
+        }
+    }
+
+    fn validate(self) -> Result<()> {
+        let required = self.required_components();
+        let valid_depth = matches!(self.bits_per_component, 1 | 2 | 4 | 8 | 16);
+        if !valid_depth
+            || self.num_components < required
+            || self.bits_per_pixel < self.num_components * self.bits_per_component
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok(())
+    }
+
+    /// The canonical format that preserves the information in the pixel data.
+    pub fn canonical_format(self) -> Result<PixelFormat> {
+        self.validate()?;
+        let wide = self.bits_per_component > 8;
+        Ok(match self.pixel_data_type {
+            ICScannerPixelDataTypeBW => PixelFormat::Bilevel,
+            ICScannerPixelDataTypeGray if self.bits_per_component == 1 => PixelFormat::Bilevel,
+            ICScannerPixelDataTypeGray if wide => PixelFormat::Gray16,
+            ICScannerPixelDataTypeGray => PixelFormat::Gray8,
+            ICScannerPixelDataTypeRGB if self.num_components > 3 && !wide => PixelFormat::Rgba8,
+            ICScannerPixelDataTypePalette => PixelFormat::Rgb8,
+            _ if wide => PixelFormat::Rgb16,
+            _ => PixelFormat::Rgb8,
+        })
+    }
+}
+
+/// Converts raw scanner pixel data into one of the canonical pixel formats.
+///
+/// Colour spaces are converted as follows, with components normalised to the range 0 to 1:
+///
+/// * CMY and CMYK are inverted, with K darkening the result: `R = (1 - C) * (1 - K)`.
+/// * YUV uses the full range ITU-R BT.601 equations with U and V centred on 0.5; the K component
+///   of YUVK darkens the result like the K component of CMYK.
+/// * CIEXYZ is converted to sRGB with the D65 matrix and the sRGB transfer curve.
+/// * Palette indices are looked up in the palette, or treated as gray levels without a palette.
+///
+/// Colour pixels converted to a gray format use the BT.601 luma weights, and gray levels converted
+/// to bilevel are thresholded at 50%.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelConverter {
+    source: SourceFormat,
+    target: PixelFormat,
+    palette: Option<Vec<[u8; 3]>>,
+    white_is_zero: bool,
+}
+
+/// Maximum component value of the intermediate representation.
+const FULL: u32 = 0xffff;
+
+impl PixelConverter {
+    /// Create a converter to the canonical format of `source`.
+    pub fn new(source: SourceFormat) -> Result<PixelConverter> {
+        Ok(PixelConverter {
+            source,
+            target: source.canonical_format()?,
+            palette: None,
+            white_is_zero: false,
+        })
+    }
+
+    /// Create a converter for the pixel data in a scanner band.
+    pub fn for_band(band: &ScannerBand) -> Result<PixelConverter> {
+        PixelConverter::new(SourceFormat::from_band(band))
+    }
+
+    /// Convert to `target` instead of the canonical format of the source.
+    pub fn with_target(mut self, target: PixelFormat) -> PixelConverter {
+        self.target = target;
+        self
+    }
+
+    /// Use `palette` to look up the colours of `ICScannerPixelDataTypePalette` pixels.
+    /// Indices outside the palette are black.
+    pub fn with_palette(mut self, palette: Vec<[u8; 3]>) -> PixelConverter {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Treat a zero bit of `ICScannerPixelDataTypeBW` data as white instead of black.
+    pub fn with_white_is_zero(mut self, white_is_zero: bool) -> PixelConverter {
+        self.white_is_zero = white_is_zero;
+        self
+    }
+
+    /// The format pixels are converted from.
+    pub fn source(&self) -> SourceFormat {
+        self.source
+    }
+
+    /// The format pixels are converted to.
+    pub fn target(&self) -> PixelFormat {
+        self.target
+    }
+
+    /// Convert a row of `width` pixels from `source` into `target`.
+    /// `target` must hold at least a row of `width` pixels in the target format.
+    pub fn convert_row(&self, source: &[u8], width: usize, target: &mut [u8]) {
+        for x in 0..width {
+            let rgba = self.decode(source, x);
+            self.encode(rgba, target, x);
+        }
+    }
+
+    /// Convert a full image whose pixels are in the source format.
+    pub fn convert(&self, image: &ImageBuffer) -> Result<Image> {
+        if image.bits_per_pixel() != self.source.bits_per_pixel {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let mut converted = Image::new(self.target, image.width(), image.height());
+        for y in 0..image.height() {
+            self.convert_row(image.row(y), image.width(), converted.row_mut(y));
+        }
+        Ok(converted)
+    }
+
+    /// Read component `index` of pixel `x`, scaled to 16 bits.
+    fn component(&self, row: &[u8], x: usize, index: usize) -> u32 {
+        let bits = self.source.bits_per_component;
+        let offset = x * self.source.bits_per_pixel + index * bits;
+        match bits {
+            16 => {
+                let (a, b) = (u32::from(row[offset / 8]), u32::from(row[offset / 8 + 1]));
+                if self.source.is_big_endian {
+                    (a << 8) | b
+                } else {
+                    (b << 8) | a
+                }
+            }
+            8 => u32::from(row[offset / 8]) * 0x101,
+            _ => {
+                let max = (1u32 << bits) - 1;
+                let value = (u32::from(row[offset / 8]) >> (8 - bits - offset % 8)) & max;
+                value * FULL / max
+            }
+        }
+    }
+
+    /// Decode pixel `x` into 16 bit RGBA.
+    fn decode(&self, row: &[u8], x: usize) -> [u32; 4] {
+        let c = |index| self.component(row, x, index);
+        let unit = |index| self.component(row, x, index) as f64 / FULL as f64;
+        let opaque = |r: u32, g: u32, b: u32| [r, g, b, FULL];
+        match self.source.pixel_data_type {
+            ICScannerPixelDataTypeBW => {
+                let mut value = c(0);
+                if self.white_is_zero {
+                    value = FULL - value;
+                }
+                opaque(value, value, value)
+            }
+            ICScannerPixelDataTypeGray => opaque(c(0), c(0), c(0)),
+            ICScannerPixelDataTypeRGB => {
+                let alpha = if self.source.num_components > 3 {
+                    c(3)
+                } else {
+                    FULL
+                };
+                [c(0), c(1), c(2), alpha]
+            }
+            ICScannerPixelDataTypePalette => {
+                let bits = self.source.bits_per_component;
+                let index = c(0) >> (16 - bits.min(16));
+                match self.palette {
+                    Some(ref palette) => {
+                        let [r, g, b] = palette.get(index as usize).cloned().unwrap_or([0; 3]);
+                        opaque(
+                            u32::from(r) * 0x101,
+                            u32::from(g) * 0x101,
+                            u32::from(b) * 0x101,
+                        )
+                    }
+                    None => opaque(c(0), c(0), c(0)),
+                }
+            }
+            ICScannerPixelDataTypeCMY => opaque(FULL - c(0), FULL - c(1), FULL - c(2)),
+            ICScannerPixelDataTypeCMYK => {
+                let k = FULL - c(3);
+                opaque(
+                    (FULL - c(0)) * k / FULL,
+                    (FULL - c(1)) * k / FULL,
+                    (FULL - c(2)) * k / FULL,
+                )
+            }
+            ICScannerPixelDataTypeYUV | ICScannerPixelDataTypeYUVK => {
+                let (y, u, v) = (unit(0), unit(1) - 0.5, unit(2) - 0.5);
+                let k = if self.source.pixel_data_type == ICScannerPixelDataTypeYUVK {
+                    1.0 - unit(3)
+                } else {
+                    1.0
+                };
+                let r = y + 1.402 * v;
+                let g = y - 0.344_136 * u - 0.714_136 * v;
+                let b = y + 1.772 * u;
+                opaque(to_full(r * k), to_full(g * k), to_full(b * k))
+            }
+            ICScannerPixelDataTypeCIEXYZ => {
+                let (x, y, z) = (unit(0), unit(1), unit(2));
+                let r = 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z;
+                let g = -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z;
+                let b = 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z;
+                opaque(
+                    to_full(srgb_gamma(r)),
+                    to_full(srgb_gamma(g)),
+                    to_full(srgb_gamma(b)),
+                )
+            }
+        }
+    }
+
+    /// Encode 16 bit RGBA as pixel `x` of the target format.
+    fn encode(&self, rgba: [u32; 4], row: &mut [u8], x: usize) {
+        let [r, g, b, a] = rgba;
+        let high = |value: u32| ((value * 255 + 0x7fff) / FULL) as u8;
+        match self.target {
+            PixelFormat::Bilevel => {
+                let mask = 0x80 >> (x % 8);
+                if luma(r, g, b) > FULL / 2 {
+                    row[x / 8] |= mask;
+                } else {
+                    row[x / 8] &= !mask;
+                }
+            }
+            PixelFormat::Gray8 => row[x] = high(luma(r, g, b)),
+            PixelFormat::Gray16 => {
+                let value = luma(r, g, b);
+                row[2 * x] = (value >> 8) as u8;
+                row[2 * x + 1] = value as u8;
+            }
+            PixelFormat::Rgb8 => {
+                row[3 * x] = high(r);
+                row[3 * x + 1] = high(g);
+                row[3 * x + 2] = high(b);
+            }
+            PixelFormat::Rgb16 => {
+                for (i, &value) in [r, g, b].iter().enumerate() {
+                    row[6 * x + 2 * i] = (value >> 8) as u8;
+                    row[6 * x + 2 * i + 1] = value as u8;
+                }
+            }
+            PixelFormat::Rgba8 => {
+                row[4 * x] = high(r);
+                row[4 * x + 1] = high(g);
+                row[4 * x + 2] = high(b);
+                row[4 * x + 3] = high(a);
+            }
+        }
+    }
+}
+
+/// BT.601 luma of 16 bit RGB.
+fn luma(r: u32, g: u32, b: u32) -> u32 {
+    if r == g && g == b {
+        return r;
+    }
+    (r * 19595 + g * 38470 + b * 7471 + 0x8000) >> 16
+}
+
+fn to_full(value: f64) -> u32 {
+    (value.clamp(0.0, 1.0) * FULL as f64).round() as u32
+}
+
+fn srgb_gamma(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(
+        pixel_data_type: ICScannerPixelDataType,
+        bits_per_component: usize,
+        num_components: usize,
+        bits_per_pixel: usize,
+    ) -> SourceFormat {
+        SourceFormat {
+            pixel_data_type,
+            bits_per_component,
+            num_components,
+            bits_per_pixel,
+            is_big_endian: false,
+        }
+    }
+
+    #[test]
+    fn canonical_formats_keep_depth_and_alpha() {
+        let canonical = |source: SourceFormat| source.canonical_format();
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeGray, 1, 1, 1)),
+            Ok(PixelFormat::Bilevel)
+        );
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeGray, 16, 1, 16)),
+            Ok(PixelFormat::Gray16)
+        );
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeRGB, 8, 4, 32)),
+            Ok(PixelFormat::Rgba8)
+        );
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeCMYK, 16, 4, 64)),
+            Ok(PixelFormat::Rgb16)
+        );
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeRGB, 8, 3, 16)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+        assert_eq!(
+            canonical(source(ICScannerPixelDataTypeGray, 12, 1, 16)),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+    }
+
+    #[test]
+    fn cmyk_is_inverted_and_darkened() {
+        let converter = PixelConverter::new(source(ICScannerPixelDataTypeCMYK, 8, 4, 32)).unwrap();
+        let mut row = [0; 6];
+        converter.convert_row(&[0, 255, 255, 0, 0, 0, 0, 0x80], 2, &mut row);
+        assert_eq!(row, [255, 0, 0, 127, 127, 127]);
+    }
+
+    #[test]
+    fn sixteen_bit_samples_follow_the_byte_order() {
+        let mut format = source(ICScannerPixelDataTypeGray, 16, 1, 16);
+        let image = ImageBuffer::from_data(1, 1, 16, 2, vec![0x34, 0x12]).unwrap();
+        let converted = PixelConverter::new(format)
+            .unwrap()
+            .convert(&image)
+            .unwrap();
+        assert_eq!(converted.row(0), [0x12, 0x34]);
+        format.is_big_endian = true;
+        let converted = PixelConverter::new(format)
+            .unwrap()
+            .convert(&image)
+            .unwrap();
+        assert_eq!(converted.row(0), [0x34, 0x12]);
+    }
+
+    #[test]
+    fn palettes_and_bilevel_polarity() {
+        let converter = PixelConverter::new(source(ICScannerPixelDataTypePalette, 4, 1, 4))
+            .unwrap()
+            .with_palette(vec![[10, 20, 30], [40, 50, 60]]);
+        let mut row = [0; 6];
+        converter.convert_row(&[0x1f], 2, &mut row);
+        // Index 15 is outside the palette.
+        assert_eq!(row, [40, 50, 60, 0, 0, 0]);
+
+        let bw = PixelConverter::new(source(ICScannerPixelDataTypeBW, 1, 1, 1)).unwrap();
+        let mut row = [0];
+        bw.convert_row(&[0b1010_0000], 4, &mut row);
+        assert_eq!(row, [0b1010_0000]);
+        bw.with_white_is_zero(true)
+            .convert_row(&[0b1010_0000], 4, &mut row);
+        assert_eq!(row[0] & 0xf0, 0b0101_0000);
+
+        let gray = PixelConverter::new(source(ICScannerPixelDataTypeRGB, 8, 3, 24))
+            .unwrap()
+            .with_target(PixelFormat::Gray8);
+        let mut row = [0; 2];
+        gray.convert_row(&[255, 0, 0, 0, 255, 0], 2, &mut row);
+        assert_eq!(row, [76, 150]);
+    }
+}
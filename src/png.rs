@@ -0,0 +1,555 @@
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerMeasurementUnit::{self, *};
+use crate::error::{Error, Result};
+use crate::image::{min_bytes_per_row, Image, PixelFormat};
+use crate::measurement::convert;
+use crate::pixel_format::PixelConverter;
+use miniz_oxide::deflate::core::{
+    compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Compressed data is written in IDAT chunks of this size.
+const IDAT_SIZE: usize = 32 * 1024;
+
+/// Name of the embedded colour profile.
+const PROFILE_NAME: &[u8] = b"ICC profile";
+
+/// Description of an image written by `PngWriter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngInfo {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    resolution: Option<(f64, f64, ICScannerMeasurementUnit)>,
+    icc_profile: Option<Vec<u8>>,
+    compression_level: u8,
+}
+
+impl PngInfo {
+    /// Describe an image without resolution or colour profile.
+    pub fn new(format: PixelFormat, width: usize, height: usize) -> PngInfo {
+        PngInfo {
+            format,
+            width,
+            height,
+            resolution: None,
+            icc_profile: None,
+            compression_level: 6,
+        }
+    }
+
+    /// Describe an image holding `image`.
+    pub fn for_image(image: &Image) -> PngInfo {
+        PngInfo::new(image.format(), image.width(), image.height())
+    }
+
+    /// Set the resolution in pixels per `unit`, as reported by the scanner. The resolution is
+    /// written in pixels per metre, or as a pixel aspect ratio for `ICScannerMeasurementUnitPixels`.
+    pub fn with_resolution(
+        mut self,
+        x_resolution: f64,
+        y_resolution: f64,
+        unit: ICScannerMeasurementUnit,
+    ) -> PngInfo {
+        self.resolution = Some((x_resolution, y_resolution, unit));
+        self
+    }
+
+    /// Embed an ICC colour profile. Writing fails if the colour space of the profile does not
+    /// match the pixel format.
+    pub fn with_icc_profile(mut self, profile: Vec<u8>) -> PngInfo {
+        self.icc_profile = Some(profile);
+        self
+    }
+
+    /// Embed the colour profile of a scanner band, if it has one.
+    /// Fails if the profile file cannot be read.
+    pub fn with_band_profile(self, band: &ScannerBand) -> Result<PngInfo> {
+        match band.color_sync_profile_path {
+            Some(ref path) => self.with_icc_profile_file(path),
+            None => Ok(self),
+        }
+    }
+
+    /// Embed the colour profile stored in a file. Fails if the file cannot be read.
+    pub fn with_icc_profile_file<P: AsRef<Path>>(self, path: P) -> Result<PngInfo> {
+        Ok(self.with_icc_profile(fs::read(path)?))
+    }
+
+    /// Set the zlib compression level, from 0 (none) to 10 (smallest). The default is 6.
+    pub fn with_compression_level(mut self, level: u8) -> PngInfo {
+        self.compression_level = level.min(10);
+        self
+    }
+
+    /// The pixel format.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn row_bytes(&self) -> usize {
+        min_bytes_per_row(self.width, self.format.bits_per_pixel())
+    }
+
+    /// The bit depth and colour type of the IHDR chunk.
+    fn header_type(&self) -> (u8, u8) {
+        match self.format {
+            PixelFormat::Bilevel => (1, 0),
+            PixelFormat::Gray8 => (8, 0),
+            PixelFormat::Gray16 => (16, 0),
+            PixelFormat::Rgb8 => (8, 2),
+            PixelFormat::Rgb16 => (16, 2),
+            PixelFormat::Rgba8 => (8, 6),
+        }
+    }
+
+    /// The pixels per unit and unit specifier of the pHYs chunk.
+    fn physical_dimensions(&self) -> Result<Option<(u32, u32, u8)>> {
+        let (x, y, unit) = match self.resolution {
+            Some(resolution) => resolution,
+            None => return Ok(None),
+        };
+        let (scale, specifier) = match unit {
+            ICScannerMeasurementUnitPixels => (1.0, 0),
+            unit => (
+                convert(100.0, ICScannerMeasurementUnitCentimeters, unit, 0)?,
+                1,
+            ),
+        };
+        let to_u32 = |value: f64| (value * scale).round().min(f64::from(u32::MAX)) as u32;
+        Ok(Some((to_u32(x), to_u32(y), specifier)))
+    }
+
+    fn validate(&self) -> Result<()> {
+        let resolution_valid = match self.resolution {
+            Some((x, y, _)) => x > 0.0 && y > 0.0,
+            None => true,
+        };
+        if self.width == 0
+            || self.height == 0
+            || self.width > i32::MAX as usize
+            || self.height > i32::MAX as usize
+            || !resolution_valid
+            || !self.profile_matches_format()
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.physical_dimensions().map(|_| ())
+    }
+
+    /// Indicates whether the colour space in the header of the ICC profile, if there is one,
+    /// matches the colour type of the image, as PNG requires.
+    fn profile_matches_format(&self) -> bool {
+        let profile = match self.icc_profile {
+            Some(ref profile) => profile,
+            None => return true,
+        };
+        let color_space: &[u8] = match self.header_type().1 {
+            0 => b"GRAY",
+            _ => b"RGB ",
+        };
+        profile.get(16..20) == Some(color_space)
+    }
+}
+
+/// Writes PNG images row by row.
+///
+/// Each row is filtered and fed to the compressor as soon as it is written, and compressed data is
+/// written out in IDAT chunks as it accumulates, so only a couple of rows are held in memory at a
+/// time. Bilevel images are written as 1 bit gray, with 0 for black.
+pub struct PngWriter<W: Write> {
+    writer: W,
+    info: PngInfo,
+    compressor: Box<CompressorOxide>,
+    previous: Vec<u8>,
+    filtered: Vec<u8>,
+    compressed: Vec<u8>,
+    rows_written: usize,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Start an image by writing the signature and the header chunks.
+    /// Fails if the description is invalid.
+    pub fn new(mut writer: W, info: PngInfo) -> Result<PngWriter<W>> {
+        info.validate()?;
+        writer.write_all(&SIGNATURE)?;
+        let (bit_depth, color_type) = info.header_type();
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(info.width as u32).to_be_bytes());
+        header.extend_from_slice(&(info.height as u32).to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        write_chunk(&mut writer, b"IHDR", &header)?;
+        if let Some((x, y, unit)) = info.physical_dimensions()? {
+            let mut dimensions = Vec::with_capacity(9);
+            dimensions.extend_from_slice(&x.to_be_bytes());
+            dimensions.extend_from_slice(&y.to_be_bytes());
+            dimensions.push(unit);
+            write_chunk(&mut writer, b"pHYs", &dimensions)?;
+        }
+        if let Some(ref profile) = info.icc_profile {
+            let mut chunk = PROFILE_NAME.to_vec();
+            chunk.extend_from_slice(&[0, 0]);
+            chunk.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(profile, 9));
+            write_chunk(&mut writer, b"iCCP", &chunk)?;
+        }
+        let flags = create_comp_flags_from_zip_params(i32::from(info.compression_level), 15, 0);
+        let row_bytes = info.row_bytes();
+        Ok(PngWriter {
+            writer,
+            compressor: Box::new(CompressorOxide::new(flags)),
+            previous: vec![0; row_bytes],
+            filtered: Vec::with_capacity(row_bytes + 1),
+            compressed: Vec::new(),
+            rows_written: 0,
+            info,
+        })
+    }
+
+    /// Number of rows written so far.
+    pub fn rows_written(&self) -> usize {
+        self.rows_written
+    }
+
+    /// Write the next row, in the pixel format of the image.
+    /// Fails if every row has been written or if `row` is too short.
+    pub fn write_row(&mut self, row: &[u8]) -> Result<()> {
+        let row_bytes = self.info.row_bytes();
+        if self.rows_written == self.info.height || row.len() < row_bytes {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let row = &row[..row_bytes];
+        let bytes_per_pixel = (self.info.format.bits_per_pixel() / 8).max(1);
+        // Sub-byte images are left unfiltered, as the PNG specification recommends.
+        let filter = if self.info.format.bits_per_pixel() < 8 {
+            0
+        } else {
+            choose_filter(row, &self.previous, bytes_per_pixel)
+        };
+        self.filtered.clear();
+        self.filtered.push(filter);
+        apply_filter(
+            filter,
+            row,
+            &self.previous,
+            bytes_per_pixel,
+            &mut self.filtered,
+        );
+        self.previous.copy_from_slice(row);
+        self.rows_written += 1;
+        let filtered = std::mem::take(&mut self.filtered);
+        let result = self.deflate(&filtered, TDEFLFlush::None);
+        self.filtered = filtered;
+        result
+    }
+
+    /// Write the rows of a scanner band, converting them to the pixel format of the image.
+    ///
+    /// Bands have to arrive in order, starting with the row after the last row written. Fails for
+    /// planar bands, which cannot be written before every channel has arrived; assemble those
+    /// with `BandAssembler` instead.
+    pub fn write_band(&mut self, band: &ScannerBand, converter: &PixelConverter) -> Result<()> {
+        if band.is_planar()
+            || band.data_start_row != self.rows_written
+            || band.full_image_width != self.info.width
+            || band.bits_per_pixel != converter.source().bits_per_pixel
+            || converter.target() != self.info.format
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let mut row = vec![0; self.info.row_bytes()];
+        for y in 0..band.available_rows() {
+            converter.convert_row(band.row(y), self.info.width, &mut row);
+            self.write_row(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the image and return the writer. Fails if rows are missing.
+    pub fn finish(mut self) -> Result<W> {
+        if self.rows_written != self.info.height {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.deflate(&[], TDEFLFlush::Finish)?;
+        if !self.compressed.is_empty() {
+            write_chunk(&mut self.writer, b"IDAT", &self.compressed)?;
+        }
+        write_chunk(&mut self.writer, b"IEND", &[])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Write a complete image.
+    pub fn write_image(writer: W, info: PngInfo, image: &Image) -> Result<W> {
+        if info.format != image.format()
+            || info.width != image.width()
+            || info.height != image.height()
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let mut png = PngWriter::new(writer, info)?;
+        for y in 0..image.height() {
+            png.write_row(image.row(y))?;
+        }
+        png.finish()
+    }
+
+    /// Compress `input`, writing an IDAT chunk whenever enough data has accumulated.
+    fn deflate(&mut self, mut input: &[u8], flush: TDEFLFlush) -> Result<()> {
+        let mut output = [0; 16 * 1024];
+        loop {
+            let (status, consumed, produced) =
+                compress(&mut self.compressor, input, &mut output, flush);
+            input = &input[consumed..];
+            self.compressed.extend_from_slice(&output[..produced]);
+            while self.compressed.len() >= IDAT_SIZE {
+                write_chunk(&mut self.writer, b"IDAT", &self.compressed[..IDAT_SIZE])?;
+                self.compressed.drain(..IDAT_SIZE);
+            }
+            match status {
+                TDEFLStatus::Done => return Ok(()),
+                TDEFLStatus::Okay if input.is_empty() && produced < output.len() => {
+                    if flush == TDEFLFlush::None {
+                        return Ok(());
+                    }
+                }
+                TDEFLStatus::Okay => {}
+                _ => {
+                    return Err(
+                        io::Error::new(io::ErrorKind::Other, "PNG compression failed").into(),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Pick the filter with the smallest sum of absolute filtered values.
+fn choose_filter(row: &[u8], previous: &[u8], bytes_per_pixel: usize) -> u8 {
+    let mut best = (u64::MAX, 0);
+    let mut filtered = Vec::with_capacity(row.len());
+    for filter in 0..5 {
+        filtered.clear();
+        apply_filter(filter, row, previous, bytes_per_pixel, &mut filtered);
+        let cost = filtered
+            .iter()
+            .map(|&byte| u64::from((byte as i8).unsigned_abs()))
+            .sum();
+        if cost < best.0 {
+            best = (cost, filter);
+        }
+    }
+    best.1
+}
+
+fn apply_filter(
+    filter: u8,
+    row: &[u8],
+    previous: &[u8],
+    bytes_per_pixel: usize,
+    output: &mut Vec<u8>,
+) {
+    for (i, &byte) in row.iter().enumerate() {
+        let left = if i >= bytes_per_pixel {
+            row[i - bytes_per_pixel]
+        } else {
+            0
+        };
+        let up = previous[i];
+        let up_left = if i >= bytes_per_pixel {
+            previous[i - bytes_per_pixel]
+        } else {
+            0
+        };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        output.push(byte.wrapping_sub(predictor));
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance_left = (estimate - i16::from(left)).abs();
+    let distance_up = (estimate - i16::from(up)).abs();
+    let distance_up_left = (estimate - i16::from(up_left)).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32_update(crc32_update(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    writer.write_all(&crc.to_be_bytes())?;
+    Ok(())
+}
+
+/// Table driven CRC-32 as used by PNG chunks.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    const fn table() -> [u32; 256] {
+        let mut table = [0; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    }
+    const TABLE: [u32; 256] = table();
+    for &byte in data {
+        crc = TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icc::IccProfile;
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+    /// Split a PNG file into its chunks, checking the signature and the CRC of each chunk.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let kind = [rest[4], rest[5], rest[6], rest[7]];
+            let data = rest[8..8 + length].to_vec();
+            let crc = &rest[8 + length..12 + length];
+            // Bitwise CRC-32, independent of the table of the writer.
+            let mut expected = !0u32;
+            for &byte in kind.iter().chain(&data) {
+                expected ^= u32::from(byte);
+                for _ in 0..8 {
+                    expected = (expected >> 1) ^ (0xedb8_8320 & (expected & 1).wrapping_neg());
+                }
+            }
+            assert_eq!(crc, (!expected).to_be_bytes(), "{:?}", kind);
+            chunks.push((kind, data));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    fn chunk<'a>(chunks: &'a [([u8; 4], Vec<u8>)], kind: &[u8; 4]) -> Option<&'a [u8]> {
+        chunks
+            .iter()
+            .find(|(found, _)| found == kind)
+            .map(|(_, data)| &data[..])
+    }
+
+    #[test]
+    fn header_and_rows_round_trip() {
+        let mut image = Image::new(PixelFormat::Rgb16, 3, 4);
+        for y in 0..4 {
+            for (x, byte) in image.row_mut(y).iter_mut().enumerate() {
+                *byte = (x * 37 + y * 11) as u8;
+            }
+        }
+        let png = PngWriter::write_image(Vec::new(), PngInfo::for_image(&image), &image).unwrap();
+        let chunks = chunks(&png);
+        assert_eq!(chunks.first().unwrap().0, *b"IHDR");
+        assert_eq!(chunks.last().unwrap(), &(*b"IEND", Vec::new()));
+        assert_eq!(
+            chunk(&chunks, b"IHDR").unwrap(),
+            [0, 0, 0, 3, 0, 0, 0, 4, 16, 2, 0, 0, 0]
+        );
+        assert_eq!(chunk(&chunks, b"pHYs"), None);
+
+        // Undo the row filters and compare with the image.
+        let data = decompress_to_vec_zlib(chunk(&chunks, b"IDAT").unwrap()).unwrap();
+        let row_bytes = 3 * 6;
+        let mut previous = vec![0; row_bytes];
+        for (y, line) in data.chunks(row_bytes + 1).enumerate() {
+            let mut row = vec![0u8; row_bytes];
+            for x in 0..row_bytes {
+                let left = if x >= 6 { row[x - 6] } else { 0 };
+                let up_left = if x >= 6 { previous[x - 6] } else { 0 };
+                let predictor = match line[0] {
+                    0 => 0,
+                    1 => left,
+                    2 => previous[x],
+                    3 => ((u16::from(left) + u16::from(previous[x])) / 2) as u8,
+                    4 => paeth(left, previous[x], up_left),
+                    filter => panic!("filter {}", filter),
+                };
+                row[x] = line[1 + x].wrapping_add(predictor);
+            }
+            assert_eq!(row, image.row(y));
+            previous = row;
+        }
+    }
+
+    #[test]
+    fn resolution_is_written_per_metre() {
+        let info = PngInfo::new(PixelFormat::Bilevel, 8, 1).with_resolution(
+            300.0,
+            600.0,
+            ICScannerMeasurementUnitInches,
+        );
+        let mut png = PngWriter::new(Vec::new(), info).unwrap();
+        png.write_row(&[0x55]).unwrap();
+        let chunks = chunks(&png.finish().unwrap());
+        assert_eq!(chunk(&chunks, b"IHDR").unwrap()[8..10], [1, 0]);
+        let mut expected = 11811u32.to_be_bytes().to_vec();
+        expected.extend_from_slice(&23622u32.to_be_bytes());
+        expected.push(1);
+        assert_eq!(chunk(&chunks, b"pHYs").unwrap(), &expected[..]);
+    }
+
+    #[test]
+    fn profiles_must_match_the_colour_type() {
+        let profile = IccProfile::srgb().into_data();
+        let gray = PngInfo::new(PixelFormat::Gray8, 1, 1).with_icc_profile(profile.clone());
+        assert!(PngWriter::new(Vec::new(), gray).is_err());
+
+        let rgb = PngInfo::new(PixelFormat::Rgba8, 1, 1).with_icc_profile(profile.clone());
+        let mut png = PngWriter::new(Vec::new(), rgb).unwrap();
+        png.write_row(&[1, 2, 3, 4]).unwrap();
+        let chunks = chunks(&png.finish().unwrap());
+        let iccp = chunk(&chunks, b"iCCP").unwrap();
+        assert!(iccp.starts_with(b"ICC profile\0\0"));
+        assert_eq!(
+            decompress_to_vec_zlib(&iccp[PROFILE_NAME.len() + 2..]).unwrap(),
+            profile
+        );
+    }
+}
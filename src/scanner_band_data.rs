@@ -1,7 +1,8 @@
 use cocoa::base::{id, BOOL};
 use cocoa::foundation::NSUInteger;
 use objc::*;
-use crate::scanner_functional_units::ICScannerPixelDataType;
+use crate::constants::{ICScannerPixelDataType, UnknownValue};
+use std::convert::TryFrom;
 
 /// ICScannerBandData
 pub trait ICScannerBandData: Sized {
@@ -18,7 +19,7 @@ pub trait ICScannerBandData: Sized {
     /// Describes if the banded image data is reported in big endian.
     unsafe fn isBigEndian(self) -> BOOL;
     /// Type of pixel data that is contained in the band.
-    unsafe fn pixelDataType(self) -> ICScannerPixelDataType;
+    unsafe fn pixelDataType(self) -> Result<ICScannerPixelDataType, UnknownValue>;
     /// Returns the path to the color profile matching the banded data.
     unsafe fn colorSyncProfilePath(self) -> id;
     /// Describes how many bytes are in each image band row.
@@ -58,8 +59,9 @@ impl ICScannerBandData for id {
         msg_send![self, isBigEndian]
     }
 
-    unsafe fn pixelDataType(self) -> ICScannerPixelDataType {
-        msg_send![self, pixelDataType]
+    unsafe fn pixelDataType(self) -> Result<ICScannerPixelDataType, UnknownValue> {
+        let pixelDataType: NSUInteger = msg_send![self, pixelDataType];
+        ICScannerPixelDataType::try_from(pixelDataType)
     }
 
     unsafe fn colorSyncProfilePath(self) -> id {
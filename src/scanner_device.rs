@@ -1,15 +1,9 @@
 use cocoa::base::id;
+use cocoa::foundation::NSUInteger;
 use objc::*;
-
-/// Transfer mode to be used when transferring scan data from the scanner functional unit.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerTransferMode {
-    /// Save the scan as a file.
-    ICScannerTransferModeFileBased = 0,
-    /// Transfer the scan as data.
-    ICScannerTransferModeMemoryBased = 1,
-}
+pub use crate::constants::ICScannerTransferMode;
+use crate::constants::UnknownValue;
+use std::convert::TryFrom;
 
 pub trait ICScannerDevice: Sized {
     /// An array of functional unit types available on this scanner device.
@@ -18,7 +12,7 @@ pub trait ICScannerDevice: Sized {
     /// The currently selected functional unit on the scanner device.
     unsafe fn selectedFunctionalUnit(self) -> id;
     /// The transfer mode for scanned document.
-    unsafe fn transferMode(self) -> ICScannerTransferMode;
+    unsafe fn transferMode(self) -> Result<ICScannerTransferMode, UnknownValue>;
     /// Set the transfer mode for scanned document.
     unsafe fn setTransferMode(self, transferMode: ICScannerTransferMode);
     /// ￼The total maximum band size requested when performing a ICScannerTransferModeMemoryBased.
@@ -66,12 +60,13 @@ impl ICScannerDevice for id {
         msg_send![self, selectedFunctionalUnit]
     }
 
-    unsafe fn transferMode(self) -> ICScannerTransferMode {
-        msg_send![self, transferMode]
+    unsafe fn transferMode(self) -> Result<ICScannerTransferMode, UnknownValue> {
+        let transferMode: NSUInteger = msg_send![self, transferMode];
+        ICScannerTransferMode::try_from(transferMode)
     }
 
     unsafe fn setTransferMode(self, transferMode: ICScannerTransferMode) {
-        msg_send![self, setTransferMode: transferMode]
+        msg_send![self, setTransferMode: NSUInteger::from(transferMode)]
     }
 
     unsafe fn maxMemoryBandSize(self) -> u32 {
@@ -1,192 +1,21 @@
 use cocoa::base::{id, BOOL};
 use cocoa::foundation::{NSRect, NSSize, NSUInteger};
-use crate::constants::ICEXIFOrientationType;
+use crate::constants::{ICEXIFOrientationType, UnknownValue};
+pub use crate::constants::{
+    ICScannerBitDepth, ICScannerColorDataFormatType, ICScannerDocumentType, ICScannerFeatureType,
+    ICScannerFunctionalUnitState, ICScannerFunctionalUnitType, ICScannerMeasurementUnit,
+    ICScannerPixelDataType,
+};
 use core_graphics::base::CGFloat;
 use core_graphics::image::CGImageRef;
 use libc::c_uchar;
 use objc::*;
-
-/// Scanner Functional Unit Types
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerFunctionalUnitType {
-    /// Flatbed functional unit.
-    ICScannerFunctionalUnitTypeFlatbed = 0,
-    /// Transparency functional unit for scanning positives.
-    ICScannerFunctionalUnitTypePositiveTransparency = 1,
-    /// Transparency functional unit for scanning negatives.
-    ICScannerFunctionalUnitTypeNegativeTransparency = 2,
-    /// Document feeder functional unit.
-    ICScannerFunctionalUnitTypeDocumentFeeder = 3,
-}
-
-/// Unit of measurement used by the scanner.
-/// This corresponds to values used for ICAP_UNITS as defined in the TWAIN Specification.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerMeasurementUnit {
-    ICScannerMeasurementUnitInches = 0,
-    ICScannerMeasurementUnitCentimeters = 1,
-    ICScannerMeasurementUnitPicas = 2,
-    ICScannerMeasurementUnitPoints = 3,
-    ICScannerMeasurementUnitTwips = 4,
-    ICScannerMeasurementUnitPixels = 5,
-}
-
-/// Bits per channel in the scanned image.
-/// This corresponds to values used for ICAP_UNITS as defined in the TWAIN Specification.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerBitDepth {
-    ICScannerBitDepth1Bit = 1,
-    ICScannerBitDepth8Bits = 8,
-    ICScannerBitDepth16Bits = 16,
-}
-
-/// Bits per channel in the scanned image.
-/// This corresponds to values used for ICAP_UNITS as defined in the TWAIN Specification.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerColorDataFormatType {
-    /// For multi-channel data (e.g., RGB) data from all channels are interleaved.
-    ICScannerColorDataFormatTypeChunky = 0,
-    /// For multi-channel data (e.g., RGB) each channel is transferred sequentially.
-    ICScannerColorDataFormatTypePlanar = 1,
-}
-
-/// Pixel data types.
-/// Corresponds to "ICAP_PIXELTYPE" of the TWAIN Specification.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerPixelDataType {
-    /// Monochrome 1 bit pixel image.
-    ICScannerPixelDataTypeBW = 0,
-    /// 8 bit pixel Gray color space.
-    ICScannerPixelDataTypeGray = 1,
-    /// Color image RGB color space.
-    ICScannerPixelDataTypeRGB = 2,
-    /// Indexed Color image.
-    ICScannerPixelDataTypePalette = 3,
-    /// Color image in CMY color space.
-    ICScannerPixelDataTypeCMY = 4,
-    /// Color image in CMYK color space.
-    ICScannerPixelDataTypeCMYK = 5,
-    /// Color image in YUV color space.
-    ICScannerPixelDataTypeYUV = 6,
-    /// Color image in YUVK color space.
-    ICScannerPixelDataTypeYUVK = 7,
-    /// Color image in CIEXYZ color space.
-    ICScannerPixelDataTypeCIEXYZ = 8,
-}
-
-/// Document size types.
-/// Corresponds to "ICAP_SUPPORTEDSIZES" used by the Image Catpure scanner modules.
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerDocumentType {
-    ICScannerDocumentTypeDefault = 0,
-    ICScannerDocumentTypeA4 = 1,
-    ICScannerDocumentTypeB5 = 2,
-    ICScannerDocumentTypeUSLetter = 3,
-    ICScannerDocumentTypeUSLegal = 4,
-    ICScannerDocumentTypeA5 = 5,
-    ICScannerDocumentTypeISOB4 = 6,
-    ICScannerDocumentTypeISOB6 = 7,
-    ICScannerDocumentTypeUSLedger = 9,
-    ICScannerDocumentTypeUSExecutive = 10,
-    ICScannerDocumentTypeA3 = 11,
-    ICScannerDocumentTypeISOB3 = 12,
-    ICScannerDocumentTypeA6 = 13,
-    ICScannerDocumentTypeC4 = 14,
-    ICScannerDocumentTypeC5 = 15,
-    ICScannerDocumentTypeC6 = 16,
-    ICScannerDocumentType4A0 = 17,
-    ICScannerDocumentType2A0 = 18,
-    ICScannerDocumentTypeA0 = 19,
-    ICScannerDocumentTypeA1 = 20,
-    ICScannerDocumentTypeA2 = 21,
-    ICScannerDocumentTypeA7 = 22,
-    ICScannerDocumentTypeA8 = 23,
-    ICScannerDocumentTypeA9 = 24,
-    ICScannerDocumentType10 = 25,
-    ICScannerDocumentTypeISOB0 = 26,
-    ICScannerDocumentTypeISOB1 = 27,
-    ICScannerDocumentTypeISOB2 = 28,
-    ICScannerDocumentTypeISOB5 = 29,
-    ICScannerDocumentTypeISOB7 = 30,
-    ICScannerDocumentTypeISOB8 = 31,
-    ICScannerDocumentTypeISOB9 = 32,
-    ICScannerDocumentTypeISOB10 = 33,
-    ICScannerDocumentTypeJISB0 = 34,
-    ICScannerDocumentTypeJISB1 = 35,
-    ICScannerDocumentTypeJISB2 = 36,
-    ICScannerDocumentTypeJISB3 = 37,
-    ICScannerDocumentTypeJISB4 = 38,
-    ICScannerDocumentTypeJISB6 = 39,
-    ICScannerDocumentTypeJISB7 = 40,
-    ICScannerDocumentTypeJISB8 = 41,
-    ICScannerDocumentTypeJISB9 = 42,
-    ICScannerDocumentTypeJISB10 = 43,
-    ICScannerDocumentTypeC0 = 44,
-    ICScannerDocumentTypeC1 = 45,
-    ICScannerDocumentTypeC2 = 46,
-    ICScannerDocumentTypeC3 = 47,
-    ICScannerDocumentTypeC7 = 48,
-    ICScannerDocumentTypeC8 = 49,
-    ICScannerDocumentTypeC9 = 50,
-    ICScannerDocumentTypeC10 = 51,
-    ICScannerDocumentTypeUSStatement = 52,
-    ICScannerDocumentTypeBusinessCard = 53,
-    ICScannerDocumentTypeE = 60,
-    ICScannerDocumentType3R = 61,
-    ICScannerDocumentType4R = 62,
-    ICScannerDocumentType5R = 63,
-    ICScannerDocumentType6R = 64,
-    ICScannerDocumentType8R = 65,
-    ICScannerDocumentTypeS8R = 66,
-    ICScannerDocumentType10R = 67,
-    ICScannerDocumentTypeS10R = 68,
-    ICScannerDocumentType11R = 69,
-    ICScannerDocumentType12R = 70,
-    ICScannerDocumentTypeS12R = 71,
-    ICScannerDocumentType110 = 72,
-    ICScannerDocumentTypeAPSH = 73,
-    ICScannerDocumentTypeAPSC = 74,
-    ICScannerDocumentTypeAPSP = 75,
-    ICScannerDocumentType135 = 76,
-    ICScannerDocumentTypeMF = 77,
-    ICScannerDocumentTypeLF = 78,
-}
-
-/// A flag to indicate the scanner functional unit's state
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerFunctionalUnitState {
-    /// The scanner functional unit is ready for operation.
-    ICScannerFunctionalUnitStateReady = (1 << 0),
-    /// The scanner functional unit is performing a scan.
-    ICScannerFunctionalUnitStateScanInProgress = (1 << 1),
-    /// The scanner functional unit is performing an overview scan.
-    ICScannerFunctionalUnitStateOverviewScanInProgress = (1 << 2),
-}
-
-/// Scanner Feature Types
-#[repr(u64)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ICScannerFeatureType {
-    /// This feature can have one of several discrete values, strings or numbers.
-    ICScannerFeatureTypeEnumeration = 0,
-    /// This value of this feature lies within a range.
-    ICScannerFeatureTypeRange = 1,
-    /// The value of this feature can be YES or NO.
-    ICScannerFeatureTypeBoolean = 2,
-    ICScannerFeatureTypeTemplate = 3,
-}
+use std::convert::TryFrom;
 
 /// ICScannerFeature class is an abstract base class used to describe a scanner feature.
 pub trait ICScannerFeature: Sized {
     /// Scanner feature type.
-    unsafe fn type_(self) -> ICScannerFeatureType;
+    unsafe fn type_(self) -> Result<ICScannerFeatureType, UnknownValue>;
     /// The internal name of this feature.
     unsafe fn internalName(self) -> id;
     /// The human readable name of this feature.
@@ -196,8 +25,9 @@ pub trait ICScannerFeature: Sized {
 }
 
 impl ICScannerFeature for id {
-    unsafe fn type_(self) -> ICScannerFeatureType {
-        msg_send![self, type]
+    unsafe fn type_(self) -> Result<ICScannerFeatureType, UnknownValue> {
+        let type_: NSUInteger = msg_send![self, type];
+        ICScannerFeatureType::try_from(type_)
     }
 
     unsafe fn internalName(self) -> id {
@@ -329,21 +159,21 @@ impl ICScannerFeatureTemplate for id {
 /// ICScannerDevice creates instances of these concrete subclasses.
 pub trait ICScannerFunctionalUnit: Sized {
     /// Functional unit type.
-    unsafe fn type_(self) -> ICScannerFunctionalUnitType;
+    unsafe fn type_(self) -> Result<ICScannerFunctionalUnitType, UnknownValue>;
     /// The pixel data type.
-    unsafe fn pixelDataType(self) -> ICScannerPixelDataType;
+    unsafe fn pixelDataType(self) -> Result<ICScannerPixelDataType, UnknownValue>;
     /// Set the pixel data type.
     unsafe fn setPixelDataType(self, pixelDataType: ICScannerPixelDataType);
     /// Supported bit depths. The values in this set are valid values defined by ICScannerBitDepth.
     unsafe fn supportedBitDepths(self) -> id;
     /// The bit depth to use when performing the final scan. This will always be one of the supported bit depths.
-    unsafe fn bitDepth(self) -> ICScannerBitDepth;
+    unsafe fn bitDepth(self) -> Result<ICScannerBitDepth, UnknownValue>;
     /// Set the bit depth to use when performing the final scan.
     unsafe fn setBitDepth(self, bitDepth: ICScannerBitDepth);
     /// Supported measurement units. The values in this set are valid values defined by ICScannerMeasurementUnit.
     unsafe fn supportedMeasurementUnits(self) -> id;
     /// Current measurement unit. This will always be one of the supported measurement units.
-    unsafe fn measurementUnit(self) -> ICScannerMeasurementUnit;
+    unsafe fn measurementUnit(self) -> Result<ICScannerMeasurementUnit, UnknownValue>;
     /// Set current measurement unit.
     unsafe fn setMeasurementUnit(self, measurementUnit: ICScannerMeasurementUnit);
     /// Supported scan resolutions in DPI.
@@ -377,7 +207,7 @@ pub trait ICScannerFunctionalUnit: Sized {
     /// Set the area to be scanned.
     unsafe fn setScanArea(self, scanArea: NSRect);
     /// Desired orientation of the scan area. This property along with scanArea describes the area to be scanned.
-    unsafe fn scanAreaOrientation(self) -> ICEXIFOrientationType;
+    unsafe fn scanAreaOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue>;
     /// Set the orientation of the scan area.
     unsafe fn setScanAreaOrientation(self, scanAreaOrientation: ICEXIFOrientationType);
     /// Indicates if this functional unit accepts threshold value to be used when performing a scan in black & white.
@@ -399,7 +229,8 @@ pub trait ICScannerFunctionalUnit: Sized {
         thresholdForBlackAndWhiteScanning: c_uchar,
     );
     /// Indicates the current state of the functional unit.
-    unsafe fn state(self) -> ICScannerFunctionalUnitState;
+    /// Fails with the raw state if it has flags that are not known to this crate.
+    unsafe fn state(self) -> Result<ICScannerFunctionalUnitState, UnknownValue>;
     /// Indicates if a scan is in progress.
     unsafe fn scanInProgress(self) -> BOOL;
     /// Indicates percentage of scan completed.
@@ -417,40 +248,44 @@ pub trait ICScannerFunctionalUnit: Sized {
 }
 
 impl ICScannerFunctionalUnit for id {
-    unsafe fn type_(self) -> ICScannerFunctionalUnitType {
-        msg_send![self, type]
+    unsafe fn type_(self) -> Result<ICScannerFunctionalUnitType, UnknownValue> {
+        let type_: NSUInteger = msg_send![self, type];
+        ICScannerFunctionalUnitType::try_from(type_)
     }
 
-    unsafe fn pixelDataType(self) -> ICScannerPixelDataType {
-        msg_send![self, pixelDataType]
+    unsafe fn pixelDataType(self) -> Result<ICScannerPixelDataType, UnknownValue> {
+        let pixelDataType: NSUInteger = msg_send![self, pixelDataType];
+        ICScannerPixelDataType::try_from(pixelDataType)
     }
 
     unsafe fn setPixelDataType(self, pixelDataType: ICScannerPixelDataType) {
-        msg_send![self, setPixelDataType: pixelDataType]
+        msg_send![self, setPixelDataType: NSUInteger::from(pixelDataType)]
     }
 
     unsafe fn supportedBitDepths(self) -> id {
         msg_send![self, supportedBitDepths]
     }
 
-    unsafe fn bitDepth(self) -> ICScannerBitDepth {
-        msg_send![self, bitDepth]
+    unsafe fn bitDepth(self) -> Result<ICScannerBitDepth, UnknownValue> {
+        let bitDepth: NSUInteger = msg_send![self, bitDepth];
+        ICScannerBitDepth::try_from(bitDepth)
     }
 
     unsafe fn setBitDepth(self, bitDepth: ICScannerBitDepth) {
-        msg_send![self, setBitDepth: bitDepth]
+        msg_send![self, setBitDepth: NSUInteger::from(bitDepth)]
     }
 
     unsafe fn supportedMeasurementUnits(self) -> id {
         msg_send![self, supportedMeasurementUnits]
     }
 
-    unsafe fn measurementUnit(self) -> ICScannerMeasurementUnit {
-        msg_send![self, measurementUnit]
+    unsafe fn measurementUnit(self) -> Result<ICScannerMeasurementUnit, UnknownValue> {
+        let measurementUnit: NSUInteger = msg_send![self, measurementUnit];
+        ICScannerMeasurementUnit::try_from(measurementUnit)
     }
 
     unsafe fn setMeasurementUnit(self, measurementUnit: ICScannerMeasurementUnit) {
-        msg_send![self, setMeasurementUnit: measurementUnit]
+        msg_send![self, setMeasurementUnit: NSUInteger::from(measurementUnit)]
     }
 
     unsafe fn supportedResolutions(self) -> id {
@@ -513,12 +348,13 @@ impl ICScannerFunctionalUnit for id {
         msg_send![self, setScanArea: scanArea]
     }
 
-    unsafe fn scanAreaOrientation(self) -> ICEXIFOrientationType {
-        msg_send![self, scanAreaOrientation]
+    unsafe fn scanAreaOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue> {
+        let scanAreaOrientation: NSUInteger = msg_send![self, scanAreaOrientation];
+        ICEXIFOrientationType::try_from(scanAreaOrientation)
     }
 
     unsafe fn setScanAreaOrientation(self, scanAreaOrientation: ICEXIFOrientationType) {
-        msg_send![self, setScanAreaOrientation: scanAreaOrientation]
+        msg_send![self, setScanAreaOrientation: NSUInteger::from(scanAreaOrientation)]
     }
 
     unsafe fn acceptsThresholdForBlackAndWhiteScanning(self) -> BOOL {
@@ -557,8 +393,9 @@ impl ICScannerFunctionalUnit for id {
         ]
     }
 
-    unsafe fn state(self) -> ICScannerFunctionalUnitState {
-        msg_send![self, state]
+    unsafe fn state(self) -> Result<ICScannerFunctionalUnitState, UnknownValue> {
+        let state: NSUInteger = msg_send![self, state];
+        ICScannerFunctionalUnitState::from_bits(state).ok_or(UnknownValue(state))
     }
 
     unsafe fn scanInProgress(self) -> BOOL {
@@ -596,7 +433,7 @@ pub trait ICScannerFunctionalUnitFlatbed: Sized {
     /// Supported document types. The values in this set are valid values defined by ICScannerDocumentType.
     unsafe fn supportedDocumentTypes(self) -> id;
     /// Current document type. This will always be one of the supported document types.
-    unsafe fn documentType(self) -> ICScannerDocumentType;
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue>;
     /// Set the current document type.
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType);
     /// ￼Document size of the current document type expressed in current measurement unit.
@@ -608,12 +445,13 @@ impl ICScannerFunctionalUnitFlatbed for id {
         msg_send![self, supportedDocumentTypes]
     }
 
-    unsafe fn documentType(self) -> ICScannerDocumentType {
-        msg_send![self, documentType]
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue> {
+        let documentType: NSUInteger = msg_send![self, documentType];
+        ICScannerDocumentType::try_from(documentType)
     }
 
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType) {
-        msg_send![self, setDocumentType: documentType]
+        msg_send![self, setDocumentType: NSUInteger::from(documentType)]
     }
 
     unsafe fn documentSize(self) -> NSSize {
@@ -627,7 +465,7 @@ pub trait ICScannerFunctionalUnitPositiveTransparency: Sized {
     /// Supported document types. The values in this set are valid values defined by ICScannerDocumentType.
     unsafe fn supportedDocumentTypes(self) -> id;
     /// Current document type. This will always be one of the supported document types.
-    unsafe fn documentType(self) -> ICScannerDocumentType;
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue>;
     /// Set the current document type.
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType);
     /// ￼Document size of the current document type expressed in current measurement unit.
@@ -639,12 +477,13 @@ impl ICScannerFunctionalUnitPositiveTransparency for id {
         msg_send![self, supportedDocumentTypes]
     }
 
-    unsafe fn documentType(self) -> ICScannerDocumentType {
-        msg_send![self, documentType]
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue> {
+        let documentType: NSUInteger = msg_send![self, documentType];
+        ICScannerDocumentType::try_from(documentType)
     }
 
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType) {
-        msg_send![self, setDocumentType: documentType]
+        msg_send![self, setDocumentType: NSUInteger::from(documentType)]
     }
 
     unsafe fn documentSize(self) -> NSSize {
@@ -658,7 +497,7 @@ pub trait ICScannerFunctionalUnitNegativeTransparency: Sized {
     /// Supported document types. The values in this set are valid values defined by ICScannerDocumentType.
     unsafe fn supportedDocumentTypes(self) -> id;
     /// Current document type. This will always be one of the supported document types.
-    unsafe fn documentType(self) -> ICScannerDocumentType;
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue>;
     /// Set the current document type.
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType);
     /// ￼Document size of the current document type expressed in current measurement unit.
@@ -670,12 +509,13 @@ impl ICScannerFunctionalUnitNegativeTransparency for id {
         msg_send![self, supportedDocumentTypes]
     }
 
-    unsafe fn documentType(self) -> ICScannerDocumentType {
-        msg_send![self, documentType]
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue> {
+        let documentType: NSUInteger = msg_send![self, documentType];
+        ICScannerDocumentType::try_from(documentType)
     }
 
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType) {
-        msg_send![self, setDocumentType: documentType]
+        msg_send![self, setDocumentType: NSUInteger::from(documentType)]
     }
 
     unsafe fn documentSize(self) -> NSSize {
@@ -689,7 +529,7 @@ pub trait ICScannerFunctionalUnitDocumentFeeder: Sized {
     /// Supported document types. The values in this set are valid values defined by ICScannerDocumentType.
     unsafe fn supportedDocumentTypes(self) -> id;
     /// Current document type. This will always be one of the supported document types.
-    unsafe fn documentType(self) -> ICScannerDocumentType;
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue>;
     /// Set the current document type.
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType);
     /// ￼Document size of the current document type expressed in current measurement unit.
@@ -704,11 +544,11 @@ pub trait ICScannerFunctionalUnitDocumentFeeder: Sized {
     /// This value will change when the document is loaded or removed from the feeder, if the scanner module has the capability to detect this state.
     unsafe fn documentLoaded(self) -> BOOL;
     /// Desired orientation of the odd pages of the scanned document.
-    unsafe fn oddPageOrientation(self) -> ICEXIFOrientationType;
+    unsafe fn oddPageOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue>;
     /// Set the desired orientation of the odd pages of the scanned document.
     unsafe fn setOddPageOrientation(self, oddPageOrientation: ICEXIFOrientationType);
     /// Desired orientation of the even pages of the scanned document.
-    unsafe fn evenPageOrientation(self) -> ICEXIFOrientationType;
+    unsafe fn evenPageOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue>;
     /// Set the desired orientation of the even pages of the scanned document.
     unsafe fn setEvenPageOrientation(self, evenPageOrientation: ICEXIFOrientationType);
     /// Indicates whether the document feeder reads pages from back to front.
@@ -720,12 +560,13 @@ impl ICScannerFunctionalUnitDocumentFeeder for id {
         msg_send![self, supportedDocumentTypes]
     }
 
-    unsafe fn documentType(self) -> ICScannerDocumentType {
-        msg_send![self, documentType]
+    unsafe fn documentType(self) -> Result<ICScannerDocumentType, UnknownValue> {
+        let documentType: NSUInteger = msg_send![self, documentType];
+        ICScannerDocumentType::try_from(documentType)
     }
 
     unsafe fn setDocumentType(self, documentType: ICScannerDocumentType) {
-        msg_send![self, setDocumentType: documentType]
+        msg_send![self, setDocumentType: NSUInteger::from(documentType)]
     }
 
     unsafe fn documentSize(self) -> NSSize {
@@ -748,20 +589,22 @@ impl ICScannerFunctionalUnitDocumentFeeder for id {
         msg_send![self, documentLoaded]
     }
 
-    unsafe fn oddPageOrientation(self) -> ICEXIFOrientationType {
-        msg_send![self, oddPageOrientation]
+    unsafe fn oddPageOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue> {
+        let oddPageOrientation: NSUInteger = msg_send![self, oddPageOrientation];
+        ICEXIFOrientationType::try_from(oddPageOrientation)
     }
 
     unsafe fn setOddPageOrientation(self, oddPageOrientation: ICEXIFOrientationType) {
-        msg_send![self, setOddPageOrientation: oddPageOrientation]
+        msg_send![self, setOddPageOrientation: NSUInteger::from(oddPageOrientation)]
     }
 
-    unsafe fn evenPageOrientation(self) -> ICEXIFOrientationType {
-        msg_send![self, evenPageOrientation]
+    unsafe fn evenPageOrientation(self) -> Result<ICEXIFOrientationType, UnknownValue> {
+        let evenPageOrientation: NSUInteger = msg_send![self, evenPageOrientation];
+        ICEXIFOrientationType::try_from(evenPageOrientation)
     }
 
     unsafe fn setEvenPageOrientation(self, evenPageOrientation: ICEXIFOrientationType) {
-        msg_send![self, setEvenPageOrientation: evenPageOrientation]
+        msg_send![self, setEvenPageOrientation: NSUInteger::from(evenPageOrientation)]
     }
 
     unsafe fn reverseFeederPageOrder(self) -> BOOL {
@@ -0,0 +1,915 @@
+use crate::backend::{
+    destination, file_name, is_raw_uti, is_sidecar, same_stem, uti_for_name, Backend, CameraItem,
+    CameraStatus, DeviceId, DeviceInfo, DeviceKind, DownloadOptions, Event, FunctionalUnit, ItemId,
+    ScanSettings,
+};
+use crate::constants::ICEXIFOrientationType::{self, ICEXIFOrientation1};
+use crate::constants::ICReturnCode::{
+    ICReturnDeleteFilesFailed, ICReturnDeviceFailedToCloseSession,
+    ICReturnDeviceFailedToOpenSession, ICReturnInvalidParam, ICReturnUploadFailed,
+};
+use crate::constants::ICScannerFunctionalUnitType;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use jpeg_encoder::{ColorType, Encoder};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Name of the folder new pictures and uploads are stored in, if the first storage has it.
+const PICTURE_FOLDER: &str = "DCIM";
+
+/// Width and height of the pictures taken in tethered capture.
+const PICTURE_SIZE: (u16, u16) = (160, 120);
+
+/// The data of a simulated file.
+#[derive(Clone, Debug, PartialEq)]
+enum Contents {
+    /// Data held in memory.
+    Memory(Vec<u8>),
+    /// A file on disk, read when it is needed.
+    Disk(PathBuf),
+}
+
+impl Contents {
+    fn len(&self) -> u64 {
+        match self {
+            Contents::Memory(data) => data.len() as u64,
+            Contents::Disk(path) => fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0),
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Contents::Memory(data) => Ok(data.clone()),
+            Contents::Disk(path) => Ok(fs::read(path)?),
+        }
+    }
+}
+
+/// A file on a `SimulatedCamera`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedFile {
+    name: String,
+    contents: Contents,
+    uti: Option<String>,
+    creation_date: Option<SystemTime>,
+    modification_date: Option<SystemTime>,
+    orientation: ICEXIFOrientationType,
+    locked: bool,
+    sidecars: Vec<(String, Contents)>,
+}
+
+impl SimulatedFile {
+    /// Create a file holding `data`, with the Uniform Type Identifier of its extension.
+    pub fn new(name: &str, data: Vec<u8>) -> SimulatedFile {
+        SimulatedFile {
+            name: name.to_owned(),
+            contents: Contents::Memory(data),
+            uti: Some(uti_for_name(name).to_owned()),
+            creation_date: None,
+            modification_date: None,
+            orientation: ICEXIFOrientation1,
+            locked: false,
+            sidecars: Vec::new(),
+        }
+    }
+
+    /// Create a file with the contents and dates of a file on disk. The contents are read when
+    /// they are downloaded. Fails if the file cannot be read.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<SimulatedFile> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        Ok(SimulatedFile {
+            contents: Contents::Disk(path.to_owned()),
+            creation_date: metadata.created().ok(),
+            modification_date: metadata.modified().ok(),
+            ..SimulatedFile::new(&name, Vec::new())
+        })
+    }
+
+    /// Set the Uniform Type Identifier.
+    pub fn with_uti(mut self, uti: &str) -> SimulatedFile {
+        self.uti = Some(uti.to_owned());
+        self
+    }
+
+    /// Set the creation and modification dates.
+    pub fn with_dates(mut self, creation: SystemTime, modification: SystemTime) -> SimulatedFile {
+        self.creation_date = Some(creation);
+        self.modification_date = Some(modification);
+        self
+    }
+
+    /// Set the orientation.
+    pub fn with_orientation(mut self, orientation: ICEXIFOrientationType) -> SimulatedFile {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Protect the file from deletion.
+    pub fn with_locked(mut self, locked: bool) -> SimulatedFile {
+        self.locked = locked;
+        self
+    }
+
+    /// Add a sidecar file, such as an XMP file, which is downloaded with the file on request.
+    pub fn with_sidecar(mut self, name: &str, data: Vec<u8>) -> SimulatedFile {
+        self.sidecars
+            .push((name.to_owned(), Contents::Memory(data)));
+        self
+    }
+}
+
+/// A folder on a `SimulatedCamera`. The folders at the top are the storages of the camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedFolder {
+    name: String,
+    folders: Vec<SimulatedFolder>,
+    files: Vec<SimulatedFile>,
+}
+
+impl SimulatedFolder {
+    /// Create an empty folder.
+    pub fn new(name: &str) -> SimulatedFolder {
+        SimulatedFolder {
+            name: name.to_owned(),
+            folders: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Create a folder with the files and folders of a directory on disk, in name order. XMP,
+    /// THM and AAE files become sidecars of the file with the same name, if there is one.
+    pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<SimulatedFolder> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+        let mut folder = SimulatedFolder::new(&name);
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        entries.sort();
+
+        let mut sidecars = Vec::new();
+        for entry in entries {
+            if entry.is_dir() {
+                folder
+                    .folders
+                    .push(SimulatedFolder::from_directory(&entry)?);
+            } else if is_sidecar(&entry) {
+                sidecars.push(entry);
+            } else if entry.is_file() {
+                folder.files.push(SimulatedFile::from_path(&entry)?);
+            }
+        }
+        for sidecar in sidecars {
+            let owner = folder
+                .files
+                .iter_mut()
+                .find(|file| same_stem(Path::new(&file.name), &sidecar));
+            match owner {
+                Some(file) => file
+                    .sidecars
+                    .push((file_name(&sidecar), Contents::Disk(sidecar.clone()))),
+                None => folder.files.push(SimulatedFile::from_path(&sidecar)?),
+            }
+        }
+        Ok(folder)
+    }
+
+    /// Add a folder.
+    pub fn with_folder(mut self, folder: SimulatedFolder) -> SimulatedFolder {
+        self.folders.push(folder);
+        self
+    }
+
+    /// Add a file.
+    pub fn with_file(mut self, file: SimulatedFile) -> SimulatedFolder {
+        self.files.push(file);
+        self
+    }
+}
+
+/// An item in the catalog of a simulated camera.
+#[derive(Clone, Debug)]
+struct Node {
+    item: CameraItem,
+    /// The data of a file, or `None` for folders.
+    contents: Option<Contents>,
+    sidecars: Vec<(String, Contents)>,
+    /// Indicates whether the item has been reported to the client.
+    catalogued: bool,
+}
+
+/// A portable camera that behaves like an `ICCameraDevice`, for testing import workflows without
+/// a camera.
+///
+/// The camera has storages holding a tree of folders and files, given in memory or read from
+/// directories on disk. Items get PTP object handles from 1 in catalog order, which also serve
+/// as their identifiers. Once a session is open the catalog is delivered in batches of
+/// `ItemsAdded` events, with `camera_status` reporting the progress, and ends with
+/// `CatalogCompleted`. Downloads, reads, deletes and uploads complete as `next_event` is called,
+/// without waiting. With tethered capture enabled, `take_picture` adds a small JPEG image to the
+/// `DCIM` folder of the first storage, or to the storage itself, where uploads go too.
+#[derive(Clone, Debug)]
+pub struct SimulatedCamera {
+    info: DeviceInfo,
+    nodes: Vec<Node>,
+    next_handle: u32,
+    battery_level: Option<u8>,
+    batch_size: usize,
+    pictures_taken: u32,
+    announced: bool,
+    session: bool,
+    tethering: bool,
+    catalog_complete: bool,
+    events: VecDeque<Event>,
+}
+
+impl Default for SimulatedCamera {
+    fn default() -> SimulatedCamera {
+        SimulatedCamera::new("Simulated Camera")
+    }
+}
+
+impl SimulatedCamera {
+    /// Create a camera without storages.
+    pub fn new(name: &str) -> SimulatedCamera {
+        SimulatedCamera {
+            info: DeviceInfo {
+                id: DeviceId("simulated-camera".to_owned()),
+                kind: DeviceKind::Camera,
+                name: name.to_owned(),
+                transport: None,
+                serial_number: Some("SIM-0002".to_owned()),
+                is_remote: false,
+                capabilities: vec![
+                    "ICCameraDeviceCanTakePicture".to_owned(),
+                    "ICCameraDeviceCanDeleteOneFile".to_owned(),
+                    "ICCameraDeviceCanReceiveFile".to_owned(),
+                ],
+            },
+            nodes: Vec::new(),
+            next_handle: 1,
+            battery_level: Some(80),
+            batch_size: 10,
+            pictures_taken: 0,
+            announced: false,
+            session: false,
+            tethering: false,
+            catalog_complete: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Create a camera with a storage holding the files and folders of a directory on disk.
+    pub fn from_directory<P: AsRef<Path>>(name: &str, path: P) -> Result<SimulatedCamera> {
+        Ok(SimulatedCamera::new(name).with_storage(SimulatedFolder::from_directory(path)?))
+    }
+
+    /// Set the identifier of the camera.
+    pub fn with_id(mut self, id: &str) -> SimulatedCamera {
+        self.info.id = DeviceId(id.to_owned());
+        self
+    }
+
+    /// Add a storage.
+    pub fn with_storage(mut self, storage: SimulatedFolder) -> SimulatedCamera {
+        self.add_folder(storage, None);
+        self
+    }
+
+    /// Set the battery level in percent, or `None` for a camera that does not report it.
+    pub fn with_battery_level(mut self, level: Option<u8>) -> SimulatedCamera {
+        self.battery_level = level.map(|level| level.min(100));
+        self
+    }
+
+    /// Set how many items each `ItemsAdded` event of the catalog holds.
+    pub fn with_catalog_batch_size(mut self, size: usize) -> SimulatedCamera {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// The identifier of the camera.
+    pub fn id(&self) -> &DeviceId {
+        &self.info.id
+    }
+
+    /// Add a folder and its contents to the catalog, folders before their contents.
+    fn add_folder(&mut self, folder: SimulatedFolder, parent: Option<ItemId>) {
+        let item = CameraItem {
+            is_folder: true,
+            uti: Some("public.folder".to_owned()),
+            ..self.new_item(&folder.name, parent)
+        };
+        let id = self.add_node(item, None, Vec::new());
+        for file in folder.files {
+            self.add_file(file, Some(id.clone()));
+        }
+        for child in folder.folders {
+            self.add_folder(child, Some(id.clone()));
+        }
+    }
+
+    fn add_file(&mut self, file: SimulatedFile, parent: Option<ItemId>) -> ItemId {
+        let item = CameraItem {
+            is_raw: file.uti.as_deref().map_or(false, is_raw_uti),
+            uti: file.uti,
+            size: file.contents.len(),
+            orientation: file.orientation,
+            is_locked: file.locked,
+            creation_date: file.creation_date,
+            modification_date: file.modification_date,
+            sidecar_files: file.sidecars.iter().map(|(name, _)| name.clone()).collect(),
+            ..self.new_item(&file.name, parent)
+        };
+        self.add_node(item, Some(file.contents), file.sidecars)
+    }
+
+    /// An item with the next PTP object handle.
+    fn new_item(&mut self, name: &str, parent: Option<ItemId>) -> CameraItem {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        CameraItem {
+            id: ItemId(handle.to_string()),
+            name: name.to_owned(),
+            parent,
+            is_folder: false,
+            uti: None,
+            size: 0,
+            orientation: ICEXIFOrientation1,
+            is_raw: false,
+            is_locked: false,
+            creation_date: None,
+            modification_date: None,
+            ptp_object_handle: handle,
+            sidecar_files: Vec::new(),
+            added_after_catalog: false,
+        }
+    }
+
+    fn add_node(
+        &mut self,
+        item: CameraItem,
+        contents: Option<Contents>,
+        sidecars: Vec<(String, Contents)>,
+    ) -> ItemId {
+        let id = item.id.clone();
+        self.nodes.push(Node {
+            item,
+            contents,
+            sidecars,
+            catalogued: false,
+        });
+        id
+    }
+
+    /// Add a file after the catalog is complete to the picture folder and report it.
+    fn add_new_file(&mut self, file: SimulatedFile) -> Result<ItemId> {
+        let storage = self
+            .nodes
+            .iter()
+            .find(|node| node.item.parent.is_none())
+            .map(|node| node.item.id.clone())
+            .ok_or(Error::ReturnCode(ICReturnUploadFailed))?;
+        let folder = self
+            .nodes
+            .iter()
+            .find(|node| {
+                node.item.is_folder
+                    && node.item.parent.as_ref() == Some(&storage)
+                    && node.item.name == PICTURE_FOLDER
+            })
+            .map_or(storage, |node| node.item.id.clone());
+        if self
+            .nodes
+            .iter()
+            .any(|node| node.item.parent.as_ref() == Some(&folder) && node.item.name == file.name)
+        {
+            return Err(Error::ReturnCode(ICReturnUploadFailed));
+        }
+
+        let id = self.add_file(file, Some(folder));
+        let node = self.nodes.last_mut().expect("the file was just added");
+        node.item.added_after_catalog = true;
+        node.catalogued = true;
+        let item = node.item.clone();
+        let device = self.info.id.clone();
+        self.push(Event::ItemsAdded {
+            device,
+            items: vec![item],
+        });
+        Ok(id)
+    }
+
+    /// Check that `device` is this camera and that it has been found.
+    fn check_device(&self, device: &DeviceId) -> Result<()> {
+        if self.announced && *device == self.info.id {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// Check that `device` is this camera and has an open session.
+    fn check_session(&self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        if self.session {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// The catalogued file with an identifier on this camera with an open session.
+    fn file(&self, device: &DeviceId, item: &ItemId) -> Result<&Node> {
+        self.check_session(device)?;
+        self.nodes
+            .iter()
+            .find(|node| node.catalogued && node.item.id == *item && !node.item.is_folder)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn push(&mut self, event: Event) {
+        self.events.push_back(event);
+    }
+
+    /// Remove files and report them.
+    fn remove(&mut self, items: &[ItemId]) {
+        self.nodes.retain(|node| !items.contains(&node.item.id));
+        let device = self.info.id.clone();
+        self.push(Event::ItemsRemoved {
+            device,
+            items: items.to_vec(),
+        });
+    }
+
+    /// Deliver the next batch of the catalog, or report that it is complete.
+    fn advance_catalog(&mut self) -> Option<Event> {
+        if !self.session || self.catalog_complete {
+            return None;
+        }
+        let device = self.info.id.clone();
+        let items: Vec<CameraItem> = self
+            .nodes
+            .iter_mut()
+            .filter(|node| !node.catalogued)
+            .take(self.batch_size)
+            .map(|node| {
+                node.catalogued = true;
+                node.item.clone()
+            })
+            .collect();
+        if items.is_empty() {
+            self.catalog_complete = true;
+            Some(Event::CatalogCompleted(device))
+        } else {
+            Some(Event::ItemsAdded { device, items })
+        }
+    }
+
+    /// Save a file and its sidecars for a download, and return the path of the file.
+    fn save(&self, node: &Node, options: &DownloadOptions) -> Result<PathBuf> {
+        let name = options.file_name.as_deref().unwrap_or(&node.item.name);
+        let path = destination(&options.directory.join(name), options.overwrite);
+        let contents = node
+            .contents
+            .as_ref()
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        fs::write(&path, contents.read()?)?;
+        if options.sidecar_files {
+            for (sidecar, contents) in &node.sidecars {
+                let extension = Path::new(sidecar).extension().unwrap_or_default();
+                let sidecar_path = destination(&path.with_extension(extension), options.overwrite);
+                fs::write(sidecar_path, contents.read()?)?;
+            }
+        }
+        Ok(path)
+    }
+}
+
+impl Backend for SimulatedCamera {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        if !self.announced && kinds.contains(&DeviceKind::Camera) {
+            self.announced = true;
+            let info = self.info.clone();
+            self.push(Event::DeviceAdded(info));
+        }
+        Ok(())
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        if self.announced {
+            vec![self.info.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn next_event(&mut self, _timeout: Duration) -> Option<Event> {
+        match self.events.pop_front() {
+            Some(event) => Some(event),
+            None => self.advance_catalog(),
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        let device = device.clone();
+        if self.session {
+            self.push(Event::SessionOpened {
+                device,
+                result: Err(Error::ReturnCode(ICReturnDeviceFailedToOpenSession)),
+            });
+            return Ok(());
+        }
+        self.session = true;
+        self.catalog_complete = false;
+        self.push(Event::SessionOpened {
+            device: device.clone(),
+            result: Ok(()),
+        });
+        self.push(Event::DeviceReady(device));
+        Ok(())
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        let result = if self.session {
+            self.session = false;
+            self.tethering = false;
+            for node in &mut self.nodes {
+                node.catalogued = false;
+                node.item.added_after_catalog = false;
+            }
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnDeviceFailedToCloseSession))
+        };
+        self.push(Event::SessionClosed {
+            device: device.clone(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        self.check_device(device)?;
+        Ok(self.session)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        self.check_device(device)?;
+        Ok(self
+            .nodes
+            .iter()
+            .filter(|node| node.catalogued)
+            .map(|node| node.item.clone())
+            .collect())
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let node = self.file(device, item)?;
+        let result = self.save(node, options);
+        let delete = result.is_ok() && options.delete_after_download && !node.item.is_locked;
+        self.push(Event::Downloaded {
+            device: device.clone(),
+            item: item.clone(),
+            result,
+        });
+        if delete {
+            self.remove(std::slice::from_ref(item));
+        }
+        Ok(())
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        self.check_device(device)?;
+        let catalogued = self.nodes.iter().filter(|node| node.catalogued).count();
+        let catalog_percent_completed = if self.catalog_complete || self.nodes.is_empty() {
+            if self.session {
+                100
+            } else {
+                0
+            }
+        } else {
+            (catalogued * 100 / self.nodes.len()) as u8
+        };
+        Ok(CameraStatus {
+            battery_level: self.battery_level,
+            catalog_percent_completed,
+            tethered_capture_enabled: self.tethering,
+            access_restricted: false,
+        })
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let node = self.file(device, item)?;
+        let result = node
+            .contents
+            .as_ref()
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+            .and_then(Contents::read)
+            .and_then(|data| {
+                let start = usize::try_from(offset)
+                    .ok()
+                    .filter(|&start| start <= data.len())
+                    .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+                let end = start.saturating_add(length as usize).min(data.len());
+                Ok(data[start..end].to_vec())
+            });
+        self.push(Event::DataRead {
+            device: device.clone(),
+            item: item.clone(),
+            offset,
+            result,
+        });
+        Ok(())
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        for item in items {
+            if self.file(device, item)?.item.is_locked {
+                return Err(Error::ReturnCode(ICReturnDeleteFilesFailed));
+            }
+        }
+        if !items.is_empty() {
+            self.remove(items);
+        }
+        Ok(())
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()> {
+        self.check_session(device)?;
+        let result = fs::read(path)
+            .map_err(Error::from)
+            .and_then(|data| {
+                let file = SimulatedFile::new(&file_name(path), data)
+                    .with_dates(SystemTime::now(), SystemTime::now());
+                self.add_new_file(file)
+            })
+            .map(|_| ());
+        self.push(Event::Uploaded {
+            device: device.clone(),
+            path: path.to_owned(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()> {
+        self.check_session(device)?;
+        self.tethering = enabled;
+        Ok(())
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_session(device)?;
+        if !self.tethering {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.pictures_taken += 1;
+        let file = SimulatedFile::new(
+            &format!("IMG_{:04}.JPG", self.pictures_taken),
+            picture(self.pictures_taken)?,
+        )
+        .with_dates(SystemTime::now(), SystemTime::now());
+        self.add_new_file(file)?;
+        Ok(())
+    }
+
+    fn functional_unit_types(
+        &self,
+        _device: &DeviceId,
+    ) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        _device: &DeviceId,
+        _unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn functional_unit(&self, _device: &DeviceId) -> Result<FunctionalUnit> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn configure(
+        &mut self,
+        _device: &DeviceId,
+        _settings: &ScanSettings,
+    ) -> Result<FunctionalUnit> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn request_overview_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn overview_image(&self, _device: &DeviceId) -> Result<Option<Image>> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn request_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn cancel_scan(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+}
+
+/// A JPEG image for picture number `number` taken in tethered capture: a colour gradient whose
+/// hue depends on the number.
+fn picture(number: u32) -> Result<Vec<u8>> {
+    let (width, height) = PICTURE_SIZE;
+    let tint = (number * 47 % 256) as u8;
+    let mut samples = Vec::with_capacity(usize::from(width) * usize::from(height) * 3);
+    for y in 0..height {
+        for x in 0..width {
+            samples.push((u32::from(x) * 255 / u32::from(width - 1)) as u8);
+            samples.push((u32::from(y) * 255 / u32::from(height - 1)) as u8);
+            samples.push(tint);
+        }
+    }
+    let mut data = Vec::new();
+    Encoder::new(&mut data, 90)
+        .encode(&samples, width, height, ColorType::Rgb)
+        .map_err(|error| Error::from(io::Error::new(io::ErrorKind::Other, error.to_string())))?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::temp_dir;
+
+    /// A camera with a storage holding a raw image with a sidecar, a locked JPEG image and an
+    /// empty `DCIM` folder.
+    fn camera() -> SimulatedCamera {
+        let storage = SimulatedFolder::new("Card")
+            .with_file(
+                SimulatedFile::new("IMG_0001.CR2", b"raw".to_vec())
+                    .with_sidecar("IMG_0001.XMP", b"xmp".to_vec()),
+            )
+            .with_file(SimulatedFile::new("IMG_0002.JPG", b"jpeg".to_vec()).with_locked(true))
+            .with_folder(SimulatedFolder::new(PICTURE_FOLDER));
+        SimulatedCamera::new("Camera")
+            .with_storage(storage)
+            .with_catalog_batch_size(2)
+    }
+
+    /// A camera with an open session and a complete catalog, and the catalogued items.
+    fn open(mut camera: SimulatedCamera) -> (SimulatedCamera, Vec<CameraItem>) {
+        let id = camera.id().clone();
+        camera.start_browsing(&[DeviceKind::Camera]).unwrap();
+        camera.open_session(&id).unwrap();
+        let mut items = Vec::new();
+        while let Some(event) = camera.next_event(Duration::from_secs(0)) {
+            if let Event::ItemsAdded { items: added, .. } = event {
+                items.extend(added);
+            }
+        }
+        (camera, items)
+    }
+
+    fn item<'a>(items: &'a [CameraItem], name: &str) -> &'a CameraItem {
+        items.iter().find(|item| item.name == name).unwrap()
+    }
+
+    #[test]
+    fn catalog_arrives_in_batches() {
+        let mut camera = camera();
+        let id = camera.id().clone();
+        camera.start_browsing(&[DeviceKind::Camera]).unwrap();
+        camera.open_session(&id).unwrap();
+        let mut batches = Vec::new();
+        while let Some(event) = camera.next_event(Duration::from_secs(0)) {
+            match event {
+                Event::ItemsAdded { items, .. } => {
+                    let status = camera.camera_status(&id).unwrap();
+                    batches.push((items.len(), status.catalog_percent_completed));
+                }
+                Event::CatalogCompleted(_) => break,
+                _ => {}
+            }
+        }
+        assert_eq!(batches, vec![(2, 50), (2, 100)]);
+        assert_eq!(
+            camera.camera_status(&id).unwrap().catalog_percent_completed,
+            100
+        );
+
+        let items = camera.camera_items(&id).unwrap();
+        let raw = item(&items, "IMG_0001.CR2");
+        assert!(raw.is_raw);
+        assert_eq!(raw.sidecar_files, vec!["IMG_0001.XMP".to_owned()]);
+        assert_eq!(raw.parent, Some(item(&items, "Card").id.clone()));
+    }
+
+    #[test]
+    fn reads_return_the_requested_range() {
+        let (mut camera, items) = open(camera());
+        let id = camera.id().clone();
+        let jpeg = item(&items, "IMG_0002.JPG").id.clone();
+        camera.read_file(&id, &jpeg, 1, 2).unwrap();
+        camera.read_file(&id, &jpeg, 5, 1).unwrap();
+        let results: Vec<Result<Vec<u8>>> = std::iter::from_fn(|| camera.events.pop_front())
+            .filter_map(|event| match event {
+                Event::DataRead { result, .. } => Some(result),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(b"pe".to_vec()),
+                Err(Error::ReturnCode(ICReturnInvalidParam))
+            ]
+        );
+    }
+
+    #[test]
+    fn downloads_bring_sidecars_and_delete_unlocked_files() {
+        let (mut camera, items) = open(camera());
+        let id = camera.id().clone();
+        let directory = temp_dir("simulated-camera");
+        let options = DownloadOptions::new(&directory)
+            .with_sidecar_files(true)
+            .with_delete_after_download(true);
+        for name in &["IMG_0001.CR2", "IMG_0002.JPG"] {
+            let item = item(&items, name).id.clone();
+            camera.download_file(&id, &item, &options).unwrap();
+        }
+        assert_eq!(fs::read(directory.join("IMG_0001.CR2")).unwrap(), b"raw");
+        assert_eq!(fs::read(directory.join("IMG_0001.XMP")).unwrap(), b"xmp");
+        assert_eq!(fs::read(directory.join("IMG_0002.JPG")).unwrap(), b"jpeg");
+
+        let left: Vec<String> = camera
+            .camera_items(&id)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(left, vec!["Card", "IMG_0002.JPG", PICTURE_FOLDER]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn tethered_pictures_go_to_the_picture_folder() {
+        let (mut camera, items) = open(camera());
+        let id = camera.id().clone();
+        assert_eq!(
+            camera.take_picture(&id),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+        camera.set_tethering(&id, true).unwrap();
+        camera.take_picture(&id).unwrap();
+        match camera.next_event(Duration::from_secs(0)) {
+            Some(Event::ItemsAdded { items: added, .. }) => {
+                assert_eq!(added.len(), 1);
+                assert_eq!(added[0].name, "IMG_0001.JPG");
+                assert_eq!(
+                    added[0].parent,
+                    Some(item(&items, PICTURE_FOLDER).id.clone())
+                );
+                assert!(added[0].added_after_catalog);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+}
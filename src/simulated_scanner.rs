@@ -0,0 +1,1195 @@
+use crate::backend::{
+    destination, Backend, CameraItem, CameraStatus, DeviceId, DeviceInfo, DeviceKind,
+    DownloadOptions, Event, FunctionalUnit, ItemId, ScanSettings, ScannerFeature,
+};
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICEXIFOrientationType::ICEXIFOrientation1;
+use crate::constants::ICReturnCode::{
+    ICReturnDeviceFailedToCloseSession, ICReturnDeviceFailedToOpenSession, ICReturnInvalidParam,
+    ICReturnScanOperationCanceled, ICReturnScannerFailedToCompleteScan,
+    ICReturnScannerFailedToSelectFunctionalUnit,
+};
+use crate::constants::ICScannerBitDepth::{
+    self, ICScannerBitDepth16Bits, ICScannerBitDepth1Bit, ICScannerBitDepth8Bits,
+};
+use crate::constants::ICScannerColorDataFormatType::ICScannerColorDataFormatTypeChunky;
+use crate::constants::ICScannerDocumentType::{
+    self, ICScannerDocumentType135, ICScannerDocumentTypeA4, ICScannerDocumentTypeA5,
+    ICScannerDocumentTypeDefault, ICScannerDocumentTypeUSLegal, ICScannerDocumentTypeUSLetter,
+};
+use crate::constants::ICScannerFunctionalUnitType::{
+    self, ICScannerFunctionalUnitTypeDocumentFeeder, ICScannerFunctionalUnitTypeFlatbed,
+    ICScannerFunctionalUnitTypeNegativeTransparency,
+    ICScannerFunctionalUnitTypePositiveTransparency,
+};
+use crate::constants::ICScannerMeasurementUnit::ICScannerMeasurementUnitInches;
+use crate::constants::ICScannerPixelDataType::{
+    self, ICScannerPixelDataTypeBW, ICScannerPixelDataTypeGray, ICScannerPixelDataTypeRGB,
+};
+use crate::constants::ICScannerTransferMode::{
+    ICScannerTransferModeFileBased, ICScannerTransferModeMemoryBased,
+};
+use crate::error::{Error, Result};
+use crate::image::{Image, PixelFormat};
+use crate::measurement::{convert, ScanRect};
+use crate::png::{PngInfo, PngWriter};
+use crate::tiff::{TiffPage, TiffWriter};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Digits 0 to 9 of the page numbers, three pixels wide and five high, row by row from the most
+/// significant bit.
+const DIGITS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_010_010_010,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+
+/// Number of black and of white spokes of the resolution target.
+const SPOKES: f64 = 36.0;
+
+/// Colour of unexposed negative film: the orange mask of colour negatives.
+const FILM_BASE: [f64; 3] = [1.0, 0.62, 0.38];
+
+/// Density of negative film exposed by white.
+const MAX_DENSITY: f64 = 2.0;
+
+/// Description of a functional unit of a `SimulatedScanner`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedUnit {
+    unit_type: ICScannerFunctionalUnitType,
+    physical_size: (f64, f64),
+    resolutions: Vec<u64>,
+    bit_depths: Vec<ICScannerBitDepth>,
+    document_types: Vec<ICScannerDocumentType>,
+    vendor_features: Vec<ScannerFeature>,
+    pages: u32,
+    duplex: bool,
+}
+
+impl SimulatedUnit {
+    fn new(
+        unit_type: ICScannerFunctionalUnitType,
+        physical_size: (f64, f64),
+        resolutions: &[u64],
+        document_types: &[ICScannerDocumentType],
+    ) -> SimulatedUnit {
+        SimulatedUnit {
+            unit_type,
+            physical_size,
+            resolutions: resolutions.to_vec(),
+            bit_depths: vec![
+                ICScannerBitDepth1Bit,
+                ICScannerBitDepth8Bits,
+                ICScannerBitDepth16Bits,
+            ],
+            document_types: document_types.to_vec(),
+            vendor_features: Vec::new(),
+            pages: 0,
+            duplex: false,
+        }
+    }
+
+    /// A flatbed of 8.5 by 11.7 inches that scans at 75 to 1200 pixels per inch.
+    pub fn flatbed() -> SimulatedUnit {
+        SimulatedUnit::new(
+            ICScannerFunctionalUnitTypeFlatbed,
+            (8.5, 11.7),
+            &[75, 150, 300, 600, 1200],
+            &[
+                ICScannerDocumentTypeDefault,
+                ICScannerDocumentTypeA4,
+                ICScannerDocumentTypeA5,
+                ICScannerDocumentTypeUSLetter,
+            ],
+        )
+    }
+
+    /// A transparency unit for a 35 mm frame of slide film that scans at 300 to 2400 pixels per
+    /// inch.
+    pub fn positive_transparency() -> SimulatedUnit {
+        SimulatedUnit::new(
+            ICScannerFunctionalUnitTypePositiveTransparency,
+            (36.0 / 25.4, 24.0 / 25.4),
+            &[300, 600, 1200, 2400],
+            &[ICScannerDocumentTypeDefault, ICScannerDocumentType135],
+        )
+    }
+
+    /// A transparency unit for a 35 mm frame of colour negative film that scans at 300 to 2400
+    /// pixels per inch. Scans show the negative with its orange mask, as scanners deliver it.
+    pub fn negative_transparency() -> SimulatedUnit {
+        SimulatedUnit {
+            unit_type: ICScannerFunctionalUnitTypeNegativeTransparency,
+            ..SimulatedUnit::positive_transparency()
+        }
+    }
+
+    /// A document feeder for pages of up to 8.5 by 14 inches, loaded with `pages` sheets, that
+    /// scans at 75 to 600 pixels per inch. A duplex feeder scans both sides of each sheet once
+    /// duplex scanning is enabled.
+    pub fn document_feeder(pages: u32, duplex: bool) -> SimulatedUnit {
+        SimulatedUnit {
+            pages,
+            duplex,
+            ..SimulatedUnit::new(
+                ICScannerFunctionalUnitTypeDocumentFeeder,
+                (8.5, 14.0),
+                &[75, 150, 200, 300, 600],
+                &[
+                    ICScannerDocumentTypeDefault,
+                    ICScannerDocumentTypeA4,
+                    ICScannerDocumentTypeUSLetter,
+                    ICScannerDocumentTypeUSLegal,
+                ],
+            )
+        }
+    }
+
+    /// Set the width and height of the area the unit scans, in inches.
+    pub fn with_physical_size(mut self, width: f64, height: f64) -> SimulatedUnit {
+        self.physical_size = (width, height);
+        self
+    }
+
+    /// Set the supported resolutions in pixels per inch.
+    pub fn with_resolutions(mut self, resolutions: &[u64]) -> SimulatedUnit {
+        self.resolutions = resolutions.to_vec();
+        self
+    }
+
+    /// Set the supported bit depths.
+    pub fn with_bit_depths(mut self, bit_depths: &[ICScannerBitDepth]) -> SimulatedUnit {
+        self.bit_depths = bit_depths.to_vec();
+        self
+    }
+
+    /// Set the supported document types.
+    pub fn with_document_types(
+        mut self,
+        document_types: &[ICScannerDocumentType],
+    ) -> SimulatedUnit {
+        self.document_types = document_types.to_vec();
+        self
+    }
+
+    /// Add a vendor specific feature. Features can be read and changed but do not affect scans.
+    pub fn with_feature(mut self, feature: ScannerFeature) -> SimulatedUnit {
+        self.vendor_features.push(feature);
+        self
+    }
+
+    /// The type of the unit.
+    pub fn unit_type(&self) -> ICScannerFunctionalUnitType {
+        self.unit_type
+    }
+
+    /// The configuration of the unit before any settings are applied, in inches.
+    fn configuration(&self) -> FunctionalUnit {
+        let preferred = |preferred, supported: &[u64]| {
+            if supported.contains(&preferred) {
+                preferred
+            } else {
+                supported.first().copied().unwrap_or(preferred)
+            }
+        };
+        let bit_depth = if self.bit_depths.contains(&ICScannerBitDepth8Bits) {
+            ICScannerBitDepth8Bits
+        } else {
+            self.bit_depths
+                .first()
+                .copied()
+                .unwrap_or(ICScannerBitDepth8Bits)
+        };
+        let feeder = self.unit_type == ICScannerFunctionalUnitTypeDocumentFeeder;
+        let (width, height) = self.physical_size;
+        FunctionalUnit {
+            unit_type: self.unit_type,
+            measurement_unit: ICScannerMeasurementUnitInches,
+            physical_size: self.physical_size,
+            supported_resolutions: self.resolutions.clone(),
+            resolution: preferred(300, &self.resolutions),
+            supported_bit_depths: self.bit_depths.clone(),
+            bit_depth,
+            pixel_data_type: if bit_depth == ICScannerBitDepth1Bit {
+                ICScannerPixelDataTypeBW
+            } else {
+                ICScannerPixelDataTypeRGB
+            },
+            scan_area: ScanRect::new(0.0, 0.0, width, height, ICScannerMeasurementUnitInches),
+            scan_area_orientation: ICEXIFOrientation1,
+            supported_document_types: self.document_types.clone(),
+            document_type: ICScannerDocumentTypeDefault,
+            can_perform_overview_scan: !feeder,
+            overview_resolution: preferred(75, &self.resolutions),
+            accepts_threshold: true,
+            threshold: None,
+            duplex: if feeder && self.duplex {
+                Some(false)
+            } else {
+                None
+            },
+            transfer_mode: ICScannerTransferModeMemoryBased,
+            downloads_directory: Some(std::env::temp_dir()),
+            document_name: Some("Scan".to_owned()),
+            document_uti: Some("public.tiff".to_owned()),
+            max_memory_band_size: 256 * 1024,
+            vendor_features: self.vendor_features.clone(),
+        }
+    }
+}
+
+/// A functional unit and its state.
+#[derive(Clone, Debug)]
+struct Unit {
+    /// The configuration, with the physical size and the scan area in inches.
+    config: FunctionalUnit,
+    /// Sheets in a document feeder.
+    pages: u32,
+    /// The image of the last overview scan.
+    overview: Option<Image>,
+}
+
+impl Unit {
+    /// The configuration with the physical size and the scan area in the measurement unit.
+    fn reported(&self) -> Result<FunctionalUnit> {
+        let mut config = self.config.clone();
+        let (unit, resolution) = (config.measurement_unit, config.resolution);
+        let (width, height) = config.physical_size;
+        config.physical_size = (
+            convert(width, ICScannerMeasurementUnitInches, unit, resolution)?,
+            convert(height, ICScannerMeasurementUnitInches, unit, resolution)?,
+        );
+        config.scan_area = config.scan_area.to(unit, resolution)?;
+        Ok(config)
+    }
+}
+
+/// A page of a scan that is being delivered in bands.
+#[derive(Clone, Debug)]
+struct PageScan {
+    image: Image,
+    next_row: usize,
+}
+
+/// A scan in progress.
+#[derive(Clone, Debug)]
+struct ScanJob {
+    /// Numbers of the pages still to scan.
+    pages: VecDeque<u32>,
+    /// Indicates whether the names of saved files are numbered.
+    numbered: bool,
+    /// The page being delivered in bands.
+    current: Option<PageScan>,
+}
+
+/// A portable scanner that behaves like an `ICScannerDevice`, for testing scan workflows without
+/// a scanner.
+///
+/// The scanner offers one or more functional units and scans a deterministic test pattern: a
+/// gray ramp, red, green and blue ramps, a star of 36 spokes as a resolution target and the page
+/// number. The pattern is laid out on the physical area of the unit, so a scan shows the part of
+/// the pattern under its scan area at its resolution, bit depth and pixel data type. Memory based
+/// scans are delivered in bands of at most `max_memory_band_size` bytes and file based scans are
+/// saved as TIFF or PNG files. Negative transparency units deliver the pattern as a colour
+/// negative.
+///
+/// Requests complete in order as `next_event` is called, without waiting. A document feeder
+/// scans all loaded sheets, numbering the pages from 1, and fails if it is empty.
+#[derive(Clone, Debug)]
+pub struct SimulatedScanner {
+    info: DeviceInfo,
+    units: Vec<Unit>,
+    selected: usize,
+    announced: bool,
+    session: bool,
+    events: VecDeque<Event>,
+    scan: Option<ScanJob>,
+}
+
+impl Default for SimulatedScanner {
+    fn default() -> SimulatedScanner {
+        SimulatedScanner::new("Simulated Scanner")
+    }
+}
+
+impl SimulatedScanner {
+    /// Create a scanner with a flatbed.
+    pub fn new(name: &str) -> SimulatedScanner {
+        SimulatedScanner {
+            info: DeviceInfo {
+                id: DeviceId("simulated-scanner".to_owned()),
+                kind: DeviceKind::Scanner,
+                name: name.to_owned(),
+                transport: None,
+                serial_number: Some("SIM-0001".to_owned()),
+                is_remote: false,
+                capabilities: Vec::new(),
+            },
+            units: Vec::new(),
+            selected: 0,
+            announced: false,
+            session: false,
+            events: VecDeque::new(),
+            scan: None,
+        }
+        .with_unit(SimulatedUnit::flatbed())
+    }
+
+    /// Set the identifier of the scanner.
+    pub fn with_id(mut self, id: &str) -> SimulatedScanner {
+        self.info.id = DeviceId(id.to_owned());
+        self
+    }
+
+    /// Add a functional unit, replacing the unit of the same type.
+    pub fn with_unit(mut self, unit: SimulatedUnit) -> SimulatedScanner {
+        let unit = Unit {
+            config: unit.configuration(),
+            pages: unit.pages,
+            overview: None,
+        };
+        match self
+            .units
+            .iter()
+            .position(|existing| existing.config.unit_type == unit.config.unit_type)
+        {
+            Some(index) => self.units[index] = unit,
+            None => self.units.push(unit),
+        }
+        self
+    }
+
+    /// The identifier of the scanner.
+    pub fn id(&self) -> &DeviceId {
+        &self.info.id
+    }
+
+    /// Load sheets into the document feeder. Fails if the scanner has no document feeder.
+    pub fn load_pages(&mut self, pages: u32) -> Result<()> {
+        self.feeder_mut()?.pages = pages;
+        Ok(())
+    }
+
+    /// Number of sheets in the document feeder. Fails if the scanner has no document feeder.
+    pub fn pages_loaded(&self) -> Result<u32> {
+        self.units
+            .iter()
+            .find(|unit| unit.config.unit_type == ICScannerFunctionalUnitTypeDocumentFeeder)
+            .map(|unit| unit.pages)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    /// The image a scan with the selected functional unit delivers for page `page` with the
+    /// current configuration.
+    pub fn render_page(&self, page: u32) -> Result<Image> {
+        let unit = &self.units[self.selected];
+        let format = pixel_format(&unit.config);
+        render(
+            &unit.config,
+            unit.config.scan_area,
+            unit.config.resolution,
+            format,
+            page,
+        )
+    }
+
+    fn feeder_mut(&mut self) -> Result<&mut Unit> {
+        self.units
+            .iter_mut()
+            .find(|unit| unit.config.unit_type == ICScannerFunctionalUnitTypeDocumentFeeder)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    /// Check that `device` is this scanner and that it has been found.
+    fn check_device(&self, device: &DeviceId) -> Result<()> {
+        if self.announced && *device == self.info.id {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// Check that `device` is this scanner and has an open session.
+    fn check_session(&self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        if self.session {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// Check that `device` is this scanner, has an open session and is not scanning.
+    fn check_idle(&self, device: &DeviceId) -> Result<()> {
+        self.check_session(device)?;
+        if self.scan.is_none() {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        self.events.push_back(event);
+    }
+
+    /// Deliver the next part of the scan in progress.
+    fn advance_scan(&mut self) -> Option<Event> {
+        let mut job = self.scan.take()?;
+        let device = self.info.id.clone();
+        let config = self.units[self.selected].config.clone();
+        let mut page = match job.current.take() {
+            Some(page) => page,
+            None => {
+                let number = match job.pages.pop_front() {
+                    Some(number) => number,
+                    None => {
+                        return Some(Event::ScanCompleted {
+                            device,
+                            result: Ok(()),
+                        })
+                    }
+                };
+                let image = match self.render_page(number) {
+                    Ok(image) => image,
+                    Err(error) => {
+                        return Some(Event::ScanCompleted {
+                            device,
+                            result: Err(error),
+                        })
+                    }
+                };
+                if config.transfer_mode == ICScannerTransferModeFileBased {
+                    let name = if job.numbered {
+                        format!(
+                            "{} {}",
+                            config.document_name.as_deref().unwrap_or("Scan"),
+                            number
+                        )
+                    } else {
+                        config
+                            .document_name
+                            .clone()
+                            .unwrap_or_else(|| "Scan".to_owned())
+                    };
+                    return match save(&config, &image, &name) {
+                        Ok(path) => {
+                            self.scan = Some(job);
+                            Some(Event::ScannedFile { device, path })
+                        }
+                        Err(error) => Some(Event::ScanCompleted {
+                            device,
+                            result: Err(error),
+                        }),
+                    };
+                }
+                PageScan { image, next_row: 0 }
+            }
+        };
+
+        let band = band(&config, &page.image, page.next_row);
+        page.next_row += band.data_num_rows;
+        if page.next_row < page.image.height() {
+            job.current = Some(page);
+        }
+        self.scan = Some(job);
+        Some(Event::ScannedBand { device, band })
+    }
+}
+
+impl Backend for SimulatedScanner {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        if !self.announced && kinds.contains(&DeviceKind::Scanner) {
+            self.announced = true;
+            let info = self.info.clone();
+            self.push(Event::DeviceAdded(info));
+        }
+        Ok(())
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        if self.announced {
+            vec![self.info.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn next_event(&mut self, _timeout: Duration) -> Option<Event> {
+        match self.events.pop_front() {
+            Some(event) => Some(event),
+            None => self.advance_scan(),
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        let device = device.clone();
+        if self.session {
+            self.push(Event::SessionOpened {
+                device,
+                result: Err(Error::ReturnCode(ICReturnDeviceFailedToOpenSession)),
+            });
+            return Ok(());
+        }
+        self.session = true;
+        let unit_type = self.units[self.selected].config.unit_type;
+        self.push(Event::SessionOpened {
+            device: device.clone(),
+            result: Ok(()),
+        });
+        self.push(Event::FunctionalUnitSelected {
+            device: device.clone(),
+            result: Ok(unit_type),
+        });
+        self.push(Event::DeviceReady(device));
+        Ok(())
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_device(device)?;
+        let result = if self.session {
+            self.session = false;
+            self.scan = None;
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnDeviceFailedToCloseSession))
+        };
+        self.push(Event::SessionClosed {
+            device: device.clone(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        self.check_device(device)?;
+        Ok(self.session)
+    }
+
+    fn camera_items(&self, _device: &DeviceId) -> Result<Vec<CameraItem>> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn download_file(
+        &mut self,
+        _device: &DeviceId,
+        _item: &ItemId,
+        _options: &DownloadOptions,
+    ) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn camera_status(&self, _device: &DeviceId) -> Result<CameraStatus> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn read_file(
+        &mut self,
+        _device: &DeviceId,
+        _item: &ItemId,
+        _offset: u64,
+        _length: u64,
+    ) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn delete_files(&mut self, _device: &DeviceId, _items: &[ItemId]) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn upload_file(&mut self, _device: &DeviceId, _path: &Path) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn set_tethering(&mut self, _device: &DeviceId, _enabled: bool) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn take_picture(&mut self, _device: &DeviceId) -> Result<()> {
+        Err(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        self.check_session(device)?;
+        Ok(self
+            .units
+            .iter()
+            .map(|unit| unit.config.unit_type)
+            .collect())
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        self.check_idle(device)?;
+        let result = match self
+            .units
+            .iter()
+            .position(|unit| unit.config.unit_type == unit_type)
+        {
+            Some(index) => {
+                self.selected = index;
+                Ok(unit_type)
+            }
+            None => Err(Error::ReturnCode(
+                ICReturnScannerFailedToSelectFunctionalUnit,
+            )),
+        };
+        self.push(Event::FunctionalUnitSelected {
+            device: device.clone(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        self.check_session(device)?;
+        self.units[self.selected].reported()
+    }
+
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit> {
+        self.check_idle(device)?;
+        let unit = &self.units[self.selected];
+        unit.reported()?.validate(settings)?;
+        let mut config = unit.config.clone();
+        apply(&mut config, settings)?;
+
+        // The transfer settings belong to the scanner rather than to the unit.
+        for unit in &mut self.units {
+            unit.config.transfer_mode = config.transfer_mode;
+            unit.config.downloads_directory = config.downloads_directory.clone();
+            unit.config.document_name = config.document_name.clone();
+            unit.config.document_uti = config.document_uti.clone();
+            unit.config.max_memory_band_size = config.max_memory_band_size;
+        }
+        let unit = &mut self.units[self.selected];
+        unit.config = config;
+        unit.reported()
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_idle(device)?;
+        let unit = &mut self.units[self.selected];
+        if !unit.config.can_perform_overview_scan {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let (width, height) = unit.config.physical_size;
+        let bed = ScanRect::new(0.0, 0.0, width, height, ICScannerMeasurementUnitInches);
+        let result = render(
+            &unit.config,
+            bed,
+            unit.config.overview_resolution,
+            PixelFormat::Rgb8,
+            1,
+        )
+        .map(|image| unit.overview = Some(image));
+        self.push(Event::OverviewCompleted {
+            device: device.clone(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        self.check_session(device)?;
+        Ok(self.units[self.selected].overview.clone())
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_idle(device)?;
+        let unit = &mut self.units[self.selected];
+        let pages: VecDeque<u32> =
+            if unit.config.unit_type == ICScannerFunctionalUnitTypeDocumentFeeder {
+                let sides = if unit.config.duplex == Some(true) {
+                    2
+                } else {
+                    1
+                };
+                let pages = unit.pages * sides;
+                unit.pages = 0;
+                (1..=pages).collect()
+            } else {
+                vec![1].into()
+            };
+        if pages.is_empty() {
+            self.push(Event::ScanCompleted {
+                device: device.clone(),
+                result: Err(Error::ReturnCode(ICReturnScannerFailedToCompleteScan)),
+            });
+            return Ok(());
+        }
+        self.scan = Some(ScanJob {
+            numbered: pages.len() > 1,
+            pages,
+            current: None,
+        });
+        Ok(())
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.check_session(device)?;
+        if self.scan.take().is_some() {
+            self.push(Event::ScanCompleted {
+                device: device.clone(),
+                result: Err(Error::ReturnCode(ICReturnScanOperationCanceled)),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Apply validated settings to a configuration with the physical size and scan area in inches.
+/// Fails for settings the simulator does not support.
+fn apply(config: &mut FunctionalUnit, settings: &ScanSettings) -> Result<()> {
+    let invalid = Error::ReturnCode(ICReturnInvalidParam);
+    if let Some(pixel_data_type) = settings.pixel_data_type {
+        if !supports_pixel_data_type(pixel_data_type) {
+            return Err(invalid);
+        }
+        config.pixel_data_type = pixel_data_type;
+    }
+    if let Some(ref uti) = settings.document_uti {
+        if extension(uti).is_none() {
+            return Err(invalid);
+        }
+        config.document_uti = Some(uti.clone());
+    }
+    let resolution = settings.resolution.unwrap_or(config.resolution);
+    if let Some(unit) = settings.measurement_unit {
+        convert(1.0, unit, ICScannerMeasurementUnitInches, resolution)?;
+        config.measurement_unit = unit;
+    }
+    if let Some(area) = settings.scan_area {
+        config.scan_area = area.to(ICScannerMeasurementUnitInches, resolution)?;
+    }
+    config.resolution = resolution;
+    if let Some(bit_depth) = settings.bit_depth {
+        config.bit_depth = bit_depth;
+    }
+    if let Some(orientation) = settings.scan_area_orientation {
+        config.scan_area_orientation = orientation;
+    }
+    if let Some(document_type) = settings.document_type {
+        config.document_type = document_type;
+    }
+    if let Some(duplex) = settings.duplex {
+        if config.duplex.is_some() {
+            config.duplex = Some(duplex);
+        }
+    }
+    if let Some(threshold) = settings.threshold {
+        config.threshold = Some(threshold);
+    }
+    if let Some(resolution) = settings.overview_resolution {
+        config.overview_resolution = resolution;
+    }
+    if let Some(transfer_mode) = settings.transfer_mode {
+        config.transfer_mode = transfer_mode;
+    }
+    if let Some(ref directory) = settings.downloads_directory {
+        config.downloads_directory = Some(directory.clone());
+    }
+    if let Some(ref name) = settings.document_name {
+        config.document_name = Some(name.clone());
+    }
+    if let Some(size) = settings.max_memory_band_size {
+        config.max_memory_band_size = size;
+    }
+    for (name, value) in &settings.features {
+        config
+            .vendor_features
+            .iter_mut()
+            .find(|feature| feature.name == *name)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?
+            .set_value(value)?;
+    }
+    // A scan area that rounds to no pixels would make an empty image.
+    let pixels = config.scan_area.to_pixels(config.resolution)?;
+    if pixels.width <= 0 || pixels.height <= 0 {
+        return Err(invalid);
+    }
+    Ok(())
+}
+
+fn supports_pixel_data_type(pixel_data_type: ICScannerPixelDataType) -> bool {
+    matches!(
+        pixel_data_type,
+        ICScannerPixelDataTypeBW | ICScannerPixelDataTypeGray | ICScannerPixelDataTypeRGB
+    )
+}
+
+/// The file name extension for a document type the simulator can save.
+fn extension(uti: &str) -> Option<&'static str> {
+    match uti {
+        "public.tiff" => Some("tiff"),
+        "public.png" => Some("png"),
+        _ => None,
+    }
+}
+
+/// The format of scans with a configuration.
+fn pixel_format(config: &FunctionalUnit) -> PixelFormat {
+    match (config.pixel_data_type, config.bit_depth) {
+        (ICScannerPixelDataTypeBW, _) | (_, ICScannerBitDepth1Bit) => PixelFormat::Bilevel,
+        (ICScannerPixelDataTypeGray, ICScannerBitDepth16Bits) => PixelFormat::Gray16,
+        (ICScannerPixelDataTypeGray, _) => PixelFormat::Gray8,
+        (_, ICScannerBitDepth16Bits) => PixelFormat::Rgb16,
+        _ => PixelFormat::Rgb8,
+    }
+}
+
+/// Render the test pattern under `area` at `resolution`.
+fn render(
+    config: &FunctionalUnit,
+    area: ScanRect,
+    resolution: u64,
+    format: PixelFormat,
+    page: u32,
+) -> Result<Image> {
+    let pixels = area.to_pixels(resolution)?;
+    let (width, height) = (pixels.width.max(0) as usize, pixels.height.max(0) as usize);
+    let bed = (config.physical_size.0 * 25.4, config.physical_size.1 * 25.4);
+    let negative = config.unit_type == ICScannerFunctionalUnitTypeNegativeTransparency;
+    let threshold = f64::from(config.threshold.unwrap_or(128));
+    let millimeters =
+        |pixel: i64, index: usize| (pixel as f64 + index as f64 + 0.5) * 25.4 / resolution as f64;
+
+    let mut image = Image::new(format, width, height);
+    for y in 0..height {
+        let y_mm = millimeters(pixels.y, y);
+        let row = image.row_mut(y);
+        for x in 0..width {
+            let mut color = pattern(millimeters(pixels.x, x), y_mm, bed, page);
+            if negative {
+                for (value, base) in color.iter_mut().zip(FILM_BASE.iter()) {
+                    *value = base * 10f64.powf(-MAX_DENSITY * *value);
+                }
+            }
+            let gray = 0.299 * color[0] + 0.587 * color[1] + 0.114 * color[2];
+            let eight = |value: f64| (value * 255.0).round() as u8;
+            let sixteen = |value: f64| ((value * 65535.0).round() as u16).to_be_bytes();
+            match format {
+                PixelFormat::Bilevel => {
+                    if (gray * 255.0).round() >= threshold {
+                        row[x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+                PixelFormat::Gray8 => row[x] = eight(gray),
+                PixelFormat::Gray16 => row[2 * x..2 * x + 2].copy_from_slice(&sixteen(gray)),
+                PixelFormat::Rgb8 | PixelFormat::Rgba8 => {
+                    let components = format.components();
+                    for (c, &value) in color.iter().enumerate() {
+                        row[components * x + c] = eight(value);
+                    }
+                    if components == 4 {
+                        row[4 * x + 3] = u8::MAX;
+                    }
+                }
+                PixelFormat::Rgb16 => {
+                    for (c, &value) in color.iter().enumerate() {
+                        row[6 * x + 2 * c..6 * x + 2 * c + 2].copy_from_slice(&sixteen(value));
+                    }
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// The colour of the test pattern at `x`, `y` millimetres on a bed of `width` by `height`
+/// millimetres, from 0 to 1.
+fn pattern(x: f64, y: f64, (width, height): (f64, f64), page: u32) -> [f64; 3] {
+    const WHITE: [f64; 3] = [1.0; 3];
+    const BLACK: [f64; 3] = [0.0; 3];
+    if x < 0.0 || y < 0.0 || x >= width || y >= height {
+        return WHITE;
+    }
+
+    // Ramps from black to full intensity across the bed, near the top.
+    let margin = 0.05 * width;
+    if x >= margin && x < width - margin {
+        let t = (x - margin) / (width - 2.0 * margin);
+        let v = y / height;
+        if (0.05..0.10).contains(&v) {
+            return [t; 3];
+        } else if (0.11..0.13).contains(&v) {
+            return [t, 0.0, 0.0];
+        } else if (0.13..0.15).contains(&v) {
+            return [0.0, t, 0.0];
+        } else if (0.15..0.17).contains(&v) {
+            return [0.0, 0.0, t];
+        }
+    }
+
+    // A star of alternating black and white spokes in the middle, which blurs towards its centre
+    // at the resolution limit.
+    let radius = 0.2 * width.min(height);
+    let (dx, dy) = (x - width / 2.0, y - 0.45 * height);
+    if dx.hypot(dy) < radius {
+        let spoke = ((dy.atan2(dx) / PI + 1.0) * SPOKES).floor() as i64;
+        return if spoke % 2 == 0 { BLACK } else { WHITE };
+    }
+
+    // The page number near the bottom, in digits of three by five cells with a cell between them.
+    let digits: Vec<usize> = page
+        .to_string()
+        .bytes()
+        .map(|digit| usize::from(digit - b'0'))
+        .collect();
+    let cell = 0.08 * height / 5.0;
+    let left = (width - cell * (4 * digits.len() - 1) as f64) / 2.0;
+    let top = 0.8 * height;
+    if x >= left && y >= top {
+        let (column, row) = (((x - left) / cell) as usize, ((y - top) / cell) as usize);
+        if row < 5 && column < 4 * digits.len() - 1 && column % 4 < 3 {
+            let bit = 14 - (row * 3 + column % 4);
+            if DIGITS[digits[column / 4]] & (1 << bit) != 0 {
+                return BLACK;
+            }
+        }
+    }
+    WHITE
+}
+
+/// The band of a memory based scan starting at row `start` of a page.
+fn band(config: &FunctionalUnit, image: &Image, start: usize) -> ScannerBand {
+    let format = image.format();
+    let bytes_per_row = image.row(0).len();
+    let rows = (config.max_memory_band_size as usize / bytes_per_row.max(1))
+        .max(1)
+        .min(image.height() - start);
+    let mut data = Vec::with_capacity(rows * bytes_per_row);
+    for y in start..start + rows {
+        data.extend_from_slice(image.row(y));
+    }
+    ScannerBand {
+        full_image_width: image.width(),
+        full_image_height: image.height(),
+        bits_per_pixel: format.bits_per_pixel(),
+        bits_per_component: format.bits_per_component(),
+        num_components: format.components(),
+        is_big_endian: true,
+        pixel_data_type: match format {
+            PixelFormat::Bilevel => ICScannerPixelDataTypeBW,
+            PixelFormat::Gray8 | PixelFormat::Gray16 => ICScannerPixelDataTypeGray,
+            _ => ICScannerPixelDataTypeRGB,
+        },
+        color_data_format: ICScannerColorDataFormatTypeChunky,
+        color_sync_profile_path: None,
+        bytes_per_row,
+        data_start_row: start,
+        data_num_rows: rows,
+        data,
+    }
+}
+
+/// Save a page of a file based scan in the downloads directory.
+fn save(config: &FunctionalUnit, image: &Image, name: &str) -> Result<PathBuf> {
+    let uti = config.document_uti.as_deref().unwrap_or("public.tiff");
+    let extension = extension(uti).ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+    let directory = config
+        .downloads_directory
+        .clone()
+        .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+    let path = destination(&directory.join(format!("{}.{}", name, extension)), false);
+    let resolution = config.resolution as f64;
+    let file = BufWriter::new(File::create(&path)?);
+    if extension == "png" {
+        let info = PngInfo::for_image(image).with_resolution(
+            resolution,
+            resolution,
+            ICScannerMeasurementUnitInches,
+        );
+        PngWriter::write_image(file, info, image)?;
+    } else {
+        let page = TiffPage::for_image(image).with_resolution(
+            resolution,
+            resolution,
+            ICScannerMeasurementUnitInches,
+        );
+        let mut tiff = TiffWriter::new(file)?;
+        tiff.write_page(page, image)?;
+        tiff.finish()?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::temp_dir;
+    use crate::constants::ICScannerMeasurementUnit::ICScannerMeasurementUnitPixels;
+    use std::fs;
+
+    /// A scanner with an open session and its events drained.
+    fn open(scanner: SimulatedScanner) -> SimulatedScanner {
+        let mut scanner = scanner;
+        let id = scanner.id().clone();
+        scanner.start_browsing(&[DeviceKind::Scanner]).unwrap();
+        scanner.open_session(&id).unwrap();
+        while scanner.events.pop_front().is_some() {}
+        scanner
+    }
+
+    /// The events of a request, up to the end of the scan.
+    fn scan_events(scanner: &mut SimulatedScanner) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = scanner.next_event(Duration::from_secs(0)) {
+            let done = matches!(event, Event::ScanCompleted { .. });
+            events.push(event);
+            if done {
+                break;
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn scan_areas_without_pixels_are_rejected() {
+        let mut scanner = open(SimulatedScanner::default());
+        let id = scanner.id().clone();
+        let small = ScanRect::new(1.0, 1.0, 1.0, 1.0, ICScannerMeasurementUnitInches);
+        scanner
+            .configure(&id, &ScanSettings::new().with_scan_area(small))
+            .unwrap();
+        let sliver = ScanRect::new(1.0, 1.0, 1.0, 0.001, ICScannerMeasurementUnitInches);
+        let settings = ScanSettings::new()
+            .with_resolution(75)
+            .with_scan_area(sliver);
+        assert_eq!(
+            scanner.configure(&id, &settings),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+        scanner.request_scan(&id).unwrap();
+        let events = scan_events(&mut scanner);
+        assert!(matches!(
+            events.last(),
+            Some(Event::ScanCompleted { result: Ok(()), .. })
+        ));
+    }
+
+    #[test]
+    fn memory_scans_arrive_in_bands_that_cover_the_page() {
+        let mut scanner = open(SimulatedScanner::default());
+        let id = scanner.id().clone();
+        let area = ScanRect::new(0.0, 0.0, 100.0, 50.0, ICScannerMeasurementUnitPixels);
+        let settings = ScanSettings::new()
+            .with_resolution(75)
+            .with_scan_area(area)
+            .with_max_memory_band_size(3000);
+        scanner.configure(&id, &settings).unwrap();
+        let page = scanner.render_page(1).unwrap();
+        assert_eq!((page.width(), page.height()), (100, 50));
+
+        scanner.request_scan(&id).unwrap();
+        let mut next_row = 0;
+        for event in scan_events(&mut scanner) {
+            match event {
+                Event::ScannedBand { band, .. } => {
+                    assert_eq!(band.data_start_row, next_row);
+                    assert!(band.data.len() <= 3000);
+                    for (y, row) in band.data.chunks(band.bytes_per_row).enumerate() {
+                        assert_eq!(row, page.row(next_row + y));
+                    }
+                    next_row += band.data_num_rows;
+                }
+                Event::ScanCompleted { result, .. } => assert_eq!(result, Ok(())),
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(next_row, 50);
+    }
+
+    #[test]
+    fn duplex_feeders_scan_both_sides_and_then_run_empty() {
+        let mut scanner =
+            open(SimulatedScanner::default().with_unit(SimulatedUnit::document_feeder(2, true)));
+        let id = scanner.id().clone();
+        scanner
+            .select_functional_unit(&id, ICScannerFunctionalUnitTypeDocumentFeeder)
+            .unwrap();
+        let settings = ScanSettings::new()
+            .with_duplex(true)
+            .with_scan_area(ScanRect::new(
+                0.0,
+                0.0,
+                1.0,
+                1.0,
+                ICScannerMeasurementUnitInches,
+            ))
+            .with_max_memory_band_size(u32::MAX);
+        scanner.configure(&id, &settings).unwrap();
+        scanner.events.clear();
+
+        scanner.request_scan(&id).unwrap();
+        let bands = scan_events(&mut scanner)
+            .into_iter()
+            .filter(|event| matches!(event, Event::ScannedBand { .. }))
+            .count();
+        assert_eq!(bands, 4);
+        assert_eq!(scanner.pages_loaded(), Ok(0));
+
+        scanner.request_scan(&id).unwrap();
+        assert!(matches!(
+            scan_events(&mut scanner).last(),
+            Some(Event::ScanCompleted {
+                result: Err(Error::ReturnCode(ICReturnScannerFailedToCompleteScan)),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn file_scans_do_not_replace_earlier_scans() {
+        let directory = temp_dir("simulated-scanner");
+        let mut scanner = open(SimulatedScanner::default());
+        let id = scanner.id().clone();
+        let area = ScanRect::new(0.0, 0.0, 20.0, 10.0, ICScannerMeasurementUnitPixels);
+        let settings = ScanSettings::new()
+            .with_resolution(75)
+            .with_scan_area(area)
+            .with_transfer_mode(ICScannerTransferModeFileBased)
+            .with_document(&directory, "Scan");
+        scanner.configure(&id, &settings).unwrap();
+
+        let mut paths = Vec::new();
+        for _ in 0..2 {
+            scanner.request_scan(&id).unwrap();
+            for event in scan_events(&mut scanner) {
+                match event {
+                    Event::ScannedFile { path, .. } => paths.push(path),
+                    Event::ScanCompleted { result, .. } => assert_eq!(result, Ok(())),
+                    event => panic!("unexpected event {:?}", event),
+                }
+            }
+        }
+        assert_eq!(
+            paths,
+            [directory.join("Scan.tiff"), directory.join("Scan 1.tiff")]
+        );
+        for path in &paths {
+            let data = fs::read(path).unwrap();
+            assert!(data.starts_with(b"II*\0") || data.starts_with(b"MM\0*"));
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,824 @@
+use crate::band_assembler::ScannerBand;
+use crate::ccitt::encode_group4;
+use crate::constants::ICEXIFOrientationType::{self, ICEXIFOrientation1};
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerMeasurementUnit::{self, *};
+use crate::error::{Error, Result};
+use crate::image::{min_bytes_per_row, Image, PixelFormat};
+use crate::measurement::convert;
+use crate::pixel_format::PixelConverter;
+use std::collections::HashMap;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Compression schemes supported by `TiffWriter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TiffCompression {
+    /// No compression.
+    None,
+    /// Lempel-Ziv-Welch compression.
+    Lzw,
+    /// Deflate (zlib) compression.
+    Deflate,
+    /// PackBits run-length compression.
+    PackBits,
+    /// CCITT Group 4 fax compression. Only valid for `PixelFormat::Bilevel` pages.
+    Group4,
+}
+
+impl TiffCompression {
+    /// The value of the TIFF `Compression` tag.
+    pub fn tag_value(self) -> u16 {
+        match self {
+            TiffCompression::None => 1,
+            TiffCompression::Group4 => 4,
+            TiffCompression::Lzw => 5,
+            TiffCompression::Deflate => 8,
+            TiffCompression::PackBits => 32773,
+        }
+    }
+}
+
+/// Description of a page written by `TiffWriter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TiffPage {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    x_resolution: f64,
+    y_resolution: f64,
+    resolution_unit: ICScannerMeasurementUnit,
+    orientation: ICEXIFOrientationType,
+    compression: TiffCompression,
+    rows_per_strip: Option<usize>,
+}
+
+/// Number of bytes the rows of a strip occupy, unless the page sets the rows per strip.
+const DEFAULT_STRIP_BYTES: usize = 8192;
+
+impl TiffPage {
+    /// Describe an uncompressed, upright page at 72 pixels per inch.
+    pub fn new(format: PixelFormat, width: usize, height: usize) -> TiffPage {
+        TiffPage {
+            format,
+            width,
+            height,
+            x_resolution: 72.0,
+            y_resolution: 72.0,
+            resolution_unit: ICScannerMeasurementUnitInches,
+            orientation: ICEXIFOrientation1,
+            compression: TiffCompression::None,
+            rows_per_strip: None,
+        }
+    }
+
+    /// Describe a page holding `image`.
+    pub fn for_image(image: &Image) -> TiffPage {
+        TiffPage::new(image.format(), image.width(), image.height())
+    }
+
+    /// Set the resolution in pixels per `unit`, as reported by the scanner.
+    ///
+    /// Inches and centimetres are written as they are. Picas, points and twips are converted to
+    /// pixels per inch. `ICScannerMeasurementUnitPixels` writes the values without an absolute unit.
+    pub fn with_resolution(
+        mut self,
+        x_resolution: f64,
+        y_resolution: f64,
+        unit: ICScannerMeasurementUnit,
+    ) -> TiffPage {
+        self.x_resolution = x_resolution;
+        self.y_resolution = y_resolution;
+        self.resolution_unit = unit;
+        self
+    }
+
+    /// Set the orientation the page is stored with.
+    pub fn with_orientation(mut self, orientation: ICEXIFOrientationType) -> TiffPage {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Set the compression of the page data.
+    pub fn with_compression(mut self, compression: TiffCompression) -> TiffPage {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the number of rows in each strip. By default strips hold about 8 KiB of pixel data.
+    pub fn with_rows_per_strip(mut self, rows_per_strip: usize) -> TiffPage {
+        self.rows_per_strip = Some(rows_per_strip);
+        self
+    }
+
+    /// The pixel format.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The compression of the page data.
+    pub fn compression(&self) -> TiffCompression {
+        self.compression
+    }
+
+    /// The `ResolutionUnit` tag value and the resolutions in that unit.
+    fn resolution(&self) -> Result<(u16, f64, f64)> {
+        let (unit, scale) = match self.resolution_unit {
+            ICScannerMeasurementUnitInches => (2, 1.0),
+            ICScannerMeasurementUnitCentimeters => (3, 1.0),
+            ICScannerMeasurementUnitPixels => (1, 1.0),
+            unit => (2, convert(1.0, ICScannerMeasurementUnitInches, unit, 0)?),
+        };
+        Ok((unit, self.x_resolution * scale, self.y_resolution * scale))
+    }
+
+    fn row_bytes(&self) -> usize {
+        min_bytes_per_row(self.width, self.format.bits_per_pixel())
+    }
+
+    fn rows_per_strip(&self) -> usize {
+        self.rows_per_strip
+            .unwrap_or(DEFAULT_STRIP_BYTES / self.row_bytes().max(1))
+            .clamp(1, self.height.max(1))
+    }
+
+    fn validate(&self) -> Result<()> {
+        let orientation = u64::from(self.orientation);
+        let resolution_valid = self.x_resolution > 0.0 && self.y_resolution > 0.0;
+        if self.width == 0
+            || self.height == 0
+            || self.width > u32::MAX as usize
+            || self.height > u32::MAX as usize
+            || !(1..=8).contains(&orientation)
+            || !resolution_valid
+            || self.rows_per_strip == Some(0)
+            || (self.compression == TiffCompression::Group4 && self.format != PixelFormat::Bilevel)
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.resolution().map(|_| ())
+    }
+}
+
+/// State of the page being written.
+#[derive(Clone, Debug)]
+struct PageState {
+    page: TiffPage,
+    rows_per_strip: usize,
+    strip: Vec<u8>,
+    strip_rows: usize,
+    rows_written: usize,
+    strip_offsets: Vec<u32>,
+    strip_byte_counts: Vec<u32>,
+}
+
+/// Writes single-page or multi-page TIFF files.
+///
+/// Pages are written row by row. Rows are collected into strips, and each strip is compressed and
+/// written as soon as it is full, so a page never has to be held in memory as a whole. The image
+/// file directory of a page follows its strips and is linked into the file when the page ends.
+///
+/// Files are written big endian, so 16 bit samples are stored as they are in `Image`. Bilevel
+/// pages are written `BlackIsZero`, except with Group 4 compression, which is written
+/// `WhiteIsZero` as fax readers expect.
+#[derive(Debug)]
+pub struct TiffWriter<W: Write + Seek> {
+    writer: W,
+    /// Position of the offset that links the next image file directory.
+    next_ifd_offset: u64,
+    pages: u16,
+    page: Option<PageState>,
+}
+
+impl<W: Write + Seek> TiffWriter<W> {
+    /// Start a TIFF file by writing its header.
+    pub fn new(mut writer: W) -> Result<TiffWriter<W>> {
+        let start = writer.stream_position()?;
+        writer.write_all(b"MM\0\x2a\0\0\0\0")?;
+        Ok(TiffWriter {
+            writer,
+            next_ifd_offset: start + 4,
+            pages: 0,
+            page: None,
+        })
+    }
+
+    /// Number of pages completed so far.
+    pub fn pages(&self) -> usize {
+        usize::from(self.pages)
+    }
+
+    /// Start a new page. Fails if a page is in progress or if the page description is invalid.
+    pub fn begin_page(&mut self, page: TiffPage) -> Result<()> {
+        if self.page.is_some() || self.pages == u16::MAX {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        page.validate()?;
+        self.page = Some(PageState {
+            rows_per_strip: page.rows_per_strip(),
+            page,
+            strip: Vec::new(),
+            strip_rows: 0,
+            rows_written: 0,
+            strip_offsets: Vec::new(),
+            strip_byte_counts: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Write the next row of the current page, in the pixel format of the page.
+    /// Fails if no page is in progress, if every row has been written, or if `row` is too short.
+    pub fn write_row(&mut self, row: &[u8]) -> Result<()> {
+        let state = match self.page {
+            Some(ref mut state) => state,
+            None => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        };
+        let row_bytes = state.page.row_bytes();
+        if state.rows_written == state.page.height || row.len() < row_bytes {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        state.strip.extend_from_slice(&row[..row_bytes]);
+        state.strip_rows += 1;
+        state.rows_written += 1;
+        if state.strip_rows == state.rows_per_strip || state.rows_written == state.page.height {
+            self.flush_strip()?;
+        }
+        Ok(())
+    }
+
+    /// Write the rows of a scanner band, converting them to the pixel format of the page.
+    ///
+    /// Bands have to arrive in order, starting with the row after the last row written. Fails for
+    /// planar bands, which cannot be written before every channel has arrived; assemble those
+    /// with `BandAssembler` instead.
+    pub fn write_band(&mut self, band: &ScannerBand, converter: &PixelConverter) -> Result<()> {
+        let (format, width, rows_written) = match self.page {
+            Some(ref state) => (state.page.format, state.page.width, state.rows_written),
+            None => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        };
+        if band.is_planar()
+            || band.data_start_row != rows_written
+            || band.full_image_width != width
+            || band.bits_per_pixel != converter.source().bits_per_pixel
+            || converter.target() != format
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        let mut row = vec![0; min_bytes_per_row(width, format.bits_per_pixel())];
+        for y in 0..band.available_rows() {
+            converter.convert_row(band.row(y), width, &mut row);
+            self.write_row(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the current page by writing its image file directory.
+    /// Fails if no page is in progress or if rows are missing.
+    pub fn end_page(&mut self) -> Result<()> {
+        match self.page {
+            Some(ref state) if state.rows_written == state.page.height => {}
+            _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+        let state = self.page.take().expect("page is in progress");
+        self.write_ifd(&state)?;
+        self.pages += 1;
+        Ok(())
+    }
+
+    /// Write a complete page.
+    pub fn write_page(&mut self, page: TiffPage, image: &Image) -> Result<()> {
+        if page.format != image.format()
+            || page.width != image.width()
+            || page.height != image.height()
+        {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.begin_page(page)?;
+        for y in 0..image.height() {
+            self.write_row(image.row(y))?;
+        }
+        self.end_page()
+    }
+
+    /// Finish the file and return the writer.
+    /// Fails if a page is in progress or if no page has been written.
+    pub fn finish(mut self) -> Result<W> {
+        if self.page.is_some() || self.pages == 0 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn position(&mut self) -> Result<u32> {
+        let position = self.writer.stream_position()?;
+        to_offset(position)
+    }
+
+    fn flush_strip(&mut self) -> Result<()> {
+        let state = self.page.as_mut().expect("page is in progress");
+        let page = &state.page;
+        let data = match page.compression {
+            TiffCompression::None => std::mem::take(&mut state.strip),
+            TiffCompression::Lzw => lzw_encode(&state.strip),
+            TiffCompression::Deflate => miniz_oxide::deflate::compress_to_vec_zlib(&state.strip, 6),
+            TiffCompression::PackBits => state
+                .strip
+                .chunks(page.row_bytes())
+                .flat_map(packbits_encode)
+                .collect(),
+            TiffCompression::Group4 => encode_group4(
+                &state.strip,
+                page.width,
+                state.strip_rows,
+                page.row_bytes(),
+                true,
+            ),
+        };
+        state.strip.clear();
+        state.strip_rows = 0;
+        let offset = self.position()?;
+        self.writer.write_all(&data)?;
+        let state = self.page.as_mut().expect("page is in progress");
+        state.strip_offsets.push(offset);
+        state.strip_byte_counts.push(to_offset(data.len() as u64)?);
+        Ok(())
+    }
+
+    fn write_ifd(&mut self, state: &PageState) -> Result<()> {
+        let page = &state.page;
+        let (photometric, extra_samples) = match page.format {
+            PixelFormat::Bilevel if page.compression == TiffCompression::Group4 => (0, None),
+            PixelFormat::Bilevel | PixelFormat::Gray8 | PixelFormat::Gray16 => (1, None),
+            PixelFormat::Rgb8 | PixelFormat::Rgb16 => (2, None),
+            // Unassociated alpha.
+            PixelFormat::Rgba8 => (2, Some(2)),
+        };
+        let (resolution_unit, x_resolution, y_resolution) = page.resolution()?;
+        let components = page.format.components();
+        let bits = page.format.bits_per_component() as u16;
+        let mut entries = vec![
+            Entry::long(256, &[page.width as u32]),
+            Entry::long(257, &[page.height as u32]),
+            Entry::short(258, &vec![bits; components]),
+            Entry::short(259, &[page.compression.tag_value()]),
+            Entry::short(262, &[photometric]),
+            Entry::long(273, &state.strip_offsets),
+            Entry::short(274, &[u64::from(page.orientation) as u16]),
+            Entry::short(277, &[components as u16]),
+            Entry::long(278, &[state.rows_per_strip as u32]),
+            Entry::long(279, &state.strip_byte_counts),
+            Entry::rational(282, x_resolution),
+            Entry::rational(283, y_resolution),
+            Entry::short(284, &[1]),
+            Entry::short(296, &[resolution_unit]),
+            Entry::short(297, &[self.pages, 0]),
+        ];
+        if page.compression == TiffCompression::Group4 {
+            entries.push(Entry::long(293, &[0]));
+        }
+        if let Some(extra_samples) = extra_samples {
+            entries.push(Entry::short(338, &[extra_samples]));
+        }
+        entries.sort_by_key(|entry| entry.tag);
+
+        // Image file directories start on a word boundary.
+        if self.position()? % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let ifd_offset = self.position()?;
+        let next_offset = u64::from(ifd_offset) + 2 + 12 * entries.len() as u64;
+        let mut directory = Vec::new();
+        let mut values = Vec::new();
+        directory.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+        for entry in &entries {
+            directory.extend_from_slice(&entry.tag.to_be_bytes());
+            directory.extend_from_slice(&entry.kind.to_be_bytes());
+            directory.extend_from_slice(&entry.count.to_be_bytes());
+            if entry.data.len() <= 4 {
+                let mut inline = [0; 4];
+                inline[..entry.data.len()].copy_from_slice(&entry.data);
+                directory.extend_from_slice(&inline);
+            } else {
+                let offset = to_offset(next_offset + 4 + values.len() as u64)?;
+                directory.extend_from_slice(&offset.to_be_bytes());
+                values.extend_from_slice(&entry.data);
+                if entry.data.len() % 2 == 1 {
+                    values.push(0);
+                }
+            }
+        }
+        directory.extend_from_slice(&[0; 4]);
+        self.writer.write_all(&directory)?;
+        self.writer.write_all(&values)?;
+
+        // Link the directory from the header or the previous directory.
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.next_ifd_offset))?;
+        self.writer.write_all(&ifd_offset.to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.next_ifd_offset = next_offset;
+        Ok(())
+    }
+}
+
+/// A field of an image file directory.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn short(tag: u16, values: &[u16]) -> Entry {
+        Entry {
+            tag,
+            kind: 3,
+            count: values.len() as u32,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect(),
+        }
+    }
+
+    fn long(tag: u16, values: &[u32]) -> Entry {
+        Entry {
+            tag,
+            kind: 4,
+            count: values.len() as u32,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect(),
+        }
+    }
+
+    fn rational(tag: u16, value: f64) -> Entry {
+        let (numerator, denominator) = if value.fract() == 0.0 && value <= f64::from(u32::MAX) {
+            (value as u32, 1u32)
+        } else {
+            (
+                (value * 1000.0).round().min(f64::from(u32::MAX)) as u32,
+                1000,
+            )
+        };
+        let mut data = numerator.to_be_bytes().to_vec();
+        data.extend_from_slice(&denominator.to_be_bytes());
+        Entry {
+            tag,
+            kind: 5,
+            count: 1,
+            data,
+        }
+    }
+}
+
+fn to_offset(position: u64) -> Result<u32> {
+    if position > u64::from(u32::MAX) {
+        return Err(io::Error::new(io::ErrorKind::Other, "TIFF file exceeds 4 GiB").into());
+    }
+    Ok(position as u32)
+}
+
+/// Writes variable width codes most significant bit first.
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u32) {
+        self.bits = (self.bits << width) | u32::from(code);
+        self.bit_count += width;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            self.output.push((self.bits >> self.bit_count) as u8);
+        }
+        self.bits &= (1 << self.bit_count) - 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.output.push((self.bits << (8 - self.bit_count)) as u8);
+        }
+        self.output
+    }
+}
+
+const LZW_CLEAR: u16 = 256;
+const LZW_END: u16 = 257;
+const LZW_FIRST: u16 = 258;
+/// The table is reset before it would need 13 bit codes.
+const LZW_LIMIT: u16 = 4094;
+
+/// TIFF flavoured LZW: codes start at 9 bits and grow one code early, as TIFF readers expect.
+fn lzw_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = BitWriter {
+        output: Vec::new(),
+        bits: 0,
+        bit_count: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = 9;
+    let mut next = LZW_FIRST;
+    output.put(LZW_CLEAR, width);
+    let mut bytes = data.iter();
+    let mut current = match bytes.next() {
+        Some(&byte) => u16::from(byte),
+        None => {
+            output.put(LZW_END, width);
+            return output.finish();
+        }
+    };
+    for &byte in bytes {
+        match table.get(&(current, byte)) {
+            Some(&code) => current = code,
+            None => {
+                output.put(current, width);
+                table.insert((current, byte), next);
+                lzw_add_entry(&mut output, &mut table, &mut width, &mut next);
+                current = u16::from(byte);
+            }
+        }
+    }
+    output.put(current, width);
+    // Readers add an entry for the last code too, which can widen the end code.
+    lzw_add_entry(&mut output, &mut table, &mut width, &mut next);
+    output.put(LZW_END, width);
+    output.finish()
+}
+
+/// Account for a new table entry, which may widen the codes or require the table to be reset.
+fn lzw_add_entry(
+    output: &mut BitWriter,
+    table: &mut HashMap<(u16, u8), u16>,
+    width: &mut u32,
+    next: &mut u16,
+) {
+    *next += 1;
+    if *next == LZW_LIMIT {
+        output.put(LZW_CLEAR, *width);
+        table.clear();
+        *width = 9;
+        *next = LZW_FIRST;
+    } else if u32::from(*next) >= 1 << *width {
+        *width += 1;
+    }
+}
+
+/// PackBits run-length encoding of a single row.
+fn packbits_encode(row: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < row.len() {
+        let run = row[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&byte| byte == row[i])
+            .count();
+        if run >= 2 {
+            output.push((1 - run as i16) as u8);
+            output.push(row[i]);
+            i += run;
+            continue;
+        }
+        // Copy literally up to the next run of at least three equal bytes.
+        let start = i;
+        while i < row.len() && i - start < 128 {
+            if i + 2 < row.len() && row[i] == row[i + 1] && row[i] == row[i + 2] {
+                break;
+            }
+            i += 1;
+        }
+        output.push((i - start - 1) as u8);
+        output.extend_from_slice(&row[start..i]);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn packbits_decode(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let header = data[i] as i8;
+            i += 1;
+            if header >= 0 {
+                let count = header as usize + 1;
+                output.extend_from_slice(&data[i..i + count]);
+                i += count;
+            } else if header != -128 {
+                output.extend(std::iter::repeat(data[i]).take((1 - isize::from(header)) as usize));
+                i += 1;
+            }
+        }
+        output
+    }
+
+    /// Decode TIFF LZW data, returning it and the widest code read.
+    fn lzw_decode(data: &[u8]) -> (Vec<u8>, u32) {
+        let mut output = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut width, mut widest) = (9, 9);
+        let mut position = 0;
+        loop {
+            let mut code = 0;
+            for _ in 0..width {
+                let bit = (data[position / 8] >> (7 - position % 8)) & 1;
+                code = code << 1 | usize::from(bit);
+                position += 1;
+            }
+            widest = widest.max(width);
+            if code == usize::from(LZW_CLEAR) {
+                table = (0..=255).map(|byte| vec![byte]).collect();
+                table.extend(vec![Vec::new(); 2]);
+                previous = None;
+                width = 9;
+                continue;
+            }
+            if code == usize::from(LZW_END) {
+                return (output, widest);
+            }
+            let entry = match table.get(code) {
+                Some(entry) => entry.clone(),
+                None => {
+                    assert_eq!(code, table.len());
+                    let mut entry = previous.clone().unwrap();
+                    entry.push(entry[0]);
+                    entry
+                }
+            };
+            output.extend_from_slice(&entry);
+            if let Some(mut previous) = previous {
+                previous.push(entry[0]);
+                table.push(previous);
+            }
+            previous = Some(entry);
+            // Codes widen one entry early, at 511, 1023 and 2047.
+            if table.len() + 1 == 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+    }
+
+    /// Bytes that repeat enough to build long table entries without filling the table at once.
+    fn sample_data(length: usize) -> Vec<u8> {
+        let mut state = 12345u32;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8 % 16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packbits_round_trips() {
+        let mut row = vec![7; 200];
+        row.extend_from_slice(&[1, 2, 3, 3, 4, 4, 4, 5]);
+        row.extend(sample_data(300));
+        for row in &[Vec::new(), vec![9], row] {
+            assert_eq!(&packbits_decode(&packbits_encode(row)), row);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips_through_every_code_width() {
+        assert_eq!(lzw_decode(&lzw_encode(&[])), (Vec::new(), 9));
+        assert_eq!(lzw_decode(&lzw_encode(b"ababab")).0, b"ababab");
+        // Enough data to widen the codes to 12 bits and reset the table more than once.
+        let data = sample_data(40000);
+        let (decoded, widest) = lzw_decode(&lzw_encode(&data));
+        assert_eq!(widest, 12);
+        assert_eq!(decoded, data);
+        // End the data just before, at and after each switch of the code width.
+        for width in 10..=12 {
+            let (mut low, mut high) = (1, data.len());
+            while low < high {
+                let middle = (low + high) / 2;
+                if lzw_decode(&lzw_encode(&data[..middle])).1 < width {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+            for length in low - 4..low + 4 {
+                let data = &data[..length];
+                assert_eq!(lzw_decode(&lzw_encode(data)).0, data, "{} bytes", length);
+            }
+        }
+    }
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    /// The fields of the image file directory at `offset`, as tag and first value, and the offset
+    /// of the next directory.
+    fn read_ifd(data: &[u8], offset: usize) -> (HashMap<u16, u32>, usize) {
+        let count = usize::from(read_u16(data, offset));
+        let mut fields = HashMap::new();
+        for index in 0..count {
+            let entry = offset + 2 + index * 12;
+            let value = match read_u16(data, entry + 2) {
+                3 => u32::from(read_u16(data, entry + 8)),
+                _ => read_u32(data, entry + 8),
+            };
+            fields.insert(read_u16(data, entry), value);
+        }
+        (fields, read_u32(data, offset + 2 + count * 12) as usize)
+    }
+
+    #[test]
+    fn pages_are_chained() {
+        let mut first = Image::new(PixelFormat::Gray8, 40, 3);
+        for y in 0..3 {
+            for (x, pixel) in first.row_mut(y).iter_mut().enumerate() {
+                *pixel = (x / 8 + y) as u8;
+            }
+        }
+        let mut second = Image::new(PixelFormat::Rgb8, 5, 2);
+        second.row_mut(1)[3] = 200;
+        let mut tiff = TiffWriter::new(Cursor::new(Vec::new())).unwrap();
+        tiff.write_page(
+            TiffPage::for_image(&first).with_compression(TiffCompression::PackBits),
+            &first,
+        )
+        .unwrap();
+        tiff.write_page(
+            TiffPage::for_image(&second)
+                .with_compression(TiffCompression::Lzw)
+                .with_resolution(300.0, 300.0, ICScannerMeasurementUnitInches),
+            &second,
+        )
+        .unwrap();
+        assert_eq!(tiff.pages(), 2);
+        let data = tiff.finish().unwrap().into_inner();
+        assert_eq!(&data[..4], b"MM\0\x2a");
+
+        let (fields, next) = read_ifd(&data, read_u32(&data, 4) as usize);
+        assert_eq!(fields[&256], 40);
+        assert_eq!(fields[&257], 3);
+        assert_eq!(fields[&259], 32773);
+        assert_eq!(fields[&297], 0);
+        let (offset, length) = (fields[&273] as usize, fields[&279] as usize);
+        assert_eq!(
+            packbits_decode(&data[offset..offset + length]),
+            first.buffer().data()
+        );
+
+        assert_ne!(next, 0);
+        let (fields, next) = read_ifd(&data, next);
+        assert_eq!(next, 0);
+        assert_eq!(fields[&256], 5);
+        assert_eq!(fields[&259], 5);
+        assert_eq!(fields[&262], 2);
+        assert_eq!(fields[&297], 1);
+        let resolution = fields[&282] as usize;
+        assert_eq!(read_u32(&data, resolution), 300);
+        assert_eq!(read_u32(&data, resolution + 4), 1);
+        let (offset, length) = (fields[&273] as usize, fields[&279] as usize);
+        assert_eq!(
+            lzw_decode(&data[offset..offset + length]).0,
+            second.buffer().data()
+        );
+    }
+
+    #[test]
+    fn group4_pages_must_be_bilevel() {
+        let mut tiff = TiffWriter::new(Cursor::new(Vec::new())).unwrap();
+        let page =
+            TiffPage::new(PixelFormat::Gray8, 8, 8).with_compression(TiffCompression::Group4);
+        assert_eq!(
+            tiff.begin_page(page),
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        );
+        assert!(tiff.finish().is_err());
+    }
+}
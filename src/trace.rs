@@ -0,0 +1,2063 @@
+use crate::backend::{
+    Backend, CameraItem, CameraStatus, DeviceId, DeviceInfo, DeviceKind, DownloadOptions, Event,
+    FeatureKind, FunctionalUnit, ItemId, ScanSettings, ScannerFeature,
+};
+use crate::band_assembler::ScannerBand;
+use crate::constants::ICReturnCode::ICReturnInvalidParam;
+use crate::constants::ICScannerFunctionalUnitType;
+use crate::error::{Error, Result, IC_ERROR_DOMAIN};
+use crate::image::{Image, ImageBuffer, PixelFormat};
+use crate::measurement::ScanRect;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A backend that passes requests and events through to another backend and writes them to a
+/// trace, which `TraceReplay` plays back. Wrapping an `ImageCaptureBackend` records the exact
+/// order and timing of the delegate callbacks of real devices, so traces from the field can be
+/// replayed as regression tests on any platform.
+///
+/// The trace is in JSON lines format: one JSON object per line, with `"time"` in microseconds
+/// since the recorder was created. A line has one of these keys:
+///
+/// - `"event"`: an event delivered by `next_event`, as an object whose `"type"` names the
+///   `Event` variant.
+/// - `"request"`: the name of a `Backend` method that makes a request, such as
+///   `"open_session"`, with `"device"` and the `"error"` it returned or `null`.
+/// - `"functional_unit"`, `"overview"` or `"status"`: the functional unit of a scanner after it
+///   is selected or configured, its overview image after an overview scan, or the status of a
+///   camera after it changes, so the replay can answer the same queries.
+///
+/// Binary data is encoded in Base64 and dates are given in seconds since 1970.
+///
+/// Lines are flushed as they are written. Write errors do not interrupt the backend; the first
+/// one is reported by `finish`.
+#[derive(Debug)]
+pub struct TraceRecorder<B: Backend, W: Write> {
+    backend: B,
+    writer: W,
+    start: Instant,
+    error: Option<Error>,
+}
+
+impl<B: Backend, W: Write> TraceRecorder<B, W> {
+    /// Record the requests and events of `backend` to `writer`.
+    pub fn new(backend: B, writer: W) -> TraceRecorder<B, W> {
+        TraceRecorder {
+            backend,
+            writer,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// The backend being recorded.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Stop recording and return the backend and the writer.
+    /// Fails with the first error writing the trace.
+    pub fn finish(mut self) -> Result<(B, W)> {
+        if let Err(error) = self.writer.flush() {
+            self.error.get_or_insert(error.into());
+        }
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok((self.backend, self.writer)),
+        }
+    }
+
+    /// Write a line with the given key and value, and the time.
+    fn write(&mut self, mut fields: Vec<(&str, Json)>) {
+        let time = self.start.elapsed().as_micros() as u64;
+        fields.insert(0, ("time", Json::from(time)));
+        let mut line = String::new();
+        object(fields).write(&mut line);
+        line.push('\n');
+        let result = self
+            .writer
+            .write_all(line.as_bytes())
+            .and_then(|_| self.writer.flush());
+        if let Err(error) = result {
+            self.error.get_or_insert(error.into());
+        }
+    }
+
+    /// Record a request and its result.
+    fn request<T>(&mut self, name: &str, device: Option<&DeviceId>, result: &Result<T>) {
+        let mut fields = vec![("request", Json::from(name))];
+        if let Some(device) = device {
+            fields.push(("device", Json::from(device.0.as_str())));
+        }
+        fields.push(("error", error_json(result)));
+        self.write(fields);
+    }
+
+    /// Record the functional unit of a scanner.
+    fn functional_unit_snapshot(&mut self, device: &DeviceId) {
+        if let (Ok(types), Ok(unit)) = (
+            self.backend.functional_unit_types(device),
+            self.backend.functional_unit(device),
+        ) {
+            self.write(vec![
+                ("device", Json::from(device.0.as_str())),
+                ("functional_unit", unit.to_json()),
+                (
+                    "unit_types",
+                    Json::Array(
+                        types
+                            .into_iter()
+                            .map(|t| Json::from(u64::from(t)))
+                            .collect(),
+                    ),
+                ),
+            ]);
+        }
+    }
+
+    /// Record the state of a camera.
+    fn status_snapshot(&mut self, device: &DeviceId) {
+        if let Ok(status) = self.backend.camera_status(device) {
+            self.write(vec![
+                ("device", Json::from(device.0.as_str())),
+                ("status", status.to_json()),
+            ]);
+        }
+    }
+}
+
+impl<B: Backend, W: Write> Backend for TraceRecorder<B, W> {
+    fn start_browsing(&mut self, kinds: &[DeviceKind]) -> Result<()> {
+        let result = self.backend.start_browsing(kinds);
+        self.request("start_browsing", None, &result);
+        result
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        let result = self.backend.stop_browsing();
+        self.request("stop_browsing", None, &result);
+        result
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.backend.devices()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        let event = self.backend.next_event(timeout)?;
+        self.write(vec![("event", event.to_json())]);
+        match &event {
+            Event::FunctionalUnitSelected {
+                device,
+                result: Ok(_),
+            } => self.functional_unit_snapshot(device),
+            Event::OverviewCompleted {
+                device,
+                result: Ok(()),
+            } => {
+                if let Ok(Some(image)) = self.backend.overview_image(device) {
+                    self.write(vec![
+                        ("device", Json::from(device.0.as_str())),
+                        ("overview", image.to_json()),
+                    ]);
+                }
+            }
+            Event::DeviceReady(device)
+            | Event::ItemsAdded { device, .. }
+            | Event::CatalogCompleted(device)
+            | Event::AccessRestrictionChanged { device, .. } => self.status_snapshot(device),
+            _ => {}
+        }
+        Some(event)
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.open_session(device);
+        self.request("open_session", Some(device), &result);
+        result
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.close_session(device);
+        self.request("close_session", Some(device), &result);
+        result
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        self.backend.has_open_session(device)
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        self.backend.camera_items(device)
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let result = self.backend.download_file(device, item, options);
+        self.request("download_file", Some(device), &result);
+        result
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        self.backend.camera_status(device)
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        item: &ItemId,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let result = self.backend.read_file(device, item, offset, length);
+        self.request("read_file", Some(device), &result);
+        result
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, items: &[ItemId]) -> Result<()> {
+        let result = self.backend.delete_files(device, items);
+        self.request("delete_files", Some(device), &result);
+        result
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, path: &Path) -> Result<()> {
+        let result = self.backend.upload_file(device, path);
+        self.request("upload_file", Some(device), &result);
+        result
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, enabled: bool) -> Result<()> {
+        let result = self.backend.set_tethering(device, enabled);
+        self.request("set_tethering", Some(device), &result);
+        if result.is_ok() {
+            self.status_snapshot(device);
+        }
+        result
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.take_picture(device);
+        self.request("take_picture", Some(device), &result);
+        result
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        self.backend.functional_unit_types(device)
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        let result = self.backend.select_functional_unit(device, unit_type);
+        self.request("select_functional_unit", Some(device), &result);
+        result
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        self.backend.functional_unit(device)
+    }
+
+    fn configure(&mut self, device: &DeviceId, settings: &ScanSettings) -> Result<FunctionalUnit> {
+        let result = self.backend.configure(device, settings);
+        self.request("configure", Some(device), &result);
+        if result.is_ok() {
+            self.functional_unit_snapshot(device);
+        }
+        result
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.request_overview_scan(device);
+        self.request("request_overview_scan", Some(device), &result);
+        result
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        self.backend.overview_image(device)
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.request_scan(device);
+        self.request("request_scan", Some(device), &result);
+        result
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        let result = self.backend.cancel_scan(device);
+        self.request("cancel_scan", Some(device), &result);
+        result
+    }
+}
+
+/// A line of a trace.
+#[derive(Clone, Debug)]
+enum Entry {
+    Event(Event),
+    Request {
+        name: String,
+        device: Option<DeviceId>,
+        result: Result<()>,
+    },
+    FunctionalUnit {
+        device: DeviceId,
+        types: Vec<ICScannerFunctionalUnitType>,
+        unit: Box<FunctionalUnit>,
+    },
+    Overview {
+        device: DeviceId,
+        image: Image,
+    },
+    Status {
+        device: DeviceId,
+        status: CameraStatus,
+    },
+}
+
+/// A backend that plays back a trace written by `TraceRecorder`.
+///
+/// Events are delivered with their original timing, or faster or slower with `with_speed`.
+/// Recorded requests hold back the events after them until the client makes the same request
+/// to the same device, and then return the recorded result, so the events keep their order
+/// relative to the requests of the client. Requests that are not next in the trace succeed
+/// without effect. The devices, sessions and camera items are those of the events delivered so
+/// far, and functional units, overview images and camera status are those recorded with them.
+#[derive(Clone, Debug)]
+pub struct TraceReplay {
+    entries: VecDeque<(Duration, Entry)>,
+    speed: f64,
+    /// The instant the trace time was at, once the replay has started.
+    anchor: Option<(Instant, Duration)>,
+    devices: Vec<DeviceInfo>,
+    sessions: HashSet<DeviceId>,
+    items: HashMap<DeviceId, Vec<CameraItem>>,
+    units: HashMap<DeviceId, (Vec<ICScannerFunctionalUnitType>, FunctionalUnit)>,
+    overviews: HashMap<DeviceId, Image>,
+    statuses: HashMap<DeviceId, CameraStatus>,
+}
+
+impl TraceReplay {
+    /// Read a trace. Fails with `ICReturnInvalidParam` if a line is not a valid trace line.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<TraceReplay> {
+        let mut entries = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push_back(parse_entry(&Json::parse(&line)?)?);
+            }
+        }
+        Ok(TraceReplay {
+            entries,
+            speed: 1.0,
+            anchor: None,
+            devices: Vec::new(),
+            sessions: HashSet::new(),
+            items: HashMap::new(),
+            units: HashMap::new(),
+            overviews: HashMap::new(),
+            statuses: HashMap::new(),
+        })
+    }
+
+    /// Read a trace from a file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<TraceReplay> {
+        TraceReplay::from_reader(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// Set how many times faster than recorded the events are delivered. `f64::INFINITY`
+    /// delivers events without waiting.
+    pub fn with_speed(mut self, speed: f64) -> TraceReplay {
+        if speed > 0.0 {
+            self.speed = speed;
+        }
+        self
+    }
+
+    /// Indicates whether every event has been delivered and every request made.
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of events and requests left.
+    pub fn remaining(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| matches!(entry, Entry::Event(_) | Entry::Request { .. }))
+            .count()
+    }
+
+    /// Start the clock of the replay if it has not started yet.
+    fn start(&mut self) {
+        if self.anchor.is_none() {
+            self.anchor = Some((Instant::now(), Duration::from_secs(0)));
+        }
+    }
+
+    /// When an event recorded at `time` is due.
+    fn due(&self, time: Duration) -> Instant {
+        let (instant, anchor) = self.anchor.expect("the replay has started");
+        let delay = time.saturating_sub(anchor).as_secs_f64() / self.speed;
+        if delay.is_finite() {
+            instant + Duration::from_secs_f64(delay)
+        } else {
+            instant
+        }
+    }
+
+    /// Apply the recorded states at the front of the trace.
+    fn apply_states(&mut self, index: usize) {
+        while let Some((_, entry)) = self.entries.get(index) {
+            match entry {
+                Entry::Event(_) | Entry::Request { .. } => break,
+                _ => {}
+            }
+            if let Some((_, entry)) = self.entries.remove(index) {
+                match entry {
+                    Entry::FunctionalUnit {
+                        device,
+                        types,
+                        unit,
+                    } => {
+                        self.units.insert(device, (types, *unit));
+                    }
+                    Entry::Overview { device, image } => {
+                        self.overviews.insert(device, image);
+                    }
+                    Entry::Status { device, status } => {
+                        self.statuses.insert(device, status);
+                    }
+                    Entry::Event(_) | Entry::Request { .. } => {}
+                }
+            }
+        }
+    }
+
+    /// Update the devices, sessions and items for a delivered event.
+    fn apply_event(&mut self, event: &Event) {
+        match event {
+            Event::DeviceAdded(info) => {
+                self.devices.retain(|device| device.id != info.id);
+                self.devices.push(info.clone());
+            }
+            Event::DeviceRemoved(device) => {
+                self.devices.retain(|info| info.id != *device);
+                self.sessions.remove(device);
+                self.items.remove(device);
+            }
+            Event::SessionOpened {
+                device,
+                result: Ok(()),
+            } => {
+                self.sessions.insert(device.clone());
+            }
+            Event::SessionClosed {
+                device,
+                result: Ok(()),
+            } => {
+                self.sessions.remove(device);
+                self.items.remove(device);
+            }
+            Event::ItemsAdded { device, items } => {
+                self.items
+                    .entry(device.clone())
+                    .or_default()
+                    .extend(items.iter().cloned());
+            }
+            Event::ItemsRemoved { device, items } => {
+                if let Some(catalog) = self.items.get_mut(device) {
+                    catalog.retain(|item| !items.contains(&item.id));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fail with `ICReturnInvalidParam` for a device that has not been added.
+    fn check_device(&self, device: &DeviceId) -> Result<()> {
+        if self.devices.iter().any(|info| info.id == *device) {
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// Match a request of the client with the next request of the trace.
+    fn request(&mut self, name: &str, device: Option<&DeviceId>) -> Result<()> {
+        self.start();
+        let index = self
+            .entries
+            .iter()
+            .position(|(_, entry)| matches!(entry, Entry::Request { .. }));
+        let matches = index.and_then(|index| match &self.entries[index].1 {
+            Entry::Request {
+                name: recorded,
+                device: recorded_device,
+                ..
+            } => Some(recorded == name && recorded_device.as_ref() == device),
+            _ => None,
+        });
+        match (index, matches) {
+            (Some(index), Some(true)) => {
+                let (time, entry) = self.entries.remove(index).expect("the request is there");
+                // Events after the request keep their timing relative to it.
+                if index == 0 {
+                    self.anchor = Some((Instant::now(), time));
+                }
+                self.apply_states(index);
+                match entry {
+                    Entry::Request { result, .. } => result,
+                    _ => unreachable!(),
+                }
+            }
+            _ => device.map_or(Ok(()), |device| self.check_device(device)),
+        }
+    }
+}
+
+impl Backend for TraceReplay {
+    fn start_browsing(&mut self, _kinds: &[DeviceKind]) -> Result<()> {
+        self.request("start_browsing", None)
+    }
+
+    fn stop_browsing(&mut self) -> Result<()> {
+        self.request("stop_browsing", None)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.devices.clone()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        self.start();
+        self.apply_states(0);
+        let due = match self.entries.front() {
+            Some((time, Entry::Event(_))) => self.due(*time),
+            // The client has not made the next request yet.
+            _ => return None,
+        };
+        let now = Instant::now();
+        if due > now {
+            let wait = due - now;
+            if wait > timeout {
+                thread::sleep(timeout);
+                return None;
+            }
+            thread::sleep(wait);
+        }
+        match self.entries.pop_front() {
+            Some((_, Entry::Event(event))) => {
+                self.apply_event(&event);
+                self.apply_states(0);
+                Some(event)
+            }
+            _ => None,
+        }
+    }
+
+    fn open_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("open_session", Some(device))
+    }
+
+    fn close_session(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("close_session", Some(device))
+    }
+
+    fn has_open_session(&self, device: &DeviceId) -> Result<bool> {
+        self.check_device(device)?;
+        Ok(self.sessions.contains(device))
+    }
+
+    fn camera_items(&self, device: &DeviceId) -> Result<Vec<CameraItem>> {
+        self.check_device(device)?;
+        Ok(self.items.get(device).cloned().unwrap_or_default())
+    }
+
+    fn download_file(
+        &mut self,
+        device: &DeviceId,
+        _item: &ItemId,
+        _options: &DownloadOptions,
+    ) -> Result<()> {
+        self.request("download_file", Some(device))
+    }
+
+    fn camera_status(&self, device: &DeviceId) -> Result<CameraStatus> {
+        self.check_device(device)?;
+        self.statuses
+            .get(device)
+            .copied()
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn read_file(
+        &mut self,
+        device: &DeviceId,
+        _item: &ItemId,
+        _offset: u64,
+        _length: u64,
+    ) -> Result<()> {
+        self.request("read_file", Some(device))
+    }
+
+    fn delete_files(&mut self, device: &DeviceId, _items: &[ItemId]) -> Result<()> {
+        self.request("delete_files", Some(device))
+    }
+
+    fn upload_file(&mut self, device: &DeviceId, _path: &Path) -> Result<()> {
+        self.request("upload_file", Some(device))
+    }
+
+    fn set_tethering(&mut self, device: &DeviceId, _enabled: bool) -> Result<()> {
+        self.request("set_tethering", Some(device))
+    }
+
+    fn take_picture(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("take_picture", Some(device))
+    }
+
+    fn functional_unit_types(&self, device: &DeviceId) -> Result<Vec<ICScannerFunctionalUnitType>> {
+        self.check_device(device)?;
+        self.units
+            .get(device)
+            .map(|(types, _)| types.clone())
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn select_functional_unit(
+        &mut self,
+        device: &DeviceId,
+        _unit_type: ICScannerFunctionalUnitType,
+    ) -> Result<()> {
+        self.request("select_functional_unit", Some(device))
+    }
+
+    fn functional_unit(&self, device: &DeviceId) -> Result<FunctionalUnit> {
+        self.check_device(device)?;
+        self.units
+            .get(device)
+            .map(|(_, unit)| unit.clone())
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn configure(&mut self, device: &DeviceId, _settings: &ScanSettings) -> Result<FunctionalUnit> {
+        self.request("configure", Some(device))?;
+        self.functional_unit(device)
+    }
+
+    fn request_overview_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("request_overview_scan", Some(device))
+    }
+
+    fn overview_image(&self, device: &DeviceId) -> Result<Option<Image>> {
+        self.check_device(device)?;
+        Ok(self.overviews.get(device).cloned())
+    }
+
+    fn request_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("request_scan", Some(device))
+    }
+
+    fn cancel_scan(&mut self, device: &DeviceId) -> Result<()> {
+        self.request("cancel_scan", Some(device))
+    }
+}
+
+/// Parse a line of a trace.
+fn parse_entry(json: &Json) -> Result<(Duration, Entry)> {
+    let time = Duration::from_micros(json.field("time")?.to_u64()?);
+    let device =
+        || -> Result<DeviceId> { Ok(DeviceId(json.field("device")?.to_str()?.to_owned())) };
+    let entry = if let Some(event) = json.optional("event") {
+        Entry::Event(Event::from_json(event)?)
+    } else if let Some(name) = json.optional("request") {
+        Entry::Request {
+            name: name.to_str()?.to_owned(),
+            device: json.optional("device").map(|_| device()).transpose()?,
+            result: result_from_json(json, |_| Ok(()))?,
+        }
+    } else if let Some(unit) = json.optional("functional_unit") {
+        Entry::FunctionalUnit {
+            device: device()?,
+            types: json
+                .field("unit_types")?
+                .to_array()?
+                .iter()
+                .map(Json::to_enum)
+                .collect::<Result<_>>()?,
+            unit: Box::new(FunctionalUnit::from_json(unit)?),
+        }
+    } else if let Some(image) = json.optional("overview") {
+        Entry::Overview {
+            device: device()?,
+            image: Image::from_json(image)?,
+        }
+    } else if let Some(status) = json.optional("status") {
+        Entry::Status {
+            device: device()?,
+            status: CameraStatus::from_json(status)?,
+        }
+    } else {
+        return Err(Error::ReturnCode(ICReturnInvalidParam));
+    };
+    Ok((time, entry))
+}
+
+/// Conversion of the values of a trace to and from JSON.
+trait TraceJson: Sized {
+    fn to_json(&self) -> Json;
+    fn from_json(json: &Json) -> Result<Self>;
+}
+
+/// `null` for a success, or the error of a failure.
+fn error_json<T>(result: &Result<T>) -> Json {
+    match result {
+        Ok(_) => Json::Null,
+        Err(error) => error.to_json(),
+    }
+}
+
+/// The result of an object with an `"error"` key, with the value of a success read by `value`.
+fn result_from_json<T, F>(json: &Json, value: F) -> Result<Result<T>>
+where
+    F: FnOnce(&Json) -> Result<T>,
+{
+    match json.optional("error") {
+        Some(error) => Ok(Err(Error::from_json(error)?)),
+        None => value(json).map(Ok),
+    }
+}
+
+impl TraceJson for Error {
+    fn to_json(&self) -> Json {
+        match self {
+            Error::ReturnCode(code) => object(vec![("code", Json::from(i64::from(*code)))]),
+            Error::UnknownReturnCode(code) => object(vec![("code", Json::from(*code))]),
+            Error::UnknownValue(value) => object(vec![("unknown", Json::from(*value))]),
+            Error::Domain { domain, code } => object(vec![
+                ("domain", Json::from(domain.as_str())),
+                ("code", Json::from(*code)),
+            ]),
+            Error::Io {
+                kind,
+                code,
+                message,
+            } => object(vec![
+                ("io", Json::from(format!("{:?}", kind))),
+                ("code", Json::from(*code)),
+                ("message", Json::from(message.as_str())),
+            ]),
+        }
+    }
+
+    fn from_json(json: &Json) -> Result<Error> {
+        if let Some(value) = json.optional("unknown") {
+            return Ok(Error::UnknownValue(value.to_u64()?));
+        }
+        let code = json.field("code")?.to_i64()?;
+        Ok(if let Some(kind) = json.optional("io") {
+            Error::Io {
+                kind: io_error_kind(kind.to_str()?),
+                code,
+                message: json.field("message")?.to_str()?.to_owned(),
+            }
+        } else if let Some(domain) = json.optional("domain") {
+            Error::Domain {
+                domain: domain.to_str()?.to_owned(),
+                code,
+            }
+        } else {
+            Error::from_domain(IC_ERROR_DOMAIN, code)
+        })
+    }
+}
+
+/// The I/O error kind with the given name, or `Other` for kinds that are not recognised.
+fn io_error_kind(name: &str) -> io::ErrorKind {
+    use std::io::ErrorKind::*;
+    [
+        NotFound,
+        PermissionDenied,
+        ConnectionRefused,
+        ConnectionReset,
+        ConnectionAborted,
+        NotConnected,
+        AddrInUse,
+        AddrNotAvailable,
+        BrokenPipe,
+        AlreadyExists,
+        WouldBlock,
+        InvalidInput,
+        InvalidData,
+        TimedOut,
+        WriteZero,
+        Interrupted,
+        Unsupported,
+        UnexpectedEof,
+        OutOfMemory,
+    ]
+    .iter()
+    .copied()
+    .find(|kind| format!("{:?}", kind) == name)
+    .unwrap_or(Other)
+}
+
+impl TraceJson for DeviceInfo {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("id", Json::from(self.id.0.as_str())),
+            (
+                "kind",
+                Json::from(match self.kind {
+                    DeviceKind::Camera => "camera",
+                    DeviceKind::Scanner => "scanner",
+                }),
+            ),
+            ("name", Json::from(self.name.as_str())),
+            ("transport", Json::from(self.transport.as_deref())),
+            ("serial_number", Json::from(self.serial_number.as_deref())),
+            ("is_remote", Json::Bool(self.is_remote)),
+            ("capabilities", strings(&self.capabilities)),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<DeviceInfo> {
+        Ok(DeviceInfo {
+            id: DeviceId(json.field("id")?.to_str()?.to_owned()),
+            kind: match json.field("kind")?.to_str()? {
+                "camera" => DeviceKind::Camera,
+                "scanner" => DeviceKind::Scanner,
+                _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+            },
+            name: json.field("name")?.to_str()?.to_owned(),
+            transport: json.optional_string("transport")?,
+            serial_number: json.optional_string("serial_number")?,
+            is_remote: json.field("is_remote")?.to_bool()?,
+            capabilities: json.field("capabilities")?.to_strings()?,
+        })
+    }
+}
+
+impl TraceJson for CameraItem {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("id", Json::from(self.id.0.as_str())),
+            ("name", Json::from(self.name.as_str())),
+            (
+                "parent",
+                Json::from(self.parent.as_ref().map(|parent| parent.0.as_str())),
+            ),
+            ("is_folder", Json::Bool(self.is_folder)),
+            ("uti", Json::from(self.uti.as_deref())),
+            ("size", Json::from(self.size)),
+            ("orientation", Json::from(u64::from(self.orientation))),
+            ("is_raw", Json::Bool(self.is_raw)),
+            ("is_locked", Json::Bool(self.is_locked)),
+            ("creation_date", date_json(self.creation_date)),
+            ("modification_date", date_json(self.modification_date)),
+            (
+                "ptp_object_handle",
+                Json::from(u64::from(self.ptp_object_handle)),
+            ),
+            ("sidecar_files", strings(&self.sidecar_files)),
+            ("added_after_catalog", Json::Bool(self.added_after_catalog)),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<CameraItem> {
+        Ok(CameraItem {
+            id: ItemId(json.field("id")?.to_str()?.to_owned()),
+            name: json.field("name")?.to_str()?.to_owned(),
+            parent: json.optional_string("parent")?.map(ItemId),
+            is_folder: json.field("is_folder")?.to_bool()?,
+            uti: json.optional_string("uti")?,
+            size: json.field("size")?.to_u64()?,
+            orientation: json.field("orientation")?.to_enum()?,
+            is_raw: json.field("is_raw")?.to_bool()?,
+            is_locked: json.field("is_locked")?.to_bool()?,
+            creation_date: json.optional("creation_date").map(date).transpose()?,
+            modification_date: json.optional("modification_date").map(date).transpose()?,
+            ptp_object_handle: json.field("ptp_object_handle")?.to_u32()?,
+            sidecar_files: json.field("sidecar_files")?.to_strings()?,
+            added_after_catalog: json.field("added_after_catalog")?.to_bool()?,
+        })
+    }
+}
+
+impl TraceJson for CameraStatus {
+    fn to_json(&self) -> Json {
+        object(vec![
+            (
+                "battery_level",
+                self.battery_level
+                    .map_or(Json::Null, |level| Json::from(u64::from(level))),
+            ),
+            (
+                "catalog_percent_completed",
+                Json::from(u64::from(self.catalog_percent_completed)),
+            ),
+            (
+                "tethered_capture_enabled",
+                Json::Bool(self.tethered_capture_enabled),
+            ),
+            ("access_restricted", Json::Bool(self.access_restricted)),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<CameraStatus> {
+        Ok(CameraStatus {
+            battery_level: json
+                .optional("battery_level")
+                .map(Json::to_u8)
+                .transpose()?,
+            catalog_percent_completed: json.field("catalog_percent_completed")?.to_u8()?,
+            tethered_capture_enabled: json.field("tethered_capture_enabled")?.to_bool()?,
+            access_restricted: json.field("access_restricted")?.to_bool()?,
+        })
+    }
+}
+
+impl TraceJson for ScannerBand {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("full_image_width", Json::from(self.full_image_width as u64)),
+            (
+                "full_image_height",
+                Json::from(self.full_image_height as u64),
+            ),
+            ("bits_per_pixel", Json::from(self.bits_per_pixel as u64)),
+            (
+                "bits_per_component",
+                Json::from(self.bits_per_component as u64),
+            ),
+            ("num_components", Json::from(self.num_components as u64)),
+            ("is_big_endian", Json::Bool(self.is_big_endian)),
+            (
+                "pixel_data_type",
+                Json::from(u64::from(self.pixel_data_type)),
+            ),
+            (
+                "color_data_format",
+                Json::from(u64::from(self.color_data_format)),
+            ),
+            (
+                "color_sync_profile_path",
+                Json::from(self.color_sync_profile_path.as_deref()),
+            ),
+            ("bytes_per_row", Json::from(self.bytes_per_row as u64)),
+            ("data_start_row", Json::from(self.data_start_row as u64)),
+            ("data_num_rows", Json::from(self.data_num_rows as u64)),
+            ("data", Json::from(base64_encode(&self.data))),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<ScannerBand> {
+        Ok(ScannerBand {
+            full_image_width: json.field("full_image_width")?.to_usize()?,
+            full_image_height: json.field("full_image_height")?.to_usize()?,
+            bits_per_pixel: json.field("bits_per_pixel")?.to_usize()?,
+            bits_per_component: json.field("bits_per_component")?.to_usize()?,
+            num_components: json.field("num_components")?.to_usize()?,
+            is_big_endian: json.field("is_big_endian")?.to_bool()?,
+            pixel_data_type: json.field("pixel_data_type")?.to_enum()?,
+            color_data_format: json.field("color_data_format")?.to_enum()?,
+            color_sync_profile_path: json.optional_string("color_sync_profile_path")?,
+            bytes_per_row: json.field("bytes_per_row")?.to_usize()?,
+            data_start_row: json.field("data_start_row")?.to_usize()?,
+            data_num_rows: json.field("data_num_rows")?.to_usize()?,
+            data: base64_decode(json.field("data")?.to_str()?)?,
+        })
+    }
+}
+
+impl TraceJson for ScanRect {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("x", Json::from(self.x)),
+            ("y", Json::from(self.y)),
+            ("width", Json::from(self.width)),
+            ("height", Json::from(self.height)),
+            ("unit", Json::from(u64::from(self.unit))),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<ScanRect> {
+        Ok(ScanRect::new(
+            json.field("x")?.to_f64()?,
+            json.field("y")?.to_f64()?,
+            json.field("width")?.to_f64()?,
+            json.field("height")?.to_f64()?,
+            json.field("unit")?.to_enum()?,
+        ))
+    }
+}
+
+impl TraceJson for ScannerFeature {
+    fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("name", Json::from(self.name.as_str())),
+            ("label", Json::from(self.label.as_deref())),
+        ];
+        match &self.kind {
+            FeatureKind::Enumeration { values, current } => {
+                fields.push(("kind", Json::from("enumeration")));
+                fields.push(("values", strings(values)));
+                fields.push(("current", Json::from(current.as_str())));
+            }
+            FeatureKind::Range {
+                min,
+                max,
+                step,
+                current,
+            } => {
+                fields.push(("kind", Json::from("range")));
+                fields.push(("min", Json::from(*min)));
+                fields.push(("max", Json::from(*max)));
+                fields.push(("step", Json::from(*step)));
+                fields.push(("current", Json::from(*current)));
+            }
+            FeatureKind::Boolean(value) => {
+                fields.push(("kind", Json::from("boolean")));
+                fields.push(("current", Json::Bool(*value)));
+            }
+        }
+        object(fields)
+    }
+
+    fn from_json(json: &Json) -> Result<ScannerFeature> {
+        let current = json.field("current")?;
+        let kind = match json.field("kind")?.to_str()? {
+            "enumeration" => FeatureKind::Enumeration {
+                values: json.field("values")?.to_strings()?,
+                current: current.to_str()?.to_owned(),
+            },
+            "range" => FeatureKind::Range {
+                min: json.field("min")?.to_f64()?,
+                max: json.field("max")?.to_f64()?,
+                step: json.field("step")?.to_f64()?,
+                current: current.to_f64()?,
+            },
+            "boolean" => FeatureKind::Boolean(current.to_bool()?),
+            _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        };
+        Ok(ScannerFeature {
+            name: json.field("name")?.to_str()?.to_owned(),
+            label: json.optional_string("label")?,
+            kind,
+        })
+    }
+}
+
+impl TraceJson for FunctionalUnit {
+    fn to_json(&self) -> Json {
+        let numbers = |values: Vec<u64>| Json::Array(values.into_iter().map(Json::from).collect());
+        object(vec![
+            ("unit_type", Json::from(u64::from(self.unit_type))),
+            (
+                "measurement_unit",
+                Json::from(u64::from(self.measurement_unit)),
+            ),
+            (
+                "physical_size",
+                Json::Array(vec![
+                    Json::from(self.physical_size.0),
+                    Json::from(self.physical_size.1),
+                ]),
+            ),
+            (
+                "supported_resolutions",
+                numbers(self.supported_resolutions.clone()),
+            ),
+            ("resolution", Json::from(self.resolution)),
+            (
+                "supported_bit_depths",
+                numbers(
+                    self.supported_bit_depths
+                        .iter()
+                        .map(|&depth| depth.into())
+                        .collect(),
+                ),
+            ),
+            ("bit_depth", Json::from(u64::from(self.bit_depth))),
+            (
+                "pixel_data_type",
+                Json::from(u64::from(self.pixel_data_type)),
+            ),
+            ("scan_area", self.scan_area.to_json()),
+            (
+                "scan_area_orientation",
+                Json::from(u64::from(self.scan_area_orientation)),
+            ),
+            (
+                "supported_document_types",
+                numbers(
+                    self.supported_document_types
+                        .iter()
+                        .map(|&document_type| document_type.into())
+                        .collect(),
+                ),
+            ),
+            ("document_type", Json::from(u64::from(self.document_type))),
+            (
+                "can_perform_overview_scan",
+                Json::Bool(self.can_perform_overview_scan),
+            ),
+            ("overview_resolution", Json::from(self.overview_resolution)),
+            ("accepts_threshold", Json::Bool(self.accepts_threshold)),
+            (
+                "threshold",
+                self.threshold
+                    .map_or(Json::Null, |threshold| Json::from(u64::from(threshold))),
+            ),
+            ("duplex", self.duplex.map_or(Json::Null, Json::Bool)),
+            ("transfer_mode", Json::from(u64::from(self.transfer_mode))),
+            (
+                "downloads_directory",
+                Json::from(
+                    self.downloads_directory
+                        .as_ref()
+                        .map(|path| path.to_string_lossy().into_owned()),
+                ),
+            ),
+            ("document_name", Json::from(self.document_name.as_deref())),
+            ("document_uti", Json::from(self.document_uti.as_deref())),
+            (
+                "max_memory_band_size",
+                Json::from(u64::from(self.max_memory_band_size)),
+            ),
+            (
+                "vendor_features",
+                Json::Array(
+                    self.vendor_features
+                        .iter()
+                        .map(TraceJson::to_json)
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<FunctionalUnit> {
+        let numbers = |key: &str| -> Result<Vec<u64>> {
+            json.field(key)?
+                .to_array()?
+                .iter()
+                .map(Json::to_u64)
+                .collect()
+        };
+        let size = json.field("physical_size")?.to_array()?;
+        if size.len() != 2 {
+            return Err(Error::ReturnCode(ICReturnInvalidParam));
+        }
+        Ok(FunctionalUnit {
+            unit_type: json.field("unit_type")?.to_enum()?,
+            measurement_unit: json.field("measurement_unit")?.to_enum()?,
+            physical_size: (size[0].to_f64()?, size[1].to_f64()?),
+            supported_resolutions: numbers("supported_resolutions")?,
+            resolution: json.field("resolution")?.to_u64()?,
+            supported_bit_depths: json
+                .field("supported_bit_depths")?
+                .to_array()?
+                .iter()
+                .map(Json::to_enum)
+                .collect::<Result<_>>()?,
+            bit_depth: json.field("bit_depth")?.to_enum()?,
+            pixel_data_type: json.field("pixel_data_type")?.to_enum()?,
+            scan_area: ScanRect::from_json(json.field("scan_area")?)?,
+            scan_area_orientation: json.field("scan_area_orientation")?.to_enum()?,
+            supported_document_types: json
+                .field("supported_document_types")?
+                .to_array()?
+                .iter()
+                .map(Json::to_enum)
+                .collect::<Result<_>>()?,
+            document_type: json.field("document_type")?.to_enum()?,
+            can_perform_overview_scan: json.field("can_perform_overview_scan")?.to_bool()?,
+            overview_resolution: json.field("overview_resolution")?.to_u64()?,
+            accepts_threshold: json.field("accepts_threshold")?.to_bool()?,
+            threshold: json.optional("threshold").map(Json::to_u8).transpose()?,
+            duplex: json.optional("duplex").map(Json::to_bool).transpose()?,
+            transfer_mode: json.field("transfer_mode")?.to_enum()?,
+            downloads_directory: json
+                .optional_string("downloads_directory")?
+                .map(PathBuf::from),
+            document_name: json.optional_string("document_name")?,
+            document_uti: json.optional_string("document_uti")?,
+            max_memory_band_size: json.field("max_memory_band_size")?.to_u32()?,
+            vendor_features: json
+                .field("vendor_features")?
+                .to_array()?
+                .iter()
+                .map(ScannerFeature::from_json)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl TraceJson for Image {
+    fn to_json(&self) -> Json {
+        let buffer = self.buffer();
+        object(vec![
+            ("format", Json::from(format!("{:?}", self.format()))),
+            ("width", Json::from(buffer.width() as u64)),
+            ("height", Json::from(buffer.height() as u64)),
+            ("bytes_per_row", Json::from(buffer.bytes_per_row() as u64)),
+            ("data", Json::from(base64_encode(buffer.data()))),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<Image> {
+        let name = json.field("format")?.to_str()?;
+        let format = [
+            PixelFormat::Bilevel,
+            PixelFormat::Gray8,
+            PixelFormat::Gray16,
+            PixelFormat::Rgb8,
+            PixelFormat::Rgb16,
+            PixelFormat::Rgba8,
+        ]
+        .iter()
+        .copied()
+        .find(|format| format!("{:?}", format) == name)
+        .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        let buffer = ImageBuffer::from_data(
+            json.field("width")?.to_usize()?,
+            json.field("height")?.to_usize()?,
+            format.bits_per_pixel(),
+            json.field("bytes_per_row")?.to_usize()?,
+            base64_decode(json.field("data")?.to_str()?)?,
+        )?;
+        Image::from_buffer(format, buffer)
+    }
+}
+
+impl TraceJson for Event {
+    fn to_json(&self) -> Json {
+        let device = |device: &DeviceId| ("device", Json::from(device.0.as_str()));
+        let item = |item: &ItemId| ("item", Json::from(item.0.as_str()));
+        let (name, mut fields) = match self {
+            Event::DeviceAdded(info) => ("DeviceAdded", vec![("info", info.to_json())]),
+            Event::DeviceRemoved(id) => ("DeviceRemoved", vec![device(id)]),
+            Event::SessionOpened { device: id, result } => (
+                "SessionOpened",
+                vec![device(id), ("error", error_json(result))],
+            ),
+            Event::SessionClosed { device: id, result } => (
+                "SessionClosed",
+                vec![device(id), ("error", error_json(result))],
+            ),
+            Event::DeviceReady(id) => ("DeviceReady", vec![device(id)]),
+            Event::Status {
+                device: id,
+                message,
+            } => (
+                "Status",
+                vec![device(id), ("message", Json::from(message.as_str()))],
+            ),
+            Event::DeviceError { device: id, error } => {
+                ("DeviceError", vec![device(id), ("error", error.to_json())])
+            }
+            Event::ItemsAdded { device: id, items } => (
+                "ItemsAdded",
+                vec![
+                    device(id),
+                    (
+                        "items",
+                        Json::Array(items.iter().map(TraceJson::to_json).collect()),
+                    ),
+                ],
+            ),
+            Event::ItemsRemoved { device: id, items } => (
+                "ItemsRemoved",
+                vec![
+                    device(id),
+                    (
+                        "items",
+                        Json::Array(
+                            items
+                                .iter()
+                                .map(|item| Json::from(item.0.as_str()))
+                                .collect(),
+                        ),
+                    ),
+                ],
+            ),
+            Event::CatalogCompleted(id) => ("CatalogCompleted", vec![device(id)]),
+            Event::AccessRestrictionChanged {
+                device: id,
+                restricted,
+            } => (
+                "AccessRestrictionChanged",
+                vec![device(id), ("restricted", Json::Bool(*restricted))],
+            ),
+            Event::Downloaded {
+                device: id,
+                item: file,
+                result,
+            } => (
+                "Downloaded",
+                vec![
+                    device(id),
+                    item(file),
+                    (
+                        "path",
+                        Json::from(
+                            result
+                                .as_ref()
+                                .ok()
+                                .map(|path| path.to_string_lossy().into_owned()),
+                        ),
+                    ),
+                    ("error", error_json(result)),
+                ],
+            ),
+            Event::DataRead {
+                device: id,
+                item: file,
+                offset,
+                result,
+            } => (
+                "DataRead",
+                vec![
+                    device(id),
+                    item(file),
+                    ("offset", Json::from(*offset)),
+                    (
+                        "data",
+                        Json::from(result.as_ref().ok().map(|data| base64_encode(data))),
+                    ),
+                    ("error", error_json(result)),
+                ],
+            ),
+            Event::Uploaded {
+                device: id,
+                path,
+                result,
+            } => (
+                "Uploaded",
+                vec![
+                    device(id),
+                    ("path", Json::from(path.to_string_lossy().into_owned())),
+                    ("error", error_json(result)),
+                ],
+            ),
+            Event::FunctionalUnitSelected { device: id, result } => (
+                "FunctionalUnitSelected",
+                vec![
+                    device(id),
+                    (
+                        "unit_type",
+                        result
+                            .as_ref()
+                            .map_or(Json::Null, |&unit_type| Json::from(u64::from(unit_type))),
+                    ),
+                    ("error", error_json(result)),
+                ],
+            ),
+            Event::ScannedBand { device: id, band } => {
+                ("ScannedBand", vec![device(id), ("band", band.to_json())])
+            }
+            Event::ScannedFile { device: id, path } => (
+                "ScannedFile",
+                vec![
+                    device(id),
+                    ("path", Json::from(path.to_string_lossy().into_owned())),
+                ],
+            ),
+            Event::OverviewCompleted { device: id, result } => (
+                "OverviewCompleted",
+                vec![device(id), ("error", error_json(result))],
+            ),
+            Event::ScanCompleted { device: id, result } => (
+                "ScanCompleted",
+                vec![device(id), ("error", error_json(result))],
+            ),
+        };
+        fields.insert(0, ("type", Json::from(name)));
+        object(fields)
+    }
+
+    fn from_json(json: &Json) -> Result<Event> {
+        let device =
+            || -> Result<DeviceId> { Ok(DeviceId(json.field("device")?.to_str()?.to_owned())) };
+        let item = || -> Result<ItemId> { Ok(ItemId(json.field("item")?.to_str()?.to_owned())) };
+        let path = || -> Result<PathBuf> { Ok(PathBuf::from(json.field("path")?.to_str()?)) };
+        let unit = |_: &Json| Ok(());
+        Ok(match json.field("type")?.to_str()? {
+            "DeviceAdded" => Event::DeviceAdded(DeviceInfo::from_json(json.field("info")?)?),
+            "DeviceRemoved" => Event::DeviceRemoved(device()?),
+            "SessionOpened" => Event::SessionOpened {
+                device: device()?,
+                result: result_from_json(json, unit)?,
+            },
+            "SessionClosed" => Event::SessionClosed {
+                device: device()?,
+                result: result_from_json(json, unit)?,
+            },
+            "DeviceReady" => Event::DeviceReady(device()?),
+            "Status" => Event::Status {
+                device: device()?,
+                message: json.field("message")?.to_str()?.to_owned(),
+            },
+            "DeviceError" => Event::DeviceError {
+                device: device()?,
+                error: Error::from_json(json.field("error")?)?,
+            },
+            "ItemsAdded" => Event::ItemsAdded {
+                device: device()?,
+                items: json
+                    .field("items")?
+                    .to_array()?
+                    .iter()
+                    .map(CameraItem::from_json)
+                    .collect::<Result<_>>()?,
+            },
+            "ItemsRemoved" => Event::ItemsRemoved {
+                device: device()?,
+                items: json
+                    .field("items")?
+                    .to_strings()?
+                    .into_iter()
+                    .map(ItemId)
+                    .collect(),
+            },
+            "CatalogCompleted" => Event::CatalogCompleted(device()?),
+            "AccessRestrictionChanged" => Event::AccessRestrictionChanged {
+                device: device()?,
+                restricted: json.field("restricted")?.to_bool()?,
+            },
+            "Downloaded" => Event::Downloaded {
+                device: device()?,
+                item: item()?,
+                result: result_from_json(json, |_| path())?,
+            },
+            "DataRead" => Event::DataRead {
+                device: device()?,
+                item: item()?,
+                offset: json.field("offset")?.to_u64()?,
+                result: result_from_json(json, |json| {
+                    base64_decode(json.field("data")?.to_str()?)
+                })?,
+            },
+            "Uploaded" => Event::Uploaded {
+                device: device()?,
+                path: path()?,
+                result: result_from_json(json, unit)?,
+            },
+            "FunctionalUnitSelected" => Event::FunctionalUnitSelected {
+                device: device()?,
+                result: result_from_json(json, |json| json.field("unit_type")?.to_enum())?,
+            },
+            "ScannedBand" => Event::ScannedBand {
+                device: device()?,
+                band: ScannerBand::from_json(json.field("band")?)?,
+            },
+            "ScannedFile" => Event::ScannedFile {
+                device: device()?,
+                path: path()?,
+            },
+            "OverviewCompleted" => Event::OverviewCompleted {
+                device: device()?,
+                result: result_from_json(json, unit)?,
+            },
+            "ScanCompleted" => Event::ScanCompleted {
+                device: device()?,
+                result: result_from_json(json, unit)?,
+            },
+            _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+        })
+    }
+}
+
+/// A date as a number of seconds since 1970 with nine decimals, which keeps every nanosecond.
+fn date_json(date: Option<SystemTime>) -> Json {
+    match date {
+        Some(date) => Json::Number(match date.duration_since(UNIX_EPOCH) {
+            Ok(since) => format!("{}.{:09}", since.as_secs(), since.subsec_nanos()),
+            Err(error) => {
+                let before = error.duration();
+                format!("-{}.{:09}", before.as_secs(), before.subsec_nanos())
+            }
+        }),
+        None => Json::Null,
+    }
+}
+
+fn date(json: &Json) -> Result<SystemTime> {
+    let invalid = || Error::ReturnCode(ICReturnInvalidParam);
+    let text = match json {
+        Json::Number(text) => text.as_str(),
+        _ => return Err(invalid()),
+    };
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (seconds, fraction) = match text.find('.') {
+        Some(point) => (&text[..point], &text[point + 1..]),
+        None => (text, ""),
+    };
+    if fraction.len() > 9 || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let seconds: u64 = seconds.parse().map_err(|_| invalid())?;
+    let nanos: u32 = if fraction.is_empty() {
+        0
+    } else {
+        format!("{:0<9}", fraction).parse().map_err(|_| invalid())?
+    };
+    let duration = Duration::new(seconds, nanos);
+    if negative {
+        UNIX_EPOCH.checked_sub(duration).ok_or_else(invalid)
+    } else {
+        UNIX_EPOCH.checked_add(duration).ok_or_else(invalid)
+    }
+}
+
+fn strings(values: &[String]) -> Json {
+    Json::Array(
+        values
+            .iter()
+            .map(|value| Json::from(value.as_str()))
+            .collect(),
+    )
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
+
+/// A JSON value. Numbers keep their text, so integers of any size read back exactly.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Json {
+        Json::Number(value.to_string())
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Json {
+        Json::Number(value.to_string())
+    }
+}
+
+impl From<f64> for Json {
+    /// Finite numbers are written in the shortest form that reads back exactly. JSON has no
+    /// infinities or NaN, which become `null`.
+    fn from(value: f64) -> Json {
+        if value.is_finite() {
+            Json::Number(value.to_string())
+        } else {
+            Json::Null
+        }
+    }
+}
+
+impl Json {
+    fn write(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Json::Number(text) => out.push_str(text),
+            Json::String(text) => {
+                out.push('"');
+                for c in text.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            Json::Array(values) => {
+                out.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    value.write(out);
+                }
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    Json::String(key.clone()).write(out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    /// Parse a JSON text. Fails with `ICReturnInvalidParam` if it is not valid JSON.
+    fn parse(text: &str) -> Result<Json> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position == parser.bytes.len() {
+            Ok(value)
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    /// The value of a key of an object. Fails if the key is missing.
+    fn field(&self, key: &str) -> Result<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+                .ok_or(Error::ReturnCode(ICReturnInvalidParam)),
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    /// The value of a key of an object, or `None` if it is missing or `null`.
+    fn optional(&self, key: &str) -> Option<&Json> {
+        self.field(key).ok().filter(|value| **value != Json::Null)
+    }
+
+    fn optional_string(&self, key: &str) -> Result<Option<String>> {
+        self.optional(key)
+            .map(|value| value.to_str().map(str::to_owned))
+            .transpose()
+    }
+
+    fn to_str(&self) -> Result<&str> {
+        match self {
+            Json::String(text) => Ok(text),
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    fn to_bool(&self) -> Result<bool> {
+        match self {
+            Json::Bool(value) => Ok(*value),
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    fn to_array(&self) -> Result<&[Json]> {
+        match self {
+            Json::Array(values) => Ok(values),
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    fn to_strings(&self) -> Result<Vec<String>> {
+        self.to_array()?
+            .iter()
+            .map(|value| value.to_str().map(str::to_owned))
+            .collect()
+    }
+
+    fn number<T: std::str::FromStr>(&self) -> Result<T> {
+        match self {
+            Json::Number(text) => text
+                .parse()
+                .map_err(|_| Error::ReturnCode(ICReturnInvalidParam)),
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    fn to_u64(&self) -> Result<u64> {
+        self.number()
+    }
+
+    /// A raw ImageCaptureCore value, which must be known to this crate.
+    fn to_enum<T: TryFrom<u64>>(&self) -> Result<T> {
+        T::try_from(self.to_u64()?).map_err(|_| Error::ReturnCode(ICReturnInvalidParam))
+    }
+
+    fn to_u32(&self) -> Result<u32> {
+        self.number()
+    }
+
+    fn to_u8(&self) -> Result<u8> {
+        self.number()
+    }
+
+    fn to_usize(&self) -> Result<usize> {
+        self.number()
+    }
+
+    fn to_i64(&self) -> Result<i64> {
+        self.number()
+    }
+
+    fn to_f64(&self) -> Result<f64> {
+        self.number()
+    }
+}
+
+/// A recursive descent parser for JSON.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        if self.bytes[self.position..].starts_with(text.as_bytes()) {
+            self.position += text.len();
+            Ok(())
+        } else {
+            Err(Error::ReturnCode(ICReturnInvalidParam))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.bytes.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.whitespace();
+                if self.bytes.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    self.whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.position;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.bytes.get(self.position)
+                {
+                    self.position += 1;
+                }
+                let text = std::str::from_utf8(&self.bytes[start..self.position])
+                    .map_err(|_| Error::ReturnCode(ICReturnInvalidParam))?;
+                text.parse::<f64>()
+                    .map_err(|_| Error::ReturnCode(ICReturnInvalidParam))?;
+                Ok(Json::Number(text.to_owned()))
+            }
+            _ => Err(Error::ReturnCode(ICReturnInvalidParam)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect("\"")?;
+        let mut text = String::new();
+        loop {
+            let start = self.position;
+            while let Some(&byte) = self.bytes.get(self.position) {
+                if byte == b'"' || byte == b'\\' {
+                    break;
+                }
+                self.position += 1;
+            }
+            text.push_str(
+                std::str::from_utf8(&self.bytes[start..self.position])
+                    .map_err(|_| Error::ReturnCode(ICReturnInvalidParam))?,
+            );
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(text);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escape = *self
+                        .bytes
+                        .get(self.position)
+                        .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+                    self.position += 1;
+                    match escape {
+                        b'"' => text.push('"'),
+                        b'\\' => text.push('\\'),
+                        b'/' => text.push('/'),
+                        b'b' => text.push('\u{8}'),
+                        b'f' => text.push('\u{c}'),
+                        b'n' => text.push('\n'),
+                        b'r' => text.push('\r'),
+                        b't' => text.push('\t'),
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // A surrogate pair encodes a character outside the basic plane.
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(Error::ReturnCode(ICReturnInvalidParam));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            } else if (0xdc00..0xe000).contains(&code) {
+                                return Err(Error::ReturnCode(ICReturnInvalidParam));
+                            }
+                            text.push(
+                                std::char::from_u32(code)
+                                    .ok_or(Error::ReturnCode(ICReturnInvalidParam))?,
+                            );
+                        }
+                        _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+                    }
+                }
+                _ => return Err(Error::ReturnCode(ICReturnInvalidParam)),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for byte in text.bytes() {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|&c| c == byte)
+            .ok_or(Error::ReturnCode(ICReturnInvalidParam))?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ICScannerMeasurementUnit::ICScannerMeasurementUnitInches;
+    use crate::error::NS_COCOA_ERROR_DOMAIN;
+    use crate::simulated_scanner::SimulatedScanner;
+
+    /// Scan a square inch and collect the events.
+    fn scan<B: Backend>(backend: &mut B, device: &DeviceId) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut drain = |backend: &mut B| {
+            while let Some(event) = backend.next_event(Duration::from_secs(0)) {
+                events.push(event);
+            }
+        };
+        backend.start_browsing(&[DeviceKind::Scanner]).unwrap();
+        backend.open_session(device).unwrap();
+        drain(backend);
+        let area = ScanRect::new(0.0, 0.0, 1.0, 1.0, ICScannerMeasurementUnitInches);
+        let settings = ScanSettings::new()
+            .with_resolution(75)
+            .with_scan_area(area)
+            .with_max_memory_band_size(4096);
+        backend.configure(device, &settings).unwrap();
+        backend.request_scan(device).unwrap();
+        drain(backend);
+        events
+    }
+
+    #[test]
+    fn replays_deliver_the_recorded_events() {
+        let scanner = SimulatedScanner::default();
+        let id = scanner.id().clone();
+        let mut recorder = TraceRecorder::new(scanner, Vec::new());
+        let recorded = scan(&mut recorder, &id);
+        let (_, trace) = recorder.finish().unwrap();
+
+        let mut replay = TraceReplay::from_reader(&trace[..])
+            .unwrap()
+            .with_speed(f64::INFINITY);
+        assert_eq!(scan(&mut replay, &id), recorded);
+        assert!(replay.is_finished());
+        assert!(recorded
+            .iter()
+            .any(|event| matches!(event, Event::ScanCompleted { result: Ok(()), .. })));
+    }
+
+    #[test]
+    fn errors_round_trip() {
+        for error in &[
+            Error::ReturnCode(ICReturnInvalidParam),
+            Error::UnknownReturnCode(-9999),
+            Error::UnknownValue(42),
+            Error::Domain {
+                domain: NS_COCOA_ERROR_DOMAIN.to_owned(),
+                code: 3072,
+            },
+            Error::Io {
+                kind: io::ErrorKind::NotFound,
+                code: 2,
+                message: "missing".to_owned(),
+            },
+        ] {
+            assert_eq!(Error::from_json(&error.to_json()).as_ref(), Ok(error));
+        }
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let text = "quote \" slash \\ tab \t bell \u{7} \u{e9} \u{1f600}";
+        let mut json = String::new();
+        Json::from(text).write(&mut json);
+        assert_eq!(Json::parse(&json), Ok(Json::from(text)));
+    }
+
+    #[test]
+    fn surrogate_pairs_are_decoded() {
+        assert_eq!(
+            Json::parse(r#""\ud83d\ude00\u00e9""#),
+            Ok(Json::from("\u{1f600}\u{e9}"))
+        );
+    }
+
+    #[test]
+    fn invalid_escapes_are_rejected() {
+        for text in &[
+            r#""\ud800\u0041""#,
+            r#""\udc00""#,
+            r#""\ud800""#,
+            r#""\u+0041""#,
+        ] {
+            assert_eq!(
+                Json::parse(text),
+                Err(Error::ReturnCode(ICReturnInvalidParam)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        let data: Vec<u8> = (0..=255).collect();
+        for length in 0..6 {
+            let encoded = base64_encode(&data[..length]);
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(base64_decode(&encoded), Ok(data[..length].to_vec()));
+        }
+        assert_eq!(base64_encode(b"scan"), "c2Nhbg==");
+    }
+}